@@ -26,11 +26,10 @@ pub async fn verify_token<T>(
 where
     T: TokenVerifier + Clone + Send + Sync + 'static,
 {
-    let user = app_state.verify(&token);
-    let Ok(user) = user else {
+    let Ok(ctx) = app_state.verify(&token).await else {
         return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
     };
-    request.extensions_mut().insert(user);
+    request.extensions_mut().insert(ctx);
     next.run(request).await
 }
 
@@ -69,14 +68,14 @@ mod test {
 
     use crate::{
         utils::{DecodingKey, EncodingKey},
-        User,
+        AuthContext, User,
     };
 
     use super::*;
     use anyhow::Result;
     use axum::{
         body::Body, http::StatusCode, middleware::from_fn_with_state, response::IntoResponse,
-        routing::get, Router,
+        routing::get, Extension, Router,
     };
     use http_body_util::BodyExt;
     use request::Request;
@@ -90,14 +89,16 @@ mod test {
         pub ek: EncodingKey,
     }
 
+    #[async_trait]
     impl TokenVerifier for AppState {
         type Error = anyhow::Error;
-        fn verify(&self, token: &str) -> Result<User, Self::Error> {
-            self.0.pk.verify(token)
+        async fn verify(&self, token: &str) -> Result<AuthContext, Self::Error> {
+            Ok(self.0.pk.verify(token)?.into())
         }
     }
 
-    async fn handler() -> impl IntoResponse {
+    async fn handler(Extension(ctx): Extension<AuthContext>) -> impl IntoResponse {
+        assert_eq!(ctx.user_id, 11);
         (StatusCode::OK, "OK")
     }
     #[tokio::test]
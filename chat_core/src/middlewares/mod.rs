@@ -2,15 +2,68 @@ mod auth;
 mod request_id;
 mod server_time;
 
-use crate::User;
+use axum::async_trait;
 
+use crate::{Scope, UserClaims};
+
+#[async_trait]
 pub trait TokenVerifier {
     type Error;
-    fn verify(&self, token: &str) -> Result<User, Self::Error>;
+    async fn verify(&self, token: &str) -> Result<AuthContext, Self::Error>;
+}
+
+// the authenticated user and what the token is allowed to do,
+// servers needing the whole user load it by the ids
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthContext {
+    pub user_id: i64,
+    // the workspace the token acts in
+    pub ws_id: i64,
+    // session of the signed in device
+    pub sid: Option<i64>,
+    // None for signed in users, personal access tokens are limited to their scopes
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl AuthContext {
+    pub fn new(user_id: i64, ws_id: i64) -> Self {
+        Self {
+            user_id,
+            ws_id,
+            sid: None,
+            scopes: None,
+        }
+    }
+
+    pub fn with_scopes(user_id: i64, ws_id: i64, scopes: Vec<Scope>) -> Self {
+        Self {
+            scopes: Some(scopes),
+            ..Self::new(user_id, ws_id)
+        }
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.as_ref().is_none_or(|s| s.contains(&scope))
+    }
+
+    pub fn is_access_token(&self) -> bool {
+        self.scopes.is_some()
+    }
+}
+
+impl From<UserClaims> for AuthContext {
+    fn from(claims: UserClaims) -> Self {
+        Self {
+            user_id: claims.user_id,
+            ws_id: claims.ws_id,
+            sid: claims.sid,
+            scopes: claims.scopes,
+        }
+    }
 }
 
 pub const REQUEST_ID_HEADER: &str = "x-request-id";
 pub const SERVER_TIME_HEADER: &str = "x-server-time";
-pub use auth::verify_token;
+pub use auth::{verify_token, AuthHeader};
 pub use request_id::set_request_id;
 pub use server_time::ServerTimeLayer;
@@ -13,13 +13,48 @@ pub struct User {
     pub password_hash: Option<String>,
     pub created_at: DateTime<Utc>,
     pub ws_id: i64,
+    // bots could only authenticate with personal access tokens
+    #[sqlx(default)]
+    #[serde(default)]
+    pub is_bot: bool,
 }
 
+// columns of users u as a ChatUser, an expired status is returned as none
+pub const CHAT_USER_COLUMNS: &str = r#"u.id, u.fullname, u.email, u.display_name, u.avatar_url, u.title, u.timezone,
+    CASE WHEN u.status_expires_at IS NULL OR u.status_expires_at > NOW() THEN u.status_text END AS status_text,
+    CASE WHEN u.status_expires_at IS NULL OR u.status_expires_at > NOW() THEN u.status_emoji END AS status_emoji,
+    CASE WHEN u.status_expires_at > NOW() THEN u.status_expires_at END AS status_expires_at"#;
+
 #[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
 pub struct ChatUser {
-    id: i64,
-    fullname: String,
-    email: String,
+    pub id: i64,
+    pub fullname: String,
+    pub email: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub profile: UserProfile,
+}
+
+// the status is none once it expired
+#[derive(Debug, Clone, Default, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct UserProfile {
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub title: Option<String>,
+    pub timezone: Option<String>,
+    pub status_text: Option<String>,
+    pub status_emoji: Option<String>,
+    pub status_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "dm_policy", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DmPolicy {
+    #[default]
+    Anyone,
+    // only users sharing a channel could start a direct message
+    SharedChannels,
 }
 
 #[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
@@ -27,6 +62,230 @@ pub struct Workspace {
     pub id: i64,
     pub name: String,
     pub owner_id: i64,
+    pub join_policy: JoinPolicy,
+    pub allowed_domains: Vec<String>,
+    #[sqlx(json)]
+    #[serde(default)]
+    pub settings: WorkspaceSettings,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct WorkspaceSettings {
+    // new members are added to these chats
+    #[serde(default)]
+    pub default_channels: Vec<i64>,
+    // messages older than this are deleted, kept forever if not set
+    #[serde(default)]
+    pub message_retention_days: Option<i32>,
+    // max size of an uploaded file in bytes
+    #[serde(default)]
+    pub max_upload_size: Option<u64>,
+    // members must verify their email before posting or joining channels
+    #[serde(default)]
+    pub require_verified_email: bool,
+    // members must enable two-factor authentication before using the workspace
+    #[serde(default)]
+    pub require_two_factor: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "workspace_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceRole {
+    Owner,
+    Admin,
+    #[default]
+    Member,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct WorkspaceMember {
+    pub id: i64,
+    pub fullname: String,
+    pub email: String,
+    pub role: WorkspaceRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "join_policy", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum JoinPolicy {
+    #[default]
+    InviteOnly,
+    // users with an email in the allowed domains could join
+    Domain,
+    Open,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct WorkspaceInvite {
+    pub id: i64,
+    pub ws_id: i64,
+    pub code: String,
+    pub email: Option<String>,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: DateTime<Utc>,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+// what a personal access token is allowed to do
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Type, ToSchema)]
+#[sqlx(type_name = "token_scope")]
+pub enum Scope {
+    #[sqlx(rename = "chats:read")]
+    #[serde(rename = "chats:read")]
+    ChatsRead,
+    #[sqlx(rename = "chats:write")]
+    #[serde(rename = "chats:write")]
+    ChatsWrite,
+    #[sqlx(rename = "messages:read")]
+    #[serde(rename = "messages:read")]
+    MessagesRead,
+    #[sqlx(rename = "messages:write")]
+    #[serde(rename = "messages:write")]
+    MessagesWrite,
+    #[sqlx(rename = "workspaces:read")]
+    #[serde(rename = "workspaces:read")]
+    WorkspacesRead,
+    #[sqlx(rename = "workspaces:write")]
+    #[serde(rename = "workspaces:write")]
+    WorkspacesWrite,
+    // deleting or transferring workspaces and removing their members, admins only
+    #[sqlx(rename = "workspaces:admin")]
+    #[serde(rename = "workspaces:admin")]
+    WorkspacesAdmin,
+    // provisioning users and groups of the workspace, admins only
+    #[sqlx(rename = "scim")]
+    #[serde(rename = "scim")]
+    Scim,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct AccessToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub ws_id: i64,
+    pub name: String,
+    // first characters of the token to tell tokens apart
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_by: i64,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct Session {
+    pub id: i64,
+    pub user_id: i64,
+    pub device_name: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    // whether the session is the one used by the request
+    pub current: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Type, ToSchema)]
+#[sqlx(type_name = "audit_action", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    SigninLocked,
+    SigninUnlocked,
+    UserDeactivated,
+    UserReactivated,
+    UserDeletionRequested,
+    UserDeleted,
+    UserDeletionFailed,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct AuditLog {
+    pub id: i64,
+    pub ws_id: Option<i64>,
+    // the account the event is about
+    pub user_id: Option<i64>,
+    // the admin who made the change, None for the server itself
+    pub actor_id: Option<i64>,
+    pub action: AuditAction,
+    pub ip: Option<String>,
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "deletion_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+// background job anonymizing an account
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct UserDeletion {
+    pub id: i64,
+    pub user_id: i64,
+    pub ws_id: Option<i64>,
+    pub requested_by: i64,
+    pub scrub_messages: bool,
+    pub scrub_files: bool,
+    pub status: DeletionStatus,
+    pub attempts: i32,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "export_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    Pending,
+    Running,
+    Ready,
+    Failed,
+    Expired,
+}
+
+// archive of the personal data of a user
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct DataExport {
+    pub id: i64,
+    pub user_id: i64,
+    pub ws_id: i64,
+    pub status: ExportStatus,
+    #[serde(skip)]
+    pub file_name: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    // set once the archive is ready, until it expires
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub download_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct WorkspaceCommand {
+    pub id: i64,
+    pub ws_id: i64,
+    pub name: String,
+    pub url: String,
+    pub description: String,
+    pub created_by: i64,
     pub created_at: DateTime<Utc>,
 }
 
@@ -47,6 +306,7 @@ pub struct Chat {
     pub name: Option<String>,
     pub r#type: ChatType,
     pub members: Vec<i64>,
+    pub topic: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -55,11 +315,97 @@ pub struct Message {
     pub id: i64,
     pub chat_id: i64,
     pub sender_id: i64,
+    pub kind: MessageKind,
+    pub format: ContentFormat,
+    // raw content as sent by the user
     pub content: String,
+    // left out of the database notifications, rendered again from the content
+    #[serde(default)]
+    pub content_html: String,
+    #[serde(default)]
+    pub content_text: String,
     pub files: Vec<String>,
+    #[sqlx(json)]
+    #[serde(default)]
+    pub previews: Vec<LinkPreview>,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Poll>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "message_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    #[default]
+    Text,
+    Poll,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Type, ToSchema)]
+#[sqlx(type_name = "content_format", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFormat {
+    Plain,
+    #[default]
+    Markdown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Poll {
+    pub message_id: i64,
+    pub chat_id: i64,
+    pub question: String,
+    pub options: Vec<PollOption>,
+    pub multi_select: bool,
+    pub anonymous: bool,
+    pub closed: bool,
+    // number of distinct users who voted
+    pub voters: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct PollOption {
+    pub text: String,
+    pub votes: i64,
+    // always empty for anonymous polls
+    pub voters: Vec<i64>,
+}
+
+// a message only delivered to a single user through the notify server, never stored
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct EphemeralMessage {
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub content: String,
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize, PartialEq, ToSchema)]
+pub struct SavedMessage {
+    pub saved_id: i64,
+    pub chat_name: Option<String>,
+    pub saved_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    pub message: Message,
+}
+
+impl sqlx::postgres::PgHasArrayType for Scope {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_token_scope")
+    }
+}
+
 impl User {
     pub fn new(id: i64, fullname: &str, email: &str, ws_id: i64) -> Self {
         Self {
@@ -69,6 +415,7 @@ impl User {
             password_hash: None,
             created_at: chrono::Utc::now(),
             ws_id,
+            is_bot: false,
         }
     }
 }
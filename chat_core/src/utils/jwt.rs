@@ -1,46 +1,213 @@
 use jwt_simple::prelude::*;
 
-use crate::User;
+use crate::{Scope, User};
 use anyhow::Result;
+use utoipa::ToSchema;
 
-const JWT_DURATION: u64 = 60 * 60 * 24 * 7; // 7 days
+pub const JWT_DURATION: u64 = 60 * 15; // 15 minutes, refresh tokens are used to renew it
 const JWT_ISS: &str = "chat_server";
 const JWT_AUD: &str = "chat_web";
+const JWK_KTY: &str = "OKP";
+const JWK_CRV: &str = "Ed25519";
+const JWK_ALG: &str = "EdDSA";
 
+// the signing key, its kid is set in the header of the issued tokens
 #[derive(Clone)]
 pub struct EncodingKey(Ed25519KeyPair);
 
+// only the ids are kept in the token, the user is loaded by the servers when needed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserClaims {
+    pub user_id: i64,
+    pub ws_id: i64,
+    // session the token is issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i64>,
+    // None for full access
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Scope>>,
+}
+
+// the verification key set, several keys are accepted while rotating the signing key
 #[derive(Debug, Clone)]
-pub struct DecodingKey(Ed25519PublicKey);
+pub struct DecodingKey(Vec<Ed25519PublicKey>);
+
+// https://www.rfc-editor.org/rfc/rfc8037 public keys in a JWK set
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    // base64url encoded public key
+    pub x: String,
+    pub kid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+}
 
 impl EncodingKey {
     pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
-        Ok(EncodingKey(Ed25519KeyPair::from_pem(pem)?))
+        let key = Ed25519KeyPair::from_pem(pem)?;
+        let kid = key_id(&key.public_key());
+        Ok(EncodingKey(key.with_key_id(&kid)))
     }
 
-    pub fn sign(&self, user: User) -> Result<String, jwt_simple::Error> {
-        let mut claims = Claims::with_custom_claims(user, Duration::from_secs(JWT_DURATION));
-        claims = claims.with_issuer(JWT_ISS).with_audience(JWT_AUD);
+    pub fn kid(&self) -> &str {
+        self.0.key_id().as_deref().unwrap_or_default()
+    }
+
+    pub fn sign(&self, user: impl Into<UserClaims>) -> Result<String, jwt_simple::Error> {
+        self.sign_claims(user.into())
+    }
+
+    pub fn sign_session(
+        &self,
+        user: impl Into<UserClaims>,
+        sid: i64,
+    ) -> Result<String, jwt_simple::Error> {
+        self.sign_claims(UserClaims {
+            sid: Some(sid),
+            ..user.into()
+        })
+    }
+
+    pub fn sign_claims(&self, custom: UserClaims) -> Result<String, jwt_simple::Error> {
+        let mut claims = Claims::with_custom_claims(custom, Duration::from_secs(JWT_DURATION));
+        claims = claims
+            .with_issuer(JWT_ISS)
+            .with_audience(JWT_AUD)
+            .with_jwt_id(uuid::Uuid::now_v7().to_string());
         self.0.sign(claims)
     }
 }
 
 impl DecodingKey {
     pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
-        Ok(DecodingKey(Ed25519PublicKey::from_pem(pem)?))
+        Self::load_all([pem])
+    }
+
+    // load the current key and the keys of a rotation, duplicated keys are ignored
+    pub fn load_all<'a>(
+        pems: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, jwt_simple::Error> {
+        let mut keys: Vec<Ed25519PublicKey> = Vec::new();
+        for pem in pems {
+            let key = Ed25519PublicKey::from_pem(pem)?;
+            let kid = key_id(&key);
+            if keys.iter().all(|k| k.key_id().as_deref() != Some(&kid)) {
+                keys.push(key.with_key_id(&kid));
+            }
+        }
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("no verification key"));
+        }
+        Ok(DecodingKey(keys))
+    }
+
+    pub fn from_jwks(jwks: &Jwks) -> Result<Self, jwt_simple::Error> {
+        let mut keys = Vec::new();
+        for jwk in &jwks.keys {
+            // keys of other algorithms are skipped
+            if jwk.kty != JWK_KTY || jwk.crv != JWK_CRV {
+                continue;
+            }
+            let bytes = Base64UrlSafeNoPadding::decode_to_vec(&jwk.x, None)?;
+            keys.push(Ed25519PublicKey::from_bytes(&bytes)?.with_key_id(&jwk.kid));
+        }
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("no Ed25519 key in the key set"));
+        }
+        Ok(DecodingKey(keys))
     }
 
-    pub fn verify(&self, token: &str) -> Result<User, jwt_simple::Error> {
+    pub fn to_jwks(&self) -> Jwks {
+        let keys = self
+            .0
+            .iter()
+            .map(|key| Jwk {
+                kty: JWK_KTY.to_string(),
+                crv: JWK_CRV.to_string(),
+                x: Base64UrlSafeNoPadding::encode_to_string(key.to_bytes()).unwrap_or_default(),
+                kid: key.key_id().clone().unwrap_or_default(),
+                alg: Some(JWK_ALG.to_string()),
+                key_use: Some("sig".to_string()),
+            })
+            .collect();
+        Jwks { keys }
+    }
+
+    pub fn kids(&self) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter_map(|k| k.key_id().as_deref())
+            .collect()
+    }
+
+    pub fn verify(&self, token: &str) -> Result<UserClaims, jwt_simple::Error> {
+        Ok(self.decode(token)?.custom)
+    }
+
+    // verify the token and return all the claims, including jti and expiration
+    pub fn decode(&self, token: &str) -> Result<JWTClaims<UserClaims>, jwt_simple::Error> {
         let options = VerificationOptions {
             allowed_issuers: Some(HashSet::from_strings(&[JWT_ISS])),
             allowed_audiences: Some(HashSet::from_strings(&[JWT_AUD])),
             ..Default::default()
         };
-        let claims = self.0.verify_token::<User>(token, Some(options))?;
-        Ok(claims.custom)
+        let metadata = Token::decode_metadata(token)?;
+        match metadata.key_id() {
+            Some(kid) => {
+                let key = self
+                    .0
+                    .iter()
+                    .find(|k| k.key_id().as_deref() == Some(kid))
+                    .ok_or_else(|| anyhow::anyhow!("unknown key id: {}", kid))?;
+                key.verify_token::<UserClaims>(token, Some(options))
+            }
+            // tokens issued before key ids were introduced
+            None => {
+                let mut ret = Err(anyhow::anyhow!("no verification key"));
+                for key in &self.0 {
+                    ret = key.verify_token::<UserClaims>(token, Some(options.clone()));
+                    if ret.is_ok() {
+                        break;
+                    }
+                }
+                ret
+            }
+        }
+    }
+}
+
+impl From<&User> for UserClaims {
+    fn from(user: &User) -> Self {
+        Self {
+            user_id: user.id,
+            ws_id: user.ws_id,
+            sid: None,
+            scopes: None,
+        }
+    }
+}
+
+impl From<User> for UserClaims {
+    fn from(user: User) -> Self {
+        Self::from(&user)
     }
 }
 
+// derived from the public key so all the servers agree on it
+fn key_id(key: &Ed25519PublicKey) -> String {
+    let thumbprint = key.sha256_thumbprint();
+    thumbprint[..16.min(thumbprint.len())].to_string()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -54,10 +221,68 @@ mod tests {
         let user = User::new(1, "tom", "tom@123.com", 0);
 
         let token = ek.sign(user.clone())?;
-        let user2 = pk.verify(&token)?;
+        let claims = pk.verify(&token)?;
+
+        assert_eq!(claims.user_id, user.id);
+        assert_eq!(claims.ws_id, user.ws_id);
+        assert_eq!(claims.scopes, None);
+        // no profile data in the token
+        let payload = token.split('.').nth(1).unwrap_or_default();
+        let payload = Base64UrlSafeNoPadding::decode_to_vec(payload, None)?;
+        let payload = String::from_utf8(payload)?;
+        assert!(!payload.contains("tom@123.com"));
+        assert!(!payload.contains("fullname"));
+
+        let claims = pk.decode(&token)?;
+        assert!(claims.jwt_id.is_some());
+        assert_eq!(claims.custom.sid, None);
+        assert_ne!(claims.jwt_id, pk.decode(&ek.sign(user.clone())?)?.jwt_id);
+
+        let token = ek.sign_session(user.clone(), 3)?;
+        let claims = pk.decode(&token)?;
+        assert_eq!(claims.custom.user_id, user.id);
+        assert_eq!(claims.custom.sid, Some(3));
+
+        let token = ek.sign_claims(UserClaims {
+            scopes: Some(vec![Scope::ChatsRead]),
+            ..user.into()
+        })?;
+        assert_eq!(pk.verify(&token)?.scopes, Some(vec![Scope::ChatsRead]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_rotation_should_work() -> Result<()> {
+        let old = EncodingKey::load(include_str!("../../fixtures/encoding.pem"))?;
+        let new = EncodingKey(Ed25519KeyPair::generate());
+        let new = EncodingKey::load(&new.0.to_pem())?;
+        assert_ne!(old.kid(), new.kid());
+
+        let old_pem = include_str!("../../fixtures/decoding.pem");
+        let new_pem = new.0.public_key().to_pem();
+        let user = UserClaims::from(User::new(1, "tom", "tom@123.com", 0));
+        let old_token = old.sign(user.clone())?;
+        let new_token = new.sign(user.clone())?;
+
+        // both keys are accepted during the rotation
+        let pk = DecodingKey::load_all([new_pem.as_str(), old_pem, old_pem])?;
+        assert_eq!(pk.kids(), vec![new.kid(), old.kid()]);
+        assert_eq!(pk.verify(&old_token)?, user);
+        assert_eq!(pk.verify(&new_token)?, user);
 
-        assert_eq!(user, user2);
+        // old tokens are rejected once the old key is retired
+        let pk = DecodingKey::load(&new_pem)?;
+        assert!(pk.verify(&old_token).is_err());
 
+        // the key set could be published and loaded as jwks
+        let jwks = DecodingKey::load_all([new_pem.as_str(), old_pem])?.to_jwks();
+        let json = serde_json::to_string(&jwks)?;
+        let jwks: Jwks = serde_json::from_str(&json)?;
+        assert_eq!(jwks.keys[0].kid, new.kid());
+        let pk = DecodingKey::from_jwks(&jwks)?;
+        assert_eq!(pk.verify(&old_token)?, user);
+        assert_eq!(pk.verify(&new_token)?, user);
         Ok(())
     }
 }
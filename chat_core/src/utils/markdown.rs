@@ -0,0 +1,105 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+use crate::ContentFormat;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedContent {
+    // sanitized html, safe to be embedded by clients
+    pub html: String,
+    // plain text without any markup, used for search and previews
+    pub text: String,
+}
+
+impl ContentFormat {
+    pub fn render(&self, content: &str) -> RenderedContent {
+        match self {
+            ContentFormat::Plain => render_plain(content),
+            ContentFormat::Markdown => render_markdown(content),
+        }
+    }
+}
+
+fn render_plain(content: &str) -> RenderedContent {
+    let mut events = vec![Event::Start(Tag::Paragraph)];
+    for (i, line) in content.lines().enumerate() {
+        if i > 0 {
+            events.push(Event::HardBreak);
+        }
+        events.push(Event::Text(CowStr::Borrowed(line)));
+    }
+    events.push(Event::End(TagEnd::Paragraph));
+
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, events.into_iter());
+    RenderedContent {
+        html: ammonia::clean(&html_output),
+        text: content.trim().to_string(),
+    }
+}
+
+fn render_markdown(content: &str) -> RenderedContent {
+    let options =
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, Parser::new_ext(content, options));
+
+    let mut text = String::new();
+    for event in Parser::new_ext(content, options) {
+        match event {
+            Event::Text(s) | Event::Code(s) => text.push_str(&s),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak | Event::Rule => text.push('\n'),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::Item
+                | TagEnd::CodeBlock
+                | TagEnd::TableRow
+                | TagEnd::TableHead,
+            ) => text.push('\n'),
+            Event::End(TagEnd::TableCell) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    RenderedContent {
+        html: ammonia::clean(&html_output),
+        text: text.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_markdown_should_work() {
+        let rendered = ContentFormat::Markdown.render("# Hello\n\n**bold** and `code`");
+        assert_eq!(
+            rendered.html,
+            "<h1>Hello</h1>\n<p><strong>bold</strong> and <code>code</code></p>\n"
+        );
+        assert_eq!(rendered.text, "Hello\nbold and code");
+    }
+
+    #[test]
+    fn render_markdown_should_sanitize_html() {
+        let rendered = ContentFormat::Markdown.render(
+            "hi <script>alert(1)</script> [link](javascript:alert(1)) <img src=x onerror=alert(1)>",
+        );
+        assert!(!rendered.html.contains("<script"));
+        assert!(!rendered.html.contains("javascript:"));
+        assert!(!rendered.html.contains("onerror"));
+    }
+
+    #[test]
+    fn render_plain_should_escape_html() {
+        let rendered = ContentFormat::Plain.render("**not bold**\n<b>hi</b>");
+        assert_eq!(
+            rendered.html,
+            "<p>**not bold**<br>\n&lt;b&gt;hi&lt;/b&gt;</p>\n"
+        );
+        assert_eq!(rendered.text, "**not bold**\n<b>hi</b>");
+    }
+}
@@ -1,2 +1,6 @@
 mod jwt;
-pub use jwt::{DecodingKey, EncodingKey};
+mod markdown;
+mod revocation;
+pub use jwt::{DecodingKey, EncodingKey, Jwk, Jwks, UserClaims, JWT_DURATION};
+pub use markdown::RenderedContent;
+pub use revocation::{revoked_items, RevocationList, Revoked};
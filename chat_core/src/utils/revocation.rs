@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use jwt_simple::prelude::JWTClaims;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::UserClaims;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Revoked {
+    // a single access token by its jti
+    Token(String),
+    // all the access tokens issued for a session
+    Session(i64),
+}
+
+// revoked access tokens and sessions with their expiration (unix seconds),
+// entries could be dropped once the tokens expire by themselves
+#[derive(Debug, Clone)]
+pub struct RevocationList {
+    entries: Arc<RwLock<HashMap<Revoked, u64>>>,
+    tx: broadcast::Sender<Revoked>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            tx,
+        }
+    }
+
+    pub fn revoke(&self, item: Revoked, expires_at: u64) {
+        let inserted = self
+            .entries
+            .write()
+            .unwrap()
+            .insert(item.clone(), expires_at)
+            .is_none();
+        if inserted {
+            // no receivers is fine
+            let _ = self.tx.send(item);
+        }
+    }
+
+    pub fn is_revoked(&self, item: &Revoked) -> bool {
+        self.entries.read().unwrap().contains_key(item)
+    }
+
+    // tokens without a jti could not be revoked so they are rejected as well
+    pub fn is_claims_revoked(&self, claims: &JWTClaims<UserClaims>) -> bool {
+        revoked_items(claims).is_none_or(|items| items.iter().any(|i| self.is_revoked(i)))
+    }
+
+    // receive the items revoked from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Revoked> {
+        self.tx.subscribe()
+    }
+
+    pub fn purge_expired(&self, now: u64) {
+        self.entries.write().unwrap().retain(|_, exp| *exp > now);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for RevocationList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the items which revoke the token if any of them is revoked
+pub fn revoked_items(claims: &JWTClaims<UserClaims>) -> Option<Vec<Revoked>> {
+    let mut items = vec![Revoked::Token(claims.jwt_id.clone()?)];
+    if let Some(sid) = claims.custom.sid {
+        items.push(Revoked::Session(sid));
+    }
+    Some(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecodingKey, EncodingKey, User};
+
+    #[tokio::test]
+    async fn revocation_list_should_work() {
+        let list = RevocationList::new();
+        let mut rx = list.subscribe();
+        list.revoke(Revoked::Token("a".to_string()), 100);
+        list.revoke(Revoked::Session(1), 200);
+        // revoking twice should not notify again
+        list.revoke(Revoked::Token("a".to_string()), 100);
+        assert!(list.is_revoked(&Revoked::Token("a".to_string())));
+        assert!(!list.is_revoked(&Revoked::Token("c".to_string())));
+        assert!(!list.is_revoked(&Revoked::Session(2)));
+        assert_eq!(rx.recv().await.unwrap(), Revoked::Token("a".to_string()));
+        assert_eq!(rx.recv().await.unwrap(), Revoked::Session(1));
+        assert!(rx.try_recv().is_err());
+
+        list.purge_expired(150);
+        assert!(!list.is_revoked(&Revoked::Token("a".to_string())));
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn revoked_session_should_revoke_claims() -> anyhow::Result<()> {
+        let ek = EncodingKey::load(include_str!("../../fixtures/encoding.pem"))?;
+        let pk = DecodingKey::load(include_str!("../../fixtures/decoding.pem"))?;
+        let user = User::new(1, "tom", "tom@123.com", 0);
+        let claims = pk.decode(&ek.sign_session(user, 3)?)?;
+
+        let list = RevocationList::new();
+        assert!(!list.is_claims_revoked(&claims));
+        list.revoke(Revoked::Session(3), u64::MAX);
+        assert!(list.is_claims_revoked(&claims));
+        Ok(())
+    }
+}
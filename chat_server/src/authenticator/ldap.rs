@@ -0,0 +1,331 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::async_trait;
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+
+use super::{Authenticator, Identity};
+use crate::{config::LdapConfig, error::AppError};
+
+// invalidCredentials result code of a bind
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryEntry {
+    pub dn: String,
+    pub attrs: HashMap<String, Vec<String>>,
+}
+
+// the operations of the directory server the authenticator needs
+#[async_trait]
+pub trait Directory: Send + Sync + 'static {
+    // entries matching the filter under the base dn
+    async fn search(&self, filter: &str, attrs: &[&str]) -> Result<Vec<DirectoryEntry>, AppError>;
+    // false if the password of the entry is wrong
+    async fn bind(&self, dn: &str, password: &str) -> Result<bool, AppError>;
+}
+
+// search the entry of the login, then bind as it to check the password
+pub struct LdapAuthenticator {
+    config: LdapConfig,
+    directory: Arc<dyn Directory>,
+}
+
+impl LdapAuthenticator {
+    pub fn new(config: LdapConfig, directory: Arc<dyn Directory>) -> Self {
+        Self { config, directory }
+    }
+
+    fn first_attr<'a>(&self, entry: &'a DirectoryEntry, name: &str) -> Option<&'a str> {
+        entry
+            .attrs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first())
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+
+    // workspaces of the groups the entry is a member of
+    fn map_groups(&self, entry: &DirectoryEntry) -> Vec<i64> {
+        let groups = entry
+            .attrs
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(&self.config.group_attr))
+            .flat_map(|(_, values)| values.iter())
+            .collect::<Vec<_>>();
+        let mut ws_ids = self
+            .config
+            .groups
+            .iter()
+            .filter(|m| groups.iter().any(|g| same_dn(g, &m.group_dn)))
+            .map(|m| m.ws_id)
+            .collect::<Vec<_>>();
+        ws_ids.sort_unstable();
+        ws_ids.dedup();
+        ws_ids
+    }
+}
+
+#[async_trait]
+impl Authenticator for LdapAuthenticator {
+    async fn authenticate(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, AppError> {
+        // a bind without password is an anonymous bind and always succeeds
+        if login.trim().is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+        let filter = self
+            .config
+            .user_filter
+            .replace("{login}", &ldap_escape(login.trim()));
+        let attrs = [
+            self.config.fullname_attr.as_str(),
+            self.config.email_attr.as_str(),
+            self.config.group_attr.as_str(),
+        ];
+        let mut entries = self.directory.search(&filter, &attrs).await?;
+        // an ambiguous login is rejected
+        if entries.len() != 1 {
+            return Ok(None);
+        }
+        let entry = entries.remove(0);
+        if !self.directory.bind(&entry.dn, password).await? {
+            return Ok(None);
+        }
+        let Some(email) = self.first_attr(&entry, &self.config.email_attr) else {
+            return Err(AppError::LoginFailed(format!(
+                "Directory entry {} has no {}",
+                entry.dn, self.config.email_attr
+            )));
+        };
+        let fullname = self
+            .first_attr(&entry, &self.config.fullname_attr)
+            .unwrap_or(email);
+        Ok(Some(Identity {
+            email: email.to_string(),
+            fullname: fullname.to_string(),
+            managed: true,
+            ws_ids: self.map_groups(&entry),
+        }))
+    }
+}
+
+// ldap server reached with a new connection per operation
+pub struct LdapDirectory {
+    config: LdapConfig,
+}
+
+impl LdapDirectory {
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config }
+    }
+
+    async fn connect(&self) -> Result<Ldap, AppError> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(timeout)
+            .set_starttls(self.config.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.url)
+            .await
+            .map_err(ldap_error)?;
+        ldap3::drive!(conn);
+        ldap.with_timeout(timeout);
+        Ok(ldap)
+    }
+}
+
+#[async_trait]
+impl Directory for LdapDirectory {
+    async fn search(&self, filter: &str, attrs: &[&str]) -> Result<Vec<DirectoryEntry>, AppError> {
+        let mut ldap = self.connect().await?;
+        if let Some(bind_dn) = &self.config.bind_dn {
+            ldap.simple_bind(
+                bind_dn,
+                self.config.bind_password.as_deref().unwrap_or_default(),
+            )
+            .await
+            .and_then(|ret| ret.success())
+            .map_err(ldap_error)?;
+        }
+        let ret = ldap
+            .search(&self.config.base_dn, Scope::Subtree, filter, attrs.to_vec())
+            .await
+            .and_then(|ret| ret.success())
+            .map_err(ldap_error);
+        let _ = ldap.unbind().await;
+        let (entries, _) = ret?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let entry = SearchEntry::construct(entry);
+                DirectoryEntry {
+                    dn: entry.dn,
+                    attrs: entry.attrs,
+                }
+            })
+            .collect())
+    }
+
+    async fn bind(&self, dn: &str, password: &str) -> Result<bool, AppError> {
+        let mut ldap = self.connect().await?;
+        let ret = ldap.simple_bind(dn, password).await.map_err(ldap_error);
+        let _ = ldap.unbind().await;
+        match ret?.rc {
+            0 => Ok(true),
+            LDAP_INVALID_CREDENTIALS => Ok(false),
+            rc => Err(AppError::Ldap(format!("bind failed with code {}", rc))),
+        }
+    }
+}
+
+// dns compare case insensitive and ignoring the spaces around the separators
+fn same_dn(a: &str, b: &str) -> bool {
+    let normalize = |dn: &str| {
+        dn.split(',')
+            .map(|rdn| rdn.trim().to_lowercase().replace(" = ", "="))
+            .collect::<Vec<_>>()
+    };
+    normalize(a) == normalize(b)
+}
+
+fn ldap_error(e: impl ToString) -> AppError {
+    AppError::Ldap(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LdapGroupMapping;
+
+    // in-process directory with plain text passwords
+    struct FakeDirectory {
+        entries: Vec<(DirectoryEntry, String)>,
+    }
+
+    #[async_trait]
+    impl Directory for FakeDirectory {
+        async fn search(
+            &self,
+            filter: &str,
+            _attrs: &[&str],
+        ) -> Result<Vec<DirectoryEntry>, AppError> {
+            // only the default (|(uid=..)(mail=..)) filter is understood
+            let login = filter
+                .split_once("(uid=")
+                .and_then(|(_, rest)| rest.split_once(')'))
+                .map(|(login, _)| login)
+                .unwrap_or_default();
+            Ok(self
+                .entries
+                .iter()
+                .filter(|(entry, _)| {
+                    ["uid", "mail"]
+                        .iter()
+                        .any(|attr| entry.attrs[*attr].iter().any(|v| v == login))
+                })
+                .map(|(entry, _)| entry.clone())
+                .collect())
+        }
+
+        async fn bind(&self, dn: &str, password: &str) -> Result<bool, AppError> {
+            Ok(self
+                .entries
+                .iter()
+                .any(|(entry, pw)| entry.dn == dn && pw == password))
+        }
+    }
+
+    fn entry(uid: &str, mail: &str, groups: &[&str]) -> DirectoryEntry {
+        let attrs = [
+            ("uid", vec![uid]),
+            ("mail", vec![mail]),
+            ("cn", vec!["Alice Smith"]),
+            ("memberOf", groups.to_vec()),
+        ];
+        DirectoryEntry {
+            dn: format!("uid={},ou=people,dc=example,dc=org", uid),
+            attrs: attrs
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.into_iter().map(String::from).collect()))
+                .collect(),
+        }
+    }
+
+    fn authenticator() -> LdapAuthenticator {
+        let config = LdapConfig {
+            url: "ldap://localhost:389".to_string(),
+            starttls: false,
+            bind_dn: None,
+            bind_password: None,
+            base_dn: "ou=people,dc=example,dc=org".to_string(),
+            user_filter: "(|(uid={login})(mail={login}))".to_string(),
+            fullname_attr: "cn".to_string(),
+            email_attr: "mail".to_string(),
+            group_attr: "memberOf".to_string(),
+            groups: vec![
+                LdapGroupMapping {
+                    group_dn: "cn=chat,ou=groups,dc=example,dc=org".to_string(),
+                    ws_id: 1,
+                },
+                LdapGroupMapping {
+                    group_dn: "cn=ops,ou=groups,dc=example,dc=org".to_string(),
+                    ws_id: 2,
+                },
+            ],
+            local_fallback: false,
+            timeout_ms: 1000,
+        };
+        let directory = FakeDirectory {
+            entries: vec![
+                (
+                    entry(
+                        "alice",
+                        "alice@example.org",
+                        &["CN=Chat, OU=Groups, DC=example, DC=org", "cn=other"],
+                    ),
+                    "secret".to_string(),
+                ),
+                (entry("bob", "bob@example.org", &[]), "hunter2".to_string()),
+                // a duplicated uid is ambiguous
+                (entry("eve", "eve@example.org", &[]), "pw".to_string()),
+                (entry("eve", "eve2@example.org", &[]), "pw".to_string()),
+            ],
+        };
+        LdapAuthenticator::new(config, Arc::new(directory))
+    }
+
+    #[tokio::test]
+    async fn ldap_authenticate_should_work() -> Result<(), AppError> {
+        let auth = authenticator();
+        let identity = auth.authenticate("alice", "secret").await?.unwrap();
+        assert_eq!(
+            identity,
+            Identity {
+                email: "alice@example.org".to_string(),
+                fullname: "Alice Smith".to_string(),
+                managed: true,
+                ws_ids: vec![1],
+            }
+        );
+        let identity = auth.authenticate("bob", "hunter2").await?.unwrap();
+        assert!(identity.ws_ids.is_empty());
+
+        assert!(auth.authenticate("alice", "wrong").await?.is_none());
+        assert!(auth.authenticate("alice", "").await?.is_none());
+        assert!(auth.authenticate("nobody", "secret").await?.is_none());
+        assert!(auth.authenticate("eve", "pw").await?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn same_dn_should_work() {
+        assert!(same_dn(
+            "CN=Chat, OU=Groups,DC=org",
+            "cn=chat,ou=groups,dc=org"
+        ));
+        assert!(!same_dn("cn=chat,dc=org", "cn=chats,dc=org"));
+    }
+}
@@ -0,0 +1,75 @@
+mod ldap;
+mod password;
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::{config::AppConfig, error::AppError};
+
+use ldap::{LdapAuthenticator, LdapDirectory};
+use password::PasswordAuthenticator;
+
+// who the credentials of a signin belong to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub email: String,
+    pub fullname: String,
+    // the account is managed by the backend, it is created or updated on sign in
+    pub managed: bool,
+    // workspaces the user should be a member of
+    pub ws_ids: Vec<i64>,
+}
+
+#[async_trait]
+pub trait Authenticator: Send + Sync + 'static {
+    // None if the login or the password is wrong
+    async fn authenticate(&self, login: &str, password: &str)
+        -> Result<Option<Identity>, AppError>;
+}
+
+// the first backend accepting the credentials wins
+pub struct ChainAuthenticator(Vec<Arc<dyn Authenticator>>);
+
+#[async_trait]
+impl Authenticator for ChainAuthenticator {
+    async fn authenticate(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, AppError> {
+        let mut last_error = None;
+        for backend in &self.0 {
+            match backend.authenticate(login, password).await {
+                Ok(Some(identity)) => return Ok(Some(identity)),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Authentication backend failed: {}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn build_authenticator(config: &AppConfig, pool: PgPool) -> Arc<dyn Authenticator> {
+    let local: Arc<dyn Authenticator> = Arc::new(PasswordAuthenticator::new(pool));
+    let Some(ldap) = &config.ldap else {
+        return local;
+    };
+    let directory: Arc<dyn Authenticator> = Arc::new(LdapAuthenticator::new(
+        ldap.clone(),
+        Arc::new(LdapDirectory::new(ldap.clone())),
+    ));
+    if ldap.local_fallback {
+        Arc::new(ChainAuthenticator(vec![directory, local]))
+    } else {
+        directory
+    }
+}
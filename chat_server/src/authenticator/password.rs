@@ -0,0 +1,44 @@
+use axum::async_trait;
+use sqlx::PgPool;
+
+use super::{Authenticator, Identity};
+use crate::{error::AppError, models::verify_password};
+
+// argon2 password hashes of the users table
+pub struct PasswordAuthenticator {
+    pool: PgPool,
+}
+
+impl PasswordAuthenticator {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Authenticator for PasswordAuthenticator {
+    async fn authenticate(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<Identity>, AppError> {
+        // bots and sso accounts have no password
+        let user: Option<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT email, fullname, password_hash FROM users WHERE email = $1 AND NOT is_bot",
+        )
+        .bind(login)
+        .fetch_optional(&self.pool)
+        .await?;
+        match user {
+            Some((email, fullname, Some(hash))) if verify_password(password, &hash)? => {
+                Ok(Some(Identity {
+                    email,
+                    fullname,
+                    managed: false,
+                    ws_ids: vec![],
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::time::Duration;
+
+use axum::async_trait;
+use chat_core::{ChatType, EphemeralMessage, User};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use tracing::warn;
+
+use crate::{error::AppError, models::CreateMessage, state::AppState};
+
+use super::{notify_ephemeral, CommandContext, CommandResponse, SlashCommand};
+
+const MAX_TOPIC_LEN: usize = 256;
+const MAX_REMIND_SECS: i64 = 60 * 60 * 24 * 30; // 30 days
+const REMINDER_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct MeCommand;
+pub struct TopicCommand;
+pub struct InviteCommand;
+pub struct RemindCommand;
+
+#[async_trait]
+impl SlashCommand for MeCommand {
+    fn name(&self) -> &'static str {
+        "me"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/me <action>"
+    }
+
+    async fn execute(
+        &self,
+        _state: &AppState,
+        ctx: CommandContext,
+    ) -> Result<CommandResponse, AppError> {
+        if ctx.args.is_empty() {
+            return Ok(usage(self));
+        }
+        Ok(CommandResponse::Post(CreateMessage {
+            content: format!("_{} {}_", ctx.user.fullname, ctx.args),
+            ..Default::default()
+        }))
+    }
+}
+
+#[async_trait]
+impl SlashCommand for TopicCommand {
+    fn name(&self) -> &'static str {
+        "topic"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/topic <new topic>"
+    }
+
+    async fn execute(
+        &self,
+        state: &AppState,
+        ctx: CommandContext,
+    ) -> Result<CommandResponse, AppError> {
+        if ctx.args.is_empty() {
+            let chat = state.get_chat_by_id(ctx.chat_id as i64).await?;
+            let topic = chat.and_then(|c| c.topic);
+            return Ok(CommandResponse::Ephemeral(match topic {
+                Some(topic) => format!("The topic is: {}", topic),
+                None => "No topic is set".to_string(),
+            }));
+        }
+        if ctx.args.chars().count() > MAX_TOPIC_LEN {
+            return Ok(CommandResponse::Ephemeral(format!(
+                "Topic is longer than {} characters",
+                MAX_TOPIC_LEN
+            )));
+        }
+        state
+            .update_chat_topic(ctx.chat_id as i64, &ctx.args)
+            .await?;
+        Ok(CommandResponse::Post(CreateMessage {
+            content: format!("{} set the topic to: {}", ctx.user.fullname, ctx.args),
+            ..Default::default()
+        }))
+    }
+}
+
+#[async_trait]
+impl SlashCommand for InviteCommand {
+    fn name(&self) -> &'static str {
+        "invite"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/invite <email or user id> ..."
+    }
+
+    async fn execute(
+        &self,
+        state: &AppState,
+        ctx: CommandContext,
+    ) -> Result<CommandResponse, AppError> {
+        let targets = ctx.args.split_whitespace().collect::<Vec<_>>();
+        if targets.is_empty() {
+            return Ok(usage(self));
+        }
+        let Some(chat) = state.get_chat_by_id(ctx.chat_id as i64).await? else {
+            return Err(AppError::NotFound(format!(
+                "Chat with id {} not found",
+                ctx.chat_id
+            )));
+        };
+        if chat.r#type == ChatType::Single {
+            return Ok(CommandResponse::Ephemeral(
+                "Can't invite users to a direct message".to_string(),
+            ));
+        }
+
+        let ids = targets
+            .iter()
+            .filter_map(|t| t.parse::<i64>().ok())
+            .collect::<Vec<_>>();
+        let emails = targets
+            .iter()
+            .filter(|t| t.parse::<i64>().is_err())
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        let users: Vec<User> = sqlx::query_as(
+            r#"
+                    SELECT u.id, u.fullname, u.email, u.ws_id, u.created_at
+                    FROM users u
+                    JOIN workspace_members wm ON wm.user_id = u.id
+                    WHERE (u.id = ANY($1) OR u.email = ANY($2)) AND wm.ws_id = $3"#,
+        )
+        .bind(&ids)
+        .bind(&emails)
+        .bind(chat.ws_id)
+        .fetch_all(&state.pool)
+        .await?;
+        let missing = targets
+            .iter()
+            .filter(|t| {
+                !users
+                    .iter()
+                    .any(|u| u.email == **t || u.id.to_string() == **t)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Ok(CommandResponse::Ephemeral(format!(
+                "Users not found in this workspace: {}",
+                missing.join(", ")
+            )));
+        }
+
+        let users = users
+            .into_iter()
+            .filter(|u| !chat.members.contains(&u.id))
+            .collect::<Vec<_>>();
+        if users.is_empty() {
+            return Ok(CommandResponse::Ephemeral(
+                "Everyone is already in this chat".to_string(),
+            ));
+        }
+        let ids = users.iter().map(|u| u.id).collect::<Vec<_>>();
+        let unverified = state.find_unverified_users(chat.ws_id, &ids).await?;
+        if !unverified.is_empty() {
+            let names = users
+                .iter()
+                .filter(|u| unverified.contains(&u.id))
+                .map(|u| u.fullname.as_str())
+                .collect::<Vec<_>>();
+            return Ok(CommandResponse::Ephemeral(format!(
+                "Users must verify their email first: {}",
+                names.join(", ")
+            )));
+        }
+        state.add_chat_members(chat.id, &ids).await?;
+        let names = users
+            .iter()
+            .map(|u| u.fullname.as_str())
+            .collect::<Vec<_>>();
+        Ok(CommandResponse::Post(CreateMessage {
+            content: format!("{} invited {}", ctx.user.fullname, names.join(", ")),
+            ..Default::default()
+        }))
+    }
+}
+
+#[async_trait]
+impl SlashCommand for RemindCommand {
+    fn name(&self) -> &'static str {
+        "remind"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/remind <30s|10m|2h|1d> <text>"
+    }
+
+    async fn execute(
+        &self,
+        state: &AppState,
+        ctx: CommandContext,
+    ) -> Result<CommandResponse, AppError> {
+        let Some((delay, content)) = ctx.args.split_once(char::is_whitespace) else {
+            return Ok(usage(self));
+        };
+        let Some(secs) = parse_delay(delay) else {
+            return Ok(usage(self));
+        };
+        let content = content.trim();
+        if content.is_empty() {
+            return Ok(usage(self));
+        }
+
+        let remind_at = Utc::now() + chrono::Duration::seconds(secs);
+        sqlx::query(
+            "INSERT INTO reminders (user_id, chat_id, content, remind_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(ctx.user.id)
+        .bind(ctx.chat_id as i64)
+        .bind(content)
+        .bind(remind_at)
+        .execute(&state.pool)
+        .await?;
+        Ok(CommandResponse::Ephemeral(format!(
+            "I will remind you at {}",
+            remind_at.format("%Y-%m-%d %H:%M:%S UTC")
+        )))
+    }
+}
+
+fn usage(command: &dyn SlashCommand) -> CommandResponse {
+    CommandResponse::Ephemeral(format!("Usage: {}", command.usage()))
+}
+
+// "30s" => 30, "10m" => 600, "2h" => 7200, "1d" => 86400
+fn parse_delay(s: &str) -> Option<i64> {
+    let unit = s.chars().last()?;
+    let value = s[..s.len() - unit.len_utf8()].parse::<i64>().ok()?;
+    let secs = match unit {
+        's' => value,
+        'm' => value * 60,
+        'h' => value * 60 * 60,
+        'd' => value * 60 * 60 * 24,
+        _ => return None,
+    };
+    (1..=MAX_REMIND_SECS).contains(&secs).then_some(secs)
+}
+
+#[derive(Debug, FromRow)]
+struct Reminder {
+    user_id: i64,
+    chat_id: i64,
+    content: String,
+    remind_at: DateTime<Utc>,
+}
+
+pub fn spawn_reminder_worker(pool: PgPool) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(REMINDER_INTERVAL).await;
+            if let Err(e) = deliver_due_reminders(&pool).await {
+                warn!("Failed to deliver reminders: {}", e);
+            }
+        }
+    });
+}
+
+// deliver the due reminders to their owners as ephemeral messages
+pub async fn deliver_due_reminders(pool: &PgPool) -> Result<usize, AppError> {
+    let mut tx = pool.begin().await?;
+    let reminders: Vec<Reminder> = sqlx::query_as(
+        r#"
+                UPDATE reminders SET delivered = TRUE
+                WHERE NOT delivered AND remind_at <= NOW()
+                RETURNING user_id, chat_id, content, remind_at"#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for reminder in &reminders {
+        let message = EphemeralMessage {
+            user_id: reminder.user_id,
+            chat_id: reminder.chat_id,
+            content: format!("Reminder: {}", reminder.content),
+            created_at: reminder.remind_at,
+        };
+        notify_ephemeral(&mut *tx, &message).await?;
+    }
+    tx.commit().await?;
+    Ok(reminders.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandOutput;
+
+    async fn run(
+        state: &AppState,
+        email: &str,
+        chat_id: u64,
+        content: &str,
+    ) -> anyhow::Result<CommandOutput> {
+        let user = state.find_user_by_email(email).await?.unwrap();
+        let (name, args) = crate::commands::parse_command(content).unwrap();
+        Ok(state.run_command(&user, chat_id, name, args).await?)
+    }
+
+    #[test]
+    fn parse_delay_should_work() {
+        assert_eq!(parse_delay("30s"), Some(30));
+        assert_eq!(parse_delay("10m"), Some(600));
+        assert_eq!(parse_delay("2h"), Some(7200));
+        assert_eq!(parse_delay("1d"), Some(86400));
+        assert_eq!(parse_delay("0m"), None);
+        assert_eq!(parse_delay("100d"), None);
+        assert_eq!(parse_delay("m"), None);
+        assert_eq!(parse_delay("10y"), None);
+    }
+
+    #[tokio::test]
+    async fn me_command_should_work() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let output = run(&state, "test1@none.org", 1, "/me waves").await?;
+        let CommandOutput::Message(message) = output else {
+            panic!("expect message");
+        };
+        assert_eq!(message.content, "_test1 waves_");
+        assert_eq!(message.content_html, "<p><em>test1 waves</em></p>\n");
+
+        let output = run(&state, "test1@none.org", 1, "/me").await?;
+        assert!(matches!(output, CommandOutput::Ephemeral(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn topic_command_should_work() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let output = run(&state, "test1@none.org", 1, "/topic release v1").await?;
+        assert!(matches!(output, CommandOutput::Message(_)));
+        let chat = state.get_chat_by_id(1).await?.unwrap();
+        assert_eq!(chat.topic, Some("release v1".to_string()));
+
+        let output = run(&state, "test1@none.org", 1, "/topic").await?;
+        let CommandOutput::Ephemeral(message) = output else {
+            panic!("expect ephemeral message");
+        };
+        assert_eq!(message.content, "The topic is: release v1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invite_command_should_work() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // chat 2 has members 1, 2, 3 and all users are members of workspace 0
+        sqlx::query("UPDATE chats SET ws_id = 0 WHERE id = 2")
+            .execute(&state.pool)
+            .await?;
+        let output = run(&state, "test1@none.org", 2, "/invite test4@none.org 5").await?;
+        let CommandOutput::Message(message) = output else {
+            panic!("expect message");
+        };
+        assert_eq!(message.content, "test1 invited test4, test5");
+        let chat = state.get_chat_by_id(2).await?.unwrap();
+        assert_eq!(chat.members, vec![1, 2, 3, 4, 5]);
+
+        let output = run(&state, "test1@none.org", 2, "/invite nobody@none.org").await?;
+        assert!(matches!(output, CommandOutput::Ephemeral(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remind_command_should_work() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let output = run(&state, "test1@none.org", 1, "/remind 10m stand up").await?;
+        assert!(matches!(output, CommandOutput::Ephemeral(_)));
+        assert_eq!(deliver_due_reminders(&state.pool).await?, 0);
+
+        sqlx::query("UPDATE reminders SET remind_at = NOW() - INTERVAL '1 second'")
+            .execute(&state.pool)
+            .await?;
+        assert_eq!(deliver_due_reminders(&state.pool).await?, 1);
+        assert_eq!(deliver_due_reminders(&state.pool).await?, 0);
+        Ok(())
+    }
+}
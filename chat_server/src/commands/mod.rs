@@ -0,0 +1,205 @@
+mod builtin;
+mod webhook;
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chat_core::{EphemeralMessage, Message, User};
+use serde::Serialize;
+
+use crate::{error::AppError, models::CreateMessage, state::AppState};
+
+pub use builtin::spawn_reminder_worker;
+
+#[async_trait]
+pub trait SlashCommand: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+    fn usage(&self) -> &'static str;
+    async fn execute(
+        &self,
+        state: &AppState,
+        ctx: CommandContext,
+    ) -> Result<CommandResponse, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    pub user: User,
+    pub chat_id: u64,
+    pub name: String,
+    pub args: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandResponse {
+    // only delivered to the invoker
+    Ephemeral(String),
+    // posted to the chat on behalf of the invoker
+    Post(CreateMessage),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum CommandOutput {
+    Message(Message),
+    Ephemeral(EphemeralMessage),
+}
+
+pub struct CommandRegistry {
+    commands: HashMap<&'static str, Arc<dyn SlashCommand>>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self {
+            commands: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, command: impl SlashCommand) {
+        self.commands.insert(command.name(), Arc::new(command));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn SlashCommand>> {
+        self.commands.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(builtin::MeCommand);
+        registry.register(builtin::TopicCommand);
+        registry.register(builtin::InviteCommand);
+        registry.register(builtin::RemindCommand);
+        registry
+    }
+}
+
+// "/name args" => Some(("name", "args")), "//text" is an escaped message
+pub fn parse_command(content: &str) -> Option<(String, String)> {
+    let content = content.strip_prefix('/')?;
+    if content.starts_with('/') {
+        return None;
+    }
+    let (name, args) = content
+        .split_once(char::is_whitespace)
+        .unwrap_or((content, ""));
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_lowercase(), args.trim().to_string()))
+}
+
+impl AppState {
+    pub async fn run_command(
+        &self,
+        user: &User,
+        chat_id: u64,
+        name: String,
+        args: String,
+    ) -> Result<CommandOutput, AppError> {
+        let ctx = CommandContext {
+            user: user.clone(),
+            chat_id,
+            name,
+            args,
+        };
+        let response = match self.commands.get(&ctx.name) {
+            Some(command) => command.execute(self, ctx).await?,
+            None => match self
+                .find_workspace_command(user.ws_id as u64, &ctx.name)
+                .await?
+            {
+                Some(command) => {
+                    webhook::execute(&command, ctx, self.config.commands.allow_private_ips).await?
+                }
+                None => CommandResponse::Ephemeral(format!("Unknown command: /{}", ctx.name)),
+            },
+        };
+
+        match response {
+            CommandResponse::Ephemeral(content) => {
+                let message = EphemeralMessage {
+                    user_id: user.id,
+                    chat_id: chat_id as i64,
+                    content,
+                    created_at: chrono::Utc::now(),
+                };
+                notify_ephemeral(&self.pool, &message).await?;
+                Ok(CommandOutput::Ephemeral(message))
+            }
+            CommandResponse::Post(create_message) => {
+                let message = self
+                    .create_message(create_message, chat_id, user.id as u64)
+                    .await?;
+                Ok(CommandOutput::Message(message))
+            }
+        }
+    }
+}
+
+impl IntoResponse for CommandOutput {
+    fn into_response(self) -> Response {
+        match self {
+            CommandOutput::Message(message) => (StatusCode::CREATED, Json(message)).into_response(),
+            CommandOutput::Ephemeral(message) => (StatusCode::OK, Json(message)).into_response(),
+        }
+    }
+}
+
+pub async fn notify_ephemeral(
+    executor: impl sqlx::PgExecutor<'_>,
+    message: &EphemeralMessage,
+) -> Result<(), AppError> {
+    let payload = serde_json::to_string(message).map_err(anyhow::Error::from)?;
+    sqlx::query("SELECT pg_notify('ephemeral_message', $1)")
+        .bind(payload)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_should_work() {
+        assert_eq!(
+            parse_command("/Topic  release  v1 "),
+            Some(("topic".to_string(), "release  v1".to_string()))
+        );
+        assert_eq!(
+            parse_command("/me"),
+            Some(("me".to_string(), "".to_string()))
+        );
+        assert_eq!(parse_command("//escaped"), None);
+        assert_eq!(parse_command("/ nothing"), None);
+        assert_eq!(parse_command("hello /me"), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_command_should_be_ephemeral() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let output = state
+            .run_command(&user, 1, "nope".to_string(), "".to_string())
+            .await?;
+        let CommandOutput::Ephemeral(message) = output else {
+            panic!("expect ephemeral message");
+        };
+        assert_eq!(message.user_id, user.id);
+        assert_eq!(message.content, "Unknown command: /nope");
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use chat_core::WorkspaceCommand;
+use reqwest::{header, Response};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    error::AppError,
+    models::CreateMessage,
+    unfurl::{pinned_client, resolve_public_addrs, MAX_REDIRECTS},
+};
+
+use super::{CommandContext, CommandResponse};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+const MAX_TEXT_LEN: usize = 4000;
+const USER_AGENT: &str = "chat_server-command/0.1";
+
+#[derive(Debug, Serialize)]
+struct WebhookRequest<'a> {
+    command: &'a str,
+    text: &'a str,
+    user_id: i64,
+    user_name: &'a str,
+    chat_id: u64,
+    ws_id: i64,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ResponseType {
+    #[default]
+    Ephemeral,
+    InChannel,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookResponse {
+    #[serde(default)]
+    response_type: ResponseType,
+    text: String,
+}
+
+// call the endpoint registered for the workspace command
+pub async fn execute(
+    command: &WorkspaceCommand,
+    ctx: CommandContext,
+    allow_private_ips: bool,
+) -> Result<CommandResponse, AppError> {
+    let body = WebhookRequest {
+        command: &command.name,
+        text: &ctx.args,
+        user_id: ctx.user.id,
+        user_name: &ctx.user.fullname,
+        chat_id: ctx.chat_id,
+        ws_id: command.ws_id,
+    };
+    // the timeout covers the body, which is read up to the size limit
+    let ret = tokio::time::timeout(WEBHOOK_TIMEOUT, async {
+        let res = post(&command.url, &body, allow_private_ips).await?;
+        read_body(res).await
+    })
+    .await
+    .unwrap_or_else(|_| Err("timeout".to_string()));
+    let res = match ret {
+        Ok(res) => res,
+        Err(e) => {
+            return Ok(CommandResponse::Ephemeral(format!(
+                "/{} failed: {}",
+                command.name, e
+            )))
+        }
+    };
+    let output = match serde_json::from_slice::<WebhookResponse>(&res) {
+        Ok(output) => output,
+        Err(_) => {
+            return Ok(CommandResponse::Ephemeral(format!(
+                "/{} returned an invalid response",
+                command.name
+            )))
+        }
+    };
+    let text = output.text.chars().take(MAX_TEXT_LEN).collect();
+    Ok(match output.response_type {
+        ResponseType::Ephemeral => CommandResponse::Ephemeral(text),
+        ResponseType::InChannel => CommandResponse::Post(CreateMessage {
+            content: text,
+            ..Default::default()
+        }),
+    })
+}
+
+async fn read_body(mut res: Response) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await.map_err(|e| e.to_string())? {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_RESPONSE_SIZE {
+            return Err("response is too large".to_string());
+        }
+    }
+    Ok(body)
+}
+
+// each hop of the redirects must resolve to public addresses, like the links of the unfurler
+async fn post(
+    url: &str,
+    body: &WebhookRequest<'_>,
+    allow_private_ips: bool,
+) -> Result<Response, String> {
+    let mut url = Url::parse(url).map_err(|e| e.to_string())?;
+    for _ in 0..=MAX_REDIRECTS {
+        let addrs = resolve_public_addrs(&url, allow_private_ips).await?;
+        let client = pinned_client(&url, &addrs)
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let res = client
+            .post(url.clone())
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if res.status().is_redirection() {
+            let location = res
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| "redirect without location".to_string())?;
+            url = url.join(location).map_err(|e| e.to_string())?;
+            continue;
+        }
+        return res.error_for_status().map_err(|e| e.to_string());
+    }
+    Err(format!("too many redirects for {}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commands::CommandOutput, AppState};
+    use axum::{routing::post, Json, Router};
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    // private addresses could only be registered by the database directly
+    async fn insert_command(state: &AppState, url: &str) -> anyhow::Result<WorkspaceCommand> {
+        let command = sqlx::query_as(
+            r#"
+                INSERT INTO workspace_commands (ws_id, name, url, description, created_by)
+                VALUES (0, 'echo', $1, '', 0)
+                RETURNING *"#,
+        )
+        .bind(url)
+        .fetch_one(&state.pool)
+        .await?;
+        Ok(command)
+    }
+
+    #[tokio::test]
+    async fn workspace_command_should_call_webhook() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+
+        // local http stub echoing the text back
+        let app = Router::new().route(
+            "/",
+            post(|Json(body): Json<Value>| async move {
+                let text = body["text"].as_str().unwrap_or_default().to_string();
+                let response_type = if text.starts_with("public") {
+                    "in_channel"
+                } else {
+                    "ephemeral"
+                };
+                Json(json!({
+                    "response_type": response_type,
+                    "text": format!("{} by {}", text, body["user_name"].as_str().unwrap()),
+                }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let command = insert_command(&state, &format!("http://{}/", addr)).await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let ctx = |args: &str| CommandContext {
+            user: user.clone(),
+            chat_id: 1,
+            name: "echo".to_string(),
+            args: args.to_string(),
+        };
+        let response = execute(&command, ctx("hello"), true).await?;
+        assert_eq!(
+            response,
+            CommandResponse::Ephemeral("hello by test1".to_string())
+        );
+        let response = execute(&command, ctx("public hi"), true).await?;
+        let CommandResponse::Post(message) = response else {
+            panic!("expect message");
+        };
+        assert_eq!(message.content, "public hi by test1");
+
+        // the local stub is a private address
+        let output = state
+            .run_command(&user, 1, "echo".to_string(), "hello".to_string())
+            .await?;
+        let CommandOutput::Ephemeral(message) = output else {
+            panic!("expect ephemeral message");
+        };
+        assert!(message.content.contains("private address"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn webhook_response_should_be_limited() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+
+        // local http stub answering with an oversized body or text
+        let app = Router::new().route(
+            "/",
+            post(|Json(body): Json<Value>| async move {
+                let text = match body["text"].as_str().unwrap_or_default() {
+                    "big" => "a".repeat(MAX_RESPONSE_SIZE),
+                    _ => "é".repeat(MAX_TEXT_LEN + 10),
+                };
+                Json(json!({ "text": text }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let command = insert_command(&state, &format!("http://{}/", addr)).await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let ctx = |args: &str| CommandContext {
+            user: user.clone(),
+            chat_id: 1,
+            name: "echo".to_string(),
+            args: args.to_string(),
+        };
+        let response = execute(&command, ctx("big"), true).await?;
+        assert_eq!(
+            response,
+            CommandResponse::Ephemeral("/echo failed: response is too large".to_string())
+        );
+        let response = execute(&command, ctx("long"), true).await?;
+        assert_eq!(
+            response,
+            CommandResponse::Ephemeral("é".repeat(MAX_TEXT_LEN))
+        );
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
-use axum::{extract::rejection::JsonRejection, response::IntoResponse};
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
 
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+const SCIM_ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorOutput {
     error: String,
@@ -27,9 +34,69 @@ pub enum AppError {
     #[error("create chat error: {0}")]
     CreateChat(String),
 
+    #[error("block error: {0}")]
+    Block(String),
+
     #[error("create message error: {0}")]
     CreateMessage(String),
 
+    #[error("poll error: {0}")]
+    Poll(String),
+
+    #[error("unfurl error: {0}")]
+    Unfurl(String),
+
+    #[error("command error: {0}")]
+    Command(String),
+
+    #[error("invite error: {0}")]
+    Invite(String),
+
+    #[error("workspace error: {0}")]
+    Workspace(String),
+
+    #[error("access token error: {0}")]
+    AccessToken(String),
+
+    #[error("password error: {0}")]
+    Password(String),
+
+    #[error("mail error: {0}")]
+    Mail(String),
+
+    #[error("email verification error: {0}")]
+    EmailVerification(String),
+
+    #[error("two-factor authentication error: {0}")]
+    TwoFactor(String),
+
+    #[error("sso error: {0}")]
+    Sso(String),
+
+    #[error("ldap error: {0}")]
+    Ldap(String),
+
+    #[error("profile error: {0}")]
+    Profile(String),
+
+    #[error("export error: {0}")]
+    Export(String),
+
+    #[error("scim error: {0}")]
+    Scim(String),
+
+    #[error("scim conflict: {0}")]
+    ScimConflict(String),
+
+    #[error("too many failed signins, retry in {0} seconds")]
+    SigninLocked(u64),
+
+    #[error("too many requests: {0}")]
+    TooManyRequests(String),
+
+    #[error("payload too large: {0}")]
+    PayloadTooLarge(String),
+
     #[error("chat file error: {0}")]
     ChatFile(String),
 
@@ -54,6 +121,9 @@ pub enum AppError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("request header to str error: {0}")]
     RequestHeaderToStr(#[from] axum::http::header::ToStrError),
 
@@ -67,12 +137,9 @@ pub enum AppError {
     InvalidHeaderValue(#[from] axum::http::header::InvalidHeaderValue),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> axum::response::Response {
-        use axum::http::StatusCode;
-        use axum::response::Json;
-
-        let status = match &self {
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
             AppError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::PasswordHash(_) => StatusCode::UNPROCESSABLE_ENTITY,
@@ -81,16 +148,83 @@ impl IntoResponse for AppError {
             AppError::LoginFailed(_) => StatusCode::FORBIDDEN,
             AppError::EmailAlreadyExists(_) => StatusCode::CONFLICT,
             AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
             AppError::RequestHeaderToStr(_) => StatusCode::BAD_REQUEST,
             AppError::CreateChat(_) => StatusCode::BAD_REQUEST,
+            AppError::Block(_) => StatusCode::BAD_REQUEST,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::Multiple(_) => StatusCode::BAD_REQUEST,
             AppError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::InvalidHeaderValue(_) => StatusCode::BAD_REQUEST,
             AppError::CreateMessage(_) => StatusCode::BAD_REQUEST,
             AppError::ChatFile(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Poll(_) => StatusCode::BAD_REQUEST,
+            AppError::Unfurl(_) => StatusCode::BAD_GATEWAY,
+            AppError::Command(_) => StatusCode::BAD_REQUEST,
+            AppError::Invite(_) => StatusCode::FORBIDDEN,
+            AppError::Workspace(_) => StatusCode::BAD_REQUEST,
+            AppError::AccessToken(_) => StatusCode::BAD_REQUEST,
+            AppError::Password(_) => StatusCode::BAD_REQUEST,
+            AppError::Mail(_) => StatusCode::BAD_GATEWAY,
+            AppError::EmailVerification(_) => StatusCode::BAD_REQUEST,
+            AppError::TwoFactor(_) => StatusCode::BAD_REQUEST,
+            AppError::Sso(_) => StatusCode::BAD_GATEWAY,
+            AppError::Ldap(_) => StatusCode::BAD_GATEWAY,
+            AppError::Profile(_) => StatusCode::BAD_REQUEST,
+            AppError::Export(_) => StatusCode::CONFLICT,
+            AppError::Scim(_) => StatusCode::BAD_REQUEST,
+            AppError::ScimConflict(_) => StatusCode::CONFLICT,
+            AppError::SigninLocked(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.status_code();
+        let retry_after = match &self {
+            AppError::SigninLocked(secs) => Some(header::HeaderValue::from(*secs)),
+            _ => None,
         };
+        let mut response = (status, Json(ErrorOutput::new(self.to_string()))).into_response();
+        if let Some(value) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        response
+    }
+}
+
+// errors of the provisioning api in the format scim clients expect
+#[derive(Debug)]
+pub struct ScimError(pub AppError);
 
-        (status, Json(ErrorOutput::new(self.to_string()))).into_response()
+impl From<AppError> for ScimError {
+    fn from(err: AppError) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.0.status_code();
+        let scim_type = match &self.0 {
+            AppError::ScimConflict(_) | AppError::EmailAlreadyExists(_) => Some("uniqueness"),
+            AppError::Scim(_) => Some("invalidValue"),
+            _ => None,
+        };
+        let body = serde_json::json!({
+            "schemas": [SCIM_ERROR_SCHEMA],
+            "status": status.as_u16().to_string(),
+            "scimType": scim_type,
+            "detail": self.0.to_string(),
+        });
+        (
+            status,
+            [(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)],
+            Json(body),
+        )
+            .into_response()
     }
 }
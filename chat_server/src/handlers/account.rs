@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, models::DeleteAccount, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/members/{user_id}/deactivate",
+responses(
+    (status = 204, description = "deactivate workspace member in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn deactivate_member_handler(
+    Extension(user): Extension<User>,
+    Path((id, member_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .deactivate_member(id, user.id as u64, member_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/members/{user_id}/reactivate",
+responses(
+    (status = 204, description = "reactivate workspace member in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn reactivate_member_handler(
+    Extension(user): Extension<User>,
+    Path((id, member_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .reactivate_member(id, user.id as u64, member_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(delete, path = "/api/workspaces/{id}/members/{user_id}/account",
+request_body(content = DeleteAccount, description = "Whether to scrub the messages and files of the user"),
+responses(
+    (status = 202, description = "delete account of workspace member in successful", body = UserDeletion),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn delete_member_account_handler(
+    Extension(user): Extension<User>,
+    Path((id, member_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<DeleteAccount>,
+) -> Result<impl IntoResponse, AppError> {
+    let deletion = state
+        .delete_member_account(id, user.id as u64, member_id, input)
+        .await?;
+    Ok((StatusCode::ACCEPTED, Json(deletion)))
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}/deletions/{deletion_id}",
+responses(
+    (status = 200, description = "get account deletion in successful", body = UserDeletion),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_user_deletion_handler(
+    Extension(user): Extension<User>,
+    Path((id, deletion_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let deletion = state
+        .get_user_deletion(id, user.id as u64, deletion_id)
+        .await?;
+    Ok(Json(deletion))
+}
+
+#[utoipa::path(delete, path = "/api/users/me",
+request_body(content = DeleteAccount, description = "Whether to scrub the messages and files of the user"),
+responses(
+    (status = 202, description = "delete own account in successful", body = UserDeletion),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn delete_my_account_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<DeleteAccount>,
+) -> Result<impl IntoResponse, AppError> {
+    let deletion = state.delete_own_account(user.id as u64, input).await?;
+    Ok((StatusCode::ACCEPTED, Json(deletion)))
+}
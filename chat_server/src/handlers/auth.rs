@@ -1,16 +1,43 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use chat_core::{AuthHeader, Jwks, User};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::{
     error::AppError,
-    models::{SigninUser, SignupUser},
+    models::{
+        DeviceInfo, Logout, RefreshToken, SigninUser, SignupUser, TwoFactorChallenge,
+        VerifyTwoFactor,
+    },
     state::AppState,
 };
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct AuthOutput {
-    token: String,
+    // short-lived access token
+    pub token: String,
+    // used to get a new access token via /api/refresh
+    pub refresh_token: String,
+}
+
+// users with two-factor authentication enabled get a challenge instead of the tokens
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum SigninOutput {
+    Tokens(AuthOutput),
+    Challenge(TwoFactorChallenge),
+}
+
+impl AuthOutput {
+    // issue the tokens for the session of the signed in device
+    pub async fn issue(state: &AppState, user: User, session_id: i64) -> Result<Self, AppError> {
+        let refresh_token = state.create_refresh_token(&user, Some(session_id)).await?;
+        let token = state.ek.sign_session(user, session_id)?;
+        Ok(Self {
+            token,
+            refresh_token,
+        })
+    }
 }
 
 use super::AppJson;
@@ -18,17 +45,29 @@ use super::AppJson;
 #[utoipa::path(post, path = "/api/signin",
 request_body(content = SigninUser, description = "User signin details"),
 responses(
-    (status = 200, description = "User signed in successfully", body = AuthOutput),
+    (status = 200, description = "User signed in successfully, or the second factor is required", body = SigninOutput),
 ))]
 pub async fn signin_handler(
     State(state): State<AppState>,
+    device: DeviceInfo,
     AppJson(input): AppJson<SigninUser>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user = state.verify_user(&input).await?;
+    let user = state.verify_signin(&input, device.ip.as_deref()).await?;
     match user {
         Some(user) => {
-            let token = state.ek.sign(user)?;
-            Ok((StatusCode::OK, Json(AuthOutput { token })))
+            if let Some(challenge) = state
+                .create_two_factor_challenge(&user, input.device_name.as_deref())
+                .await?
+            {
+                return Ok((StatusCode::OK, Json(SigninOutput::Challenge(challenge))));
+            }
+            let device = DeviceInfo {
+                device_name: input.device_name,
+                ..device
+            };
+            let session_id = state.create_session(user.id as u64, &device).await?;
+            let output = AuthOutput::issue(&state, user, session_id).await?;
+            Ok((StatusCode::OK, Json(SigninOutput::Tokens(output))))
         }
         None => Err(AppError::LoginFailed(
             "Invalid email or password".to_string(),
@@ -36,6 +75,26 @@ pub async fn signin_handler(
     }
 }
 
+#[utoipa::path(post, path = "/api/signin/2fa",
+request_body(content = VerifyTwoFactor, description = "Challenge token of the signin and the second factor"),
+responses(
+    (status = 200, description = "User signed in successfully", body = AuthOutput),
+))]
+pub async fn signin_two_factor_handler(
+    State(state): State<AppState>,
+    device: DeviceInfo,
+    AppJson(input): AppJson<VerifyTwoFactor>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user, device_name) = state.verify_two_factor_challenge(&input).await?;
+    let device = DeviceInfo {
+        device_name,
+        ..device
+    };
+    let session_id = state.create_session(user.id as u64, &device).await?;
+    let output = AuthOutput::issue(&state, user, session_id).await?;
+    Ok((StatusCode::OK, Json(output)))
+}
+
 #[utoipa::path(post, path = "/api/signup",
 request_body(content = SignupUser, description = "User signup details"),
 responses(
@@ -43,16 +102,89 @@ responses(
 ))]
 pub async fn signup_handler(
     State(state): State<AppState>,
+    device: DeviceInfo,
     AppJson(input): AppJson<SignupUser>,
 ) -> Result<impl IntoResponse, AppError> {
     let user = state.create_user(&input).await?;
-    let token = state.ek.sign(user)?;
-    Ok((StatusCode::CREATED, Json(AuthOutput { token })))
+    state.send_signup_verification(&user).await;
+    let session_id = state.create_session(user.id as u64, &device).await?;
+    let output = AuthOutput::issue(&state, user, session_id).await?;
+    Ok((StatusCode::CREATED, Json(output)))
+}
+
+#[utoipa::path(post, path = "/api/refresh",
+request_body(content = RefreshToken, description = "Refresh token issued on signin"),
+responses(
+    (status = 200, description = "Tokens refreshed successfully", body = AuthOutput),
+))]
+pub async fn refresh_handler(
+    State(state): State<AppState>,
+    AppJson(input): AppJson<RefreshToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user, refresh_token, session_id) =
+        state.rotate_refresh_token(&input.refresh_token).await?;
+    let token = match session_id {
+        Some(session_id) => state.ek.sign_session(user, session_id)?,
+        None => state.ek.sign(user)?,
+    };
+    Ok((
+        StatusCode::OK,
+        Json(AuthOutput {
+            token,
+            refresh_token,
+        }),
+    ))
+}
+
+#[utoipa::path(post, path = "/api/logout",
+request_body(content = Logout, description = "Refresh token to revoke"),
+responses(
+    (status = 204, description = "Logged out successfully"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn logout_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AuthHeader(token): AuthHeader,
+    input: Option<AppJson<Logout>>,
+) -> Result<impl IntoResponse, AppError> {
+    let claims = state.dk.decode(&token)?;
+    if let (Some(jti), Some(exp)) = (claims.jwt_id, claims.expires_at) {
+        state.revoke_access_token(&jti, exp.as_secs()).await?;
+    }
+    // signing out ends the session of the device
+    if let Some(session_id) = claims.custom.sid {
+        match state
+            .revoke_session(user.id as u64, session_id as u64)
+            .await
+        {
+            Ok(()) | Err(AppError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some(refresh_token) = input.and_then(|AppJson(input)| input.refresh_token) {
+        state
+            .revoke_refresh_token(user.id as u64, &refresh_token)
+            .await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// public keys used to verify the access tokens, for the other servers
+#[utoipa::path(get, path = "/.well-known/jwks.json",
+responses(
+    (status = 200, description = "JSON web key set", body = Jwks),
+))]
+pub async fn jwks_handler(State(state): State<AppState>) -> Json<Jwks> {
+    Json(state.dk.to_jwks())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::models::TwoFactorCode;
     use anyhow::Result;
     use axum::http::StatusCode;
     use http_body_util::BodyExt;
@@ -65,7 +197,7 @@ mod test {
         let password = "1qa2ws3ed";
         let input = SignupUser::new(name, email, password);
 
-        let res = signup_handler(State(state.clone()), AppJson(input))
+        let res = signup_handler(State(state.clone()), DeviceInfo::default(), AppJson(input))
             .await?
             .into_response();
 
@@ -84,10 +216,10 @@ mod test {
         let email = "tom@123.com";
         let password = "1qa2ws3ed";
         let input = SignupUser::new(name, email, password);
-        signup_handler(State(state.clone()), AppJson(input)).await?;
+        signup_handler(State(state.clone()), DeviceInfo::default(), AppJson(input)).await?;
 
         let input = SigninUser::new(email, password, 0);
-        let res = signin_handler(State(state.clone()), AppJson(input))
+        let res = signin_handler(State(state.clone()), DeviceInfo::default(), AppJson(input))
             .await?
             .into_response();
 
@@ -98,4 +230,142 @@ mod test {
         assert_ne!(token, "");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn signin_with_two_factor_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("tom", "tom@123.com", "1qa2ws3ed");
+        let user = state.create_user(&input).await?;
+        state.enroll_totp(&user).await?;
+        let secret: String = sqlx::query_scalar("SELECT secret FROM user_totp WHERE user_id = $1")
+            .bind(user.id)
+            .fetch_one(&state.pool)
+            .await?;
+        let totp = totp_rs::TOTP::new(
+            totp_rs::Algorithm::SHA1,
+            6,
+            1,
+            30,
+            hex::decode(secret)?,
+            None,
+            String::new(),
+        )?;
+        let code = TwoFactorCode {
+            code: totp.generate_current()?,
+        };
+        let codes = state.confirm_totp(&user, &code).await?;
+
+        let input = SigninUser::new("tom@123.com", "1qa2ws3ed", 0);
+        let res = signin_handler(State(state.clone()), DeviceInfo::default(), AppJson(input))
+            .await?
+            .into_response();
+        let bytes = res.into_body().collect().await?.to_bytes();
+        let SigninOutput::Challenge(challenge) = serde_json::from_slice(&bytes)? else {
+            panic!("signin should return a challenge");
+        };
+
+        let input = VerifyTwoFactor {
+            challenge_token: challenge.challenge_token,
+            code: codes.recovery_codes[0].clone(),
+        };
+        let res =
+            signin_two_factor_handler(State(state.clone()), DeviceInfo::default(), AppJson(input))
+                .await?
+                .into_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        let bytes = res.into_body().collect().await?.to_bytes();
+        let output: AuthOutput = serde_json::from_slice(&bytes)?;
+        let ctx = chat_core::TokenVerifier::verify(&state, &output.token).await?;
+        assert_eq!(ctx.user_id, user.id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refresh_and_logout_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("tom", "tom@123.com", "1qa2ws3ed");
+        let res = signup_handler(State(state.clone()), DeviceInfo::default(), AppJson(input))
+            .await?
+            .into_response();
+        let bytes = res.into_body().collect().await?.to_bytes();
+        let output: AuthOutput = serde_json::from_slice(&bytes)?;
+
+        let input = RefreshToken {
+            refresh_token: output.refresh_token,
+        };
+        let res = refresh_handler(State(state.clone()), AppJson(input))
+            .await?
+            .into_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        let bytes = res.into_body().collect().await?.to_bytes();
+        let output: AuthOutput = serde_json::from_slice(&bytes)?;
+        let ctx = chat_core::TokenVerifier::verify(&state, &output.token).await?;
+        let user = state.load_user(&ctx).await?;
+
+        let input = Logout {
+            refresh_token: Some(output.refresh_token.clone()),
+        };
+        let res = logout_handler(
+            Extension(user),
+            State(state.clone()),
+            AuthHeader(output.token.clone()),
+            Some(AppJson(input)),
+        )
+        .await?
+        .into_response();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(chat_core::TokenVerifier::verify(&state, &output.token)
+            .await
+            .is_err());
+
+        let input = RefreshToken {
+            refresh_token: output.refresh_token,
+        };
+        let ret = refresh_handler(State(state.clone()), AppJson(input)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jwks_should_verify_issued_tokens() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let Json(jwks) = jwks_handler(State(state.clone())).await;
+        assert_eq!(jwks.keys.len(), 1);
+        assert_eq!(jwks.keys[0].kid, state.ek.kid());
+
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let token = state.ek.sign(user.clone())?;
+        let pk = chat_core::DecodingKey::from_jwks(&jwks)?;
+        assert_eq!(pk.verify(&token)?.user_id, user.id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spoofed_forwarded_for_should_not_bypass_ip_lockout() -> Result<()> {
+        use axum::extract::{ConnectInfo, FromRequestParts};
+        use std::net::SocketAddr;
+
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let max = state.config.lockout.max_ip_failures;
+        for i in 0..=max {
+            // a new forged client address and account for each attempt
+            let mut req = axum::http::Request::builder()
+                .header("X-Forwarded-For", format!("10.1.{}.{}", i / 256, i % 256))
+                .body(())?;
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([203, 0, 113, 7], 443))));
+            let mut parts = req.into_parts().0;
+            let device = DeviceInfo::from_request_parts(&mut parts, &state).await?;
+            assert_eq!(device.ip.as_deref(), Some("203.0.113.7"));
+
+            let input = SigninUser::new(&format!("nobody{}@none.org", i), "wrong", 0);
+            let ret = signin_handler(State(state.clone()), device, AppJson(input)).await;
+            if i < max {
+                assert!(matches!(ret, Err(AppError::LoginFailed(_))));
+            } else {
+                assert!(matches!(ret, Err(AppError::SigninLocked(_))));
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, models::PrivacySettings, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(get, path = "/api/users/me/blocks",
+responses(
+    (status = 200, description = "list blocked users in successful", body = Vec<ChatUser>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_blocked_users_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let users = state.list_blocked_users(user.id as u64).await?;
+    Ok(Json(users))
+}
+
+#[utoipa::path(put, path = "/api/users/me/blocks/{id}",
+params(
+    ("id" = u64, Path, description = "Id of the user to block")
+),
+responses(
+    (status = 204, description = "block user in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn block_user_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.block_user(user.id as u64, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(delete, path = "/api/users/me/blocks/{id}",
+params(
+    ("id" = u64, Path, description = "Id of the user to unblock")
+),
+responses(
+    (status = 204, description = "unblock user in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn unblock_user_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.unblock_user(user.id as u64, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(get, path = "/api/users/me/privacy",
+responses(
+    (status = 200, description = "get privacy settings in successful", body = PrivacySettings),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_privacy_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = state.get_privacy_settings(user.id as u64).await?;
+    Ok(Json(settings))
+}
+
+#[utoipa::path(put, path = "/api/users/me/privacy",
+request_body(content = PrivacySettings, description = "Privacy settings of the user"),
+responses(
+    (status = 200, description = "update privacy settings in successful", body = PrivacySettings),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn update_privacy_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<PrivacySettings>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = state.update_privacy_settings(user.id as u64, input).await?;
+    Ok(Json(settings))
+}
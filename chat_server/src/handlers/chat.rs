@@ -4,7 +4,7 @@ use axum::{
     response::IntoResponse,
     Extension, Json,
 };
-use chat_core::User;
+use chat_core::{Chat, ChatType, User};
 
 use crate::{
     error::AppError,
@@ -54,6 +54,14 @@ pub async fn create_chat_handler(
     if user.ws_id != create_chat.ws_id {
         return Err(AppError::Unauthorized("ws_id does not match".to_string()));
     }
+    app_state.ensure_email_verified(user.ws_id, user.id).await?;
+    if Chat::get_chat_type_by(&create_chat.members, &create_chat.name, create_chat.public)
+        == ChatType::Single
+    {
+        app_state
+            .verify_direct_message(user.id, &create_chat.members)
+            .await?;
+    }
     // handle create chat here
     let chat = app_state.create_chat(create_chat).await?;
     Ok((StatusCode::CREATED, Json(chat)))
@@ -84,10 +92,25 @@ security(
     ("Authorization" = [])
 ))]
 pub async fn update_chat_handler(
+    Extension(user): Extension<User>,
     Path(id): Path<u64>,
     State(app_state): State<AppState>,
     AppJson(update_chat): AppJson<UpdateChat>,
 ) -> Result<impl IntoResponse, AppError> {
+    // the user is a member of the chat, checked by verify_is_chat_member,
+    // a direct message could not be handed over to two other users
+    if Chat::get_chat_type_by(&update_chat.members, &update_chat.name, update_chat.public)
+        == ChatType::Single
+    {
+        if !update_chat.members.contains(&user.id) {
+            return Err(AppError::Forbidden(
+                "You must be a member of the direct message".to_string(),
+            ));
+        }
+        app_state
+            .verify_direct_message(user.id, &update_chat.members)
+            .await?;
+    }
     let chat = app_state.update_chat(id as i64, update_chat).await?;
     Ok((StatusCode::OK, Json(chat)))
 }
@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, models::CreateCommand, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(get, path = "/api/commands",
+responses(
+    (status = 200, description = "list workspace commands in successful", body = Vec<WorkspaceCommand>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_commands_handler(
+    Extension(user): Extension<User>,
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let commands = app_state
+        .fetch_workspace_commands(user.ws_id as u64)
+        .await?;
+    Ok((StatusCode::OK, Json(commands)))
+}
+
+#[utoipa::path(post, path = "/api/commands",
+responses(
+    (status = 201, description = "create workspace command in successful", body = WorkspaceCommand),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_command_handler(
+    Extension(user): Extension<User>,
+    State(app_state): State<AppState>,
+    AppJson(input): AppJson<CreateCommand>,
+) -> Result<impl IntoResponse, AppError> {
+    let command = app_state
+        .create_workspace_command(user.ws_id as u64, user.id as u64, input)
+        .await?;
+    Ok((StatusCode::CREATED, Json(command)))
+}
+
+#[utoipa::path(delete, path = "/api/commands/{id}",
+responses(
+    (status = 200, description = "delete workspace command in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn delete_command_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    app_state
+        .delete_workspace_command(user.ws_id as u64, user.id as u64, id)
+        .await?;
+    Ok(StatusCode::OK)
+}
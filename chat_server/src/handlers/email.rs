@@ -0,0 +1,35 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use chat_core::User;
+
+use crate::{error::AppError, models::VerifyEmail, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(post, path = "/api/email/verify",
+request_body(content = VerifyEmail, description = "Token from the verification mail"),
+responses(
+    (status = 204, description = "Email verified"),
+))]
+pub async fn verify_email_handler(
+    State(state): State<AppState>,
+    AppJson(input): AppJson<VerifyEmail>,
+) -> Result<impl IntoResponse, AppError> {
+    state.verify_email(&input).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(post, path = "/api/email/resend",
+responses(
+    (status = 202, description = "Verification mail sent"),
+    (status = 429, description = "A verification mail was sent recently"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn resend_verification_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.send_verification_email(&user).await?;
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,80 @@
+use std::fs;
+
+use axum::{
+    extract::{Path, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, state::AppState};
+
+#[utoipa::path(post, path = "/api/users/me/exports",
+responses(
+    (status = 202, description = "request an export of the user data in successful", body = DataExport),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn request_data_export_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let export = state
+        .request_data_export(user.id as u64, user.ws_id as u64)
+        .await?;
+    Ok((StatusCode::ACCEPTED, Json(export)))
+}
+
+#[utoipa::path(get, path = "/api/users/me/exports/{id}",
+params(
+    ("id" = u64, Path, description = "Export id")
+),
+responses(
+    (status = 200, description = "get data export in successful, with the download url once ready", body = DataExport),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_data_export_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let export = state.get_data_export(user.id as u64, id).await?;
+    Ok(Json(export))
+}
+
+#[utoipa::path(get, path = "/api/users/me/exports/{id}/download",
+params(
+    ("id" = u64, Path, description = "Export id")
+),
+responses(
+    (status = 200, description = "download the archive of a data export in successful", content_type = "application/zip"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn download_data_export_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let archive = state.get_export_archive(user.id as u64, id).await?;
+    if !archive.exists() {
+        return Err(AppError::NotFound(format!("Export {} not found", id)));
+    }
+    let body = fs::read(archive)?;
+    let headers = [
+        (CONTENT_TYPE, "application/zip".to_string()),
+        (
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"export-{}.zip\"", id),
+        ),
+    ];
+    Ok((headers, body))
+}
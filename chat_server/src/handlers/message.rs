@@ -3,13 +3,14 @@ use std::fs;
 use axum::{
     extract::{Multipart, Path, Query, State},
     http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, StatusCode},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use chat_core::User;
 use tracing::{info, warn};
 
 use crate::{
+    commands::parse_command,
     error::AppError,
     models::{ChatFile, CreateMessage, ListMessage},
     state::AppState,
@@ -21,21 +22,36 @@ pub async fn send_message_handler(
     Extension(user): Extension<User>,
     Path(id): Path<u64>,
     State(app_state): State<AppState>,
-    AppJson(create_message): AppJson<CreateMessage>,
-) -> Result<impl IntoResponse, AppError> {
-    // handle send message here
+    AppJson(mut create_message): AppJson<CreateMessage>,
+) -> Result<Response, AppError> {
+    if let Some(chat) = app_state.get_chat_by_id(id as i64).await? {
+        app_state.ensure_email_verified(chat.ws_id, user.id).await?;
+    }
+    if create_message.poll.is_none() {
+        if let Some((name, args)) = parse_command(&create_message.content) {
+            let output = app_state.run_command(&user, id, name, args).await?;
+            return Ok(output.into_response());
+        }
+        // "//text" is sent as "/text"
+        if create_message.content.starts_with("//") {
+            create_message.content.remove(0);
+        }
+    }
     let message = app_state
         .create_message(create_message, id, user.id as u64)
         .await?;
-    Ok((StatusCode::CREATED, Json(message)))
+    Ok((StatusCode::CREATED, Json(message)).into_response())
 }
 
 pub async fn list_message_handler(
+    Extension(user): Extension<User>,
     Path(id): Path<u64>,
     State(app_state): State<AppState>,
     Query(list_message): Query<ListMessage>,
 ) -> Result<impl IntoResponse, AppError> {
-    let messages = app_state.list_message(id, list_message).await?;
+    let messages = app_state
+        .list_message(id, user.id as u64, list_message)
+        .await?;
     Ok((StatusCode::OK, Json(messages)))
 }
 
@@ -47,6 +63,13 @@ pub async fn file_handler(
     if user.ws_id != ws_id as i64 {
         return Err(AppError::Unauthorized("ws_id does not match".to_string()));
     }
+    // only the uploads of the workspace, data exports have their own route
+    if path
+        .split(['/', '\\'])
+        .any(|part| matches!(part, "" | "." | ".." | "exports"))
+    {
+        return Err(AppError::NotFound(format!("file not found - {}", path)));
+    }
 
     let base_dir = app_state.config.server.base_dir.join(ws_id.to_string());
     let path = base_dir.join(path);
@@ -70,12 +93,22 @@ pub async fn upload_handler(
 ) -> Result<impl IntoResponse, AppError> {
     let ws_id = user.ws_id;
     let base_dir = &app_state.config.server.base_dir;
+    let max_upload_size = app_state
+        .find_workspace_by_id(ws_id as u64)
+        .await?
+        .and_then(|ws| ws.settings.max_upload_size);
     let mut paths = vec![];
     while let Some(field) = multipart.next_field().await? {
         let filename = field.file_name().map(String::from);
         let name = field.name().map(String::from);
         let bytes = field.bytes().await;
         if let (Some(filename), Ok(bytes)) = (filename.clone(), bytes) {
+            if let Some(max) = max_upload_size.filter(|max| bytes.len() as u64 > *max) {
+                return Err(AppError::PayloadTooLarge(format!(
+                    "{} is larger than {} bytes",
+                    filename, max
+                )));
+            }
             let file = ChatFile::new(&filename, &bytes, ws_id as u64);
             let path = file.path(base_dir);
             if !path.exists() {
@@ -1,13 +1,41 @@
+mod account;
 mod auth;
+mod block;
 mod chat;
+mod command;
+mod email;
+mod export;
 mod message;
+mod password;
+mod poll;
+mod profile;
+mod saved;
+mod scim;
+mod session;
+mod sso;
+mod token;
+mod two_factor;
 mod workspace;
 
+pub use account::*;
 pub use auth::*;
 use axum::response::IntoResponse;
 use axum_macros::FromRequest;
+pub use block::*;
 pub use chat::*;
+pub use command::*;
+pub use email::*;
+pub use export::*;
 pub use message::*;
+pub use password::*;
+pub use poll::*;
+pub use profile::*;
+pub use saved::*;
+pub use scim::*;
+pub use session::*;
+pub use sso::*;
+pub use token::*;
+pub use two_factor::*;
 pub use workspace::*;
 
 use crate::error::AppError;
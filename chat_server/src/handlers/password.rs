@@ -0,0 +1,56 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use chat_core::{AuthContext, User};
+
+use crate::{
+    error::AppError,
+    models::{ChangePassword, ForgotPassword, ResetPassword},
+    state::AppState,
+};
+
+use super::AppJson;
+
+#[utoipa::path(put, path = "/api/password",
+request_body(content = ChangePassword, description = "Current and new password"),
+responses(
+    (status = 204, description = "Password changed, the other devices are signed out"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn change_password_handler(
+    Extension(user): Extension<User>,
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ChangePassword>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .change_password(user.id as u64, ctx.sid, &input)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(post, path = "/api/password/forgot",
+request_body(content = ForgotPassword, description = "Email of the account"),
+responses(
+    (status = 202, description = "A reset link is mailed if the account exists"),
+))]
+pub async fn forgot_password_handler(
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ForgotPassword>,
+) -> Result<impl IntoResponse, AppError> {
+    state.request_password_reset(&input).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(post, path = "/api/password/reset",
+request_body(content = ResetPassword, description = "Reset token from the mail and the new password"),
+responses(
+    (status = 204, description = "Password reset, all the devices are signed out"),
+))]
+pub async fn reset_password_handler(
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ResetPassword>,
+) -> Result<impl IntoResponse, AppError> {
+    state.reset_password(&input).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, models::VotePoll, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(get, path = "/api/polls/{msg_id}",
+responses(
+    (status = 200, description = "get poll in successful", body = Poll),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_poll_handler(
+    Extension(user): Extension<User>,
+    Path(msg_id): Path<u64>,
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let poll = app_state.get_poll(msg_id).await?;
+    match poll {
+        Some(poll)
+            if app_state
+                .is_chat_member(poll.chat_id as u64, user.id as u64)
+                .await? =>
+        {
+            Ok((StatusCode::OK, Json(poll)))
+        }
+        _ => Err(AppError::NotFound(format!(
+            "Poll with id {} not found",
+            msg_id
+        ))),
+    }
+}
+
+#[utoipa::path(post, path = "/api/polls/{msg_id}/vote",
+request_body(content = VotePoll, description = "Vote poll details"),
+responses(
+    (status = 200, description = "vote poll in successful", body = Poll),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn vote_poll_handler(
+    Extension(user): Extension<User>,
+    Path(msg_id): Path<u64>,
+    State(app_state): State<AppState>,
+    AppJson(input): AppJson<VotePoll>,
+) -> Result<impl IntoResponse, AppError> {
+    let poll = app_state.vote_poll(msg_id, user.id as u64, input).await?;
+    Ok((StatusCode::OK, Json(poll)))
+}
+
+#[utoipa::path(post, path = "/api/polls/{msg_id}/close",
+responses(
+    (status = 200, description = "close poll in successful", body = Poll),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn close_poll_handler(
+    Extension(user): Extension<User>,
+    Path(msg_id): Path<u64>,
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let poll = app_state.close_poll(msg_id, user.id as u64).await?;
+    Ok((StatusCode::OK, Json(poll)))
+}
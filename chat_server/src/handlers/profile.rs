@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, models::UpdateProfile, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(get, path = "/api/users/me",
+responses(
+    (status = 200, description = "get profile of the user in successful", body = ChatUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_my_profile_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state.get_chat_user(user.id as u64).await?;
+    Ok(Json(user))
+}
+
+#[utoipa::path(patch, path = "/api/users/me",
+request_body(content = UpdateProfile, description = "Absent fields are kept, empty strings clear them"),
+responses(
+    (status = 200, description = "update profile of the user in successful", body = ChatUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn update_my_profile_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<UpdateProfile>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state.update_profile(user.id as u64, input).await?;
+    Ok(Json(user))
+}
+
+#[utoipa::path(get, path = "/api/users/{id}",
+params(
+    ("id" = u64, Path, description = "User id")
+),
+responses(
+    (status = 200, description = "get profile of the user in successful", body = ChatUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_user_profile_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let profile = state.get_user_profile(user.id as u64, id).await?;
+    Ok(Json(profile))
+}
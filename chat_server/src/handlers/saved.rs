@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{error::AppError, models::ListSaved, state::AppState};
+
+#[utoipa::path(get, path = "/api/saved",
+params(
+    ("last_id" = Option<u64>, Query, description = "Saved id to paginate from"),
+    ("limit" = u64, Query, description = "Max number of saved messages"),
+),
+responses(
+    (status = 200, description = "list saved messages in successful", body = Vec<SavedMessage>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_saved_handler(
+    Extension(user): Extension<User>,
+    State(app_state): State<AppState>,
+    Query(input): Query<ListSaved>,
+) -> Result<impl IntoResponse, AppError> {
+    let messages = app_state.list_saved_messages(user.id as u64, input).await?;
+    Ok((StatusCode::OK, Json(messages)))
+}
+
+#[utoipa::path(post, path = "/api/saved/{msg_id}",
+responses(
+    (status = 201, description = "save message in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn save_message_handler(
+    Extension(user): Extension<User>,
+    Path(msg_id): Path<u64>,
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    app_state.save_message(user.id as u64, msg_id).await?;
+    Ok(StatusCode::CREATED)
+}
+
+#[utoipa::path(delete, path = "/api/saved/{msg_id}",
+responses(
+    (status = 200, description = "remove saved message in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn unsave_message_handler(
+    Extension(user): Extension<User>,
+    Path(msg_id): Path<u64>,
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    app_state.unsave_message(user.id as u64, msg_id).await?;
+    Ok(StatusCode::OK)
+}
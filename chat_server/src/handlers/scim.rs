@@ -0,0 +1,290 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chat_core::AuthContext;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    error::{AppError, ScimError, SCIM_CONTENT_TYPE},
+    models::{ScimGroup, ScimListQuery, ScimPatch, ScimUser},
+    state::AppState,
+};
+
+use super::AppJson;
+
+// json body with the scim content type
+pub struct ScimJson<T>(pub StatusCode, pub T);
+
+impl<T: Serialize> IntoResponse for ScimJson<T> {
+    fn into_response(self) -> Response {
+        (
+            self.0,
+            [(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)],
+            Json(self.1),
+        )
+            .into_response()
+    }
+}
+
+#[utoipa::path(get, path = "/scim/v2/ServiceProviderConfig",
+responses(
+    (status = 200, description = "get scim service provider config in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn scim_service_provider_config_handler() -> impl IntoResponse {
+    let config = json!({
+        "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": 1000 },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "Personal access token",
+            "description": "Personal access token of a workspace admin with the scim scope",
+        }],
+    });
+    ScimJson(StatusCode::OK, config)
+}
+
+#[utoipa::path(get, path = "/scim/v2/Users",
+params(
+    ("filter" = Option<String>, Query, description = "Filter like userName eq \"alice@acme.org\""),
+    ("startIndex" = Option<i64>, Query, description = "1-based index of the first result"),
+    ("count" = Option<i64>, Query, description = "Maximum number of results"),
+),
+responses(
+    (status = 200, description = "list scim users in successful", body = ScimUserList),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_scim_users_handler(
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+    Query(query): Query<ScimListQuery>,
+) -> Result<impl IntoResponse, ScimError> {
+    let users = state.list_scim_users(ctx.ws_id as u64, &query).await?;
+    Ok(ScimJson(StatusCode::OK, users))
+}
+
+#[utoipa::path(get, path = "/scim/v2/Users/{id}",
+responses(
+    (status = 200, description = "get scim user in successful", body = ScimUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_scim_user_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ScimError> {
+    let user = state
+        .get_scim_user(ctx.ws_id as u64, parse_id(&id)?)
+        .await?;
+    Ok(ScimJson(StatusCode::OK, user))
+}
+
+#[utoipa::path(post, path = "/scim/v2/Users",
+request_body(content = ScimUser, description = "User to provision, userName is the email"),
+responses(
+    (status = 201, description = "create scim user in successful", body = ScimUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_scim_user_handler(
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ScimUser>,
+) -> Result<impl IntoResponse, ScimError> {
+    let user = state.create_scim_user(ctx.ws_id as u64, input).await?;
+    Ok(ScimJson(StatusCode::CREATED, user))
+}
+
+#[utoipa::path(put, path = "/scim/v2/Users/{id}",
+request_body(content = ScimUser, description = "All the attributes of the user"),
+responses(
+    (status = 200, description = "replace scim user in successful", body = ScimUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn replace_scim_user_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ScimUser>,
+) -> Result<impl IntoResponse, ScimError> {
+    let user = state
+        .replace_scim_user(ctx.ws_id as u64, parse_id(&id)?, input)
+        .await?;
+    Ok(ScimJson(StatusCode::OK, user))
+}
+
+#[utoipa::path(patch, path = "/scim/v2/Users/{id}",
+request_body(content = ScimPatch, description = "Patch operations, active=false deactivates the user"),
+responses(
+    (status = 200, description = "patch scim user in successful", body = ScimUser),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn patch_scim_user_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ScimPatch>,
+) -> Result<impl IntoResponse, ScimError> {
+    let user = state
+        .patch_scim_user(ctx.ws_id as u64, parse_id(&id)?, input)
+        .await?;
+    Ok(ScimJson(StatusCode::OK, user))
+}
+
+#[utoipa::path(delete, path = "/scim/v2/Users/{id}",
+responses(
+    (status = 204, description = "delete scim user in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn delete_scim_user_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ScimError> {
+    state
+        .delete_scim_user(ctx.ws_id as u64, parse_id(&id)?)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(get, path = "/scim/v2/Groups",
+params(
+    ("filter" = Option<String>, Query, description = "Filter like displayName eq \"Engineering\""),
+    ("startIndex" = Option<i64>, Query, description = "1-based index of the first result"),
+    ("count" = Option<i64>, Query, description = "Maximum number of results"),
+),
+responses(
+    (status = 200, description = "list scim groups in successful", body = ScimGroupList),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_scim_groups_handler(
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+    Query(query): Query<ScimListQuery>,
+) -> Result<impl IntoResponse, ScimError> {
+    let groups = state.list_scim_groups(ctx.ws_id as u64, &query).await?;
+    Ok(ScimJson(StatusCode::OK, groups))
+}
+
+#[utoipa::path(get, path = "/scim/v2/Groups/{id}",
+responses(
+    (status = 200, description = "get scim group in successful", body = ScimGroup),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_scim_group_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ScimError> {
+    let group = state
+        .get_scim_group(ctx.ws_id as u64, parse_id(&id)?)
+        .await?;
+    Ok(ScimJson(StatusCode::OK, group))
+}
+
+#[utoipa::path(post, path = "/scim/v2/Groups",
+request_body(content = ScimGroup, description = "Group with its members"),
+responses(
+    (status = 201, description = "create scim group in successful", body = ScimGroup),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_scim_group_handler(
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ScimGroup>,
+) -> Result<impl IntoResponse, ScimError> {
+    let group = state.create_scim_group(ctx.ws_id as u64, input).await?;
+    Ok(ScimJson(StatusCode::CREATED, group))
+}
+
+#[utoipa::path(put, path = "/scim/v2/Groups/{id}",
+request_body(content = ScimGroup, description = "Group with all its members"),
+responses(
+    (status = 200, description = "replace scim group in successful", body = ScimGroup),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn replace_scim_group_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ScimGroup>,
+) -> Result<impl IntoResponse, ScimError> {
+    let group = state
+        .replace_scim_group(ctx.ws_id as u64, parse_id(&id)?, input)
+        .await?;
+    Ok(ScimJson(StatusCode::OK, group))
+}
+
+#[utoipa::path(patch, path = "/scim/v2/Groups/{id}",
+request_body(content = ScimPatch, description = "Patch operations on the name and members"),
+responses(
+    (status = 200, description = "patch scim group in successful", body = ScimGroup),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn patch_scim_group_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<ScimPatch>,
+) -> Result<impl IntoResponse, ScimError> {
+    let group = state
+        .patch_scim_group(ctx.ws_id as u64, parse_id(&id)?, input)
+        .await?;
+    Ok(ScimJson(StatusCode::OK, group))
+}
+
+#[utoipa::path(delete, path = "/scim/v2/Groups/{id}",
+responses(
+    (status = 204, description = "delete scim group in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn delete_scim_group_handler(
+    Extension(ctx): Extension<AuthContext>,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ScimError> {
+    state
+        .delete_scim_group(ctx.ws_id as u64, parse_id(&id)?)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// resource ids are strings in scim
+fn parse_id(id: &str) -> Result<i64, AppError> {
+    id.parse()
+        .map_err(|_| AppError::NotFound(format!("Resource with id {} not found", id)))
+}
@@ -0,0 +1,148 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Path, State},
+    http::{header::USER_AGENT, request::Parts, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::{AuthContext, User};
+
+use crate::{error::AppError, models::DeviceInfo, state::AppState};
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+#[utoipa::path(get, path = "/api/sessions",
+responses(
+    (status = 200, description = "list sessions in successful", body = Vec<Session>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_sessions_handler(
+    Extension(user): Extension<User>,
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let sessions = state.list_sessions(user.id as u64, ctx.sid).await?;
+    Ok((StatusCode::OK, Json(sessions)))
+}
+
+#[utoipa::path(delete, path = "/api/sessions/{id}",
+responses(
+    (status = 204, description = "revoke session in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn revoke_session_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.revoke_session(user.id as u64, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for DeviceInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let user_agent = header(USER_AGENT.as_str()).map(|v| v.to_string());
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let ip = peer.map(|peer| {
+            client_ip(
+                peer,
+                header(FORWARDED_FOR_HEADER),
+                &state.config.server.trusted_proxies,
+            )
+            .to_string()
+        });
+        Ok(Self {
+            device_name: None,
+            user_agent,
+            ip,
+        })
+    }
+}
+
+// the header is only trusted when sent by one of the proxies, the client is the last address
+// not added by them as anyone could prepend forged ones
+fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut ip = peer;
+    if !trusted_proxies.contains(&peer) {
+        return ip;
+    }
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        match hop.trim().parse() {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    ip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn request(peer: &str, forwarded_for: &str) -> anyhow::Result<Parts> {
+        let mut req = Request::builder()
+            .header("User-Agent", "curl/8.4.0")
+            .header("X-Forwarded-For", forwarded_for)
+            .body(())?;
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(peer.parse()?, 443)));
+        Ok(req.into_parts().0)
+    }
+
+    #[tokio::test]
+    async fn device_info_should_be_extracted() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let mut parts = request("172.16.0.1", "10.0.0.1")?;
+        let device = DeviceInfo::from_request_parts(&mut parts, &state).await?;
+        assert_eq!(device.user_agent.as_deref(), Some("curl/8.4.0"));
+        // no proxy is trusted by default, the header is ignored
+        assert_eq!(device.ip.as_deref(), Some("172.16.0.1"));
+        assert_eq!(device.name(), "curl");
+        Ok(())
+    }
+
+    #[test]
+    fn client_ip_should_skip_forged_addresses() -> anyhow::Result<()> {
+        let proxies = vec!["10.0.0.1".parse()?, "10.0.0.2".parse()?];
+        let peer = "10.0.0.1".parse()?;
+        let ip = client_ip(peer, Some("1.1.1.1, 2.2.2.2, 10.0.0.2"), &proxies);
+        assert_eq!(ip.to_string(), "2.2.2.2");
+        let ip = client_ip(peer, Some("junk, 2.2.2.2"), &proxies);
+        assert_eq!(ip.to_string(), "2.2.2.2");
+        let ip = client_ip(peer, None, &proxies);
+        assert_eq!(ip, peer);
+        let ip = client_ip("3.3.3.3".parse()?, Some("2.2.2.2"), &proxies);
+        assert_eq!(ip.to_string(), "3.3.3.3");
+        Ok(())
+    }
+}
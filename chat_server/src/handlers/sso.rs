@@ -0,0 +1,50 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    Json,
+};
+
+use crate::{
+    error::AppError,
+    models::{DeviceInfo, SsoCallback},
+    state::AppState,
+};
+
+use super::AuthOutput;
+
+#[utoipa::path(get, path = "/api/sso/{ws_id}/login",
+responses(
+    (status = 303, description = "Redirect to the openid connect provider of the workspace"),
+    (status = 404, description = "Workspace has no single sign-on"),
+))]
+pub async fn sso_login_handler(
+    Path(ws_id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let client = state.oidc_client(ws_id)?;
+    let url = state.start_sso_login(client).await?;
+    Ok(Redirect::to(&url))
+}
+
+#[utoipa::path(get, path = "/api/sso/{ws_id}/callback",
+params(
+    ("code" = Option<String>, Query, description = "Authorization code of the provider"),
+    ("state" = Option<String>, Query, description = "State of the login"),
+    ("error" = Option<String>, Query, description = "Error returned by the provider"),
+),
+responses(
+    (status = 200, description = "User signed in successfully", body = AuthOutput),
+))]
+pub async fn sso_callback_handler(
+    Path(ws_id): Path<u64>,
+    State(state): State<AppState>,
+    device: DeviceInfo,
+    Query(input): Query<SsoCallback>,
+) -> Result<impl IntoResponse, AppError> {
+    let client = state.oidc_client(ws_id)?;
+    let user = state.finish_sso_login(client, &input).await?;
+    let session_id = state.create_session(user.id as u64, &device).await?;
+    let output = AuthOutput::issue(&state, user, session_id).await?;
+    Ok((StatusCode::OK, Json(output)))
+}
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+
+use crate::{
+    error::AppError,
+    models::{CreateAccessToken, CreateBot},
+    state::AppState,
+};
+
+use super::AppJson;
+
+#[utoipa::path(get, path = "/api/tokens",
+responses(
+    (status = 200, description = "list personal access tokens in successful", body = Vec<AccessToken>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_tokens_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let tokens = state.list_personal_tokens(user.id as u64).await?;
+    Ok((StatusCode::OK, Json(tokens)))
+}
+
+#[utoipa::path(post, path = "/api/tokens",
+request_body(content = CreateAccessToken, description = "Name and scopes of the token"),
+responses(
+    (status = 201, description = "create personal access token in successful", body = CreatedAccessToken),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_token_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<CreateAccessToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let token = state.create_personal_token(&user, input).await?;
+    Ok((StatusCode::CREATED, Json(token)))
+}
+
+#[utoipa::path(delete, path = "/api/tokens/{id}",
+responses(
+    (status = 204, description = "revoke personal access token in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn revoke_token_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.revoke_personal_token(user.id as u64, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}/bots",
+responses(
+    (status = 200, description = "list bots in successful", body = Vec<User>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_bots_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let bots = state.list_bots(id, user.id as u64).await?;
+    Ok((StatusCode::OK, Json(bots)))
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/bots",
+request_body(content = CreateBot, description = "Name of the bot and its first token"),
+responses(
+    (status = 201, description = "create bot in successful", body = CreatedBot),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_bot_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<CreateBot>,
+) -> Result<impl IntoResponse, AppError> {
+    let bot = state.create_bot(id, user.id as u64, input).await?;
+    Ok((StatusCode::CREATED, Json(bot)))
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/bots/{bot_id}/tokens",
+request_body(content = CreateAccessToken, description = "Name and scopes of the token"),
+responses(
+    (status = 201, description = "create bot token in successful", body = CreatedAccessToken),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_bot_token_handler(
+    Extension(user): Extension<User>,
+    Path((id, bot_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<CreateAccessToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let token = state
+        .create_bot_token(id, user.id as u64, bot_id, input)
+        .await?;
+    Ok((StatusCode::CREATED, Json(token)))
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}/tokens",
+responses(
+    (status = 200, description = "list workspace access tokens in successful", body = Vec<AccessToken>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_workspace_tokens_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let tokens = state.list_workspace_tokens(id, user.id as u64).await?;
+    Ok((StatusCode::OK, Json(tokens)))
+}
+
+#[utoipa::path(delete, path = "/api/workspaces/{id}/tokens/{token_id}",
+responses(
+    (status = 204, description = "revoke workspace access token in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn revoke_workspace_token_handler(
+    Extension(user): Extension<User>,
+    Path((id, token_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .revoke_workspace_token(id, user.id as u64, token_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,87 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use chat_core::User;
+
+use crate::{error::AppError, models::TwoFactorCode, state::AppState};
+
+use super::AppJson;
+
+#[utoipa::path(get, path = "/api/2fa",
+responses(
+    (status = 200, description = "Two-factor authentication status", body = TwoFactorStatus),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn two_factor_status_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let status = state.two_factor_status(user.id as u64).await?;
+    Ok((StatusCode::OK, Json(status)))
+}
+
+#[utoipa::path(post, path = "/api/2fa/totp",
+responses(
+    (status = 201, description = "Secret and provisioning uri of the authenticator app", body = TotpEnrollment),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn enroll_totp_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let enrollment = state.enroll_totp(&user).await?;
+    Ok((StatusCode::CREATED, Json(enrollment)))
+}
+
+#[utoipa::path(post, path = "/api/2fa/totp/confirm",
+request_body(content = TwoFactorCode, description = "First code of the authenticator app"),
+responses(
+    (status = 200, description = "Two-factor authentication enabled", body = RecoveryCodes),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn confirm_totp_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    let codes = state.confirm_totp(&user, &input).await?;
+    Ok((StatusCode::OK, Json(codes)))
+}
+
+#[utoipa::path(delete, path = "/api/2fa/totp",
+request_body(content = TwoFactorCode, description = "Code of the authenticator app or a recovery code"),
+responses(
+    (status = 204, description = "Two-factor authentication disabled"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn disable_two_factor_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    state.disable_two_factor(&user, &input).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(post, path = "/api/2fa/recovery-codes",
+request_body(content = TwoFactorCode, description = "Code of the authenticator app or a recovery code"),
+responses(
+    (status = 200, description = "New recovery codes, the old ones are invalidated", body = RecoveryCodes),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn regenerate_recovery_codes_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<TwoFactorCode>,
+) -> Result<impl IntoResponse, AppError> {
+    let codes = state.regenerate_recovery_codes(&user, &input).await?;
+    Ok((StatusCode::OK, Json(codes)))
+}
@@ -1,12 +1,345 @@
-use axum::{extract::State, response::IntoResponse, Extension, Json};
-use chat_core::User;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::{AuthContext, User, WorkspaceSettings};
 
-use crate::{error::AppError, state::AppState};
+use crate::{
+    error::AppError,
+    models::{
+        CreateInvite, DeviceInfo, JoinWorkspace, ListAuditLogs, ListUsers, TransferWorkspace,
+        UpdateJoinPolicy, UpdateMemberRole, UpdateWorkspace,
+    },
+    state::AppState,
+};
 
+use super::{AppJson, AuthOutput};
+
+#[utoipa::path(get, path = "/api/users",
+params(
+    ("q" = Option<String>, Query, description = "Prefix or fuzzy match of the name or email"),
+    ("status" = Option<UserStatusFilter>, Query, description = "active (default), deactivated or all"),
+    ("bot" = Option<bool>, Query, description = "Only bots if true, only people if false"),
+    ("in_chat" = Option<u64>, Query, description = "Only members of the chat"),
+    ("not_in_chat" = Option<u64>, Query, description = "Only users not in the chat"),
+    ("offset" = Option<u64>, Query, description = "Number of users to skip"),
+    ("limit" = Option<u64>, Query, description = "Maximum number of users"),
+),
+responses(
+    (status = 200, description = "search users of the workspace in successful", body = Vec<ChatUser>),
+),
+security(
+    ("Authorization" = [])
+))]
 pub async fn list_chat_users_handler(
     Extension(user): Extension<User>,
     State(state): State<AppState>,
+    Query(input): Query<ListUsers>,
 ) -> Result<impl IntoResponse, AppError> {
-    let users = state.fetch_chat_users(user.ws_id).await?;
+    let users = state
+        .search_chat_users(user.ws_id as u64, user.id as u64, input)
+        .await?;
     Ok(Json(users))
 }
+
+#[utoipa::path(get, path = "/api/workspaces",
+responses(
+    (status = 200, description = "list workspaces of the user in successful", body = Vec<Workspace>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_workspaces_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspaces = state.fetch_user_workspaces(user.id as u64).await?;
+    Ok((StatusCode::OK, Json(workspaces)))
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/switch",
+responses(
+    (status = 200, description = "switch workspace in successful", body = AuthOutput),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn switch_workspace_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    Extension(ctx): Extension<AuthContext>,
+    State(state): State<AppState>,
+    device: DeviceInfo,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state.switch_workspace(user.id as u64, id).await?;
+    // keep using the session of the device
+    let session_id = match ctx.sid {
+        Some(session_id) => session_id,
+        None => state.create_session(user.id as u64, &device).await?,
+    };
+    let output = AuthOutput::issue(&state, user, session_id).await?;
+    Ok((StatusCode::OK, Json(output)))
+}
+
+#[utoipa::path(post, path = "/api/workspaces/join",
+responses(
+    (status = 200, description = "join workspace in successful", body = Workspace),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn join_workspace_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<JoinWorkspace>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace = state
+        .join_workspace(user.id as u64, &user.email, input)
+        .await?;
+    Ok((StatusCode::OK, Json(workspace)))
+}
+
+#[utoipa::path(put, path = "/api/workspaces/{id}/policy",
+responses(
+    (status = 200, description = "update join policy in successful", body = Workspace),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn update_join_policy_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<UpdateJoinPolicy>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace = state.update_join_policy(id, user.id as u64, input).await?;
+    Ok((StatusCode::OK, Json(workspace)))
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}/invites",
+responses(
+    (status = 200, description = "list invites in successful", body = Vec<WorkspaceInvite>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_invites_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let invites = state.list_invites(id, user.id as u64).await?;
+    Ok((StatusCode::OK, Json(invites)))
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/invites",
+responses(
+    (status = 201, description = "create invite in successful", body = WorkspaceInvite),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn create_invite_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<CreateInvite>,
+) -> Result<impl IntoResponse, AppError> {
+    let invite = state.create_invite(id, user.id as u64, input).await?;
+    Ok((StatusCode::CREATED, Json(invite)))
+}
+
+#[utoipa::path(delete, path = "/api/workspaces/{id}/invites/{invite_id}",
+responses(
+    (status = 200, description = "revoke invite in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn revoke_invite_handler(
+    Extension(user): Extension<User>,
+    Path((id, invite_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.revoke_invite(id, user.id as u64, invite_id).await?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}",
+responses(
+    (status = 200, description = "get workspace in successful", body = Workspace),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn get_workspace_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace = state.get_workspace(id, user.id as u64).await?;
+    Ok((StatusCode::OK, Json(workspace)))
+}
+
+#[utoipa::path(patch, path = "/api/workspaces/{id}",
+responses(
+    (status = 200, description = "rename workspace in successful", body = Workspace),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn update_workspace_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<UpdateWorkspace>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace = state.rename_workspace(id, user.id as u64, input).await?;
+    Ok((StatusCode::OK, Json(workspace)))
+}
+
+#[utoipa::path(delete, path = "/api/workspaces/{id}",
+responses(
+    (status = 202, description = "workspace is scheduled for deletion"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn delete_workspace_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_workspace(id, user.id as u64).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(put, path = "/api/workspaces/{id}/settings",
+responses(
+    (status = 200, description = "update workspace settings in successful", body = Workspace),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn update_workspace_settings_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<WorkspaceSettings>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace = state
+        .update_workspace_settings(id, user.id as u64, input)
+        .await?;
+    Ok((StatusCode::OK, Json(workspace)))
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/transfer",
+responses(
+    (status = 200, description = "transfer workspace in successful", body = Workspace),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn transfer_workspace_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<TransferWorkspace>,
+) -> Result<impl IntoResponse, AppError> {
+    let workspace = state
+        .transfer_workspace(id, user.id as u64, input.owner_id as u64)
+        .await?;
+    Ok((StatusCode::OK, Json(workspace)))
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}/members",
+responses(
+    (status = 200, description = "list workspace members in successful", body = Vec<WorkspaceMember>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_workspace_members_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let members = state.fetch_workspace_members(id, user.id as u64).await?;
+    Ok((StatusCode::OK, Json(members)))
+}
+
+#[utoipa::path(delete, path = "/api/workspaces/{id}/members/{user_id}",
+responses(
+    (status = 200, description = "remove workspace member in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn remove_workspace_member_handler(
+    Extension(user): Extension<User>,
+    Path((id, member_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .remove_workspace_member(id, user.id as u64, member_id)
+        .await?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(post, path = "/api/workspaces/{id}/members/{user_id}/unlock",
+responses(
+    (status = 204, description = "unlock workspace member in successful"),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn unlock_member_handler(
+    Extension(user): Extension<User>,
+    Path((id, member_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.unlock_member(id, user.id as u64, member_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(get, path = "/api/workspaces/{id}/audit",
+params(
+    ("last_id" = Option<u64>, Query, description = "Entries older than this id"),
+    ("limit" = Option<u64>, Query, description = "Maximum number of entries"),
+),
+responses(
+    (status = 200, description = "list audit logs in successful", body = Vec<AuditLog>),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn list_audit_logs_handler(
+    Extension(user): Extension<User>,
+    Path(id): Path<u64>,
+    Query(input): Query<ListAuditLogs>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let logs = state.list_audit_logs(id, user.id as u64, input).await?;
+    Ok((StatusCode::OK, Json(logs)))
+}
+
+#[utoipa::path(put, path = "/api/workspaces/{id}/members/{user_id}/role",
+responses(
+    (status = 200, description = "update member role in successful", body = WorkspaceRole),
+),
+security(
+    ("Authorization" = [])
+))]
+pub async fn update_member_role_handler(
+    Extension(user): Extension<User>,
+    Path((id, member_id)): Path<(u64, u64)>,
+    State(state): State<AppState>,
+    AppJson(input): AppJson<UpdateMemberRole>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = state
+        .update_member_role(id, user.id as u64, member_id, input.role)
+        .await?;
+    Ok((StatusCode::OK, Json(role)))
+}
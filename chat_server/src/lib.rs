@@ -1,12 +1,18 @@
+mod authenticator;
+mod commands;
 mod config;
 mod error;
 mod handlers;
+mod lockout;
+mod mailer;
 mod middlewares;
 mod models;
+mod oidc;
 mod openapi;
 mod router;
 mod server;
 mod state;
+mod unfurl;
 pub use router::get_router;
 pub use server::run;
 pub use state::AppState;
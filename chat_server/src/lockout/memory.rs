@@ -0,0 +1,79 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+use super::{AttemptStore, Attempts};
+use crate::error::AppError;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    failures: u32,
+    last_failed_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+// failures only known by this node
+#[derive(Debug, Default)]
+pub struct MemoryAttemptStore(Mutex<HashMap<String, Entry>>);
+
+#[async_trait]
+impl AttemptStore for MemoryAttemptStore {
+    async fn get(&self, key: &str) -> Result<Option<Attempts>, AppError> {
+        let entries = self.0.lock().map_err(poisoned)?;
+        Ok(entries.get(key).map(|e| Attempts {
+            failures: e.failures,
+            locked_until: e.locked_until,
+        }))
+    }
+
+    async fn record_failure(&self, key: &str, window: Duration) -> Result<u32, AppError> {
+        let mut entries = self.0.lock().map_err(poisoned)?;
+        let now = Utc::now();
+        let entry = entries.entry(key.to_string()).or_insert(Entry {
+            failures: 0,
+            last_failed_at: now,
+            locked_until: None,
+        });
+        if entry.last_failed_at < now - window {
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+        entry.last_failed_at = now;
+        Ok(entry.failures)
+    }
+
+    async fn lock(&self, key: &str, until: DateTime<Utc>) -> Result<(), AppError> {
+        let mut entries = self.0.lock().map_err(poisoned)?;
+        if let Some(entry) = entries.get_mut(key) {
+            entry.locked_until = Some(until);
+        }
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), AppError> {
+        let mut entries = self.0.lock().map_err(poisoned)?;
+        if let Some(entry) = entries.get_mut(key) {
+            entry.failures = entry.failures.saturating_sub(1);
+            entry.locked_until = None;
+        }
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        self.0.lock().map_err(poisoned)?.remove(key);
+        Ok(())
+    }
+
+    async fn purge(&self, window: Duration) -> Result<(), AppError> {
+        let now = Utc::now();
+        self.0.lock().map_err(poisoned)?.retain(|_, e| {
+            e.last_failed_at >= now - window || e.locked_until.is_some_and(|until| until > now)
+        });
+        Ok(())
+    }
+}
+
+fn poisoned<T>(_: T) -> AppError {
+    AppError::Anyhow(anyhow::anyhow!("signin attempts lock poisoned"))
+}
@@ -0,0 +1,212 @@
+mod memory;
+mod postgres;
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+use crate::{
+    config::{LockoutConfig, LockoutStore},
+    error::AppError,
+};
+
+pub use memory::MemoryAttemptStore;
+pub use postgres::PgAttemptStore;
+
+// failed signins of an account or ip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempts {
+    pub failures: u32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+pub trait AttemptStore: Send + Sync + 'static {
+    async fn get(&self, key: &str) -> Result<Option<Attempts>, AppError>;
+    // count the failure, the count restarts if the previous failure is older than the window
+    async fn record_failure(&self, key: &str, window: Duration) -> Result<u32, AppError>;
+    async fn lock(&self, key: &str, until: DateTime<Utc>) -> Result<(), AppError>;
+    // take back the last failure and unlock the key
+    async fn release(&self, key: &str) -> Result<(), AppError>;
+    async fn clear(&self, key: &str) -> Result<(), AppError>;
+    // forget the keys neither locked nor failed within the window
+    async fn purge(&self, window: Duration) -> Result<(), AppError>;
+}
+
+// a failure locked the key out, not only delayed its next attempt
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lockout {
+    pub failures: u32,
+    pub locked_until: DateTime<Utc>,
+}
+
+pub struct SigninThrottle {
+    config: LockoutConfig,
+    store: Arc<dyn AttemptStore>,
+}
+
+impl SigninThrottle {
+    pub fn new(config: LockoutConfig, store: Arc<dyn AttemptStore>) -> Self {
+        Self { config, store }
+    }
+
+    // the signin is rejected without checking the password while a key is locked
+    pub async fn check(&self, keys: &[String]) -> Result<(), AppError> {
+        let now = Utc::now();
+        for key in keys {
+            let locked_until = self.store.get(key).await?.and_then(|a| a.locked_until);
+            if let Some(until) = locked_until.filter(|until| *until > now) {
+                let secs = ((until - now).num_milliseconds() + 999) / 1000;
+                return Err(AppError::SigninLocked(secs.max(1) as u64));
+            }
+        }
+        Ok(())
+    }
+
+    // signins are counted as failures before the password is checked, so parallel requests
+    // could not get past the limits, the right password takes its attempt back
+    pub async fn account_attempt(&self, key: &str) -> Result<Option<Lockout>, AppError> {
+        self.failed(key, self.config.max_account_failures).await
+    }
+
+    pub async fn ip_attempt(&self, key: &str) -> Result<Option<Lockout>, AppError> {
+        self.failed(key, self.config.max_ip_failures).await
+    }
+
+    // accounts wait exponentially longer after each failure, ips are only locked out
+    pub async fn account_failed(&self, key: &str) -> Result<(), AppError> {
+        let Some(attempts) = self.store.get(key).await? else {
+            return Ok(());
+        };
+        let delay = backoff_delay(self.config.base_delay_ms, attempts.failures)
+            .min(Duration::seconds(self.config.duration_secs as i64));
+        let until = Utc::now() + delay;
+        // a lockout by a parallel attempt is not shortened
+        if attempts.locked_until.is_none_or(|locked| locked < until) {
+            self.store.lock(key, until).await?;
+        }
+        Ok(())
+    }
+
+    // wrong second factors count against the account without delay, each challenge limits its
+    // own attempts already
+    pub async fn second_factor_failed(&self, key: &str) -> Result<Option<Lockout>, AppError> {
+        self.failed(key, self.config.max_account_failures).await
+    }
+
+    pub async fn release(&self, key: &str) -> Result<(), AppError> {
+        self.store.release(key).await
+    }
+
+    pub async fn clear(&self, key: &str) -> Result<(), AppError> {
+        self.store.clear(key).await
+    }
+
+    pub async fn purge(&self) -> Result<(), AppError> {
+        self.store.purge(self.window()).await
+    }
+
+    async fn failed(&self, key: &str, max_failures: u32) -> Result<Option<Lockout>, AppError> {
+        let failures = self.store.record_failure(key, self.window()).await?;
+        if failures >= max_failures {
+            let locked_until = Utc::now() + Duration::seconds(self.config.duration_secs as i64);
+            self.store.lock(key, locked_until).await?;
+            return Ok(Some(Lockout {
+                failures,
+                locked_until,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn window(&self) -> Duration {
+        Duration::seconds(self.config.window_secs as i64)
+    }
+}
+
+pub fn account_key(login: &str) -> String {
+    format!("account:{}", login.trim().to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+pub fn build_signin_throttle(config: &LockoutConfig, pool: PgPool) -> SigninThrottle {
+    let store: Arc<dyn AttemptStore> = match config.store {
+        LockoutStore::Memory => Arc::new(MemoryAttemptStore::default()),
+        LockoutStore::Postgres => Arc::new(PgAttemptStore::new(pool)),
+    };
+    SigninThrottle::new(config.clone(), store)
+}
+
+// base, 2 * base, 4 * base... for the 1st, 2nd, 3rd failures
+fn backoff_delay(base_ms: u64, failures: u32) -> Duration {
+    let factor = 1u64 << failures.saturating_sub(1).min(20);
+    Duration::milliseconds(base_ms.saturating_mul(factor) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(store: Arc<dyn AttemptStore>) -> SigninThrottle {
+        let config = LockoutConfig {
+            max_account_failures: 3,
+            max_ip_failures: 2,
+            base_delay_ms: 0,
+            ..Default::default()
+        };
+        SigninThrottle::new(config, store)
+    }
+
+    async fn lockout_should_work(store: Arc<dyn AttemptStore>) -> Result<(), AppError> {
+        let throttle = throttle(store);
+        let account = account_key(" Alice@Acme.org");
+        assert_eq!(account, "account:alice@acme.org");
+        let keys = vec![account.clone()];
+        throttle.check(&keys).await?;
+        assert_eq!(throttle.account_attempt(&account).await?, None);
+        throttle.account_failed(&account).await?;
+        assert_eq!(throttle.account_attempt(&account).await?, None);
+        // the right password takes its attempt back
+        throttle.release(&account).await?;
+        assert_eq!(throttle.account_attempt(&account).await?, None);
+        throttle.check(&keys).await?;
+        let lockout = throttle.account_attempt(&account).await?.unwrap();
+        assert_eq!(lockout.failures, 3);
+        let ret = throttle.check(&keys).await;
+        assert!(matches!(ret, Err(AppError::SigninLocked(secs)) if secs > 800));
+
+        throttle.clear(&account).await?;
+        throttle.check(&keys).await?;
+
+        let ip = ip_key("10.0.0.1");
+        assert_eq!(throttle.ip_attempt(&ip).await?, None);
+        assert!(throttle.ip_attempt(&ip).await?.is_some());
+        let ret = throttle.check(&[account, ip]).await;
+        assert!(matches!(ret, Err(AppError::SigninLocked(_))));
+        throttle.purge().await?;
+        Ok(())
+    }
+
+    #[test]
+    fn backoff_delay_should_double() {
+        assert_eq!(backoff_delay(1000, 1), Duration::seconds(1));
+        assert_eq!(backoff_delay(1000, 2), Duration::seconds(2));
+        assert_eq!(backoff_delay(1000, 4), Duration::seconds(8));
+    }
+
+    #[tokio::test]
+    async fn memory_lockout_should_work() -> Result<(), AppError> {
+        lockout_should_work(Arc::new(MemoryAttemptStore::default())).await
+    }
+
+    #[tokio::test]
+    async fn postgres_lockout_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = crate::AppState::new_for_test().await?;
+        lockout_should_work(Arc::new(PgAttemptStore::new(state.pool.clone()))).await
+    }
+}
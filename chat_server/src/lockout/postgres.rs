@@ -0,0 +1,101 @@
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{FromRow, PgPool};
+
+use super::{AttemptStore, Attempts};
+use crate::error::AppError;
+
+#[derive(Debug, FromRow)]
+struct AttemptRow {
+    failures: i32,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+// failures shared by all the nodes using the database
+pub struct PgAttemptStore {
+    pool: PgPool,
+}
+
+impl PgAttemptStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AttemptStore for PgAttemptStore {
+    async fn get(&self, key: &str) -> Result<Option<Attempts>, AppError> {
+        let row: Option<AttemptRow> =
+            sqlx::query_as("SELECT failures, locked_until FROM signin_attempts WHERE key = $1")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|row| Attempts {
+            failures: row.failures as u32,
+            locked_until: row.locked_until,
+        }))
+    }
+
+    async fn record_failure(&self, key: &str, window: Duration) -> Result<u32, AppError> {
+        let failures: i32 = sqlx::query_scalar(
+            r#"
+                INSERT INTO signin_attempts (key, failures, last_failed_at)
+                VALUES ($1, 1, NOW())
+                ON CONFLICT (key) DO UPDATE SET
+                    failures = CASE
+                        WHEN signin_attempts.last_failed_at < NOW() - make_interval(secs => $2) THEN 1
+                        ELSE signin_attempts.failures + 1
+                    END,
+                    last_failed_at = NOW()
+                RETURNING failures"#,
+        )
+        .bind(key)
+        .bind(window.num_seconds() as f64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(failures as u32)
+    }
+
+    async fn lock(&self, key: &str, until: DateTime<Utc>) -> Result<(), AppError> {
+        sqlx::query("UPDATE signin_attempts SET locked_until = $2 WHERE key = $1")
+            .bind(key)
+            .bind(until)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+                UPDATE signin_attempts
+                SET failures = GREATEST(failures - 1, 0), locked_until = NULL
+                WHERE key = $1"#,
+        )
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM signin_attempts WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn purge(&self, window: Duration) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+                DELETE FROM signin_attempts
+                WHERE last_failed_at < NOW() - make_interval(secs => $1)
+                    AND (locked_until IS NULL OR locked_until <= NOW())"#,
+        )
+        .bind(window.num_seconds() as f64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -28,6 +28,7 @@ pub async fn verify_is_chat_member(
 mod test {
 
     use super::*;
+    use crate::middlewares::load_user;
     use anyhow::Result;
     use axum::{
         body::Body, http::StatusCode, middleware::from_fn_with_state, routing::get, Router,
@@ -48,6 +49,7 @@ mod test {
         let app = Router::new()
             .route("/:id", get(handler))
             .layer(from_fn_with_state(state.clone(), verify_is_chat_member))
+            .layer(from_fn_with_state(state.clone(), load_user))
             .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
             .with_state(state.clone());
 
@@ -1,8 +1,13 @@
 mod chat;
+mod scim;
+mod scope;
+mod user;
 
 use axum::{middleware::from_fn, Router};
 pub use chat::verify_is_chat_member;
 use chat_core::{set_request_id, ServerTimeLayer};
+pub use scim::verify_scim_token;
+pub use scope::verify_scope;
 use tower::ServiceBuilder;
 use tower_http::{
     compression::CompressionLayer,
@@ -10,6 +15,7 @@ use tower_http::{
     LatencyUnit,
 };
 use tracing::Level;
+pub use user::load_user;
 
 pub fn set_layer(app: Router) -> Router {
     app.layer(
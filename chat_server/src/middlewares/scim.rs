@@ -0,0 +1,39 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chat_core::{AuthContext, Scope};
+
+use crate::{
+    error::{AppError, ScimError},
+    state::AppState,
+};
+
+// provisioning routes only accept scim tokens of the workspace admins,
+// the role is checked on each request as admins could be demoted
+pub async fn verify_scim_token(
+    State(state): State<AppState>,
+    Extension(ctx): Extension<AuthContext>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !ctx
+        .scopes
+        .as_ref()
+        .is_some_and(|s| s.contains(&Scope::Scim))
+    {
+        return ScimError(AppError::Forbidden(
+            "Token does not have the \"scim\" scope".to_string(),
+        ))
+        .into_response();
+    }
+    if let Err(e) = state
+        .verify_workspace_admin(ctx.ws_id as u64, ctx.user_id as u64)
+        .await
+    {
+        return ScimError(e).into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,166 @@
+use axum::{
+    extract::Request,
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chat_core::{AuthContext, Scope};
+
+use crate::error::AppError;
+
+// personal access tokens could only call the routes their scopes allow,
+// signed in users are not limited
+pub async fn verify_scope(
+    Extension(ctx): Extension<AuthContext>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !ctx.is_access_token() {
+        return next.run(request).await;
+    }
+    match required_scope(request.method(), request.uri().path()) {
+        Some(scope) if ctx.allows(scope) => next.run(request).await,
+        Some(scope) => AppError::Forbidden(format!(
+            "Token does not have the {} scope",
+            serde_json::to_string(&scope).unwrap_or_default()
+        ))
+        .into_response(),
+        None => {
+            AppError::Forbidden("Access tokens could not be used here".to_string()).into_response()
+        }
+    }
+}
+
+// scope needed to call the api route (path without /api), None for routes of the account itself
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let read = matches!(*method, Method::GET | Method::HEAD);
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let (read_scope, write_scope) = match segments.as_slice() {
+        // sending a message or listing messages of a chat
+        ["chats", _, "messages", ..] => (Scope::MessagesRead, Scope::MessagesWrite),
+        ["chats", _] if *method == Method::POST => (Scope::MessagesRead, Scope::MessagesWrite),
+        ["chats", ..] => (Scope::ChatsRead, Scope::ChatsWrite),
+        ["polls" | "saved" | "upload" | "files", ..] => (Scope::MessagesRead, Scope::MessagesWrite),
+        // tokens could not be used to mint other tokens or to join other workspaces
+        ["workspaces", _, "switch" | "bots" | "tokens", ..] => return None,
+        ["workspaces", "join"] => return None,
+        ["users", "me"] if *method == Method::DELETE => return None,
+        ["users", "me", "exports" | "blocks" | "privacy", ..] => return None,
+        // deleting or handing over the workspace and removing or deactivating members
+        ["workspaces", _] if *method == Method::DELETE => return Some(Scope::WorkspacesAdmin),
+        ["workspaces", _, "transfer"] => return Some(Scope::WorkspacesAdmin),
+        ["workspaces", _, "members", _] if *method == Method::DELETE => {
+            return Some(Scope::WorkspacesAdmin)
+        }
+        ["workspaces", _, "members", _, "role" | "deactivate" | "reactivate" | "account"] => {
+            return Some(Scope::WorkspacesAdmin)
+        }
+        ["users" | "workspaces" | "commands", ..] => {
+            (Scope::WorkspacesRead, Scope::WorkspacesWrite)
+        }
+        _ => return None,
+    };
+    Some(if read { read_scope } else { write_scope })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware::from_fn, routing::any, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn required_scope_should_work() {
+        assert_eq!(
+            required_scope(&Method::GET, "/chats"),
+            Some(Scope::ChatsRead)
+        );
+        assert_eq!(
+            required_scope(&Method::PATCH, "/chats/1"),
+            Some(Scope::ChatsWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/chats/1"),
+            Some(Scope::MessagesWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/chats/1/messages"),
+            Some(Scope::MessagesRead)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/polls/1/vote"),
+            Some(Scope::MessagesWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/users"),
+            Some(Scope::WorkspacesRead)
+        );
+        assert_eq!(
+            required_scope(&Method::PATCH, "/workspaces/1"),
+            Some(Scope::WorkspacesWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/workspaces/1/members/2/unlock"),
+            Some(Scope::WorkspacesWrite)
+        );
+        assert_eq!(required_scope(&Method::POST, "/workspaces/1/switch"), None);
+        assert_eq!(required_scope(&Method::POST, "/workspaces/join"), None);
+        assert_eq!(required_scope(&Method::GET, "/tokens"), None);
+        assert_eq!(required_scope(&Method::POST, "/workspaces/1/bots"), None);
+        assert_eq!(required_scope(&Method::GET, "/sessions"), None);
+        assert_eq!(required_scope(&Method::PUT, "/password"), None);
+        assert_eq!(required_scope(&Method::DELETE, "/users/me"), None);
+        assert_eq!(required_scope(&Method::POST, "/users/me/exports"), None);
+        assert_eq!(required_scope(&Method::PUT, "/users/me/blocks/2"), None);
+        assert_eq!(required_scope(&Method::POST, "/email/resend"), None);
+        assert_eq!(required_scope(&Method::POST, "/2fa/totp"), None);
+    }
+
+    #[test]
+    fn destructive_workspace_routes_should_need_admin_scope() {
+        let routes = [
+            (Method::DELETE, "/workspaces/1"),
+            (Method::POST, "/workspaces/1/transfer"),
+            (Method::DELETE, "/workspaces/1/members/2"),
+            (Method::PUT, "/workspaces/1/members/2/role"),
+            (Method::POST, "/workspaces/1/members/2/deactivate"),
+            (Method::POST, "/workspaces/1/members/2/reactivate"),
+            (Method::DELETE, "/workspaces/1/members/2/account"),
+        ];
+        let ctx = AuthContext::with_scopes(1, 1, vec![Scope::WorkspacesWrite]);
+        for (method, path) in routes {
+            let scope = required_scope(&method, path);
+            assert_eq!(scope, Some(Scope::WorkspacesAdmin), "{} {}", method, path);
+            assert!(!ctx.allows(scope.unwrap()), "{} {}", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn workspaces_write_token_should_not_delete_workspace() -> anyhow::Result<()> {
+        async fn handler() -> StatusCode {
+            StatusCode::OK
+        }
+        let app = |scopes: Vec<Scope>| {
+            Router::new()
+                .route("/workspaces/:id", any(handler))
+                .layer(from_fn(verify_scope))
+                .layer(Extension(AuthContext::with_scopes(1, 1, scopes)))
+        };
+        let req = || {
+            Request::builder()
+                .method(Method::DELETE)
+                .uri("/workspaces/1")
+        };
+
+        let res = app(vec![Scope::WorkspacesWrite])
+            .oneshot(req().body(Body::empty())?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = app(vec![Scope::WorkspacesAdmin])
+            .oneshot(req().body(Body::empty())?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        Ok(())
+    }
+}
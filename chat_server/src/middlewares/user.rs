@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::IntoResponse,
+    Extension,
+};
+use chat_core::AuthContext;
+
+use crate::{error::AppError, state::AppState};
+
+// the token only carries the ids, load the user for the handlers
+pub async fn load_user(
+    State(app_state): State<AppState>,
+    Extension(ctx): Extension<AuthContext>,
+    mut request: Request,
+    next: Next,
+) -> Result<impl IntoResponse, AppError> {
+    let member = app_state.load_member(&ctx).await?;
+    if member.needs_two_factor && !allowed_without_two_factor(request.uri().path()) {
+        return Err(AppError::Forbidden(
+            "Workspace requires two-factor authentication".to_string(),
+        ));
+    }
+    request.extensions_mut().insert(member.user);
+    Ok(next.run(request).await)
+}
+
+// routes to set up two-factor authentication or leave the workspace (path without /api)
+fn allowed_without_two_factor(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(
+        segments.as_slice(),
+        ["2fa", ..]
+            | ["logout"]
+            | ["sessions", ..]
+            | ["password"]
+            | ["email", ..]
+            | ["workspaces"]
+            | ["workspaces", _, "switch"]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_without_two_factor_should_work() {
+        assert!(allowed_without_two_factor("/2fa/totp/confirm"));
+        assert!(allowed_without_two_factor("/workspaces"));
+        assert!(allowed_without_two_factor("/workspaces/2/switch"));
+        assert!(!allowed_without_two_factor("/workspaces/2"));
+        assert!(!allowed_without_two_factor("/chats"));
+        assert!(!allowed_without_two_factor("/chats/1/messages"));
+    }
+}
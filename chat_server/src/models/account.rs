@@ -0,0 +1,580 @@
+use std::{path::PathBuf, str::FromStr};
+
+use chat_core::{AuditAction, UserDeletion, WorkspaceRole};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Postgres, Transaction};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use super::{AuditEvent, ChatFile};
+use crate::{error::AppError, lockout::account_key, state::AppState};
+
+const MAX_DELETION_ATTEMPTS: i32 = 3;
+const DELETED_USER_NAME: &str = "Deleted user";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DeleteAccount {
+    // empty the content of the messages sent by the user, they are kept as is otherwise
+    #[serde(default)]
+    pub scrub_messages: bool,
+    // remove the files the user uploaded, unless others sent them as well
+    #[serde(default)]
+    pub scrub_files: bool,
+}
+
+impl AppState {
+    // deactivated users keep their memberships and messages but could not sign in,
+    // their sessions and tokens are revoked at once
+    pub async fn deactivate_member(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+    ) -> Result<(), AppError> {
+        self.verify_account_manager(ws_id, user_id, member_id)
+            .await?;
+        let updated = sqlx::query(
+            r#"
+                UPDATE users SET deactivated_at = NOW()
+                WHERE id = $1 AND deactivated_at IS NULL AND deleted_at IS NULL"#,
+        )
+        .bind(member_id as i64)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(());
+        }
+        self.sign_out_everywhere(member_id).await?;
+        self.record_audit(AuditEvent {
+            ws_id: Some(ws_id as i64),
+            user_id: Some(member_id as i64),
+            actor_id: Some(user_id as i64),
+            action: AuditAction::UserDeactivated,
+            ip: None,
+            details: json!({}),
+        })
+        .await
+    }
+
+    pub async fn reactivate_member(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+    ) -> Result<(), AppError> {
+        self.verify_account_manager(ws_id, user_id, member_id)
+            .await?;
+        let updated = sqlx::query(
+            r#"
+                UPDATE users SET deactivated_at = NULL
+                WHERE id = $1 AND deactivated_at IS NOT NULL AND deleted_at IS NULL"#,
+        )
+        .bind(member_id as i64)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(());
+        }
+        self.users.forget_user(member_id as i64);
+        self.record_audit(AuditEvent {
+            ws_id: Some(ws_id as i64),
+            user_id: Some(member_id as i64),
+            actor_id: Some(user_id as i64),
+            action: AuditAction::UserReactivated,
+            ip: None,
+            details: json!({}),
+        })
+        .await
+    }
+
+    // the account is deactivated at once and anonymized by the background job
+    pub async fn delete_member_account(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+        input: DeleteAccount,
+    ) -> Result<UserDeletion, AppError> {
+        self.verify_account_manager(ws_id, user_id, member_id)
+            .await?;
+        self.request_user_deletion(Some(ws_id), user_id, member_id, input)
+            .await
+    }
+
+    pub async fn delete_own_account(
+        &self,
+        user_id: u64,
+        input: DeleteAccount,
+    ) -> Result<UserDeletion, AppError> {
+        self.verify_not_owner(user_id).await?;
+        self.request_user_deletion(None, user_id, user_id, input)
+            .await
+    }
+
+    pub async fn get_user_deletion(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        id: u64,
+    ) -> Result<UserDeletion, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let deletion = sqlx::query_as("SELECT * FROM user_deletions WHERE id = $1 AND ws_id = $2")
+            .bind(id as i64)
+            .bind(ws_id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        deletion.ok_or_else(|| AppError::NotFound(format!("Deletion {} not found", id)))
+    }
+
+    // run the pending deletions one at a time, the other servers skip the ones in progress
+    pub async fn process_user_deletions(&self) -> Result<usize, AppError> {
+        let mut processed = 0;
+        loop {
+            let job: Option<UserDeletion> = sqlx::query_as(
+                r#"
+                    UPDATE user_deletions
+                    SET status = 'running', started_at = NOW(), attempts = attempts + 1
+                    WHERE id = (
+                        SELECT id FROM user_deletions
+                        WHERE status = 'pending'
+                            OR (status = 'failed' AND attempts < $1)
+                            OR (status = 'running' AND started_at < NOW() - INTERVAL '1 hour')
+                        ORDER BY id
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    RETURNING *"#,
+            )
+            .bind(MAX_DELETION_ATTEMPTS)
+            .fetch_optional(&self.pool)
+            .await?;
+            let Some(job) = job else {
+                return Ok(processed);
+            };
+            processed += 1;
+            let (status, error, action, details) = match self.delete_user_data(&job).await {
+                Ok(details) => ("done", None, AuditAction::UserDeleted, details),
+                Err(e) => {
+                    warn!("Failed to delete user {}: {}", job.user_id, e);
+                    let details = json!({ "error": e.to_string(), "attempts": job.attempts });
+                    (
+                        "failed",
+                        Some(e.to_string()),
+                        AuditAction::UserDeletionFailed,
+                        details,
+                    )
+                }
+            };
+            sqlx::query(
+                r#"
+                    UPDATE user_deletions
+                    SET status = $2::deletion_status, error = $3,
+                        finished_at = CASE WHEN $2 = 'done' THEN NOW() END
+                    WHERE id = $1"#,
+            )
+            .bind(job.id)
+            .bind(status)
+            .bind(error)
+            .execute(&self.pool)
+            .await?;
+            self.record_audit(AuditEvent {
+                ws_id: job.ws_id,
+                user_id: Some(job.user_id),
+                actor_id: Some(job.requested_by),
+                action,
+                ip: None,
+                details,
+            })
+            .await?;
+        }
+    }
+
+    async fn request_user_deletion(
+        &self,
+        ws_id: Option<u64>,
+        user_id: u64,
+        member_id: u64,
+        input: DeleteAccount,
+    ) -> Result<UserDeletion, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let pending: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM user_deletions WHERE user_id = $1 AND status <> 'failed')",
+        )
+        .bind(member_id as i64)
+        .fetch_one(&mut *tx)
+        .await?;
+        if pending {
+            return Err(AppError::Workspace(
+                "The account is already being deleted".to_string(),
+            ));
+        }
+        sqlx::query(
+            "UPDATE users SET deactivated_at = COALESCE(deactivated_at, NOW()) WHERE id = $1",
+        )
+        .bind(member_id as i64)
+        .execute(&mut *tx)
+        .await?;
+        let deletion: UserDeletion = sqlx::query_as(
+            r#"
+                INSERT INTO user_deletions (user_id, ws_id, requested_by, scrub_messages, scrub_files)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING *"#,
+        )
+        .bind(member_id as i64)
+        .bind(ws_id.map(|id| id as i64))
+        .bind(user_id as i64)
+        .bind(input.scrub_messages)
+        .bind(input.scrub_files)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.sign_out_everywhere(member_id).await?;
+        self.record_audit(AuditEvent {
+            ws_id: deletion.ws_id,
+            user_id: Some(member_id as i64),
+            actor_id: Some(user_id as i64),
+            action: AuditAction::UserDeletionRequested,
+            ip: None,
+            details: json!({
+                "deletion_id": deletion.id,
+                "scrub_messages": deletion.scrub_messages,
+                "scrub_files": deletion.scrub_files,
+            }),
+        })
+        .await?;
+        Ok(deletion)
+    }
+
+    // admins only manage the accounts of their workspaces: the member must not belong
+    // to a workspace they do not administer, nor own a workspace
+    async fn verify_account_manager(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+    ) -> Result<(), AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        if user_id == member_id {
+            return Err(AppError::Workspace(
+                "Admins could not deactivate or delete themselves".to_string(),
+            ));
+        }
+        if !self.is_workspace_member(ws_id, member_id).await? {
+            return Err(AppError::NotFound(format!(
+                "User {} is not a member of the workspace",
+                member_id
+            )));
+        }
+        self.verify_not_owner(member_id).await?;
+        let unmanaged: bool = sqlx::query_scalar(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM workspace_members m
+                    JOIN workspaces w ON w.id = m.ws_id AND w.deleted_at IS NULL
+                    LEFT JOIN workspace_members a ON a.ws_id = m.ws_id AND a.user_id = $2
+                    WHERE m.user_id = $1
+                        AND w.owner_id <> $2
+                        AND a.role IS DISTINCT FROM $3
+                )"#,
+        )
+        .bind(member_id as i64)
+        .bind(user_id as i64)
+        .bind(WorkspaceRole::Admin)
+        .fetch_one(&self.pool)
+        .await?;
+        if unmanaged {
+            return Err(AppError::Forbidden(
+                "The user belongs to workspaces you do not administer".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn verify_not_owner(&self, user_id: u64) -> Result<(), AppError> {
+        let owner: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM workspaces WHERE owner_id = $1 AND deleted_at IS NULL)",
+        )
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        if owner {
+            return Err(AppError::Workspace(
+                "Transfer the workspaces owned by the user first".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // revoke the personal access tokens and sessions of the user, the notify server
+    // drops the user and closes the event streams
+    async fn sign_out_everywhere(&self, user_id: u64) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE access_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(user_id as i64)
+        .execute(&self.pool)
+        .await?;
+        self.revoke_other_sessions(user_id, None).await?;
+        self.users.forget_user(user_id as i64);
+        sqlx::query("SELECT pg_notify('user_deactivated', $1)")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_user_data(&self, job: &UserDeletion) -> Result<Value, AppError> {
+        let email: String = sqlx::query_scalar("SELECT email FROM users WHERE id = $1")
+            .bind(job.user_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        // files are removed once the transaction is committed, a failed attempt keeps them
+        let mut tx = self.pool.begin().await?;
+        let mut files = if job.scrub_files {
+            self.user_files(&mut tx, job.user_id).await?
+        } else {
+            vec![]
+        };
+        // archives of the data exports go with the account
+        let exports: Vec<(i64, Option<String>)> = sqlx::query_as(
+            "DELETE FROM data_exports WHERE user_id = $1 RETURNING ws_id, file_name",
+        )
+        .bind(job.user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        files.extend(exports.into_iter().filter_map(|(ws_id, file_name)| {
+            file_name.map(|file_name| self.export_dir(ws_id).join(file_name))
+        }));
+        if job.scrub_messages {
+            sqlx::query(
+                r#"
+                    UPDATE messages
+                    SET content = '', content_html = '', content_text = '', previews = '[]'
+                    WHERE sender_id = $1"#,
+            )
+            .bind(job.user_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        if job.scrub_files {
+            sqlx::query("UPDATE messages SET files = '{}' WHERE sender_id = $1")
+                .bind(job.user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let chats = sqlx::query(
+            "UPDATE chats SET members = array_remove(members, $1) WHERE $1 = ANY(members)",
+        )
+        .bind(job.user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        delete_personal_data(&mut tx, job.user_id).await?;
+        sqlx::query(
+            r#"
+                UPDATE users
+                SET fullname = $2, email = $3, password_hash = NULL,
+                    display_name = NULL, avatar_url = NULL, title = NULL, timezone = NULL,
+                    status_text = NULL, status_emoji = NULL, status_expires_at = NULL,
+                    deactivated_at = COALESCE(deactivated_at, NOW()), deleted_at = NOW()
+                WHERE id = $1"#,
+        )
+        .bind(job.user_id)
+        .bind(DELETED_USER_NAME)
+        .bind(format!("deleted-{}@deleted.invalid", job.user_id))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let mut files_removed = 0;
+        for path in files {
+            if path.exists() {
+                tokio::fs::remove_file(path).await?;
+                files_removed += 1;
+            }
+        }
+        self.signin_throttle.clear(&account_key(&email)).await?;
+        self.users.forget_user(job.user_id);
+        Ok(json!({
+            "deletion_id": job.id,
+            "scrub_messages": job.scrub_messages,
+            "scrub_files": job.scrub_files,
+            "files_removed": files_removed,
+            "chats_left": chats,
+        }))
+    }
+
+    // files of the messages of the user and the avatar, unless others use them as well
+    async fn user_files(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: i64,
+    ) -> Result<Vec<PathBuf>, AppError> {
+        let files: Vec<String> = sqlx::query_scalar(
+            r#"
+                SELECT DISTINCT f
+                FROM (
+                    SELECT unnest(files) AS f FROM messages WHERE sender_id = $1
+                    UNION
+                    SELECT avatar_url FROM users WHERE id = $1 AND avatar_url IS NOT NULL
+                ) uploads
+                WHERE NOT EXISTS (SELECT 1 FROM messages m WHERE m.sender_id <> $1 AND f = ANY(m.files))
+                    AND NOT EXISTS (SELECT 1 FROM users u WHERE u.id <> $1 AND u.avatar_url = f)"#,
+        )
+        .bind(user_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        let paths = files
+            .iter()
+            .filter_map(|file| ChatFile::from_str(file).ok())
+            .map(|file| file.path(&self.config.server.base_dir))
+            .collect();
+        Ok(paths)
+    }
+}
+
+// the credentials, memberships and bookmarks of the user, messages and votes are kept
+async fn delete_personal_data(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i64,
+) -> Result<(), AppError> {
+    for table in [
+        "workspace_members",
+        "pending_workspace_members",
+        "saved_messages",
+        "reminders",
+        "refresh_tokens",
+        "sessions",
+        "password_resets",
+        "email_verifications",
+        "user_totp",
+        "recovery_codes",
+        "two_factor_challenges",
+        "user_identities",
+        "scim_users",
+        "user_group_members",
+        "user_blocks",
+    ] {
+        sqlx::query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    // the blocks of others on the user go as well
+    sqlx::query("DELETE FROM user_blocks WHERE blocked_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{SigninUser, SignupUser};
+    use anyhow::Result;
+    use chat_core::{AuthContext, DeletionStatus};
+
+    #[tokio::test]
+    async fn deactivate_member_should_block_signin() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("Alice", "alice@acme.org", "hunter42");
+        let user = state.create_user(&input).await?;
+        let id = user.id as u64;
+        let session_id = state.create_session(id, &Default::default()).await?;
+
+        state.deactivate_member(0, 0, id).await?;
+        let ret = state.load_user(&AuthContext::new(user.id, 0)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let signin = SigninUser::new("alice@acme.org", "hunter42", 0);
+        assert!(state.verify_signin(&signin, None).await?.is_some());
+        let ret = state.create_session(id, &Default::default()).await;
+        assert!(matches!(ret, Err(AppError::LoginFailed(_))));
+        assert!(state
+            .revoked
+            .is_revoked(&chat_core::Revoked::Session(session_id)));
+
+        state.reactivate_member(0, 0, id).await?;
+        state.create_session(id, &Default::default()).await?;
+        assert_eq!(
+            state.load_user(&AuthContext::new(user.id, 0)).await?.id,
+            user.id
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deactivate_member_should_be_managed_by_admins() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // members could not deactivate each other
+        let ret = state.deactivate_member(0, 1, 2).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        // nor the owner be deactivated
+        let ret = state.delete_own_account(0, DeleteAccount::default()).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        // members of a workspace the admin does not administer are out of reach
+        let ws = state.create_workspace("other", 3).await?;
+        state.add_workspace_member(ws.id as u64, 2).await?;
+        let ret = state.deactivate_member(0, 0, 2).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_account_should_anonymize_user() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        sqlx::query("INSERT INTO messages (chat_id, sender_id, content) VALUES (0, 1, 'secret')")
+            .execute(&state.pool)
+            .await?;
+        state.block_user(2, 1).await?;
+        let archive = state.export_dir(0).join("export.zip");
+        std::fs::create_dir_all(archive.parent().unwrap())?;
+        std::fs::write(&archive, b"zip")?;
+        sqlx::query(
+            r#"
+                INSERT INTO data_exports (user_id, ws_id, status, file_name, expires_at)
+                VALUES (1, 0, 'ready', 'export.zip', NOW() + INTERVAL '1 day')"#,
+        )
+        .execute(&state.pool)
+        .await?;
+        let input = DeleteAccount {
+            scrub_messages: true,
+            scrub_files: true,
+        };
+        let deletion = state.delete_member_account(0, 0, 1, input.clone()).await?;
+        assert_eq!(deletion.status, DeletionStatus::Pending);
+        let ret = state.delete_member_account(0, 0, 1, input).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        assert_eq!(state.process_user_deletions().await?, 1);
+        let deletion = state.get_user_deletion(0, 0, deletion.id as u64).await?;
+        assert_eq!(deletion.status, DeletionStatus::Done);
+
+        let user = state.get_chat_user(1).await?;
+        assert_eq!(user.fullname, DELETED_USER_NAME);
+        assert!(state.find_user_by_email("test1@none.org").await?.is_none());
+        let chat = state.get_chat_by_id(0).await?.unwrap();
+        assert!(!chat.members.contains(&1));
+        let content: String =
+            sqlx::query_scalar("SELECT content FROM messages WHERE sender_id = 1")
+                .fetch_one(&state.pool)
+                .await?;
+        assert!(content.is_empty());
+        assert!(!state.is_workspace_member(0, 1).await?);
+        assert!(state.list_blocked_users(2).await?.is_empty());
+        assert!(!archive.exists());
+        let exports: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM data_exports WHERE user_id = 1")
+                .fetch_one(&state.pool)
+                .await?;
+        assert_eq!(exports, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use chat_core::{AuditAction, AuditLog};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+const DEFAULT_AUDIT_LIMIT: u64 = 50;
+const MAX_AUDIT_LIMIT: u64 = 200;
+
+// an event to record, actor_id is None for the events of the server itself
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub ws_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub actor_id: Option<i64>,
+    pub action: AuditAction,
+    pub ip: Option<String>,
+    pub details: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ListAuditLogs {
+    // entries older than this id
+    #[serde(default)]
+    pub last_id: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+impl AppState {
+    pub async fn record_audit(&self, event: AuditEvent) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+                INSERT INTO audit_logs (ws_id, user_id, actor_id, action, ip, details)
+                VALUES ($1, $2, $3, $4, $5, $6)"#,
+        )
+        .bind(event.ws_id)
+        .bind(event.user_id)
+        .bind(event.actor_id)
+        .bind(event.action)
+        .bind(event.ip)
+        .bind(event.details)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // events of the workspace and the account events of its members, the newest first
+    pub async fn list_audit_logs(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: ListAuditLogs,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let limit = input
+            .limit
+            .unwrap_or(DEFAULT_AUDIT_LIMIT)
+            .clamp(1, MAX_AUDIT_LIMIT);
+        let logs = sqlx::query_as(
+            r#"
+                SELECT id, ws_id, user_id, actor_id, action, ip, details, created_at
+                FROM audit_logs
+                WHERE (ws_id = $1
+                    OR (ws_id IS NULL AND user_id IN (SELECT user_id FROM workspace_members WHERE ws_id = $1)))
+                    AND ($2::BIGINT IS NULL OR id < $2)
+                ORDER BY id DESC
+                LIMIT $3"#,
+        )
+        .bind(ws_id as i64)
+        .bind(input.last_id.map(|id| id as i64))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(logs)
+    }
+}
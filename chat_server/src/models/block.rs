@@ -0,0 +1,229 @@
+use chat_core::{ChatUser, DmPolicy, CHAT_USER_COLUMNS};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct PrivacySettings {
+    // who could start a direct message with the user
+    pub dm_policy: DmPolicy,
+}
+
+impl AppState {
+    // only users sharing a workspace could be blocked, blocking twice is a no-op
+    pub async fn block_user(&self, user_id: u64, blocked_id: u64) -> Result<(), AppError> {
+        if user_id == blocked_id {
+            return Err(AppError::Block("You could not block yourself".to_string()));
+        }
+        if !self.share_workspace(user_id, blocked_id).await? {
+            return Err(AppError::NotFound(format!("User {} not found", blocked_id)));
+        }
+        sqlx::query(
+            "INSERT INTO user_blocks (user_id, blocked_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(user_id as i64)
+        .bind(blocked_id as i64)
+        .execute(&self.pool)
+        .await?;
+        self.notify_user_blocks(user_id, blocked_id, true).await
+    }
+
+    pub async fn unblock_user(&self, user_id: u64, blocked_id: u64) -> Result<(), AppError> {
+        let ret = sqlx::query("DELETE FROM user_blocks WHERE user_id = $1 AND blocked_id = $2")
+            .bind(user_id as i64)
+            .bind(blocked_id as i64)
+            .execute(&self.pool)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "User {} is not blocked",
+                blocked_id
+            )));
+        }
+        self.notify_user_blocks(user_id, blocked_id, false).await
+    }
+
+    pub async fn list_blocked_users(&self, user_id: u64) -> Result<Vec<ChatUser>, AppError> {
+        let sql = format!(
+            r#"
+                SELECT {}
+                FROM user_blocks b
+                JOIN users u ON u.id = b.blocked_id
+                WHERE b.user_id = $1
+                ORDER BY b.created_at DESC, u.id"#,
+            CHAT_USER_COLUMNS
+        );
+        let users = sqlx::query_as(&sql)
+            .bind(user_id as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(users)
+    }
+
+    pub async fn get_privacy_settings(&self, user_id: u64) -> Result<PrivacySettings, AppError> {
+        let settings = sqlx::query_as("SELECT dm_policy FROM users WHERE id = $1")
+            .bind(user_id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        settings.ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))
+    }
+
+    pub async fn update_privacy_settings(
+        &self,
+        user_id: u64,
+        input: PrivacySettings,
+    ) -> Result<PrivacySettings, AppError> {
+        let settings =
+            sqlx::query_as("UPDATE users SET dm_policy = $2 WHERE id = $1 RETURNING dm_policy")
+                .bind(user_id as i64)
+                .bind(input.dm_policy)
+                .fetch_optional(&self.pool)
+                .await?;
+        settings.ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))
+    }
+
+    // neither side blocked the other and the dm policy of the recipient lets the sender in
+    pub async fn verify_direct_message(
+        &self,
+        sender_id: i64,
+        members: &[i64],
+    ) -> Result<(), AppError> {
+        let Some(recipient_id) = members.iter().find(|id| **id != sender_id) else {
+            return Ok(());
+        };
+        let allowed: Option<bool> = sqlx::query_scalar(
+            r#"
+                SELECT NOT EXISTS (
+                        SELECT 1 FROM user_blocks
+                        WHERE (user_id = $1 AND blocked_id = $2) OR (user_id = $2 AND blocked_id = $1)
+                    )
+                    AND (u.dm_policy = 'anyone' OR EXISTS (
+                        SELECT 1 FROM chats
+                        WHERE type IN ('private_channel', 'public_channel')
+                            AND members @> ARRAY[$1, $2]::BIGINT[]
+                    ))
+                FROM users u
+                WHERE u.id = $2"#,
+        )
+        .bind(sender_id)
+        .bind(recipient_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        // unknown members are reported by the validation of the chat
+        if allowed == Some(false) {
+            return Err(AppError::Forbidden(
+                "Direct messages with this user are not allowed".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // the notify server drops the events of blocked users
+    async fn notify_user_blocks(
+        &self,
+        user_id: u64,
+        blocked_id: u64,
+        blocked: bool,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+                SELECT pg_notify('user_blocks', json_build_object(
+                    'user_id', $1::bigint,
+                    'blocked_id', $2::bigint,
+                    'blocked', $3
+                )::text)"#,
+        )
+        .bind(user_id as i64)
+        .bind(blocked_id as i64)
+        .bind(blocked)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateMessage, ListMessage};
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn block_user_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ret = state.block_user(1, 1).await;
+        assert!(matches!(ret, Err(AppError::Block(_))));
+
+        state.block_user(1, 2).await?;
+        state.block_user(1, 2).await?;
+        let users = state.list_blocked_users(1).await?;
+        assert_eq!(users.iter().map(|u| u.id).collect::<Vec<_>>(), vec![2]);
+
+        // both sides could not start a direct message
+        let ret = state.verify_direct_message(2, &[1, 2]).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let ret = state.verify_direct_message(1, &[2, 1]).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        state.unblock_user(1, 2).await?;
+        assert!(state.list_blocked_users(1).await?.is_empty());
+        state.verify_direct_message(2, &[1, 2]).await?;
+        let ret = state.unblock_user(1, 2).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blocked_messages_should_be_hidden() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            ..Default::default()
+        };
+        let message = state.create_message(input, 1, 2).await?;
+        state.block_user(1, 2).await?;
+        let input = ListMessage {
+            last_id: None,
+            limit: 100,
+        };
+        let messages = state.list_message(1, 1, input.clone()).await?;
+        assert!(messages.iter().all(|m| m.sender_id != 2));
+        let messages = state.list_message(1, 3, input).await?;
+        assert!(messages.iter().any(|m| m.id == message.id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dm_policy_should_require_shared_channels() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = crate::models::SignupUser::new("Newbie", "newbie@acme.org", "123456");
+        let id = state.create_user(&input).await?.id;
+        let settings = state
+            .update_privacy_settings(
+                id as u64,
+                PrivacySettings {
+                    dm_policy: DmPolicy::SharedChannels,
+                },
+            )
+            .await?;
+        assert_eq!(settings.dm_policy, DmPolicy::SharedChannels);
+
+        let ret = state.verify_direct_message(1, &[1, id]).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        // the policy only applies to the recipient
+        state.verify_direct_message(id, &[1, id]).await?;
+
+        state
+            .create_chat(crate::models::CreateChat {
+                name: Some("welcome".to_string()),
+                members: vec![1, id],
+                ws_id: 0,
+                public: true,
+            })
+            .await?;
+        state.verify_direct_message(1, &[1, id]).await?;
+        Ok(())
+    }
+}
@@ -111,7 +111,7 @@ impl AppState {
     pub async fn get_chat_by_id(&self, id: i64) -> Result<Option<Chat>, AppError> {
         let chat = sqlx::query_as(
             r#"
-                    SELECT id, ws_id, name, type, members, created_at
+                    SELECT id, ws_id, name, type, members, topic, created_at
                     FROM chats
                     WHERE id = $1"#,
         )
@@ -121,6 +121,33 @@ impl AppState {
         Ok(chat)
     }
 
+    pub async fn update_chat_topic(&self, id: i64, topic: &str) -> Result<Chat, AppError> {
+        let chat = sqlx::query_as("UPDATE chats SET topic = $1 WHERE id = $2 RETURNING *")
+            .bind(topic)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        chat.ok_or_else(|| AppError::NotFound(format!("Chat with id {} not found", id)))
+    }
+
+    // append the users to the members of the chat, existing members are kept as is
+    pub async fn add_chat_members(&self, id: i64, user_ids: &[i64]) -> Result<Chat, AppError> {
+        let chat = sqlx::query_as(
+            r#"
+                    UPDATE chats
+                    SET members = members || ARRAY(
+                        SELECT unnest($1::BIGINT[]) EXCEPT SELECT unnest(members) ORDER BY 1
+                    )
+                    WHERE id = $2
+                    RETURNING *"#,
+        )
+        .bind(user_ids)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        chat.ok_or_else(|| AppError::NotFound(format!("Chat with id {} not found", id)))
+    }
+
     pub async fn is_chat_member(&self, chat_id: u64, user_id: u64) -> Result<bool, AppError> {
         let chat = sqlx::query(
             r#"
@@ -0,0 +1,192 @@
+use chat_core::WorkspaceCommand;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState, unfurl::resolve_public_addrs};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct CreateCommand {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl AppState {
+    pub async fn create_workspace_command(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: CreateCommand,
+    ) -> Result<WorkspaceCommand, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+
+        let name = input.name.trim_start_matches('/').to_lowercase();
+        if name.is_empty()
+            || name.len() > 32
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(AppError::Command(format!(
+                "Invalid command name: {}",
+                input.name
+            )));
+        }
+        if self.commands.contains(&name) {
+            return Err(AppError::Command(format!(
+                "/{} is a built-in command",
+                name
+            )));
+        }
+        let url = Url::parse(&input.url)
+            .map_err(|_| AppError::Command(format!("Invalid command url: {}", input.url)))?;
+        // the webhook must not reach the internal network of the server
+        resolve_public_addrs(&url, self.config.commands.allow_private_ips)
+            .await
+            .map_err(|e| AppError::Command(format!("Invalid command url: {}", e)))?;
+        if self.find_workspace_command(ws_id, &name).await?.is_some() {
+            return Err(AppError::Command(format!("/{} already exists", name)));
+        }
+
+        let command = sqlx::query_as(
+            r#"
+                    INSERT INTO workspace_commands (ws_id, name, url, description, created_by)
+                    VALUES ($1, $2, $3, $4, $5)
+                    RETURNING *"#,
+        )
+        .bind(ws_id as i64)
+        .bind(name)
+        .bind(url.as_str())
+        .bind(input.description)
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(command)
+    }
+
+    pub async fn delete_workspace_command(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        id: u64,
+    ) -> Result<(), AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let ret = sqlx::query("DELETE FROM workspace_commands WHERE id = $1 AND ws_id = $2")
+            .bind(id as i64)
+            .bind(ws_id as i64)
+            .execute(&self.pool)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Command with id {} not found",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    pub async fn fetch_workspace_commands(
+        &self,
+        ws_id: u64,
+    ) -> Result<Vec<WorkspaceCommand>, AppError> {
+        let commands =
+            sqlx::query_as("SELECT * FROM workspace_commands WHERE ws_id = $1 ORDER BY name")
+                .bind(ws_id as i64)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(commands)
+    }
+
+    pub async fn find_workspace_command(
+        &self,
+        ws_id: u64,
+        name: &str,
+    ) -> Result<Option<WorkspaceCommand>, AppError> {
+        let command =
+            sqlx::query_as("SELECT * FROM workspace_commands WHERE ws_id = $1 AND name = $2")
+                .bind(ws_id as i64)
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_command(name: &str) -> CreateCommand {
+        CreateCommand {
+            name: name.to_string(),
+            // an ip literal is checked without resolving a domain
+            url: "https://93.184.215.14/hook".to_string(),
+            description: "deploy the app".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn workspace_command_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // workspace 1 is owned by user 0
+        let command = state
+            .create_workspace_command(1, 0, create_command("/Deploy"))
+            .await?;
+        assert_eq!(command.name, "deploy");
+
+        let commands = state.fetch_workspace_commands(1).await?;
+        assert_eq!(commands.len(), 1);
+        assert!(state.find_workspace_command(1, "deploy").await?.is_some());
+        assert!(state.find_workspace_command(2, "deploy").await?.is_none());
+
+        state
+            .delete_workspace_command(1, 0, command.id as u64)
+            .await?;
+        assert!(state.fetch_workspace_commands(1).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_workspace_command_should_error() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ret = state
+            .create_workspace_command(1, 1, create_command("deploy"))
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let ret = state
+            .create_workspace_command(1, 0, create_command("me"))
+            .await;
+        assert!(matches!(ret, Err(AppError::Command(_))));
+
+        let ret = state
+            .create_workspace_command(1, 0, create_command("de ploy"))
+            .await;
+        assert!(matches!(ret, Err(AppError::Command(_))));
+
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/hook",
+            "ftp://93.184.215.14/hook",
+        ] {
+            let input = CreateCommand {
+                url: url.to_string(),
+                ..create_command("deploy")
+            };
+            let ret = state.create_workspace_command(1, 0, input).await;
+            assert!(matches!(ret, Err(AppError::Command(_))), "{}", url);
+        }
+
+        state
+            .create_workspace_command(1, 0, create_command("deploy"))
+            .await?;
+        let ret = state
+            .create_workspace_command(1, 0, create_command("deploy"))
+            .await;
+        assert!(matches!(ret, Err(AppError::Command(_))));
+        Ok(())
+    }
+}
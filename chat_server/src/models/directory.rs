@@ -0,0 +1,251 @@
+use chat_core::{ChatUser, CHAT_USER_COLUMNS};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+const DEFAULT_DIRECTORY_LIMIT: u64 = 50;
+const MAX_DIRECTORY_LIMIT: u64 = 200;
+// lower than the default 0.6 of pg_trgm to tolerate swapped letters
+const FUZZY_THRESHOLD: &str = "0.3";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatusFilter {
+    // members of the workspace
+    #[default]
+    Active,
+    // deactivated accounts and users deprovisioned by scim
+    Deactivated,
+    All,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ListUsers {
+    // prefix or fuzzy match of the name or email
+    #[serde(default)]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub status: UserStatusFilter,
+    // only bots if true, only people if false
+    #[serde(default)]
+    pub bot: Option<bool>,
+    // members of the chat, the chat must be one of the user
+    #[serde(default)]
+    pub in_chat: Option<u64>,
+    // users not in the chat yet, to pick the ones to invite
+    #[serde(default)]
+    pub not_in_chat: Option<u64>,
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+impl AppState {
+    // best matches first when searching, by name otherwise, the id breaks the ties
+    pub async fn search_chat_users(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: ListUsers,
+    ) -> Result<Vec<ChatUser>, AppError> {
+        let in_chat = match input.in_chat {
+            Some(chat_id) => Some(self.chat_members_of(ws_id, user_id, chat_id).await?),
+            None => None,
+        };
+        let not_in_chat = match input.not_in_chat {
+            Some(chat_id) => Some(self.chat_members_of(ws_id, user_id, chat_id).await?),
+            None => None,
+        };
+        let q = input.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+        let active = match input.status {
+            UserStatusFilter::Active => Some(true),
+            UserStatusFilter::Deactivated => Some(false),
+            UserStatusFilter::All => None,
+        };
+        let limit = input
+            .limit
+            .unwrap_or(DEFAULT_DIRECTORY_LIMIT)
+            .clamp(1, MAX_DIRECTORY_LIMIT);
+
+        let sql = format!(
+            r#"
+                WITH directory AS (
+                    SELECT user_id, TRUE AS active FROM workspace_members WHERE ws_id = $1
+                    UNION
+                    SELECT user_id, FALSE FROM scim_users su
+                    WHERE su.ws_id = $1 AND NOT EXISTS (
+                        SELECT 1 FROM workspace_members wm WHERE wm.ws_id = $1 AND wm.user_id = su.user_id
+                    )
+                ), matches AS (
+                    SELECT u.*, d.active AND u.deactivated_at IS NULL AS active,
+                        u.fullname ILIKE $3 || '%' OR u.fullname ILIKE '% ' || $3 || '%'
+                            OR u.email ILIKE $3 || '%' AS prefix
+                    FROM directory d
+                    JOIN users u ON u.id = d.user_id AND u.deleted_at IS NULL
+                )
+                SELECT {}
+                FROM matches u
+                WHERE ($2::TEXT IS NULL OR u.prefix OR $2 <% u.fullname OR $2 <% u.email)
+                    AND ($4::BOOLEAN IS NULL OR u.active = $4)
+                    AND ($5::BOOLEAN IS NULL OR u.is_bot = $5)
+                    AND ($6::BIGINT[] IS NULL OR u.id = ANY($6))
+                    AND ($7::BIGINT[] IS NULL OR NOT u.id = ANY($7))
+                ORDER BY
+                    CASE WHEN $2::TEXT IS NULL OR u.prefix THEN 0 ELSE 1 END,
+                    CASE WHEN $2::TEXT IS NULL THEN 0
+                        ELSE GREATEST(word_similarity($2, u.fullname), word_similarity($2, u.email))
+                    END DESC,
+                    lower(u.fullname), u.id
+                LIMIT $8 OFFSET $9"#,
+            CHAT_USER_COLUMNS
+        );
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)")
+            .bind(FUZZY_THRESHOLD)
+            .execute(&mut *tx)
+            .await?;
+        let users = sqlx::query_as(&sql)
+            .bind(ws_id as i64)
+            .bind(q)
+            .bind(q.map(escape_like).unwrap_or_default())
+            .bind(active)
+            .bind(input.bot)
+            .bind(in_chat)
+            .bind(not_in_chat)
+            .bind(limit as i64)
+            .bind(input.offset.unwrap_or_default() as i64)
+            .fetch_all(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(users)
+    }
+
+    // chats of other workspaces or the user is not in are not found
+    async fn chat_members_of(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        chat_id: u64,
+    ) -> Result<Vec<i64>, AppError> {
+        match self.get_chat_by_id(chat_id as i64).await? {
+            Some(chat)
+                if chat.ws_id == ws_id as i64 && chat.members.contains(&(user_id as i64)) =>
+            {
+                Ok(chat.members)
+            }
+            _ => Err(AppError::NotFound(format!(
+                "Chat with id {} not found",
+                chat_id
+            ))),
+        }
+    }
+}
+
+// the query is matched literally
+fn escape_like(q: &str) -> String {
+    q.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    fn ids(users: &[ChatUser]) -> Vec<i64> {
+        users.iter().map(|u| u.id).collect()
+    }
+
+    #[test]
+    fn escape_like_should_work() {
+        assert_eq!(escape_like("50%_off\\"), "50\\%\\_off\\\\");
+    }
+
+    #[tokio::test]
+    async fn search_chat_users_should_match_prefix_and_typos() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        sqlx::query("UPDATE users SET fullname = 'Alice Wonderland' WHERE id = 1")
+            .execute(&state.pool)
+            .await?;
+        sqlx::query("UPDATE users SET fullname = 'Bob Builder' WHERE id = 2")
+            .execute(&state.pool)
+            .await?;
+
+        let search = |q: &str| ListUsers {
+            q: Some(q.to_string()),
+            ..Default::default()
+        };
+        let users = state.search_chat_users(0, 1, search("ali")).await?;
+        assert_eq!(ids(&users), vec![1]);
+        let users = state.search_chat_users(0, 1, search("wonder")).await?;
+        assert_eq!(ids(&users), vec![1]);
+        let users = state.search_chat_users(0, 1, search("Buidler")).await?;
+        assert_eq!(ids(&users), vec![2]);
+        let users = state.search_chat_users(0, 1, search("%")).await?;
+        assert!(users.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_chat_users_should_paginate() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let all = state.search_chat_users(0, 1, ListUsers::default()).await?;
+        assert_eq!(all.len(), 6);
+        let input = ListUsers {
+            offset: Some(2),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = state.search_chat_users(0, 1, input).await?;
+        assert_eq!(ids(&page), ids(&all[2..4]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_chat_users_should_filter() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = state
+            .create_chat(crate::models::CreateChat {
+                name: Some("picker".to_string()),
+                members: vec![1, 2, 3],
+                ws_id: 0,
+                public: false,
+            })
+            .await?;
+        let input = ListUsers {
+            in_chat: Some(chat.id as u64),
+            ..Default::default()
+        };
+        let users = state.search_chat_users(0, 1, input).await?;
+        assert_eq!(ids(&users).len(), 3);
+        let input = ListUsers {
+            not_in_chat: Some(chat.id as u64),
+            ..Default::default()
+        };
+        let users = state.search_chat_users(0, 1, input).await?;
+        assert!(ids(&users).iter().all(|id| ![1, 2, 3].contains(id)));
+
+        // the chat must be one of the user
+        let input = ListUsers {
+            in_chat: Some(chat.id as u64),
+            ..Default::default()
+        };
+        let ret = state.search_chat_users(0, 4, input).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        let input = ListUsers {
+            bot: Some(true),
+            ..Default::default()
+        };
+        assert!(state.search_chat_users(0, 1, input).await?.is_empty());
+        let input = ListUsers {
+            status: UserStatusFilter::Deactivated,
+            ..Default::default()
+        };
+        assert!(state.search_chat_users(0, 1, input).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use chat_core::User;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use super::{
+    token::{generate_token, hash_token},
+    workspace::insert_workspace_member,
+};
+use crate::{error::AppError, mailer::Mail, state::AppState};
+
+const VERIFY_TOKEN_TTL_HOURS: i32 = 24;
+// at most one verification mail per user in this time
+const VERIFY_MAIL_INTERVAL_SECS: i32 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VerifyEmail {
+    // the token from the verification mail
+    pub token: String,
+}
+
+impl AppState {
+    pub async fn send_verification_email(&self, user: &User) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let verified: Option<bool> = sqlx::query_scalar(
+            "SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1 FOR UPDATE",
+        )
+        .bind(user.id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        match verified {
+            None => {
+                return Err(AppError::NotFound(format!(
+                    "User with id {} not found",
+                    user.id
+                )))
+            }
+            Some(true) => {
+                return Err(AppError::EmailVerification(
+                    "Email is already verified".to_string(),
+                ))
+            }
+            Some(false) => {}
+        }
+        let recent: Option<i64> = sqlx::query_scalar(
+            r#"
+                SELECT id FROM email_verifications
+                WHERE user_id = $1 AND created_at > NOW() - make_interval(secs => $2)
+                LIMIT 1"#,
+        )
+        .bind(user.id)
+        .bind(VERIFY_MAIL_INTERVAL_SECS as f64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if recent.is_some() {
+            return Err(AppError::TooManyRequests(format!(
+                "Wait {} seconds before requesting another verification mail",
+                VERIFY_MAIL_INTERVAL_SECS
+            )));
+        }
+        // only the latest link works
+        sqlx::query(
+            "UPDATE email_verifications SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+        let token = generate_token();
+        sqlx::query(
+            r#"
+                INSERT INTO email_verifications (user_id, token_hash, expires_at)
+                VALUES ($1, $2, NOW() + make_interval(hours => $3))"#,
+        )
+        .bind(user.id)
+        .bind(hash_token(&token))
+        .bind(VERIFY_TOKEN_TTL_HOURS)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let mail = Mail {
+            to: user.email.clone(),
+            subject: "Verify your email".to_string(),
+            body: format!(
+                "Hi {},\n\nUse the link below to verify your email, it expires in {} hours:\n\n{}/verify-email?token={}\n",
+                user.fullname,
+                VERIFY_TOKEN_TTL_HOURS,
+                self.config.mail.web_url.trim_end_matches('/'),
+                token
+            ),
+        };
+        self.mailer.send(mail).await
+    }
+
+    // the user joins the workspaces and the default channels it was held back from
+    pub async fn verify_email(&self, input: &VerifyEmail) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let user_id: Option<i64> = sqlx::query_scalar(
+            r#"
+                UPDATE email_verifications SET used_at = NOW()
+                WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+                RETURNING user_id"#,
+        )
+        .bind(hash_token(&input.token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Err(AppError::EmailVerification(
+                "Invalid or expired verification token".to_string(),
+            ));
+        };
+        sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let ws_ids: Vec<i64> = sqlx::query_scalar(
+            "DELETE FROM pending_workspace_members WHERE user_id = $1 RETURNING ws_id",
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for ws_id in ws_ids {
+            insert_workspace_member(&mut tx, ws_id as u64, user_id as u64).await?;
+        }
+        sqlx::query(
+            r#"
+                UPDATE chats c
+                SET members = array_append(c.members, $1)
+                FROM workspaces w
+                JOIN workspace_members wm ON wm.ws_id = w.id AND wm.user_id = $1
+                WHERE c.ws_id = w.id
+                    AND (w.settings->>'require_verified_email')::BOOLEAN
+                    AND NOT ($1 = ANY(c.members))
+                    AND c.id IN (
+                        SELECT jsonb_array_elements_text(w.settings->'default_channels')::BIGINT
+                    )"#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // users held back by the email verification policy of the workspace
+    pub async fn find_unverified_users(
+        &self,
+        ws_id: i64,
+        user_ids: &[i64],
+    ) -> Result<Vec<i64>, AppError> {
+        let ids = sqlx::query_scalar(
+            r#"
+                SELECT u.id
+                FROM users u, workspaces w
+                WHERE u.id = ANY($2) AND w.id = $1
+                    AND u.email_verified_at IS NULL
+                    AND (w.settings->>'require_verified_email')::BOOLEAN
+                ORDER BY u.id"#,
+        )
+        .bind(ws_id)
+        .bind(user_ids)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids)
+    }
+
+    pub async fn ensure_email_verified(&self, ws_id: i64, user_id: i64) -> Result<(), AppError> {
+        if self
+            .find_unverified_users(ws_id, &[user_id])
+            .await?
+            .is_empty()
+        {
+            return Ok(());
+        }
+        Err(AppError::Forbidden(
+            "Verify your email before posting or joining channels".to_string(),
+        ))
+    }
+
+    // mailer errors are only logged, the account is created anyway
+    pub async fn send_signup_verification(&self, user: &User) {
+        if let Err(e) = self.send_verification_email(user).await {
+            warn!(
+                "Failed to send verification mail to user {}: {}",
+                user.id, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mailer::FileMailer, models::SignupUser};
+    use chat_core::WorkspaceSettings;
+
+    fn verify_token(mail: &Mail) -> String {
+        let (_, token) = mail.body.split_once("token=").unwrap();
+        token.split_whitespace().next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn email_verification_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let outbox = FileMailer::new(&state.config.mail.outbox_dir);
+        // workspace1 requires verified emails, chat 1 is a default channel
+        let settings = WorkspaceSettings {
+            default_channels: vec![1],
+            require_verified_email: true,
+            ..Default::default()
+        };
+        state.update_workspace_settings(1, 0, settings).await?;
+
+        let user = state
+            .create_user(&SignupUser::new("tom", "tom@123.com", "1qa2ws3ed"))
+            .await?;
+        state.add_workspace_member(1, user.id as u64).await?;
+        assert!(!state.is_chat_member(1, user.id as u64).await?);
+        let ret = state.ensure_email_verified(1, user.id).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        // other workspaces are not affected
+        state.ensure_email_verified(0, user.id).await?;
+        state.ensure_email_verified(1, 1).await?;
+
+        state.send_verification_email(&user).await?;
+        let ret = state.send_verification_email(&user).await;
+        assert!(matches!(ret, Err(AppError::TooManyRequests(_))));
+        let mails = outbox.mails()?;
+        assert_eq!(mails.len(), 1);
+        assert_eq!(mails[0].to, "tom@123.com");
+
+        let ret = state
+            .verify_email(&VerifyEmail {
+                token: "invalid".to_string(),
+            })
+            .await;
+        assert!(matches!(ret, Err(AppError::EmailVerification(_))));
+        let input = VerifyEmail {
+            token: verify_token(&mails[0]),
+        };
+        state.verify_email(&input).await?;
+        state.ensure_email_verified(1, user.id).await?;
+        assert!(state.is_chat_member(1, user.id as u64).await?);
+
+        // the token could only be used once
+        let ret = state.verify_email(&input).await;
+        assert!(matches!(ret, Err(AppError::EmailVerification(_))));
+        let ret = state.send_verification_email(&user).await;
+        assert!(matches!(ret, Err(AppError::EmailVerification(_))));
+        Ok(())
+    }
+}
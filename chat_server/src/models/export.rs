@@ -0,0 +1,330 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chat_core::{DataExport, Message};
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use tracing::warn;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use super::{token::generate_token, ChatFile};
+use crate::{error::AppError, state::AppState};
+
+const EXPORT_DIR: &str = "exports";
+const EXPORT_LINK_TTL: Duration = Duration::hours(24);
+
+impl AppState {
+    // one export at a time, the archive is built in the background
+    pub async fn request_data_export(
+        &self,
+        user_id: u64,
+        ws_id: u64,
+    ) -> Result<DataExport, AppError> {
+        let export: Option<DataExport> = sqlx::query_as(
+            r#"
+                INSERT INTO data_exports (user_id, ws_id)
+                SELECT $1, $2
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM data_exports
+                    WHERE user_id = $1 AND status IN ('pending', 'running')
+                )
+                RETURNING *"#,
+        )
+        .bind(user_id as i64)
+        .bind(ws_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(export) = export else {
+            return Err(AppError::Export(
+                "An export is already in progress".to_string(),
+            ));
+        };
+        let state = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = state.process_data_exports().await {
+                warn!("Failed to process data exports: {}", e);
+            }
+        });
+        Ok(export)
+    }
+
+    pub async fn get_data_export(&self, user_id: u64, id: u64) -> Result<DataExport, AppError> {
+        let export: Option<DataExport> =
+            sqlx::query_as("SELECT * FROM data_exports WHERE id = $1 AND user_id = $2")
+                .bind(id as i64)
+                .bind(user_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        let mut export =
+            export.ok_or_else(|| AppError::NotFound(format!("Export {} not found", id)))?;
+        if let (Some(_), Some(expires_at)) = (&export.file_name, export.expires_at) {
+            if expires_at > Utc::now() {
+                export.download_url = Some(format!("/users/me/exports/{}/download", export.id));
+            }
+        }
+        Ok(export)
+    }
+
+    // archives are only served to their owner until they expire
+    pub async fn get_export_archive(&self, user_id: u64, id: u64) -> Result<PathBuf, AppError> {
+        let archive: Option<(i64, String)> = sqlx::query_as(
+            r#"
+                SELECT ws_id, file_name FROM data_exports
+                WHERE id = $1 AND user_id = $2
+                    AND status = 'ready' AND expires_at > NOW()"#,
+        )
+        .bind(id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((ws_id, file_name)) = archive else {
+            return Err(AppError::NotFound(format!("Export {} not found", id)));
+        };
+        Ok(self.export_dir(ws_id).join(file_name))
+    }
+
+    pub async fn process_data_exports(&self) -> Result<usize, AppError> {
+        let mut processed = 0;
+        loop {
+            let export: Option<DataExport> = sqlx::query_as(
+                r#"
+                    UPDATE data_exports
+                    SET status = 'running', started_at = NOW()
+                    WHERE id = (
+                        SELECT id FROM data_exports
+                        WHERE status = 'pending'
+                            OR (status = 'running' AND started_at < NOW() - INTERVAL '1 hour')
+                        ORDER BY id
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    RETURNING *"#,
+            )
+            .fetch_optional(&self.pool)
+            .await?;
+            let Some(export) = export else {
+                return Ok(processed);
+            };
+            processed += 1;
+            match self.build_export(&export).await {
+                Ok((file_name, size)) => {
+                    sqlx::query(
+                        r#"
+                            UPDATE data_exports
+                            SET status = 'ready', file_name = $2, size_bytes = $3,
+                                finished_at = NOW(), expires_at = NOW() + make_interval(secs => $4)
+                            WHERE id = $1"#,
+                    )
+                    .bind(export.id)
+                    .bind(file_name)
+                    .bind(size as i64)
+                    .bind(EXPORT_LINK_TTL.num_seconds() as f64)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                Err(e) => {
+                    warn!("Failed to export data of user {}: {}", export.user_id, e);
+                    sqlx::query(
+                        "UPDATE data_exports SET status = 'failed', error = $2, finished_at = NOW() WHERE id = $1",
+                    )
+                    .bind(export.id)
+                    .bind(e.to_string())
+                    .execute(&self.pool)
+                    .await?;
+                }
+            }
+        }
+    }
+
+    // remove the archives once their link expired
+    pub async fn purge_expired_exports(&self) -> Result<(), AppError> {
+        let expired: Vec<(i64, String)> = sqlx::query_as(
+            r#"
+                UPDATE data_exports SET status = 'expired'
+                WHERE status = 'ready' AND expires_at <= NOW()
+                RETURNING ws_id, file_name"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for (ws_id, file_name) in expired {
+            let path = self.export_dir(ws_id).join(file_name);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn build_export(&self, export: &DataExport) -> Result<(String, u64), AppError> {
+        let user_id = export.user_id;
+        let user = self.get_chat_user(user_id as u64).await?;
+        let memberships: Value = sqlx::query_scalar(
+            r#"
+                SELECT COALESCE(json_agg(json_build_object(
+                    'ws_id', w.id,
+                    'name', w.name,
+                    'role', CASE WHEN w.owner_id = wm.user_id THEN 'owner' ELSE wm.role::TEXT END,
+                    'joined_at', wm.joined_at
+                ) ORDER BY w.id), '[]')
+                FROM workspace_members wm
+                JOIN workspaces w ON w.id = wm.ws_id AND w.deleted_at IS NULL
+                WHERE wm.user_id = $1"#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let messages: Vec<Message> =
+            sqlx::query_as("SELECT * FROM messages WHERE sender_id = $1 ORDER BY id")
+                .bind(user_id)
+                .fetch_all(&self.pool)
+                .await?;
+        let files: Vec<String> = sqlx::query_scalar(
+            r#"
+                SELECT DISTINCT f FROM (
+                    SELECT unnest(files) AS f FROM messages WHERE sender_id = $1
+                    UNION
+                    SELECT avatar_url FROM users WHERE id = $1 AND avatar_url IS NOT NULL
+                ) uploads"#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let base_dir = self.config.server.base_dir.clone();
+        let files = files
+            .iter()
+            .filter_map(|url| ChatFile::from_str(url).ok())
+            .map(|file| (file.url(), file.path(&base_dir)))
+            .collect::<Vec<_>>();
+        let entries = vec![
+            ("profile.json", serde_json::to_vec_pretty(&user)),
+            ("workspaces.json", serde_json::to_vec_pretty(&memberships)),
+            ("messages.json", serde_json::to_vec_pretty(&messages)),
+        ];
+        let entries = entries
+            .into_iter()
+            .map(|(name, data)| Ok((name, data.map_err(anyhow::Error::from)?)))
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        let file_name = format!("{}.zip", generate_token());
+        let dir = self.export_dir(export.ws_id);
+        let path = dir.join(&file_name);
+        let size = tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&dir)?;
+            write_archive(&path, &entries, &files)
+        })
+        .await
+        .map_err(anyhow::Error::from)??;
+        Ok((file_name, size))
+    }
+
+    // kept apart from the uploads of the workspace, which are served to all its members
+    pub(super) fn export_dir(&self, ws_id: i64) -> PathBuf {
+        self.config
+            .server
+            .base_dir
+            .join(EXPORT_DIR)
+            .join(ws_id.to_string())
+    }
+}
+
+// json documents at the root, the uploaded files under files/ by their url
+fn write_archive(
+    path: &Path,
+    entries: &[(&str, Vec<u8>)],
+    files: &[(String, PathBuf)],
+) -> Result<u64, AppError> {
+    let tmp = path.with_extension("zip.tmp");
+    let mut zip = ZipWriter::new(File::create(&tmp)?);
+    let options = SimpleFileOptions::default();
+    for (name, data) in entries {
+        zip.start_file(*name, options)
+            .map_err(anyhow::Error::from)?;
+        zip.write_all(data)?;
+    }
+    for (url, file) in files {
+        // files removed since are skipped
+        let Ok(data) = fs::read(file) else {
+            continue;
+        };
+        let name = format!("files{}", url.trim_start_matches("/files"));
+        zip.start_file(name, options).map_err(anyhow::Error::from)?;
+        zip.write_all(&data)?;
+    }
+    zip.finish().map_err(anyhow::Error::from)?;
+    fs::rename(&tmp, path)?;
+    Ok(fs::metadata(path)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use anyhow::Result;
+    use chat_core::ExportStatus;
+
+    #[tokio::test]
+    async fn data_export_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let file = ChatFile::new("hello.txt", b"hello world", 0);
+        let path = file.path(&state.config.server.base_dir);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, b"hello world")?;
+        sqlx::query(
+            "INSERT INTO messages (chat_id, sender_id, content, files) VALUES (0, 1, 'hi', $1)",
+        )
+        .bind(vec![file.url()])
+        .execute(&state.pool)
+        .await?;
+
+        let export = state.request_data_export(1, 0).await?;
+        let ret = state.request_data_export(1, 0).await;
+        assert!(matches!(ret, Err(AppError::Export(_))));
+        // the job spawned by the request may have claimed the export already
+        state.process_data_exports().await?;
+        let mut export = state.get_data_export(1, export.id as u64).await?;
+        for _ in 0..50 {
+            if export.status == ExportStatus::Ready {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            export = state.get_data_export(1, export.id as u64).await?;
+        }
+        assert_eq!(export.status, ExportStatus::Ready);
+        assert_eq!(
+            export.download_url.unwrap(),
+            format!("/users/me/exports/{}/download", export.id)
+        );
+        let archive = state.get_export_archive(1, export.id as u64).await?;
+        assert!(state.get_export_archive(2, export.id as u64).await.is_err());
+        assert!(state.get_data_export(2, export.id as u64).await.is_err());
+        assert!(!archive.starts_with(state.config.server.base_dir.join("0")));
+
+        let mut zip = zip::ZipArchive::new(File::open(&archive)?)?;
+        let mut messages = String::new();
+        zip.by_name("messages.json")?
+            .read_to_string(&mut messages)?;
+        assert!(messages.contains("\"hi\""));
+        let mut content = String::new();
+        let name = format!("files{}", file.url().trim_start_matches("/files"));
+        zip.by_name(&name)?.read_to_string(&mut content)?;
+        assert_eq!(content, "hello world");
+
+        sqlx::query("UPDATE data_exports SET expires_at = NOW() WHERE id = $1")
+            .bind(export.id)
+            .execute(&state.pool)
+            .await?;
+        state.purge_expired_exports().await?;
+        assert!(!archive.exists());
+        assert!(state.get_export_archive(1, export.id as u64).await.is_err());
+        let export = state.get_data_export(1, export.id as u64).await?;
+        assert_eq!(export.status, ExportStatus::Expired);
+        assert!(export.download_url.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,457 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::{JoinPolicy, Workspace, WorkspaceInvite};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use utoipa::ToSchema;
+
+use super::workspace::insert_workspace_member;
+use crate::{error::AppError, state::AppState};
+
+const DEFAULT_EXPIRES_IN: i64 = 60 * 60 * 24 * 7; // 7 days
+const MAX_EXPIRES_IN: i64 = 60 * 60 * 24 * 30; // 30 days
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateInvite {
+    // only the user with this email could use the invite
+    #[serde(default)]
+    pub email: Option<String>,
+    // single-use by default, null for unlimited uses
+    #[serde(default = "default_max_uses")]
+    pub max_uses: Option<i32>,
+    // seconds until the invite expires
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+// the email domain admits a user to a workspace only once the email is verified
+pub(super) enum Admission {
+    Member(i64),
+    Pending(i64),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct JoinWorkspace {
+    #[serde(default)]
+    pub ws_id: Option<i64>,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl AppState {
+    pub async fn create_invite(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: CreateInvite,
+    ) -> Result<WorkspaceInvite, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        if matches!(input.max_uses, Some(n) if n < 1) {
+            return Err(AppError::Invite(
+                "max_uses must be greater than 0".to_string(),
+            ));
+        }
+        let expires_in = input.expires_in.unwrap_or(DEFAULT_EXPIRES_IN);
+        if !(1..=MAX_EXPIRES_IN).contains(&expires_in) {
+            return Err(AppError::Invite(format!(
+                "expires_in must be between 1 and {} seconds",
+                MAX_EXPIRES_IN
+            )));
+        }
+        let email = input
+            .email
+            .map(|e| e.trim().to_lowercase())
+            .filter(|e| !e.is_empty());
+
+        let invite = sqlx::query_as(
+            r#"
+                    INSERT INTO workspace_invites (ws_id, code, email, max_uses, expires_at, created_by)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    RETURNING *"#,
+        )
+        .bind(ws_id as i64)
+        .bind(generate_invite_code())
+        .bind(email)
+        .bind(input.max_uses)
+        .bind(Utc::now() + Duration::seconds(expires_in))
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(invite)
+    }
+
+    pub async fn list_invites(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<WorkspaceInvite>, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let invites =
+            sqlx::query_as("SELECT * FROM workspace_invites WHERE ws_id = $1 ORDER BY id DESC")
+                .bind(ws_id as i64)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(invites)
+    }
+
+    pub async fn revoke_invite(&self, ws_id: u64, user_id: u64, id: u64) -> Result<(), AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let ret = sqlx::query("DELETE FROM workspace_invites WHERE id = $1 AND ws_id = $2")
+            .bind(id as i64)
+            .bind(ws_id as i64)
+            .execute(&self.pool)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Invite with id {} not found",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    // check if the user with the email could join the workspace and return the workspace id,
+    // the invite is consumed if the code is given
+    pub(super) async fn authorize_join(
+        &self,
+        conn: &mut PgConnection,
+        email: &str,
+        verified: bool,
+        ws: Option<&Workspace>,
+        code: Option<&str>,
+    ) -> Result<Admission, AppError> {
+        if let Some(code) = code {
+            let invite = consume_invite(conn, code, email).await?;
+            if matches!(ws, Some(ws) if ws.id != invite.ws_id) {
+                return Err(AppError::Invite(
+                    "Invite code is not for this workspace".to_string(),
+                ));
+            }
+            return Ok(Admission::Member(invite.ws_id));
+        }
+
+        let Some(ws) = ws else {
+            return Err(AppError::Invite("Workspace is required".to_string()));
+        };
+        match ws.join_policy {
+            JoinPolicy::Open => Ok(Admission::Member(ws.id)),
+            JoinPolicy::Domain if is_allowed_email(email, &ws.allowed_domains) && verified => {
+                Ok(Admission::Member(ws.id))
+            }
+            JoinPolicy::Domain if is_allowed_email(email, &ws.allowed_domains) => {
+                Ok(Admission::Pending(ws.id))
+            }
+            _ => Err(AppError::Invite(format!(
+                "An invite is required to join workspace {}",
+                ws.name
+            ))),
+        }
+    }
+
+    // join another workspace for a signed in user
+    pub async fn join_workspace(
+        &self,
+        user_id: u64,
+        email: &str,
+        input: JoinWorkspace,
+    ) -> Result<Workspace, AppError> {
+        let ws = match input.ws_id {
+            Some(id) => match self.find_workspace_by_id(id as u64).await? {
+                Some(ws) => Some(ws),
+                None => {
+                    return Err(AppError::NotFound(format!(
+                        "Workspace with id {} not found",
+                        id
+                    )))
+                }
+            },
+            None => None,
+        };
+        if let Some(ws) = &ws {
+            if self.is_workspace_member(ws.id as u64, user_id).await? {
+                return Ok(ws.clone());
+            }
+        }
+
+        let verified: bool =
+            sqlx::query_scalar("SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1")
+                .bind(user_id as i64)
+                .fetch_one(&self.pool)
+                .await?;
+        let mut tx = self.pool.begin().await?;
+        let ws_id = match self
+            .authorize_join(&mut tx, email, verified, ws.as_ref(), input.code.as_deref())
+            .await?
+        {
+            Admission::Member(ws_id) => ws_id,
+            Admission::Pending(ws_id) => {
+                return Err(AppError::Forbidden(format!(
+                    "Verify your email before joining workspace {}",
+                    ws_id
+                )));
+            }
+        };
+        insert_workspace_member(&mut tx, ws_id as u64, user_id).await?;
+        tx.commit().await?;
+        let ws = self.find_workspace_by_id(ws_id as u64).await?;
+        ws.ok_or_else(|| AppError::NotFound(format!("Workspace with id {} not found", ws_id)))
+    }
+}
+
+// the membership is held back until the email is verified
+pub(super) async fn insert_pending_member(
+    conn: &mut PgConnection,
+    ws_id: u64,
+    user_id: u64,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "INSERT INTO pending_workspace_members (ws_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind(ws_id as i64)
+    .bind(user_id as i64)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// the use is given back if the transaction of the join is rolled back
+async fn consume_invite(
+    conn: &mut PgConnection,
+    code: &str,
+    email: &str,
+) -> Result<WorkspaceInvite, AppError> {
+    let invite = sqlx::query_as(
+        r#"
+            UPDATE workspace_invites
+            SET uses = uses + 1
+            WHERE code = $1
+                AND (email IS NULL OR email = lower($2))
+                AND (max_uses IS NULL OR uses < max_uses)
+                AND expires_at > NOW()
+            RETURNING *"#,
+    )
+    .bind(code.trim())
+    .bind(email.trim())
+    .fetch_optional(conn)
+    .await?;
+    invite.ok_or_else(|| AppError::Invite("Invalid or expired invite code".to_string()))
+}
+
+fn default_max_uses() -> Option<i32> {
+    Some(1)
+}
+
+fn generate_invite_code() -> String {
+    let mut bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn is_allowed_email(email: &str, domains: &[String]) -> bool {
+    match email.rsplit_once('@') {
+        Some((_, domain)) => domains.contains(&domain.to_lowercase()),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mailer::FileMailer,
+        models::{SignupUser, UpdateJoinPolicy, VerifyEmail},
+    };
+
+    fn create_invite(email: Option<&str>, max_uses: Option<i32>) -> CreateInvite {
+        CreateInvite {
+            email: email.map(|e| e.to_string()),
+            max_uses,
+            expires_in: None,
+        }
+    }
+
+    fn signup(email: &str, workspace: &str, code: Option<&str>) -> SignupUser {
+        SignupUser {
+            invite_code: code.map(|c| c.to_string()),
+            workspace: workspace.to_string(),
+            ..SignupUser::new("tom", email, "1qa2ws3ed")
+        }
+    }
+
+    #[test]
+    fn is_allowed_email_should_work() {
+        let domains = vec!["acme.org".to_string()];
+        assert!(is_allowed_email("tom@ACME.org", &domains));
+        assert!(!is_allowed_email("tom@evil-acme.org", &domains));
+        assert!(!is_allowed_email("acme.org", &domains));
+    }
+
+    #[tokio::test]
+    async fn signup_should_require_invite() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ret = state
+            .create_user(&signup("tom@123.com", "workspace1", None))
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+        // the user should not be created
+        assert!(state.find_user_by_email("tom@123.com").await?.is_none());
+
+        // workspace1 is owned by user 0
+        let invite = state
+            .create_invite(1, 0, create_invite(None, Some(1)))
+            .await?;
+        let user = state
+            .create_user(&signup("tom@123.com", "workspace1", Some(&invite.code)))
+            .await?;
+        assert_eq!(user.ws_id, 1);
+
+        // single-use invite is consumed
+        let ret = state
+            .create_user(&signup("jim@123.com", "", Some(&invite.code)))
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_signup_should_not_consume_invite() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let invite = state
+            .create_invite(1, 0, create_invite(None, Some(1)))
+            .await?;
+        // the invite is for workspace1
+        let ret = state
+            .create_user(&signup("tom@123.com", "workspace2", Some(&invite.code)))
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+        assert!(state.find_user_by_email("tom@123.com").await?.is_none());
+
+        let user = state
+            .create_user(&signup("tom@123.com", "workspace1", Some(&invite.code)))
+            .await?;
+        assert_eq!(user.ws_id, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invite_should_check_email_and_expiry() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let invite = state
+            .create_invite(1, 0, create_invite(Some("Tom@123.com"), None))
+            .await?;
+        let ret = state
+            .create_user(&signup("jim@123.com", "", Some(&invite.code)))
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+        state
+            .create_user(&signup("tom@123.com", "", Some(&invite.code)))
+            .await?;
+
+        let invite = state.create_invite(1, 0, create_invite(None, None)).await?;
+        sqlx::query("UPDATE workspace_invites SET expires_at = NOW() WHERE id = $1")
+            .bind(invite.id)
+            .execute(&state.pool)
+            .await?;
+        let ret = state
+            .create_user(&signup("jim@123.com", "", Some(&invite.code)))
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+
+        let ret = state.create_invite(1, 1, create_invite(None, None)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn join_policy_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let policy = UpdateJoinPolicy {
+            join_policy: JoinPolicy::Domain,
+            allowed_domains: vec!["@ACME.org".to_string()],
+        };
+        let ws = state.update_join_policy(2, 0, policy).await?;
+        assert_eq!(ws.allowed_domains, vec!["acme.org"]);
+
+        let user = state
+            .create_user(&signup("tom@acme.org", "workspace2", None))
+            .await?;
+        assert_eq!(user.ws_id, 2);
+        // the domain is only trusted once the email is verified
+        assert!(!state.is_workspace_member(2, user.id as u64).await?);
+        let ret = state
+            .create_user(&signup("tom@123.com", "workspace2", None))
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+
+        // signed in users could join open workspaces
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let input = JoinWorkspace {
+            ws_id: Some(3),
+            code: None,
+        };
+        let ret = state
+            .join_workspace(user.id as u64, &user.email, input.clone())
+            .await;
+        assert!(matches!(ret, Err(AppError::Invite(_))));
+        let policy = UpdateJoinPolicy {
+            join_policy: JoinPolicy::Open,
+            allowed_domains: vec![],
+        };
+        state.update_join_policy(3, 0, policy).await?;
+        let ws = state
+            .join_workspace(user.id as u64, &user.email, input)
+            .await?;
+        assert_eq!(ws.id, 3);
+        assert!(state.is_workspace_member(3, user.id as u64).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn domain_join_should_need_verified_email() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let outbox = FileMailer::new(&state.config.mail.outbox_dir);
+        let policy = UpdateJoinPolicy {
+            join_policy: JoinPolicy::Domain,
+            allowed_domains: vec!["none.org".to_string()],
+        };
+        state.update_join_policy(2, 0, policy).await?;
+
+        let user = state
+            .create_user(&signup("tom@none.org", "workspace2", None))
+            .await?;
+        assert!(!state.is_workspace_member(2, user.id as u64).await?);
+        state.send_verification_email(&user).await?;
+        let mails = outbox.mails()?;
+        let (_, token) = mails[0].body.split_once("token=").unwrap();
+        let input = VerifyEmail {
+            token: token.split_whitespace().next().unwrap().to_string(),
+        };
+        state.verify_email(&input).await?;
+        assert!(state.is_workspace_member(2, user.id as u64).await?);
+
+        // signed in users are refused until they verify their email
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        sqlx::query("UPDATE users SET email_verified_at = NULL WHERE id = $1")
+            .bind(user.id)
+            .execute(&state.pool)
+            .await?;
+        let input = JoinWorkspace {
+            ws_id: Some(2),
+            code: None,
+        };
+        let ret = state
+            .join_workspace(user.id as u64, &user.email, input.clone())
+            .await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        assert!(!state.is_workspace_member(2, user.id as u64).await?);
+        sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+            .bind(user.id)
+            .execute(&state.pool)
+            .await?;
+        state
+            .join_workspace(user.id as u64, &user.email, input)
+            .await?;
+        assert!(state.is_workspace_member(2, user.id as u64).await?);
+        Ok(())
+    }
+}
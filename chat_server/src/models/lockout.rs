@@ -0,0 +1,242 @@
+use chat_core::{AuditAction, User};
+use serde_json::json;
+
+use super::{AuditEvent, SigninUser};
+use crate::{
+    error::AppError,
+    lockout::{account_key, ip_key, Lockout},
+    state::AppState,
+};
+
+impl AppState {
+    // verify the credentials unless the account or the ip is locked out by previous failures,
+    // the password is not checked at all while locked. The attempt counts as a failure until
+    // the password is known to be right, errors of the verification included. With two-factor
+    // authentication the failures are only cleared once the second factor is verified
+    pub async fn verify_signin(
+        &self,
+        input: &SigninUser,
+        ip: Option<&str>,
+    ) -> Result<Option<User>, AppError> {
+        let account = account_key(&input.email);
+        let mut keys = vec![account.clone()];
+        keys.extend(ip.map(ip_key));
+        self.signin_throttle.check(&keys).await?;
+
+        let account_lockout = self.signin_throttle.account_attempt(&account).await?;
+        let ip_lockout = match ip {
+            Some(ip) => self.signin_throttle.ip_attempt(&ip_key(ip)).await?,
+            None => None,
+        };
+        let ret = self.verify_user(input).await;
+        if let Ok(Some(user)) = &ret {
+            if self.two_factor_status(user.id as u64).await?.enabled {
+                self.signin_throttle.release(&account).await?;
+            } else {
+                self.signin_throttle.clear(&account).await?;
+            }
+            if let Some(ip) = ip {
+                self.signin_throttle.release(&ip_key(ip)).await?;
+            }
+            return ret;
+        }
+        if account_lockout.is_none() {
+            self.signin_throttle.account_failed(&account).await?;
+        }
+        if account_lockout.is_none() && ip_lockout.is_none() {
+            return ret;
+        }
+        // lockouts are recorded on the user, shown to the admins of all its workspaces,
+        // or on the workspace signed in to if there is no such user
+        let email = input.email.trim();
+        let user_id = self.find_user_by_email(email).await?.map(|u| u.id);
+        let ws_id = match (user_id, input.ws_id) {
+            (None, Some(ws_id)) => self
+                .find_workspace_by_id(ws_id as u64)
+                .await?
+                .map(|ws| ws.id),
+            _ => None,
+        };
+        if let Some(lockout) = account_lockout {
+            self.audit_lockout(ws_id, user_id, ip, "account", email, lockout)
+                .await?;
+        }
+        if let (Some(ip), Some(lockout)) = (ip, ip_lockout) {
+            self.audit_lockout(ws_id, user_id, Some(ip), "ip", ip, lockout)
+                .await?;
+        }
+        ret
+    }
+
+    // the second factor is not checked at all while the account is locked
+    pub(super) async fn check_second_factor(&self, user: &User) -> Result<(), AppError> {
+        self.signin_throttle
+            .check(&[account_key(&user.email)])
+            .await
+    }
+
+    pub(super) async fn second_factor_verified(&self, user: &User) -> Result<(), AppError> {
+        self.signin_throttle.clear(&account_key(&user.email)).await
+    }
+
+    pub(super) async fn second_factor_failed(&self, user: &User) -> Result<(), AppError> {
+        let account = account_key(&user.email);
+        if let Some(lockout) = self.signin_throttle.second_factor_failed(&account).await? {
+            self.audit_lockout(None, Some(user.id), None, "account", &user.email, lockout)
+                .await?;
+        }
+        Ok(())
+    }
+
+    // admins could unlock the members of their workspace before the lockout expires
+    pub async fn unlock_member(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+    ) -> Result<(), AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        if !self.is_workspace_member(ws_id, member_id).await? {
+            return Err(AppError::NotFound(format!(
+                "User {} is not a member of the workspace",
+                member_id
+            )));
+        }
+        let email: String = sqlx::query_scalar("SELECT email FROM users WHERE id = $1")
+            .bind(member_id as i64)
+            .fetch_one(&self.pool)
+            .await?;
+        self.signin_throttle.clear(&account_key(&email)).await?;
+        self.record_audit(AuditEvent {
+            ws_id: Some(ws_id as i64),
+            user_id: Some(member_id as i64),
+            actor_id: Some(user_id as i64),
+            action: AuditAction::SigninUnlocked,
+            ip: None,
+            details: json!({ "login": email }),
+        })
+        .await
+    }
+
+    async fn audit_lockout(
+        &self,
+        ws_id: Option<i64>,
+        user_id: Option<i64>,
+        ip: Option<&str>,
+        target: &str,
+        key: &str,
+        lockout: Lockout,
+    ) -> Result<(), AppError> {
+        self.record_audit(AuditEvent {
+            ws_id,
+            user_id,
+            actor_id: None,
+            action: AuditAction::SigninLocked,
+            ip: ip.map(|ip| ip.to_string()),
+            details: json!({
+                "target": target,
+                "key": key,
+                "failures": lockout.failures,
+                "locked_until": lockout.locked_until,
+            }),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SignupUser;
+
+    #[tokio::test]
+    async fn signin_lockout_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("tom", "tom@123.com", "1qa2ws3ed");
+        let user = state.create_user(&input).await?;
+        state.add_workspace_member(0, user.id as u64).await?;
+
+        let wrong = SigninUser::new("Tom@123.com", "wrong", 0);
+        let right = SigninUser::new("tom@123.com", "1qa2ws3ed", 0);
+        assert!(state
+            .verify_signin(&wrong, Some("10.0.0.1"))
+            .await?
+            .is_none());
+        // the next attempt is delayed, even with the right password
+        let ret = state.verify_signin(&right, Some("10.0.0.2")).await;
+        assert!(matches!(ret, Err(AppError::SigninLocked(_))));
+
+        // only admins could unlock members
+        let ret = state.unlock_member(0, 1, user.id as u64).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        state.unlock_member(0, 0, user.id as u64).await?;
+        assert!(state.verify_signin(&right, None).await?.is_some());
+
+        // errors of the verification count as failures too
+        let other = SigninUser::new("tom@123.com", "1qa2ws3ed", 1);
+        let ret = state.verify_signin(&other, None).await;
+        assert!(matches!(ret, Err(AppError::LoginFailed(_))));
+        let ret = state.verify_signin(&right, None).await;
+        assert!(matches!(ret, Err(AppError::SigninLocked(_))));
+
+        let logs = state.list_audit_logs(0, 0, Default::default()).await?;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].action, AuditAction::SigninUnlocked);
+        assert_eq!(logs[0].user_id, Some(user.id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lockouts_should_be_shown_to_workspace_admins() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("tom", "tom@123.com", "1qa2ws3ed");
+        let user = state.create_user(&input).await?;
+        let config = &state.config.lockout;
+
+        // the account and the ip reach their limits with the same attempt
+        for _ in 1..config.max_account_failures {
+            state.second_factor_failed(&user).await?;
+        }
+        for i in 1..config.max_ip_failures {
+            let signin = SigninUser::new(&format!("nobody{}@123.com", i), "wrong", 0);
+            state.verify_signin(&signin, Some("10.0.0.9")).await?;
+        }
+        let wrong = SigninUser::new("tom@123.com", "wrong", 0);
+        assert!(state
+            .verify_signin(&wrong, Some("10.0.0.9"))
+            .await?
+            .is_none());
+        // without a known user the workspace signed in to gets the entry
+        for i in 0..config.max_ip_failures {
+            let signin = SigninUser::new(&format!("somebody{}@123.com", i), "wrong", 0);
+            state.verify_signin(&signin, Some("10.0.0.10")).await?;
+        }
+
+        let logs = state.list_audit_logs(0, 0, Default::default()).await?;
+        let locks = logs
+            .iter()
+            .filter(|log| log.action == AuditAction::SigninLocked)
+            .map(|log| {
+                (
+                    log.ws_id,
+                    log.user_id,
+                    log.ip.clone(),
+                    log.details["target"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let ip = |ip: &str| Some(ip.to_string());
+        assert_eq!(
+            locks,
+            vec![
+                (Some(0), None, ip("10.0.0.10"), "ip".to_string()),
+                (None, Some(user.id), ip("10.0.0.9"), "ip".to_string()),
+                (None, Some(user.id), ip("10.0.0.9"), "account".to_string()),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,17 +1,24 @@
 use std::str::FromStr;
 
-use chat_core::Message;
+use chat_core::{ContentFormat, Message, MessageKind};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::{error::AppError, state::AppState};
 
-use super::ChatFile;
+use super::{ChatFile, CreatePoll};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct CreateMessage {
+    #[serde(default)]
     pub content: String,
+    #[serde(default)]
+    pub format: ContentFormat,
+    #[serde(default)]
     pub files: Vec<String>,
+    // if set, the message is a poll and content defaults to the question
+    #[serde(default)]
+    pub poll: Option<CreatePoll>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
@@ -27,6 +34,13 @@ impl AppState {
         chat_id: u64,
         user_id: u64,
     ) -> Result<Message, AppError> {
+        let mut create_message = create_message;
+        if let Some(poll) = &create_message.poll {
+            poll.validate()?;
+            if create_message.content.is_empty() {
+                create_message.content.clone_from(&poll.question);
+            }
+        }
         if create_message.content.is_empty() {
             return Err(AppError::CreateMessage(
                 "Message content is empty".to_string(),
@@ -47,34 +61,64 @@ impl AppState {
             }
         }
 
-        let message: Message = sqlx::query_as(
-            "INSERT INTO messages (chat_id, sender_id, content, files) VALUES ($1, $2, $3, $4) RETURNING *",
+        let kind = match create_message.poll {
+            Some(_) => MessageKind::Poll,
+            None => MessageKind::Text,
+        };
+        let rendered = create_message.format.render(&create_message.content);
+        let mut tx = self.pool.begin().await?;
+        let mut message: Message = sqlx::query_as(
+            r#"
+                    INSERT INTO messages (chat_id, sender_id, kind, format, content, content_html, content_text, files)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                    RETURNING *"#,
         )
         .bind(chat_id as i64)
         .bind(user_id as i64)
+        .bind(kind)
+        .bind(create_message.format)
         .bind(create_message.content)
+        .bind(rendered.html)
+        .bind(rendered.text)
         .bind(create_message.files)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
+        if let Some(poll) = create_message.poll {
+            message.poll = Some(Self::insert_poll(&mut tx, &message, poll).await?);
+        }
+        tx.commit().await?;
+        if let Some(unfurler) = &self.unfurler {
+            unfurler.submit(&message);
+        }
         Ok(message)
     }
 
+    // messages of the users blocked by the user are left out
     pub async fn list_message(
         &self,
         chat_id: u64,
+        user_id: u64,
         input: ListMessage,
     ) -> Result<Vec<Message>, AppError> {
         let last_id = input.last_id.unwrap_or(i64::MAX as u64);
         let limit = input.limit;
         let messages: Vec<Message> = sqlx::query_as(
-            "SELECT * FROM messages WHERE chat_id = $1 AND id < $2 ORDER BY id DESC LIMIT $3",
+            r#"
+                SELECT * FROM messages m
+                WHERE m.chat_id = $1 AND m.id < $2
+                    AND NOT EXISTS (
+                        SELECT 1 FROM user_blocks b WHERE b.user_id = $4 AND b.blocked_id = m.sender_id
+                    )
+                ORDER BY m.id DESC
+                LIMIT $3"#,
         )
         .bind(chat_id as i64)
         .bind(last_id as i64)
         .bind(limit as i64)
+        .bind(user_id as i64)
         .fetch_all(&self.pool)
         .await?;
-        Ok(messages)
+        self.attach_polls(messages).await
     }
 }
 
@@ -83,6 +127,7 @@ mod tests {
 
     use super::*;
     use crate::state::AppState;
+    use sqlx::Executor;
 
     #[tokio::test]
     async fn create_message_should_work() -> Result<(), AppError> {
@@ -94,6 +139,7 @@ mod tests {
         let create_message = CreateMessage {
             content: "".to_string(),
             files: vec![],
+            ..Default::default()
         };
 
         let result = state.create_message(create_message, chat_id, user_id).await;
@@ -107,6 +153,7 @@ mod tests {
         let create_message = CreateMessage {
             content: "Hello, World!".to_string(),
             files: vec!["".to_string()],
+            ..Default::default()
         };
 
         let result = state.create_message(create_message, chat_id, user_id).await;
@@ -121,6 +168,7 @@ mod tests {
         let create_message = CreateMessage {
             content: "Hello, World!".to_string(),
             files: vec![invalid_path.to_string()],
+            ..Default::default()
         };
 
         let result = state.create_message(create_message, chat_id, user_id).await;
@@ -134,6 +182,7 @@ mod tests {
         let create_message = CreateMessage {
             content: "Hello, World!".to_string(),
             files: vec!["/files/1/3es/32e/jis2234jisowe.txt".to_string()],
+            ..Default::default()
         };
 
         let result = state.create_message(create_message, chat_id, user_id).await;
@@ -148,6 +197,7 @@ mod tests {
         let create_message = CreateMessage {
             content: "Hello, World!".to_string(),
             files: vec![],
+            ..Default::default()
         };
 
         let message = state
@@ -155,7 +205,35 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(message.content, "Hello, World!");
+        assert_eq!(message.content_html, "<p>Hello, World!</p>\n");
+        assert_eq!(message.content_text, "Hello, World!");
         assert_eq!(message.files, Vec::<String>::new());
+
+        // test markdown content is rendered and sanitized
+        let create_message = CreateMessage {
+            content: "**hi** <script>alert(1)</script>".to_string(),
+            ..Default::default()
+        };
+
+        let message = state
+            .create_message(create_message, chat_id, user_id)
+            .await?;
+        assert_eq!(message.format, ContentFormat::Markdown);
+        assert_eq!(message.content, "**hi** <script>alert(1)</script>");
+        assert_eq!(message.content_html, "<p><strong>hi</strong> </p>\n");
+        assert_eq!(message.content_text, "hi alert(1)");
+
+        // test plain content is escaped
+        let create_message = CreateMessage {
+            content: "**hi**".to_string(),
+            format: ContentFormat::Plain,
+            ..Default::default()
+        };
+
+        let message = state
+            .create_message(create_message, chat_id, user_id)
+            .await?;
+        assert_eq!(message.content_html, "<p>**hi**</p>\n");
         Ok(())
     }
 
@@ -170,7 +248,7 @@ mod tests {
             limit: 6,
         };
 
-        let messages = state.list_message(chat_id, input).await.unwrap();
+        let messages = state.list_message(chat_id, 1, input).await.unwrap();
         assert_eq!(messages.len(), 6);
 
         let input = ListMessage {
@@ -178,9 +256,39 @@ mod tests {
             limit: 6,
         };
 
-        let messages = state.list_message(chat_id, input).await.unwrap();
+        let messages = state.list_message(chat_id, 1, input).await.unwrap();
         assert_eq!(messages.len(), 4);
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn backfilled_plain_messages_should_keep_line_breaks() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let content = "first <line>\r\n\nthird & last\n";
+        let id: i64 = sqlx::query_scalar(
+            r#"
+                INSERT INTO messages (chat_id, sender_id, content, format, content_html, content_text)
+                VALUES (1, 1, $1, 'plain', '<p>' || replace(replace(replace($1, '&', '&amp;'), '<', '&lt;'), '>', '&gt;') || '</p>', $1)
+                RETURNING id"#,
+        )
+        .bind(content)
+        .fetch_one(&state.pool)
+        .await?;
+        state
+            .pool
+            .execute(include_str!(
+                "../../../migrations/20240927090000_plain_line_breaks.sql"
+            ))
+            .await?;
+
+        let message: Message = sqlx::query_as("SELECT * FROM messages WHERE id = $1")
+            .bind(id)
+            .fetch_one(&state.pool)
+            .await?;
+        let rendered = ContentFormat::Plain.render(content);
+        assert_eq!(message.content_html, rendered.html);
+        assert_eq!(message.content_text, rendered.text);
+        Ok(())
+    }
 }
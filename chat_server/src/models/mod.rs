@@ -1,13 +1,63 @@
+mod account;
+mod audit;
+mod block;
 mod chat;
+mod command;
+mod directory;
+mod email;
+mod export;
 mod file;
+mod invite;
+mod lockout;
 mod message;
+mod password;
+mod personal_token;
+mod poll;
+mod profile;
+mod saved;
+mod scim;
+mod session;
+mod sso;
+mod token;
+mod two_factor;
 mod user;
+mod user_cache;
 mod workspace;
+pub use account::DeleteAccount;
+pub use audit::{AuditEvent, ListAuditLogs};
+pub use block::PrivacySettings;
 pub use chat::{CreateChat, UpdateChat};
+pub use command::CreateCommand;
+pub use directory::{ListUsers, UserStatusFilter};
+pub use email::VerifyEmail;
+pub use invite::{CreateInvite, JoinWorkspace};
 pub use message::{CreateMessage, ListMessage};
+pub use password::{ChangePassword, ForgotPassword, ResetPassword};
+pub use personal_token::{
+    is_personal_token, CreateAccessToken, CreateBot, CreatedAccessToken, CreatedBot,
+};
+pub use poll::{CreatePoll, VotePoll};
+pub use profile::{UpdateProfile, UpdateStatus};
+pub use saved::ListSaved;
+pub use scim::{
+    ScimEmail, ScimGroup, ScimGroupList, ScimListQuery, ScimMember, ScimMeta, ScimName, ScimPatch,
+    ScimPatchOp, ScimUser, ScimUserList,
+};
 use serde::{Deserialize, Serialize};
+pub use session::DeviceInfo;
+pub use sso::SsoCallback;
+pub use token::{spawn_token_cleanup, Logout, RefreshToken};
+pub use two_factor::{
+    RecoveryCodes, TotpEnrollment, TwoFactorChallenge, TwoFactorCode, TwoFactorStatus,
+    VerifyTwoFactor,
+};
+pub(crate) use user::verify_password;
 pub use user::{SigninUser, SignupUser};
+pub use user_cache::UserCache;
 use utoipa::ToSchema;
+pub use workspace::{
+    spawn_workspace_cleanup, TransferWorkspace, UpdateJoinPolicy, UpdateMemberRole, UpdateWorkspace,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ChatFile {
@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use super::{
+    token::{generate_token, hash_token},
+    user::{hash_password, verify_password},
+};
+use crate::{error::AppError, mailer::Mail, state::AppState};
+
+const RESET_TOKEN_TTL_MINUTES: i32 = 30;
+// at most one reset mail per user in this time
+const RESET_MAIL_INTERVAL_SECS: i32 = 60;
+const MIN_PASSWORD_LEN: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ResetPassword {
+    // the token from the reset mail
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, FromRow)]
+struct ResetUser {
+    id: i64,
+    fullname: String,
+    email: String,
+}
+
+impl AppState {
+    // the other devices are signed out, the current session is kept
+    pub async fn change_password(
+        &self,
+        user_id: u64,
+        current_session: Option<i64>,
+        input: &ChangePassword,
+    ) -> Result<(), AppError> {
+        let password_hash: Option<String> =
+            sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
+                .bind(user_id as i64)
+                .fetch_optional(&self.pool)
+                .await?
+                .flatten();
+        let Some(password_hash) = password_hash else {
+            return Err(AppError::Password(
+                "The account has no password".to_string(),
+            ));
+        };
+        if !verify_password(&input.current_password, &password_hash)? {
+            return Err(AppError::Forbidden(
+                "Current password is incorrect".to_string(),
+            ));
+        }
+        validate_password(&input.new_password)?;
+        if input.new_password == input.current_password {
+            return Err(AppError::Password(
+                "The new password must be different".to_string(),
+            ));
+        }
+        self.update_password(user_id, &input.new_password).await?;
+        self.revoke_other_sessions(user_id, current_session).await
+    }
+
+    // nothing tells whether the email is registered, the mail is only sent if it is
+    pub async fn request_password_reset(&self, input: &ForgotPassword) -> Result<(), AppError> {
+        let user: Option<ResetUser> =
+            sqlx::query_as("SELECT id, fullname, email FROM users WHERE email = $1 AND NOT is_bot")
+                .bind(input.email.trim())
+                .fetch_optional(&self.pool)
+                .await?;
+        // the link is created and mailed aside, so the time taken doesn't reveal the account
+        if let Some(user) = user {
+            let state = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = state.send_password_reset(&user).await {
+                    warn!(
+                        "Failed to send password reset mail to user {}: {}",
+                        user.id, e
+                    );
+                }
+            });
+        }
+        Ok(())
+    }
+
+    // the user is locked so concurrent requests don't get past the mail interval
+    async fn send_password_reset(&self, user: &ResetUser) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        let recent: Option<i64> = sqlx::query_scalar(
+            r#"
+                SELECT id FROM password_resets
+                WHERE user_id = $1 AND created_at > NOW() - make_interval(secs => $2)
+                LIMIT 1"#,
+        )
+        .bind(user.id)
+        .bind(RESET_MAIL_INTERVAL_SECS as f64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if recent.is_some() {
+            return Ok(());
+        }
+        // only the latest link works
+        sqlx::query(
+            "UPDATE password_resets SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+        let token = generate_token();
+        sqlx::query(
+            r#"
+                INSERT INTO password_resets (user_id, token_hash, expires_at)
+                VALUES ($1, $2, NOW() + make_interval(mins => $3))"#,
+        )
+        .bind(user.id)
+        .bind(hash_token(&token))
+        .bind(RESET_TOKEN_TTL_MINUTES)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let mail = Mail {
+            to: user.email.clone(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hi {},\n\nUse the link below to reset your password, it expires in {} minutes:\n\n{}/reset-password?token={}\n\nIf you did not request it, you could ignore this mail.\n",
+                user.fullname,
+                RESET_TOKEN_TTL_MINUTES,
+                self.config.mail.web_url.trim_end_matches('/'),
+                token
+            ),
+        };
+        self.mailer.send(mail).await
+    }
+
+    // the token could only be used once, all the devices are signed out
+    pub async fn reset_password(&self, input: &ResetPassword) -> Result<(), AppError> {
+        validate_password(&input.password)?;
+        let mut tx = self.pool.begin().await?;
+        let user_id: Option<i64> = sqlx::query_scalar(
+            r#"
+                UPDATE password_resets SET used_at = NOW()
+                WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+                RETURNING user_id"#,
+        )
+        .bind(hash_token(&input.token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Err(AppError::Password(
+                "Invalid or expired reset token".to_string(),
+            ));
+        };
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(hash_password(&input.password)?)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.revoke_other_sessions(user_id as u64, None).await
+    }
+
+    async fn update_password(&self, user_id: u64, password: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(hash_password(password)?)
+            .bind(user_id as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+fn validate_password(password: &str) -> Result<(), AppError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AppError::Password(format!(
+            "Password must have at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mailer::FileMailer,
+        models::{DeviceInfo, SigninUser, SignupUser},
+    };
+    use chat_core::TokenVerifier;
+
+    fn reset_token(mail: &Mail) -> String {
+        let (_, token) = mail.body.split_once("token=").unwrap();
+        token.split_whitespace().next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn change_password_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state
+            .create_user(&SignupUser::new("tom", "tom@123.com", "1qa2ws3ed"))
+            .await?;
+        let current = state
+            .create_session(user.id as u64, &DeviceInfo::default())
+            .await?;
+        let other = state
+            .create_session(user.id as u64, &DeviceInfo::default())
+            .await?;
+        let other_token = state.ek.sign_session(user.clone(), other)?;
+        let current_token = state.ek.sign_session(user.clone(), current)?;
+
+        let input = ChangePassword {
+            current_password: "wrong password".to_string(),
+            new_password: "new password".to_string(),
+        };
+        let ret = state
+            .change_password(user.id as u64, Some(current), &input)
+            .await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let input = ChangePassword {
+            current_password: "1qa2ws3ed".to_string(),
+            new_password: "short".to_string(),
+        };
+        let ret = state
+            .change_password(user.id as u64, Some(current), &input)
+            .await;
+        assert!(matches!(ret, Err(AppError::Password(_))));
+
+        let input = ChangePassword {
+            current_password: "1qa2ws3ed".to_string(),
+            new_password: "new password".to_string(),
+        };
+        state
+            .change_password(user.id as u64, Some(current), &input)
+            .await?;
+        assert!(state.verify(&other_token).await.is_err());
+        state.verify(&current_token).await?;
+        let sessions = state.list_sessions(user.id as u64, Some(current)).await?;
+        assert_eq!(sessions.len(), 1);
+
+        let signin = SigninUser::new("tom@123.com", "1qa2ws3ed", user.ws_id);
+        assert!(state.verify_user(&signin).await?.is_none());
+        let signin = SigninUser::new("tom@123.com", "new password", user.ws_id);
+        assert!(state.verify_user(&signin).await?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reset_password_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let outbox = FileMailer::new(&state.config.mail.outbox_dir);
+        let user = state
+            .create_user(&SignupUser::new("tom", "tom@123.com", "1qa2ws3ed"))
+            .await?;
+        let sid = state
+            .create_session(user.id as u64, &DeviceInfo::default())
+            .await?;
+        let refresh_token = state.create_refresh_token(&user, Some(sid)).await?;
+
+        // unknown emails are silently ignored
+        let input = ForgotPassword {
+            email: "nobody@123.com".to_string(),
+        };
+        state.request_password_reset(&input).await?;
+        assert!(outbox.mails()?.is_empty());
+
+        let input = ForgotPassword {
+            email: "tom@123.com".to_string(),
+        };
+        state.request_password_reset(&input).await?;
+        // the mail is sent in the background
+        for _ in 0..50 {
+            if !outbox.mails()?.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        // no more mails in a short time
+        let reset_user = ResetUser {
+            id: user.id,
+            fullname: user.fullname.clone(),
+            email: user.email.clone(),
+        };
+        state.send_password_reset(&reset_user).await?;
+        let mails = outbox.mails()?;
+        assert_eq!(mails.len(), 1);
+        assert_eq!(mails[0].to, "tom@123.com");
+        let token = reset_token(&mails[0]);
+
+        let input = ResetPassword {
+            token: "invalid".to_string(),
+            password: "new password".to_string(),
+        };
+        let ret = state.reset_password(&input).await;
+        assert!(matches!(ret, Err(AppError::Password(_))));
+
+        let input = ResetPassword {
+            token: token.clone(),
+            password: "new password".to_string(),
+        };
+        state.reset_password(&input).await?;
+        let signin = SigninUser::new("tom@123.com", "new password", user.ws_id);
+        assert!(state.verify_user(&signin).await?.is_some());
+        assert!(state.list_sessions(user.id as u64, None).await?.is_empty());
+        let ret = state.rotate_refresh_token(&refresh_token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        // the token could only be used once
+        let ret = state.reset_password(&input).await;
+        assert!(matches!(ret, Err(AppError::Password(_))));
+        Ok(())
+    }
+}
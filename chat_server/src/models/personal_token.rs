@@ -0,0 +1,424 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::{AccessToken, AuthContext, Scope, User};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::token::{generate_token, hash_token};
+use crate::{error::AppError, state::AppState};
+
+pub const PERSONAL_TOKEN_PREFIX: &str = "chat_pat_";
+const MAX_EXPIRES_IN: i64 = 60 * 60 * 24 * 365; // 1 year
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CreateAccessToken {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    // seconds until the token expires, never expires if not set
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedAccessToken {
+    pub access_token: AccessToken,
+    // only returned once
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CreateBot {
+    pub name: String,
+    // the first token of the bot
+    pub token: CreateAccessToken,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedBot {
+    pub bot: User,
+    pub token: CreatedAccessToken,
+}
+
+#[derive(Debug, FromRow)]
+struct TokenUser {
+    token_id: i64,
+    user_id: i64,
+    ws_id: i64,
+    scopes: Vec<Scope>,
+}
+
+impl AppState {
+    // token of the user acting in the current workspace
+    pub async fn create_personal_token(
+        &self,
+        user: &User,
+        input: CreateAccessToken,
+    ) -> Result<CreatedAccessToken, AppError> {
+        self.insert_personal_token(user.id, user.ws_id, user.id, input)
+            .await
+    }
+
+    pub async fn list_personal_tokens(&self, user_id: u64) -> Result<Vec<AccessToken>, AppError> {
+        let tokens = sqlx::query_as(
+            r#"
+                SELECT id, user_id, ws_id, name, prefix, scopes, created_by, last_used_at, expires_at, created_at
+                FROM access_tokens
+                WHERE user_id = $1 AND revoked_at IS NULL
+                ORDER BY id DESC"#,
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tokens)
+    }
+
+    pub async fn revoke_personal_token(&self, user_id: u64, id: u64) -> Result<(), AppError> {
+        let ret = sqlx::query(
+            "UPDATE access_tokens SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(id as i64)
+        .bind(user_id as i64)
+        .execute(&self.pool)
+        .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Token with id {} not found",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    // bots are members of the workspace which could only use personal access tokens
+    pub async fn create_bot(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: CreateBot,
+    ) -> Result<CreatedBot, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let name = input.name.trim();
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(AppError::AccessToken(format!(
+                "Invalid bot name: {}",
+                input.name
+            )));
+        }
+        validate_token(&input.token)?;
+
+        let mut bytes = [0u8; 8];
+        OsRng.fill_bytes(&mut bytes);
+        let email = format!("bot-{}@bots.invalid", hex::encode(bytes));
+        let bot: User = sqlx::query_as(
+            r#"
+                INSERT INTO users (fullname, email, ws_id, is_bot, email_verified_at)
+                VALUES ($1, $2, $3, TRUE, NOW())
+                RETURNING id, fullname, email, ws_id, created_at, is_bot"#,
+        )
+        .bind(name)
+        .bind(email)
+        .bind(ws_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        self.add_workspace_member(ws_id, bot.id as u64).await?;
+        let token = self
+            .insert_personal_token(bot.id, ws_id as i64, user_id as i64, input.token)
+            .await?;
+        Ok(CreatedBot { bot, token })
+    }
+
+    pub async fn list_bots(&self, ws_id: u64, user_id: u64) -> Result<Vec<User>, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let bots = sqlx::query_as(
+            r#"
+                SELECT u.id, u.fullname, u.email, m.ws_id, u.created_at, u.is_bot
+                FROM users u
+                JOIN workspace_members m ON m.user_id = u.id
+                WHERE m.ws_id = $1 AND u.is_bot
+                ORDER BY u.id"#,
+        )
+        .bind(ws_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bots)
+    }
+
+    pub async fn create_bot_token(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        bot_id: u64,
+        input: CreateAccessToken,
+    ) -> Result<CreatedAccessToken, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let is_bot: Option<bool> = sqlx::query_scalar(
+            r#"
+                SELECT u.is_bot
+                FROM users u
+                JOIN workspace_members m ON m.user_id = u.id
+                WHERE m.ws_id = $1 AND u.id = $2"#,
+        )
+        .bind(ws_id as i64)
+        .bind(bot_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        if is_bot != Some(true) {
+            return Err(AppError::NotFound(format!(
+                "Bot with id {} not found",
+                bot_id
+            )));
+        }
+        self.insert_personal_token(bot_id as i64, ws_id as i64, user_id as i64, input)
+            .await
+    }
+
+    // all the active tokens acting in the workspace
+    pub async fn list_workspace_tokens(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<AccessToken>, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let tokens = sqlx::query_as(
+            r#"
+                SELECT id, user_id, ws_id, name, prefix, scopes, created_by, last_used_at, expires_at, created_at
+                FROM access_tokens
+                WHERE ws_id = $1 AND revoked_at IS NULL
+                ORDER BY id DESC"#,
+        )
+        .bind(ws_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tokens)
+    }
+
+    pub async fn revoke_workspace_token(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        id: u64,
+    ) -> Result<(), AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let ret = sqlx::query(
+            "UPDATE access_tokens SET revoked_at = NOW() WHERE id = $1 AND ws_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(id as i64)
+        .bind(ws_id as i64)
+        .execute(&self.pool)
+        .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Token with id {} not found",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    // the user of the token must still be a member of the workspace the token acts in
+    pub async fn verify_personal_token(&self, token: &str) -> Result<AuthContext, AppError> {
+        let row: Option<TokenUser> = sqlx::query_as(
+            r#"
+                SELECT t.id AS token_id, t.user_id, t.ws_id, t.scopes
+                FROM access_tokens t
+                JOIN workspace_members m ON m.ws_id = t.ws_id AND m.user_id = t.user_id
+                WHERE t.token_hash = $1
+                    AND t.revoked_at IS NULL
+                    AND (t.expires_at IS NULL OR t.expires_at > NOW())"#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Err(AppError::Unauthorized(
+                "Invalid or expired access token".to_string(),
+            ));
+        };
+        // only track the usage once a minute
+        sqlx::query(
+            r#"
+                UPDATE access_tokens SET last_used_at = NOW()
+                WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - interval '1 minute')"#,
+        )
+        .bind(row.token_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(AuthContext::with_scopes(row.user_id, row.ws_id, row.scopes))
+    }
+
+    async fn insert_personal_token(
+        &self,
+        user_id: i64,
+        ws_id: i64,
+        created_by: i64,
+        input: CreateAccessToken,
+    ) -> Result<CreatedAccessToken, AppError> {
+        validate_token(&input)?;
+        // provisioning and admin tokens act for the admins of the workspace only
+        if input
+            .scopes
+            .iter()
+            .any(|s| matches!(s, Scope::Scim | Scope::WorkspacesAdmin))
+        {
+            self.verify_workspace_admin(ws_id as u64, user_id as u64)
+                .await?;
+        }
+        let mut scopes = input.scopes;
+        scopes.sort_by_key(|s| *s as u8);
+        scopes.dedup();
+        let expires_at = input
+            .expires_in
+            .map(|secs| Utc::now() + Duration::seconds(secs));
+
+        let token = format!("{}{}", PERSONAL_TOKEN_PREFIX, generate_token());
+        let prefix = &token[..PERSONAL_TOKEN_PREFIX.len() + 6];
+        let access_token = sqlx::query_as(
+            r#"
+                INSERT INTO access_tokens (user_id, ws_id, name, token_hash, prefix, scopes, created_by, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING id, user_id, ws_id, name, prefix, scopes, created_by, last_used_at, expires_at, created_at"#,
+        )
+        .bind(user_id)
+        .bind(ws_id)
+        .bind(input.name.trim())
+        .bind(hash_token(&token))
+        .bind(prefix)
+        .bind(scopes)
+        .bind(created_by)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(CreatedAccessToken {
+            access_token,
+            token,
+        })
+    }
+}
+
+pub fn is_personal_token(token: &str) -> bool {
+    token.starts_with(PERSONAL_TOKEN_PREFIX)
+}
+
+fn validate_token(input: &CreateAccessToken) -> Result<(), AppError> {
+    let name = input.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(AppError::AccessToken(format!(
+            "Invalid token name: {}",
+            input.name
+        )));
+    }
+    if input.scopes.is_empty() {
+        return Err(AppError::AccessToken(
+            "At least one scope is required".to_string(),
+        ));
+    }
+    if matches!(input.expires_in, Some(secs) if !(1..=MAX_EXPIRES_IN).contains(&secs)) {
+        return Err(AppError::AccessToken(format!(
+            "expires_in must be between 1 and {} seconds",
+            MAX_EXPIRES_IN
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_token(scopes: Vec<Scope>) -> CreateAccessToken {
+        CreateAccessToken {
+            name: "deploy".to_string(),
+            scopes,
+            expires_in: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn personal_token_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let created = state
+            .create_personal_token(
+                &user,
+                create_token(vec![Scope::ChatsRead, Scope::ChatsRead]),
+            )
+            .await?;
+        assert!(is_personal_token(&created.token));
+        assert_eq!(created.access_token.scopes, vec![Scope::ChatsRead]);
+
+        let ctx = state.verify_personal_token(&created.token).await?;
+        assert_eq!(ctx.user_id, user.id);
+        assert!(ctx.allows(Scope::ChatsRead));
+        assert!(!ctx.allows(Scope::MessagesWrite));
+
+        let tokens = state.list_personal_tokens(user.id as u64).await?;
+        assert_eq!(tokens.len(), 1);
+        assert!(tokens[0].last_used_at.is_some());
+
+        state
+            .revoke_personal_token(user.id as u64, created.access_token.id as u64)
+            .await?;
+        let ret = state.verify_personal_token(&created.token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let ret = state
+            .create_personal_token(&user, create_token(vec![]))
+            .await;
+        assert!(matches!(ret, Err(AppError::AccessToken(_))));
+        // only admins could create provisioning tokens
+        let ret = state
+            .create_personal_token(&user, create_token(vec![Scope::Scim]))
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let ret = state
+            .create_personal_token(&user, create_token(vec![Scope::WorkspacesAdmin]))
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bot_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateBot {
+            name: "deploy bot".to_string(),
+            token: create_token(vec![Scope::MessagesWrite]),
+        };
+        // only admins could create bots
+        let ret = state.create_bot(0, 1, input.clone()).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let created = state.create_bot(0, 0, input).await?;
+        assert!(created.bot.is_bot);
+        assert!(state.is_workspace_member(0, created.bot.id as u64).await?);
+        let ctx = state.verify_personal_token(&created.token.token).await?;
+        assert_eq!(ctx.user_id, created.bot.id);
+        assert!(state.load_user(&ctx).await?.is_bot);
+
+        let bots = state.list_bots(0, 0).await?;
+        assert_eq!(bots.len(), 1);
+        let token = state
+            .create_bot_token(
+                0,
+                0,
+                created.bot.id as u64,
+                create_token(vec![Scope::ChatsRead]),
+            )
+            .await?;
+        let ret = state
+            .create_bot_token(0, 0, 1, create_token(vec![Scope::ChatsRead]))
+            .await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        let tokens = state.list_workspace_tokens(0, 0).await?;
+        assert_eq!(tokens.len(), 2);
+        state
+            .revoke_workspace_token(0, 0, token.access_token.id as u64)
+            .await?;
+        let ret = state.verify_personal_token(&token.token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+}
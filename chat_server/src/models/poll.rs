@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+
+use chat_core::{Message, MessageKind, Poll, PollOption};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+const MAX_POLL_OPTIONS: usize = 10;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct CreatePoll {
+    pub question: String,
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub multi_select: bool,
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct VotePoll {
+    // index of the chosen options, an empty list retracts the vote
+    pub options: Vec<i32>,
+}
+
+#[derive(Debug, FromRow)]
+struct PollRow {
+    message_id: i64,
+    chat_id: i64,
+    sender_id: i64,
+    question: String,
+    options: Vec<String>,
+    multi_select: bool,
+    anonymous: bool,
+    closed: bool,
+}
+
+#[derive(Debug, FromRow)]
+struct PollVoteRow {
+    message_id: i64,
+    user_id: i64,
+    option_idx: i32,
+}
+
+impl CreatePoll {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.question.trim().is_empty() {
+            return Err(AppError::CreateMessage(
+                "Poll question is empty".to_string(),
+            ));
+        }
+        let len = self.options.len();
+        if !(2..=MAX_POLL_OPTIONS).contains(&len) {
+            return Err(AppError::CreateMessage(format!(
+                "Poll must have 2 to {} options",
+                MAX_POLL_OPTIONS
+            )));
+        }
+        if self.options.iter().any(|o| o.trim().is_empty()) {
+            return Err(AppError::CreateMessage("Poll option is empty".to_string()));
+        }
+        let mut options = self.options.iter().collect::<Vec<_>>();
+        options.sort_unstable();
+        options.dedup();
+        if options.len() != len {
+            return Err(AppError::CreateMessage(
+                "Poll options must be unique".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AppState {
+    pub(crate) async fn insert_poll(
+        tx: &mut Transaction<'_, Postgres>,
+        message: &Message,
+        poll: CreatePoll,
+    ) -> Result<Poll, AppError> {
+        sqlx::query(
+            "INSERT INTO polls (message_id, question, options, multi_select, anonymous) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(message.id)
+        .bind(&poll.question)
+        .bind(&poll.options)
+        .bind(poll.multi_select)
+        .bind(poll.anonymous)
+        .execute(&mut **tx)
+        .await?;
+
+        let row = PollRow {
+            message_id: message.id,
+            chat_id: message.chat_id,
+            sender_id: message.sender_id,
+            question: poll.question,
+            options: poll.options,
+            multi_select: poll.multi_select,
+            anonymous: poll.anonymous,
+            closed: false,
+        };
+        // members learn about the poll from the new message, updates are only sent for votes and closes
+        Ok(row.into_poll(&[]))
+    }
+
+    pub async fn get_poll(&self, message_id: u64) -> Result<Option<Poll>, AppError> {
+        let mut polls = self.fetch_polls(&[message_id as i64]).await?;
+        Ok(polls.remove(&(message_id as i64)))
+    }
+
+    pub async fn vote_poll(
+        &self,
+        message_id: u64,
+        user_id: u64,
+        input: VotePoll,
+    ) -> Result<Poll, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let row = self.get_poll_row(&mut tx, message_id, user_id).await?;
+        if row.closed {
+            return Err(AppError::Poll("Poll is closed".to_string()));
+        }
+
+        let mut options = input.options;
+        options.sort_unstable();
+        options.dedup();
+        if options
+            .iter()
+            .any(|idx| *idx < 0 || *idx as usize >= row.options.len())
+        {
+            return Err(AppError::Poll("Invalid poll option".to_string()));
+        }
+        if !row.multi_select && options.len() > 1 {
+            return Err(AppError::Poll(
+                "Poll only allows a single choice".to_string(),
+            ));
+        }
+
+        sqlx::query("DELETE FROM poll_votes WHERE message_id = $1 AND user_id = $2")
+            .bind(message_id as i64)
+            .bind(user_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO poll_votes (message_id, user_id, option_idx) SELECT $1, $2, UNNEST($3::INT[])",
+        )
+        .bind(message_id as i64)
+        .bind(user_id as i64)
+        .bind(&options)
+        .execute(&mut *tx)
+        .await?;
+        let poll = fetch_poll(&mut tx, row).await?;
+        notify_poll_updated(&mut *tx, &poll).await?;
+        tx.commit().await?;
+        Ok(poll)
+    }
+
+    pub async fn close_poll(&self, message_id: u64, user_id: u64) -> Result<Poll, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let mut row = self.get_poll_row(&mut tx, message_id, user_id).await?;
+        if row.sender_id != user_id as i64 {
+            return Err(AppError::Unauthorized(
+                "Only the creator could close the poll".to_string(),
+            ));
+        }
+        if row.closed {
+            return Err(AppError::Poll("Poll is closed".to_string()));
+        }
+
+        sqlx::query("UPDATE polls SET closed = TRUE WHERE message_id = $1")
+            .bind(message_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        row.closed = true;
+        let poll = fetch_poll(&mut tx, row).await?;
+        notify_poll_updated(&mut *tx, &poll).await?;
+        tx.commit().await?;
+        Ok(poll)
+    }
+
+    // attach aggregated poll results to the poll messages
+    pub(crate) async fn attach_polls(
+        &self,
+        mut messages: Vec<Message>,
+    ) -> Result<Vec<Message>, AppError> {
+        let ids = messages
+            .iter()
+            .filter(|m| m.kind == MessageKind::Poll)
+            .map(|m| m.id)
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(messages);
+        }
+        let mut polls = self.fetch_polls(&ids).await?;
+        for message in messages.iter_mut() {
+            message.poll = polls.remove(&message.id);
+        }
+        Ok(messages)
+    }
+
+    async fn fetch_polls(&self, ids: &[i64]) -> Result<HashMap<i64, Poll>, AppError> {
+        let rows: Vec<PollRow> = sqlx::query_as(
+            r#"
+                    SELECT p.message_id, m.chat_id, m.sender_id, p.question, p.options,
+                        p.multi_select, p.anonymous, p.closed
+                    FROM polls p
+                    JOIN messages m ON m.id = p.message_id
+                    WHERE p.message_id = ANY($1)"#,
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+        let votes: Vec<PollVoteRow> = sqlx::query_as(
+            "SELECT message_id, user_id, option_idx FROM poll_votes WHERE message_id = ANY($1) ORDER BY user_id",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let polls = rows
+            .into_iter()
+            .map(|row| {
+                let votes = votes
+                    .iter()
+                    .filter(|v| v.message_id == row.message_id)
+                    .collect::<Vec<_>>();
+                (row.message_id, row.into_poll(&votes))
+            })
+            .collect();
+        Ok(polls)
+    }
+
+    // get the poll and make sure the user could see it
+    // the poll is locked until the transaction ends, so a vote could not race a close
+    async fn get_poll_row(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        message_id: u64,
+        user_id: u64,
+    ) -> Result<PollRow, AppError> {
+        let row: Option<PollRow> = sqlx::query_as(
+            r#"
+                    SELECT p.message_id, m.chat_id, m.sender_id, p.question, p.options,
+                        p.multi_select, p.anonymous, p.closed
+                    FROM polls p
+                    JOIN messages m ON m.id = p.message_id
+                    WHERE p.message_id = $1
+                    FOR UPDATE OF p"#,
+        )
+        .bind(message_id as i64)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some(row) = row else {
+            return Err(AppError::NotFound(format!(
+                "Poll with id {} not found",
+                message_id
+            )));
+        };
+        if !self.is_chat_member(row.chat_id as u64, user_id).await? {
+            return Err(AppError::Unauthorized(
+                "You are not a member of this chat".to_string(),
+            ));
+        }
+        Ok(row)
+    }
+}
+
+impl PollRow {
+    fn into_poll(self, votes: &[&PollVoteRow]) -> Poll {
+        let mut voters = votes.iter().map(|v| v.user_id).collect::<Vec<_>>();
+        voters.sort_unstable();
+        voters.dedup();
+
+        let options = self
+            .options
+            .into_iter()
+            .enumerate()
+            .map(|(idx, text)| {
+                let option_voters = votes
+                    .iter()
+                    .filter(|v| v.option_idx as usize == idx)
+                    .map(|v| v.user_id)
+                    .collect::<Vec<_>>();
+                PollOption {
+                    text,
+                    votes: option_voters.len() as i64,
+                    voters: if self.anonymous {
+                        vec![]
+                    } else {
+                        option_voters
+                    },
+                }
+            })
+            .collect();
+
+        Poll {
+            message_id: self.message_id,
+            chat_id: self.chat_id,
+            question: self.question,
+            options,
+            multi_select: self.multi_select,
+            anonymous: self.anonymous,
+            closed: self.closed,
+            voters: voters.len() as i64,
+        }
+    }
+}
+
+async fn fetch_poll(tx: &mut Transaction<'_, Postgres>, row: PollRow) -> Result<Poll, AppError> {
+    let votes: Vec<PollVoteRow> = sqlx::query_as(
+        "SELECT message_id, user_id, option_idx FROM poll_votes WHERE message_id = $1 ORDER BY user_id",
+    )
+    .bind(row.message_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(row.into_poll(&votes.iter().collect::<Vec<_>>()))
+}
+
+// notify all members of the chat with the latest poll results
+async fn notify_poll_updated(
+    executor: impl sqlx::PgExecutor<'_>,
+    poll: &Poll,
+) -> Result<(), AppError> {
+    let payload = serde_json::to_string(poll).map_err(anyhow::Error::from)?;
+    sqlx::query(
+        r#"
+                SELECT pg_notify('poll_updated', json_build_object(
+                    'poll', $1::json,
+                    'chat', (SELECT row_to_json(chats) FROM chats WHERE id = $2)
+                )::text)"#,
+    )
+    .bind(payload)
+    .bind(poll.chat_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateMessage, ListMessage};
+
+    fn create_poll_message(multi_select: bool, anonymous: bool) -> CreateMessage {
+        CreateMessage {
+            poll: Some(CreatePoll {
+                question: "lunch?".to_string(),
+                options: vec!["pizza".to_string(), "noodles".to_string()],
+                multi_select,
+                anonymous,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn create_poll_validate_should_work() {
+        let poll = CreatePoll {
+            question: "lunch?".to_string(),
+            options: vec!["pizza".to_string()],
+            ..Default::default()
+        };
+        assert!(poll.validate().is_err());
+
+        let poll = CreatePoll {
+            question: "lunch?".to_string(),
+            options: vec!["pizza".to_string(), "pizza".to_string()],
+            ..Default::default()
+        };
+        assert!(poll.validate().is_err());
+
+        let poll = CreatePoll {
+            question: "".to_string(),
+            options: vec!["pizza".to_string(), "noodles".to_string()],
+            ..Default::default()
+        };
+        assert!(poll.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn create_poll_message_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let message = state
+            .create_message(create_poll_message(false, false), 1, 1)
+            .await?;
+        assert_eq!(message.kind, MessageKind::Poll);
+        assert_eq!(message.content, "lunch?");
+        let poll = message.poll.unwrap();
+        assert_eq!(poll.options.len(), 2);
+        assert_eq!(poll.voters, 0);
+
+        let input = ListMessage {
+            last_id: None,
+            limit: 1,
+        };
+        let messages = state.list_message(1, 1, input).await?;
+        assert_eq!(messages[0].id, message.id);
+        assert!(messages[0].poll.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn vote_poll_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let message = state
+            .create_message(create_poll_message(false, false), 1, 1)
+            .await?;
+        let id = message.id as u64;
+
+        let poll = state
+            .vote_poll(id, 1, VotePoll { options: vec![0] })
+            .await?;
+        assert_eq!(poll.options[0].votes, 1);
+        assert_eq!(poll.options[0].voters, vec![1]);
+
+        // change the vote
+        let poll = state
+            .vote_poll(id, 1, VotePoll { options: vec![1] })
+            .await?;
+        assert_eq!(poll.options[0].votes, 0);
+        assert_eq!(poll.options[1].votes, 1);
+
+        let poll = state
+            .vote_poll(id, 2, VotePoll { options: vec![1] })
+            .await?;
+        assert_eq!(poll.options[1].votes, 2);
+        assert_eq!(poll.voters, 2);
+
+        // single choice poll
+        let ret = state
+            .vote_poll(
+                id,
+                3,
+                VotePoll {
+                    options: vec![0, 1],
+                },
+            )
+            .await;
+        assert!(matches!(ret, Err(AppError::Poll(_))));
+
+        // invalid option
+        let ret = state.vote_poll(id, 3, VotePoll { options: vec![2] }).await;
+        assert!(matches!(ret, Err(AppError::Poll(_))));
+
+        // user 6 is not in chat 1
+        let ret = state.vote_poll(id, 6, VotePoll { options: vec![0] }).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn anonymous_multi_select_poll_should_hide_voters() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let message = state
+            .create_message(create_poll_message(true, true), 1, 1)
+            .await?;
+        let id = message.id as u64;
+
+        let poll = state
+            .vote_poll(
+                id,
+                2,
+                VotePoll {
+                    options: vec![0, 1],
+                },
+            )
+            .await?;
+        assert_eq!(poll.voters, 1);
+        assert_eq!(poll.options[0].votes, 1);
+        assert_eq!(poll.options[1].votes, 1);
+        assert!(poll.options[0].voters.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn close_poll_should_be_restricted_to_creator() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let message = state
+            .create_message(create_poll_message(false, false), 1, 1)
+            .await?;
+        let id = message.id as u64;
+
+        let ret = state.close_poll(id, 2).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let poll = state.close_poll(id, 1).await?;
+        assert!(poll.closed);
+
+        let ret = state.vote_poll(id, 2, VotePoll { options: vec![0] }).await;
+        assert!(matches!(ret, Err(AppError::Poll(_))));
+
+        let poll = state.get_poll(id).await?.unwrap();
+        assert!(poll.closed);
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::str::FromStr;
+
+use chat_core::{ChatUser, UserProfile, CHAT_USER_COLUMNS};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+use super::ChatFile;
+
+const AVATAR_EXTS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+// absent fields are kept, empty strings clear them
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateProfile {
+    #[serde(default)]
+    pub display_name: Option<String>,
+    // url of a file uploaded to one of the workspaces of the user
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    // IANA time zone like Europe/Paris
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub status: Option<UpdateStatus>,
+}
+
+// an empty text and emoji clear the status
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateStatus {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub emoji: String,
+    // the status never expires if not set
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl AppState {
+    pub async fn get_chat_user(&self, id: u64) -> Result<ChatUser, AppError> {
+        let sql = format!("SELECT {} FROM users u WHERE u.id = $1", CHAT_USER_COLUMNS);
+        let user = sqlx::query_as(&sql)
+            .bind(id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        user.ok_or_else(|| AppError::NotFound(format!("User {} not found", id)))
+    }
+
+    // profiles are visible to the users sharing a workspace
+    pub async fn get_user_profile(&self, viewer_id: u64, id: u64) -> Result<ChatUser, AppError> {
+        if viewer_id != id && !self.share_workspace(viewer_id, id).await? {
+            return Err(AppError::NotFound(format!("User {} not found", id)));
+        }
+        self.get_chat_user(id).await
+    }
+
+    pub async fn update_profile(
+        &self,
+        user_id: u64,
+        input: UpdateProfile,
+    ) -> Result<ChatUser, AppError> {
+        let mut profile: UserProfile = sqlx::query_as(
+            r#"
+                SELECT display_name, avatar_url, title, timezone, status_text, status_emoji, status_expires_at
+                FROM users
+                WHERE id = $1"#,
+        )
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", user_id)))?;
+
+        if let Some(display_name) = input.display_name {
+            profile.display_name = non_empty(&display_name, "display name", 64)?;
+        }
+        if let Some(title) = input.title {
+            profile.title = non_empty(&title, "title", 128)?;
+        }
+        if let Some(timezone) = input.timezone {
+            profile.timezone = non_empty(&timezone, "timezone", 64)?;
+            if let Some(tz) = &profile.timezone {
+                validate_timezone(tz)?;
+            }
+        }
+        if let Some(avatar_url) = input.avatar_url {
+            profile.avatar_url = non_empty(&avatar_url, "avatar url", 256)?;
+            if let Some(url) = &profile.avatar_url {
+                self.verify_avatar(user_id, url).await?;
+            }
+        }
+        if let Some(status) = input.status {
+            profile.status_text = non_empty(&status.text, "status text", 100)?;
+            profile.status_emoji = non_empty(&status.emoji, "status emoji", 32)?;
+            profile.status_expires_at = match (&profile.status_text, &profile.status_emoji) {
+                (None, None) => None,
+                _ => status.expires_at,
+            };
+            if profile.status_expires_at.is_some_and(|at| at <= Utc::now()) {
+                return Err(AppError::Profile(
+                    "Status expiry must be in the future".to_string(),
+                ));
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+                UPDATE users
+                SET display_name = $2, avatar_url = $3, title = $4, timezone = $5,
+                    status_text = $6, status_emoji = $7, status_expires_at = $8
+                WHERE id = $1"#,
+        )
+        .bind(user_id as i64)
+        .bind(&profile.display_name)
+        .bind(&profile.avatar_url)
+        .bind(&profile.title)
+        .bind(&profile.timezone)
+        .bind(&profile.status_text)
+        .bind(&profile.status_emoji)
+        .bind(profile.status_expires_at)
+        .execute(&mut *tx)
+        .await?;
+        notify_user_updated(&mut tx, user_id).await?;
+        tx.commit().await?;
+
+        self.get_chat_user(user_id).await
+    }
+
+    pub(super) async fn share_workspace(
+        &self,
+        user_id: u64,
+        other_id: u64,
+    ) -> Result<bool, AppError> {
+        let shared: bool = sqlx::query_scalar(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM workspace_members m1
+                    JOIN workspace_members m2 ON m2.ws_id = m1.ws_id
+                    WHERE m1.user_id = $1 AND m2.user_id = $2
+                )"#,
+        )
+        .bind(user_id as i64)
+        .bind(other_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(shared)
+    }
+
+    async fn verify_avatar(&self, user_id: u64, url: &str) -> Result<(), AppError> {
+        let file = ChatFile::from_str(url)
+            .map_err(|_| AppError::Profile(format!("Invalid avatar url: {}", url)))?;
+        if !AVATAR_EXTS.contains(&file.ext.to_lowercase().as_str()) {
+            return Err(AppError::Profile(format!(
+                "Avatar must be an image, got .{}",
+                file.ext
+            )));
+        }
+        if !self.is_workspace_member(file.ws_id, user_id).await?
+            || !file.path(&self.config.server.base_dir).exists()
+        {
+            return Err(AppError::Profile(format!("Avatar {} not found", url)));
+        }
+        Ok(())
+    }
+}
+
+// the notify server loads the profile and everyone sharing a workspace with the user,
+// the payload stays small whatever the size of the workspaces
+async fn notify_user_updated(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: u64,
+) -> Result<(), AppError> {
+    sqlx::query("SELECT pg_notify('user_updated', json_build_object('user_id', $1::bigint)::text)")
+        .bind(user_id as i64)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+fn non_empty(value: &str, name: &str, max_len: usize) -> Result<Option<String>, AppError> {
+    let value = value.trim();
+    if value.chars().count() > max_len {
+        return Err(AppError::Profile(format!(
+            "The {} must be at most {} characters",
+            name, max_len
+        )));
+    }
+    Ok((!value.is_empty()).then(|| value.to_string()))
+}
+
+// UTC or Area/Location names of the tz database, whether the zone exists is up to the clients
+fn validate_timezone(tz: &str) -> Result<(), AppError> {
+    let valid = tz == "UTC"
+        || tz.split('/').count() >= 2
+            && tz.split('/').all(|part| {
+                part.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+            });
+    if !valid {
+        return Err(AppError::Profile(format!("Invalid timezone: {}", tz)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use chrono::Duration;
+
+    #[test]
+    fn validate_timezone_should_work() {
+        assert!(validate_timezone("UTC").is_ok());
+        assert!(validate_timezone("Europe/Paris").is_ok());
+        assert!(validate_timezone("America/Argentina/Buenos_Aires").is_ok());
+        assert!(validate_timezone("Etc/GMT+8").is_ok());
+        assert!(validate_timezone("Paris").is_err());
+        assert!(validate_timezone("Europe/../etc").is_err());
+    }
+
+    #[tokio::test]
+    async fn update_profile_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = UpdateProfile {
+            display_name: Some(" Tyr ".to_string()),
+            title: Some("Engineer".to_string()),
+            timezone: Some("Asia/Shanghai".to_string()),
+            status: Some(UpdateStatus {
+                text: "In a meeting".to_string(),
+                emoji: ":calendar:".to_string(),
+                expires_at: Some(Utc::now() + Duration::hours(1)),
+            }),
+            ..Default::default()
+        };
+        let user = state.update_profile(1, input).await?;
+        assert_eq!(user.profile.display_name.as_deref(), Some("Tyr"));
+        assert_eq!(user.profile.title.as_deref(), Some("Engineer"));
+        assert_eq!(user.profile.status_text.as_deref(), Some("In a meeting"));
+
+        // absent fields are kept, empty ones cleared
+        let input = UpdateProfile {
+            title: Some("".to_string()),
+            ..Default::default()
+        };
+        let user = state.update_profile(1, input).await?;
+        assert_eq!(user.profile.display_name.as_deref(), Some("Tyr"));
+        assert_eq!(user.profile.title, None);
+        assert_eq!(user.profile.timezone.as_deref(), Some("Asia/Shanghai"));
+
+        // other members see the profile
+        let user = state.get_user_profile(2, 1).await?;
+        assert_eq!(user.profile.display_name.as_deref(), Some("Tyr"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_status_should_be_hidden() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        sqlx::query(
+            "UPDATE users SET status_text = 'away', status_expires_at = NOW() - INTERVAL '1 minute' WHERE id = 1",
+        )
+        .execute(&state.pool)
+        .await?;
+        let user = state.get_chat_user(1).await?;
+        assert_eq!(user.profile.status_text, None);
+        assert_eq!(user.profile.status_expires_at, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_profile_should_reject_invalid_input() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = UpdateProfile {
+            avatar_url: Some("/files/0/abc/def/ghi.png".to_string()),
+            ..Default::default()
+        };
+        let ret = state.update_profile(1, input).await;
+        assert!(matches!(ret, Err(AppError::Profile(_))));
+
+        let input = UpdateProfile {
+            avatar_url: Some("/files/0/abc/def/ghi.txt".to_string()),
+            ..Default::default()
+        };
+        let ret = state.update_profile(1, input).await;
+        assert!(matches!(ret, Err(AppError::Profile(_))));
+
+        let input = UpdateProfile {
+            status: Some(UpdateStatus {
+                text: "lunch".to_string(),
+                expires_at: Some(Utc::now() - Duration::hours(1)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let ret = state.update_profile(1, input).await;
+        assert!(matches!(ret, Err(AppError::Profile(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_user_profile_should_hide_strangers() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let mut input = crate::models::SignupUser::new("Stranger", "stranger@acme.org", "123456");
+        input.workspace = "elsewhere".to_string();
+        let user = state.create_user(&input).await?;
+        let ret = state.get_user_profile(1, user.id as u64).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use chat_core::SavedMessage;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ListSaved {
+    pub last_id: Option<u64>,
+    pub limit: u64,
+}
+
+impl AppState {
+    pub async fn save_message(&self, user_id: u64, message_id: u64) -> Result<(), AppError> {
+        // only members of the chat could see the message, so only they could save it
+        let chat_id: Option<(i64,)> = sqlx::query_as("SELECT chat_id FROM messages WHERE id = $1")
+            .bind(message_id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some((chat_id,)) = chat_id else {
+            return Err(AppError::NotFound(format!(
+                "Message with id {} not found",
+                message_id
+            )));
+        };
+        if !self.is_chat_member(chat_id as u64, user_id).await? {
+            return Err(AppError::Unauthorized(
+                "You are not a member of this chat".to_string(),
+            ));
+        }
+
+        sqlx::query(
+            "INSERT INTO saved_messages (user_id, message_id) VALUES ($1, $2) ON CONFLICT (user_id, message_id) DO NOTHING",
+        )
+        .bind(user_id as i64)
+        .bind(message_id as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn unsave_message(&self, user_id: u64, message_id: u64) -> Result<(), AppError> {
+        let ret = sqlx::query("DELETE FROM saved_messages WHERE user_id = $1 AND message_id = $2")
+            .bind(user_id as i64)
+            .bind(message_id as i64)
+            .execute(&self.pool)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Saved message with id {} not found",
+                message_id
+            )));
+        }
+        Ok(())
+    }
+
+    pub async fn list_saved_messages(
+        &self,
+        user_id: u64,
+        input: ListSaved,
+    ) -> Result<Vec<SavedMessage>, AppError> {
+        let last_id = input.last_id.unwrap_or(i64::MAX as u64);
+        let messages = sqlx::query_as(
+            r#"
+                    SELECT s.id AS saved_id, c.name AS chat_name, s.created_at AS saved_at, m.*
+                    FROM saved_messages s
+                    JOIN messages m ON m.id = s.message_id
+                    JOIN chats c ON c.id = m.chat_id
+                    WHERE s.user_id = $1 AND s.id < $2
+                    ORDER BY s.id DESC
+                    LIMIT $3"#,
+        )
+        .bind(user_id as i64)
+        .bind(last_id as i64)
+        .bind(input.limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UpdateChat;
+
+    #[tokio::test]
+    async fn save_and_list_message_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.save_message(1, 1).await?;
+        state.save_message(1, 2).await?;
+        state.save_message(1, 3).await?;
+        // save twice should be ignored
+        state.save_message(1, 3).await?;
+
+        let input = ListSaved {
+            last_id: None,
+            limit: 2,
+        };
+        let saved = state.list_saved_messages(1, input).await?;
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].message.id, 3);
+        assert_eq!(saved[0].chat_name, Some("general".to_string()));
+
+        let input = ListSaved {
+            last_id: Some(saved[1].saved_id as u64),
+            limit: 2,
+        };
+        let saved = state.list_saved_messages(1, input).await?;
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].message.id, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn save_message_should_require_membership() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // user 6 is not in chat 1
+        let ret = state.save_message(6, 1).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let ret = state.save_message(1, 1000).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unsave_message_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.save_message(1, 1).await?;
+        state.unsave_message(1, 1).await?;
+        let ret = state.unsave_message(1, 1).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn saved_message_should_be_removed_when_leaving_chat() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.save_message(1, 1).await?;
+        state.save_message(5, 1).await?;
+
+        let update_chat = UpdateChat {
+            name: Some("general".to_string()),
+            members: vec![1, 2, 3, 4],
+            public: true,
+        };
+        state.update_chat(1, update_chat).await?;
+
+        let input = ListSaved {
+            last_id: None,
+            limit: 10,
+        };
+        assert_eq!(state.list_saved_messages(1, input.clone()).await?.len(), 1);
+        assert_eq!(state.list_saved_messages(5, input).await?.len(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,1362 @@
+use std::collections::HashMap;
+
+use chat_core::WorkspaceRole;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{FromRow, Postgres, Transaction};
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+pub const SCIM_USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const SCIM_GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const SCIM_LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+const MAX_FULLNAME_LEN: usize = 64;
+const MAX_EMAIL_LEN: usize = 64;
+const MAX_GROUP_NAME_LEN: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    // the email of the user
+    pub user_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<ScimName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<ScimEmail>,
+    // inactive users are not members of the workspace
+    #[serde(default = "default_active")]
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: String,
+    pub created: DateTime<Utc>,
+    pub last_modified: DateTime<Utc>,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub display_name: String,
+    #[serde(default)]
+    pub members: Vec<ScimMember>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ScimMember {
+    // id of the user
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[aliases(ScimUserList = ScimListResponse<ScimUser>, ScimGroupList = ScimListResponse<ScimGroup>)]
+pub struct ScimListResponse<T> {
+    pub schemas: Vec<String>,
+    pub total_results: i64,
+    pub start_index: i64,
+    pub items_per_page: i64,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListQuery {
+    // `userName eq "alice@acme.org"` like filter, expressions could only be joined by `and`
+    pub filter: Option<String>,
+    // 1-based index of the first result
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ScimPatch {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOp>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ScimPatchOp {
+    // add, replace or remove
+    pub op: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub value: Value,
+}
+
+#[derive(Debug, FromRow)]
+struct ScimUserRow {
+    id: i64,
+    fullname: String,
+    email: String,
+    external_id: Option<String>,
+    active: bool,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct ScimGroupRow {
+    id: i64,
+    display_name: String,
+    external_id: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct ScimMemberRow {
+    group_id: i64,
+    user_id: i64,
+    fullname: String,
+}
+
+// the account attributes the provider manages
+#[derive(Debug, PartialEq)]
+struct UserAttributes {
+    email: String,
+    fullname: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterOp {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Pr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FilterExpr {
+    attr: String,
+    op: FilterOp,
+    value: String,
+}
+
+// attribute expressions joined by `and`, attribute names and values are compared ignoring case
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScimFilter(Vec<FilterExpr>);
+
+enum FilterToken {
+    Word(String),
+    Str(String),
+}
+
+// users of the workspace: its members and the deactivated users the provider knows
+const SCIM_USERS_QUERY: &str = r#"
+    SELECT u.id, u.fullname, u.email, u.created_at, s.external_id, s.updated_at,
+        m.user_id IS NOT NULL AS active
+    FROM users u
+    LEFT JOIN workspace_members m ON m.ws_id = $1 AND m.user_id = u.id
+    LEFT JOIN scim_users s ON s.ws_id = $1 AND s.user_id = u.id
+    WHERE (m.user_id IS NOT NULL OR s.user_id IS NOT NULL) AND NOT u.is_bot
+        AND ($2::BIGINT IS NULL OR u.id = $2)
+    ORDER BY u.id"#;
+
+impl AppState {
+    pub async fn list_scim_users(
+        &self,
+        ws_id: u64,
+        query: &ScimListQuery,
+    ) -> Result<ScimListResponse<ScimUser>, AppError> {
+        let filter = query.filter.as_deref().map(ScimFilter::parse).transpose()?;
+        let users = self
+            .fetch_scim_users(ws_id, None)
+            .await?
+            .into_iter()
+            .filter(|u| {
+                filter
+                    .as_ref()
+                    .is_none_or(|f| f.matches(|a| u.attribute(a)))
+            })
+            .collect();
+        Ok(paginate(users, query))
+    }
+
+    pub async fn get_scim_user(&self, ws_id: u64, id: i64) -> Result<ScimUser, AppError> {
+        self.fetch_scim_users(ws_id, Some(id))
+            .await?
+            .pop()
+            .ok_or_else(|| AppError::NotFound(format!("User with id {} not found", id)))
+    }
+
+    // create the account or link the existing one with the same email, only accounts of
+    // the members of the workspace or of its allowed domains could be linked
+    pub async fn create_scim_user(
+        &self,
+        ws_id: u64,
+        input: ScimUser,
+    ) -> Result<ScimUser, AppError> {
+        let attrs = UserAttributes::from_scim(&input)?;
+        let mut tx = self.pool.begin().await?;
+        let existing: Option<(i64, bool)> = sqlx::query_as(
+            "SELECT id, is_bot FROM users WHERE lower(email) = lower($1) FOR UPDATE",
+        )
+        .bind(&attrs.email)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let user_id = match existing {
+            Some((_, true)) => {
+                return Err(AppError::ScimConflict(format!(
+                    "Email {} is used by a bot",
+                    attrs.email
+                )))
+            }
+            Some((id, false)) => {
+                let (linked, linkable): (bool, bool) = sqlx::query_as(
+                    r#"
+                        SELECT
+                            EXISTS (SELECT 1 FROM scim_users WHERE ws_id = $1 AND user_id = $2),
+                            EXISTS (SELECT 1 FROM workspace_members WHERE ws_id = $1 AND user_id = $2)
+                                OR EXISTS (
+                                    SELECT 1 FROM workspaces
+                                    WHERE id = $1
+                                        AND substring(lower($3) from '@([^@]*)$') = ANY(allowed_domains)
+                                )"#,
+                )
+                .bind(ws_id as i64)
+                .bind(id)
+                .bind(&attrs.email)
+                .fetch_one(&mut *tx)
+                .await?;
+                if linked {
+                    return Err(AppError::ScimConflict(format!(
+                        "User {} already exists in the workspace",
+                        attrs.email
+                    )));
+                }
+                if !linkable {
+                    return Err(AppError::ScimConflict(format!(
+                        "Email {} is used by an account outside of the workspace",
+                        attrs.email
+                    )));
+                }
+                id
+            }
+            None => {
+                sqlx::query_scalar(
+                    r#"
+                        INSERT INTO users (fullname, email, ws_id, email_verified_at)
+                        VALUES ($1, $2, $3, NOW())
+                        RETURNING id"#,
+                )
+                .bind(&attrs.fullname)
+                .bind(&attrs.email)
+                .bind(ws_id as i64)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+        upsert_scim_user(&mut tx, ws_id, user_id, input.external_id.as_deref()).await?;
+        tx.commit().await?;
+        if input.active {
+            self.add_workspace_member(ws_id, user_id as u64).await?;
+        }
+        self.get_scim_user(ws_id, user_id).await
+    }
+
+    pub async fn replace_scim_user(
+        &self,
+        ws_id: u64,
+        id: i64,
+        input: ScimUser,
+    ) -> Result<ScimUser, AppError> {
+        let current = self.get_scim_user(ws_id, id).await?;
+        let attrs = UserAttributes::from_scim(&input)?;
+        let mut tx = self.pool.begin().await?;
+        if attrs.email != current.user_name
+            || Some(&attrs.fullname) != current.display_name.as_ref()
+        {
+            update_user_attributes(&mut tx, ws_id, id, &attrs).await?;
+        }
+        upsert_scim_user(&mut tx, ws_id, id, input.external_id.as_deref()).await?;
+        tx.commit().await?;
+        self.users.forget_user(id);
+        if input.active != current.active {
+            self.set_scim_user_active(ws_id, id, input.active).await?;
+        }
+        self.get_scim_user(ws_id, id).await
+    }
+
+    pub async fn patch_scim_user(
+        &self,
+        ws_id: u64,
+        id: i64,
+        patch: ScimPatch,
+    ) -> Result<ScimUser, AppError> {
+        let current = self.get_scim_user(ws_id, id).await?;
+        let Value::Object(mut user) =
+            serde_json::to_value(&current).map_err(anyhow::Error::from)?
+        else {
+            unreachable!("users are serialized as objects");
+        };
+        for op in &patch.operations {
+            apply_user_patch(&mut user, op)?;
+        }
+        let input: ScimUser = serde_json::from_value(Value::Object(user))
+            .map_err(|e| AppError::Scim(format!("Invalid user after patch: {}", e)))?;
+        self.replace_scim_user(ws_id, id, input).await
+    }
+
+    // deactivate the user and forget it, the account itself is kept
+    pub async fn delete_scim_user(&self, ws_id: u64, id: i64) -> Result<(), AppError> {
+        let current = self.get_scim_user(ws_id, id).await?;
+        if current.active {
+            self.set_scim_user_active(ws_id, id, false).await?;
+        }
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM scim_users WHERE ws_id = $1 AND user_id = $2")
+            .bind(ws_id as i64)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+                DELETE FROM user_group_members
+                WHERE user_id = $2 AND group_id IN (SELECT id FROM user_groups WHERE ws_id = $1)"#,
+        )
+        .bind(ws_id as i64)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // inactive users leave the workspace and their tokens of the workspace are revoked,
+    // the access to their other workspaces is kept
+    async fn set_scim_user_active(
+        &self,
+        ws_id: u64,
+        id: i64,
+        active: bool,
+    ) -> Result<(), AppError> {
+        if active {
+            return self.add_workspace_member(ws_id, id as u64).await;
+        }
+        if self.get_workspace_role(ws_id, id as u64).await? == Some(WorkspaceRole::Owner) {
+            return Err(AppError::Workspace(
+                "The owner could not be deactivated".to_string(),
+            ));
+        }
+        self.delete_workspace_member(ws_id, id as u64).await?;
+        let mut tx = self.pool.begin().await?;
+        for table in ["access_tokens", "refresh_tokens"] {
+            sqlx::query(&format!(
+                "UPDATE {} SET revoked_at = NOW() WHERE user_id = $1 AND ws_id = $2 AND revoked_at IS NULL",
+                table
+            ))
+            .bind(id)
+            .bind(ws_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn list_scim_groups(
+        &self,
+        ws_id: u64,
+        query: &ScimListQuery,
+    ) -> Result<ScimListResponse<ScimGroup>, AppError> {
+        let filter = query.filter.as_deref().map(ScimFilter::parse).transpose()?;
+        let groups = self
+            .fetch_scim_groups(ws_id, None)
+            .await?
+            .into_iter()
+            .filter(|g| {
+                filter
+                    .as_ref()
+                    .is_none_or(|f| f.matches(|a| g.attribute(a)))
+            })
+            .collect();
+        Ok(paginate(groups, query))
+    }
+
+    pub async fn get_scim_group(&self, ws_id: u64, id: i64) -> Result<ScimGroup, AppError> {
+        self.fetch_scim_groups(ws_id, Some(id))
+            .await?
+            .pop()
+            .ok_or_else(|| AppError::NotFound(format!("Group with id {} not found", id)))
+    }
+
+    pub async fn create_scim_group(
+        &self,
+        ws_id: u64,
+        input: ScimGroup,
+    ) -> Result<ScimGroup, AppError> {
+        let name = validate_group_name(&input.display_name)?;
+        let members = self.scim_member_ids(ws_id, &input.members).await?;
+        let mut tx = self.pool.begin().await?;
+        ensure_unique_group_name(&mut tx, ws_id, name, None).await?;
+        let id: i64 = sqlx::query_scalar(
+            r#"
+                INSERT INTO user_groups (ws_id, display_name, external_id)
+                VALUES ($1, $2, $3)
+                RETURNING id"#,
+        )
+        .bind(ws_id as i64)
+        .bind(name)
+        .bind(&input.external_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        insert_group_members(&mut tx, id, &members).await?;
+        tx.commit().await?;
+        self.get_scim_group(ws_id, id).await
+    }
+
+    pub async fn replace_scim_group(
+        &self,
+        ws_id: u64,
+        id: i64,
+        input: ScimGroup,
+    ) -> Result<ScimGroup, AppError> {
+        self.get_scim_group(ws_id, id).await?;
+        let name = validate_group_name(&input.display_name)?;
+        let members = self.scim_member_ids(ws_id, &input.members).await?;
+        let mut tx = self.pool.begin().await?;
+        ensure_unique_group_name(&mut tx, ws_id, name, Some(id)).await?;
+        sqlx::query(
+            r#"
+                UPDATE user_groups SET display_name = $2, external_id = $3, updated_at = NOW()
+                WHERE id = $1"#,
+        )
+        .bind(id)
+        .bind(name)
+        .bind(&input.external_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM user_group_members WHERE group_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        insert_group_members(&mut tx, id, &members).await?;
+        tx.commit().await?;
+        self.get_scim_group(ws_id, id).await
+    }
+
+    pub async fn patch_scim_group(
+        &self,
+        ws_id: u64,
+        id: i64,
+        patch: ScimPatch,
+    ) -> Result<ScimGroup, AppError> {
+        let mut group = self.get_scim_group(ws_id, id).await?;
+        for op in &patch.operations {
+            apply_group_patch(
+                &mut group,
+                &op.op.to_lowercase(),
+                op.path.as_deref(),
+                &op.value,
+            )?;
+        }
+        self.replace_scim_group(ws_id, id, group).await
+    }
+
+    pub async fn delete_scim_group(&self, ws_id: u64, id: i64) -> Result<(), AppError> {
+        let ret = sqlx::query("DELETE FROM user_groups WHERE id = $1 AND ws_id = $2")
+            .bind(id)
+            .bind(ws_id as i64)
+            .execute(&self.pool)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Group with id {} not found",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn fetch_scim_users(
+        &self,
+        ws_id: u64,
+        id: Option<i64>,
+    ) -> Result<Vec<ScimUser>, AppError> {
+        let rows: Vec<ScimUserRow> = sqlx::query_as(SCIM_USERS_QUERY)
+            .bind(ws_id as i64)
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(ScimUser::from).collect())
+    }
+
+    async fn fetch_scim_groups(
+        &self,
+        ws_id: u64,
+        id: Option<i64>,
+    ) -> Result<Vec<ScimGroup>, AppError> {
+        let rows: Vec<ScimGroupRow> = sqlx::query_as(
+            r#"
+                SELECT id, display_name, external_id, created_at, updated_at
+                FROM user_groups
+                WHERE ws_id = $1 AND ($2::BIGINT IS NULL OR id = $2)
+                ORDER BY id"#,
+        )
+        .bind(ws_id as i64)
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let member_rows: Vec<ScimMemberRow> = sqlx::query_as(
+            r#"
+                SELECT gm.group_id, gm.user_id, u.fullname
+                FROM user_group_members gm
+                JOIN user_groups g ON g.id = gm.group_id
+                JOIN users u ON u.id = gm.user_id
+                WHERE g.ws_id = $1 AND ($2::BIGINT IS NULL OR g.id = $2)
+                ORDER BY gm.user_id"#,
+        )
+        .bind(ws_id as i64)
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut members: HashMap<i64, Vec<ScimMember>> = HashMap::new();
+        for row in member_rows {
+            members.entry(row.group_id).or_default().push(ScimMember {
+                value: row.user_id.to_string(),
+                display: Some(row.fullname),
+            });
+        }
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let members = members.remove(&row.id).unwrap_or_default();
+                ScimGroup::from_row(row, members)
+            })
+            .collect())
+    }
+
+    // ids of the group members, they must be users of the workspace
+    async fn scim_member_ids(
+        &self,
+        ws_id: u64,
+        members: &[ScimMember],
+    ) -> Result<Vec<i64>, AppError> {
+        let mut ids = members
+            .iter()
+            .map(|m| {
+                m.value
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| AppError::Scim(format!("Invalid group member: {}", m.value)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        ids.sort_unstable();
+        ids.dedup();
+        let known: Vec<i64> = sqlx::query_scalar(
+            r#"
+                SELECT u.id FROM users u
+                WHERE u.id = ANY($2)
+                    AND (EXISTS (SELECT 1 FROM workspace_members WHERE ws_id = $1 AND user_id = u.id)
+                        OR EXISTS (SELECT 1 FROM scim_users WHERE ws_id = $1 AND user_id = u.id))"#,
+        )
+        .bind(ws_id as i64)
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await?;
+        if known.len() != ids.len() {
+            return Err(AppError::Scim(
+                "Group members must be users of the workspace".to_string(),
+            ));
+        }
+        Ok(ids)
+    }
+}
+
+impl ScimUser {
+    // value of the (lowercased) attribute the filters compare with
+    fn attribute(&self, attr: &str) -> Vec<String> {
+        let value = match attr {
+            "id" => self.id.clone(),
+            "externalid" => self.external_id.clone(),
+            "username" => Some(self.user_name.clone()),
+            "displayname" => self.display_name.clone(),
+            "name.formatted" => self.name.as_ref().and_then(|n| n.formatted.clone()),
+            "emails" | "emails.value" => {
+                return self.emails.iter().map(|e| e.value.clone()).collect();
+            }
+            "active" => Some(self.active.to_string()),
+            _ => None,
+        };
+        value.into_iter().collect()
+    }
+}
+
+impl From<ScimUserRow> for ScimUser {
+    fn from(row: ScimUserRow) -> Self {
+        Self {
+            schemas: vec![SCIM_USER_SCHEMA.to_string()],
+            id: Some(row.id.to_string()),
+            external_id: row.external_id,
+            user_name: row.email.clone(),
+            name: Some(ScimName {
+                formatted: Some(row.fullname.clone()),
+                ..Default::default()
+            }),
+            display_name: Some(row.fullname),
+            emails: vec![ScimEmail {
+                value: row.email,
+                kind: Some("work".to_string()),
+                primary: true,
+            }],
+            active: row.active,
+            meta: Some(ScimMeta {
+                resource_type: "User".to_string(),
+                created: row.created_at,
+                last_modified: row.updated_at.unwrap_or(row.created_at),
+                location: format!("/scim/v2/Users/{}", row.id),
+            }),
+        }
+    }
+}
+
+impl ScimGroup {
+    fn from_row(row: ScimGroupRow, members: Vec<ScimMember>) -> Self {
+        Self {
+            schemas: vec![SCIM_GROUP_SCHEMA.to_string()],
+            id: Some(row.id.to_string()),
+            external_id: row.external_id,
+            display_name: row.display_name,
+            members,
+            meta: Some(ScimMeta {
+                resource_type: "Group".to_string(),
+                created: row.created_at,
+                last_modified: row.updated_at.unwrap_or(row.created_at),
+                location: format!("/scim/v2/Groups/{}", row.id),
+            }),
+        }
+    }
+
+    fn attribute(&self, attr: &str) -> Vec<String> {
+        let value = match attr {
+            "id" => self.id.clone(),
+            "externalid" => self.external_id.clone(),
+            "displayname" => Some(self.display_name.clone()),
+            "members" | "members.value" => {
+                return self.members.iter().map(|m| m.value.clone()).collect();
+            }
+            _ => None,
+        };
+        value.into_iter().collect()
+    }
+}
+
+impl UserAttributes {
+    fn from_scim(input: &ScimUser) -> Result<Self, AppError> {
+        let email = input.user_name.trim();
+        if !email.contains('@') || email.len() > MAX_EMAIL_LEN {
+            return Err(AppError::Scim(format!(
+                "userName must be the email of the user: {}",
+                input.user_name
+            )));
+        }
+        let non_empty = |v: Option<&str>| {
+            v.map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+        };
+        let name = input.name.clone().unwrap_or_default();
+        let given = [name.given_name.as_deref(), name.family_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let fullname = non_empty(name.formatted.as_deref())
+            .or_else(|| non_empty(Some(&given)))
+            .or_else(|| non_empty(input.display_name.as_deref()))
+            .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+        Ok(Self {
+            email: email.to_string(),
+            fullname: fullname.chars().take(MAX_FULLNAME_LEN).collect(),
+        })
+    }
+}
+
+impl ScimFilter {
+    pub fn parse(input: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::Scim(format!("Invalid filter: {}", input));
+        let mut tokens = tokenize_filter(input).ok_or_else(invalid)?.into_iter();
+        let mut exprs = Vec::new();
+        loop {
+            let Some(FilterToken::Word(attr)) = tokens.next() else {
+                return Err(invalid());
+            };
+            let Some(FilterToken::Word(op)) = tokens.next() else {
+                return Err(invalid());
+            };
+            let op = match op.to_lowercase().as_str() {
+                "eq" => FilterOp::Eq,
+                "ne" => FilterOp::Ne,
+                "co" => FilterOp::Co,
+                "sw" => FilterOp::Sw,
+                "ew" => FilterOp::Ew,
+                "pr" => FilterOp::Pr,
+                _ => return Err(AppError::Scim(format!("Unsupported filter: {}", input))),
+            };
+            let value = match op {
+                FilterOp::Pr => String::new(),
+                _ => match tokens.next() {
+                    Some(FilterToken::Str(v) | FilterToken::Word(v)) => v,
+                    None => return Err(invalid()),
+                },
+            };
+            // attributes could be prefixed with the urn of their schema
+            let attr = attr.rsplit(':').next().unwrap_or_default().to_lowercase();
+            exprs.push(FilterExpr { attr, op, value });
+            match tokens.next() {
+                None => break,
+                Some(FilterToken::Word(w)) if w.eq_ignore_ascii_case("and") => {}
+                _ => return Err(AppError::Scim(format!("Unsupported filter: {}", input))),
+            }
+        }
+        Ok(Self(exprs))
+    }
+
+    // multi-valued attributes match if any of their values does
+    fn matches(&self, lookup: impl Fn(&str) -> Vec<String>) -> bool {
+        self.0.iter().all(|expr| {
+            let expected = expr.value.to_lowercase();
+            let values = lookup(&expr.attr)
+                .into_iter()
+                .map(|v| v.to_lowercase())
+                .collect::<Vec<_>>();
+            match expr.op {
+                FilterOp::Pr => values.iter().any(|v| !v.is_empty()),
+                FilterOp::Eq => values.contains(&expected),
+                FilterOp::Ne => values.iter().all(|v| *v != expected),
+                FilterOp::Co => values.iter().any(|v| v.contains(&expected)),
+                FilterOp::Sw => values.iter().any(|v| v.starts_with(&expected)),
+                FilterOp::Ew => values.iter().any(|v| v.ends_with(&expected)),
+            }
+        })
+    }
+}
+
+fn default_active() -> bool {
+    true
+}
+
+fn tokenize_filter(input: &str) -> Option<Vec<FilterToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next()? {
+                        '\\' => value.push(chars.next()?),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+                tokens.push(FilterToken::Str(value));
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                    word.push(c);
+                }
+                tokens.push(FilterToken::Word(word));
+            }
+        }
+    }
+    Some(tokens)
+}
+
+fn paginate<T>(items: Vec<T>, query: &ScimListQuery) -> ScimListResponse<T> {
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query
+        .count
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(0, MAX_PAGE_SIZE);
+    let total_results = items.len() as i64;
+    let resources = items
+        .into_iter()
+        .skip(start_index as usize - 1)
+        .take(count as usize)
+        .collect::<Vec<_>>();
+    ScimListResponse {
+        schemas: vec![SCIM_LIST_SCHEMA.to_string()],
+        total_results,
+        start_index,
+        items_per_page: resources.len() as i64,
+        resources,
+    }
+}
+
+// apply the operation on the serialized user, unknown attributes are ignored
+fn apply_user_patch(user: &mut Map<String, Value>, op: &ScimPatchOp) -> Result<(), AppError> {
+    let name = op.op.to_lowercase();
+    if !matches!(name.as_str(), "add" | "replace" | "remove") {
+        return Err(AppError::Scim(format!(
+            "Unsupported patch operation: {}",
+            op.op
+        )));
+    }
+    match op.path.as_deref() {
+        Some(path) => set_user_attribute(user, &name, path, op.value.clone()),
+        None => {
+            let Value::Object(values) = &op.value else {
+                return Err(AppError::Scim(
+                    "Patch operations without a path need an object value".to_string(),
+                ));
+            };
+            for (path, value) in values {
+                set_user_attribute(user, &name, path, value.clone())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn set_user_attribute(
+    user: &mut Map<String, Value>,
+    op: &str,
+    path: &str,
+    value: Value,
+) -> Result<(), AppError> {
+    let value = if op == "remove" { Value::Null } else { value };
+    let path = path.rsplit(':').next().unwrap_or_default().to_lowercase();
+    match path.as_str() {
+        "active" => {
+            // some providers send booleans as strings
+            let active = match &value {
+                Value::Bool(b) => *b,
+                Value::String(s) if s.eq_ignore_ascii_case("true") => true,
+                Value::String(s) if s.eq_ignore_ascii_case("false") => false,
+                _ => return Err(AppError::Scim(format!("Invalid active value: {}", value))),
+            };
+            user.insert("active".to_string(), Value::Bool(active));
+        }
+        "username" => {
+            user.insert("userName".to_string(), value);
+        }
+        // the email is the user name
+        p if p.starts_with("emails") => {
+            let email = match &value {
+                Value::Array(emails) => emails.first().and_then(|e| e.get("value")).cloned(),
+                Value::Object(email) => email.get("value").cloned(),
+                v => Some(v.clone()),
+            };
+            user.insert("userName".to_string(), email.unwrap_or(Value::Null));
+        }
+        "externalid" => {
+            user.insert("externalId".to_string(), value);
+        }
+        "displayname" => {
+            if let Some(Value::Object(name)) = user.get_mut("name") {
+                name.remove("formatted");
+            }
+            user.insert("displayName".to_string(), value);
+        }
+        "name" => {
+            user.remove("displayName");
+            user.insert("name".to_string(), value);
+        }
+        "name.formatted" | "name.givenname" | "name.familyname" => {
+            user.remove("displayName");
+            let name = user
+                .entry("name".to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !name.is_object() {
+                *name = Value::Object(Map::new());
+            }
+            if let Value::Object(name) = name {
+                let key = match path.as_str() {
+                    "name.givenname" => "givenName",
+                    "name.familyname" => "familyName",
+                    _ => "formatted",
+                };
+                // the formatted name is derived from the parts
+                if key != "formatted" {
+                    name.remove("formatted");
+                }
+                name.insert(key.to_string(), value);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn apply_group_patch(
+    group: &mut ScimGroup,
+    op: &str,
+    path: Option<&str>,
+    value: &Value,
+) -> Result<(), AppError> {
+    let members = || {
+        serde_json::from_value::<Vec<ScimMember>>(value.clone())
+            .map_err(|e| AppError::Scim(format!("Invalid group members: {}", e)))
+    };
+    let path = path.map(|p| p.trim().to_lowercase());
+    match (op, path.as_deref()) {
+        ("add" | "replace", None) => {
+            let Value::Object(values) = value else {
+                return Err(AppError::Scim(
+                    "Patch operations without a path need an object value".to_string(),
+                ));
+            };
+            for (path, value) in values {
+                apply_group_patch(group, op, Some(path), value)?;
+            }
+        }
+        ("add" | "replace", Some("displayname")) => {
+            group.display_name = value.as_str().unwrap_or_default().to_string();
+        }
+        ("add" | "replace", Some("externalid")) => {
+            group.external_id = value.as_str().map(|v| v.to_string());
+        }
+        ("add", Some("members")) => group.members.extend(members()?),
+        ("replace", Some("members")) => group.members = members()?,
+        ("remove", Some("members")) if value.is_null() => group.members.clear(),
+        ("remove", Some("members")) => {
+            let removed = members()?;
+            group
+                .members
+                .retain(|m| !removed.iter().any(|r| r.value == m.value));
+        }
+        // members[value eq "2"]
+        ("remove", Some(p)) if p.starts_with("members[") && p.ends_with(']') => {
+            let filter = ScimFilter::parse(&p["members[".len()..p.len() - 1])?;
+            group
+                .members
+                .retain(|m| !filter.matches(|a| scim_member_attribute(m, a)));
+        }
+        ("remove", Some("externalid")) => group.external_id = None,
+        _ => {
+            return Err(AppError::Scim(format!(
+                "Unsupported patch operation: {} {}",
+                op,
+                path.unwrap_or_default()
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn scim_member_attribute(member: &ScimMember, attr: &str) -> Vec<String> {
+    match attr {
+        "value" => vec![member.value.clone()],
+        "display" => member.display.clone().into_iter().collect(),
+        _ => vec![],
+    }
+}
+
+fn validate_group_name(name: &str) -> Result<&str, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > MAX_GROUP_NAME_LEN {
+        return Err(AppError::Scim(format!("Invalid group name: {}", name)));
+    }
+    Ok(name)
+}
+
+async fn ensure_unique_group_name(
+    tx: &mut Transaction<'_, Postgres>,
+    ws_id: u64,
+    name: &str,
+    id: Option<i64>,
+) -> Result<(), AppError> {
+    let exists: bool = sqlx::query_scalar(
+        r#"
+            SELECT EXISTS (
+                SELECT 1 FROM user_groups
+                WHERE ws_id = $1 AND display_name = $2 AND id IS DISTINCT FROM $3
+            )"#,
+    )
+    .bind(ws_id as i64)
+    .bind(name)
+    .bind(id)
+    .fetch_one(&mut **tx)
+    .await?;
+    if exists {
+        return Err(AppError::ScimConflict(format!(
+            "Group {} already exists",
+            name
+        )));
+    }
+    Ok(())
+}
+
+async fn insert_group_members(
+    tx: &mut Transaction<'_, Postgres>,
+    group_id: i64,
+    members: &[i64],
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            INSERT INTO user_group_members (group_id, user_id)
+            SELECT $1, unnest($2::BIGINT[])
+            ON CONFLICT DO NOTHING"#,
+    )
+    .bind(group_id)
+    .bind(members)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn upsert_scim_user(
+    tx: &mut Transaction<'_, Postgres>,
+    ws_id: u64,
+    user_id: i64,
+    external_id: Option<&str>,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            INSERT INTO scim_users (ws_id, user_id, external_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (ws_id, user_id)
+            DO UPDATE SET external_id = EXCLUDED.external_id, updated_at = NOW()"#,
+    )
+    .bind(ws_id as i64)
+    .bind(user_id)
+    .bind(external_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// the provider could only change accounts no other workspace uses
+async fn update_user_attributes(
+    tx: &mut Transaction<'_, Postgres>,
+    ws_id: u64,
+    user_id: i64,
+    attrs: &UserAttributes,
+) -> Result<(), AppError> {
+    let shared: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM workspace_members WHERE user_id = $1 AND ws_id <> $2)",
+    )
+    .bind(user_id)
+    .bind(ws_id as i64)
+    .fetch_one(&mut **tx)
+    .await?;
+    if shared {
+        return Err(AppError::Scim(
+            "The user belongs to other workspaces, its name and email could not be changed"
+                .to_string(),
+        ));
+    }
+    let taken: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM users WHERE lower(email) = lower($1) AND id <> $2)",
+    )
+    .bind(&attrs.email)
+    .bind(user_id)
+    .fetch_one(&mut **tx)
+    .await?;
+    if taken {
+        return Err(AppError::EmailAlreadyExists(attrs.email.clone()));
+    }
+    sqlx::query(
+        r#"
+            UPDATE users
+            SET fullname = $2, email = $3,
+                email_verified_at = CASE WHEN email = $3 THEN email_verified_at ELSE NOW() END
+            WHERE id = $1"#,
+    )
+    .bind(user_id)
+    .bind(&attrs.fullname)
+    .bind(&attrs.email)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceInfo;
+    use chat_core::{AuthContext, TokenVerifier};
+    use serde_json::json;
+
+    fn scim_user(email: &str, name: &str) -> ScimUser {
+        serde_json::from_value(json!({
+            "schemas": [SCIM_USER_SCHEMA],
+            "userName": email,
+            "externalId": "ext-1",
+            "name": { "givenName": name, "familyName": "Smith" },
+        }))
+        .unwrap()
+    }
+
+    fn patch(operations: Value) -> ScimPatch {
+        serde_json::from_value(json!({ "Operations": operations })).unwrap()
+    }
+
+    #[test]
+    fn scim_filter_should_work() -> Result<(), AppError> {
+        let filter = ScimFilter::parse(
+            r#"userName eq "Alice@Acme.org" and urn:ietf:params:scim:schemas:core:2.0:User:active eq true"#,
+        )?;
+        let lookup = |attr: &str| match attr {
+            "username" => vec!["alice@acme.org".to_string()],
+            "active" => vec!["true".to_string()],
+            _ => vec![],
+        };
+        assert!(filter.matches(lookup));
+        assert!(ScimFilter::parse(r#"username sw "al""#)?.matches(lookup));
+        assert!(!ScimFilter::parse("externalId pr")?.matches(lookup));
+        assert!(ScimFilter::parse(r#"externalId ne "x""#)?.matches(lookup));
+        assert!(ScimFilter::parse(r#"displayName eq "a \"b\"""#).is_ok());
+
+        assert!(ScimFilter::parse(r#"userName eq "a" or userName eq "b""#).is_err());
+        assert!(ScimFilter::parse(r#"userName gt "a""#).is_err());
+        assert!(ScimFilter::parse(r#"userName eq "a"#).is_err());
+        assert!(ScimFilter::parse("userName eq").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scim_user_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state
+            .create_scim_user(1, scim_user("alice@acme.org", "Alice"))
+            .await?;
+        assert!(user.active);
+        assert_eq!(user.display_name.as_deref(), Some("Alice Smith"));
+        assert_eq!(user.external_id.as_deref(), Some("ext-1"));
+        let id: i64 = user.id.as_deref().unwrap().parse().unwrap();
+        assert!(state.is_workspace_member(1, id as u64).await?);
+
+        // the same account could not be provisioned twice
+        let ret = state
+            .create_scim_user(1, scim_user("Alice@acme.org", "Alice"))
+            .await;
+        assert!(matches!(ret, Err(AppError::ScimConflict(_))));
+        // accounts of other workspaces are not taken over
+        let ret = state
+            .create_scim_user(1, scim_user("test2@none.org", "Test"))
+            .await;
+        assert!(matches!(ret, Err(AppError::ScimConflict(_))));
+        assert!(!state.is_workspace_member(1, 2).await?);
+        // but the accounts of the members are linked
+        state.add_workspace_member(1, 2).await?;
+        let linked = state
+            .create_scim_user(1, scim_user("test2@none.org", "Test"))
+            .await?;
+        assert_eq!(linked.display_name.as_deref(), Some("test2"));
+        // and the accounts of the allowed domains
+        let policy = crate::models::UpdateJoinPolicy {
+            join_policy: chat_core::JoinPolicy::Domain,
+            allowed_domains: vec!["none.org".to_string()],
+        };
+        state.update_join_policy(1, 0, policy).await?;
+        state
+            .create_scim_user(1, scim_user("test3@none.org", "Test"))
+            .await?;
+
+        let query = ScimListQuery {
+            filter: Some(r#"userName eq "ALICE@acme.org""#.to_string()),
+            ..Default::default()
+        };
+        let list = state.list_scim_users(1, &query).await?;
+        assert_eq!(list.total_results, 1);
+        assert_eq!(list.resources[0].id, user.id);
+        let query = ScimListQuery {
+            start_index: Some(2),
+            count: Some(1),
+            ..Default::default()
+        };
+        let list = state.list_scim_users(1, &query).await?;
+        assert_eq!(list.total_results, 3);
+        assert_eq!(list.items_per_page, 1);
+        assert_eq!(list.start_index, 2);
+
+        let user = state
+            .patch_scim_user(
+                1,
+                id,
+                patch(json!([
+                    { "op": "replace", "path": "name.givenName", "value": "Alicia" },
+                    { "op": "replace", "path": "name.familyName", "value": "Smith" },
+                    { "op": "replace", "path": "emails[type eq \"work\"].value", "value": "alicia@acme.org" },
+                ])),
+            )
+            .await?;
+        assert_eq!(user.display_name.as_deref(), Some("Alicia Smith"));
+        assert_eq!(user.user_name, "alicia@acme.org");
+
+        // accounts of other workspaces could not be renamed
+        let ret = state
+            .patch_scim_user(
+                1,
+                2,
+                patch(json!([{ "op": "replace", "path": "displayName", "value": "x" }])),
+            )
+            .await;
+        assert!(matches!(ret, Err(AppError::Scim(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deactivate_scim_user_should_revoke_access() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state
+            .create_scim_user(1, scim_user("bob@acme.org", "Bob"))
+            .await?;
+        let id: i64 = user.id.as_deref().unwrap().parse().unwrap();
+        let account = state.find_user_by_email("bob@acme.org").await?.unwrap();
+        let sid = state
+            .create_session(id as u64, &DeviceInfo::default())
+            .await?;
+        let token = state.ek.sign_session(account.clone(), sid)?;
+        state.verify(&token).await?;
+        let refresh = state.create_refresh_token(&account, Some(sid)).await?;
+
+        // azure sends booleans as strings
+        let user = state
+            .patch_scim_user(
+                1,
+                id,
+                patch(json!([{ "op": "replace", "value": { "active": "False" } }])),
+            )
+            .await?;
+        assert!(!user.active);
+        assert!(!state.is_workspace_member(1, id as u64).await?);
+        let ret = state.load_user(&AuthContext::new(id, 1)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        assert!(state.rotate_refresh_token(&refresh).await.is_err());
+        // the session is kept for the other workspaces of the user
+        state.verify(&token).await?;
+        // deactivated users are still listed
+        assert!(!state.get_scim_user(1, id).await?.active);
+
+        let user = state
+            .patch_scim_user(
+                1,
+                id,
+                patch(json!([{ "op": "replace", "path": "active", "value": true }])),
+            )
+            .await?;
+        assert!(user.active);
+        assert!(state.is_workspace_member(1, id as u64).await?);
+
+        state.delete_scim_user(1, id).await?;
+        assert!(!state.is_workspace_member(1, id as u64).await?);
+        let ret = state.get_scim_user(1, id).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        // the owner could not be deactivated
+        state.add_workspace_member(1, 0).await?;
+        let ret = state.delete_scim_user(1, 0).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scim_group_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        for id in 1..=3 {
+            state.add_workspace_member(1, id).await?;
+        }
+        let input: ScimGroup = serde_json::from_value(json!({
+            "schemas": [SCIM_GROUP_SCHEMA],
+            "displayName": "Engineering",
+            "members": [{ "value": "1" }, { "value": "2" }],
+        }))
+        .unwrap();
+        let group = state.create_scim_group(1, input.clone()).await?;
+        assert_eq!(group.members.len(), 2);
+        assert_eq!(group.members[0].display.as_deref(), Some("test1"));
+        let ret = state.create_scim_group(1, input).await;
+        assert!(matches!(ret, Err(AppError::ScimConflict(_))));
+        let id: i64 = group.id.as_deref().unwrap().parse().unwrap();
+
+        let group = state
+            .patch_scim_group(
+                1,
+                id,
+                patch(json!([
+                    { "op": "add", "path": "members", "value": [{ "value": "3" }] },
+                    { "op": "remove", "path": "members[value eq \"1\"]" },
+                    { "op": "replace", "value": { "displayName": "Platform" } },
+                ])),
+            )
+            .await?;
+        assert_eq!(group.display_name, "Platform");
+        let members = group
+            .members
+            .iter()
+            .map(|m| m.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(members, vec!["2", "3"]);
+
+        // members must be users of the workspace
+        let ret = state
+            .patch_scim_group(
+                1,
+                id,
+                patch(json!([{ "op": "add", "path": "members", "value": [{ "value": "999" }] }])),
+            )
+            .await;
+        assert!(matches!(ret, Err(AppError::Scim(_))));
+
+        let query = ScimListQuery {
+            filter: Some(r#"displayName eq "platform""#.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(state.list_scim_groups(1, &query).await?.total_results, 1);
+        state.delete_scim_group(1, id).await?;
+        let ret = state.get_scim_group(1, id).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+}
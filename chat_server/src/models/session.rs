@@ -0,0 +1,218 @@
+use chat_core::{Revoked, Session, JWT_DURATION};
+use chrono::Utc;
+
+use crate::{error::AppError, state::AppState};
+
+const MAX_DEVICE_NAME_LEN: usize = 128;
+
+// the device a session is created from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceInfo {
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl AppState {
+    // every signin ends here, deactivated users are turned away whatever the way they signed in
+    pub async fn create_session(&self, user_id: u64, device: &DeviceInfo) -> Result<i64, AppError> {
+        let id = sqlx::query_scalar(
+            r#"
+                INSERT INTO sessions (user_id, device_name, user_agent, ip)
+                SELECT id, $2, $3, $4 FROM users WHERE id = $1 AND deactivated_at IS NULL
+                RETURNING id"#,
+        )
+        .bind(user_id as i64)
+        .bind(device.name())
+        .bind(&device.user_agent)
+        .bind(&device.ip)
+        .fetch_optional(&self.pool)
+        .await?;
+        id.ok_or_else(|| AppError::LoginFailed("The account is deactivated".to_string()))
+    }
+
+    // active sessions of the user, the most recently used first
+    pub async fn list_sessions(
+        &self,
+        user_id: u64,
+        current: Option<i64>,
+    ) -> Result<Vec<Session>, AppError> {
+        let sessions = sqlx::query_as(
+            r#"
+                SELECT id, user_id, device_name, user_agent, ip, id = $2 AS current,
+                    created_at, last_seen_at
+                FROM sessions
+                WHERE user_id = $1 AND revoked_at IS NULL
+                ORDER BY last_seen_at DESC, id DESC"#,
+        )
+        .bind(user_id as i64)
+        .bind(current.unwrap_or(0))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    // sign out the device: its refresh tokens are revoked and its access tokens rejected
+    pub async fn revoke_session(&self, user_id: u64, id: u64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let ret = sqlx::query(
+            r#"
+                UPDATE sessions SET revoked_at = NOW()
+                WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"#,
+        )
+        .bind(id as i64)
+        .bind(user_id as i64)
+        .execute(&mut *tx)
+        .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Session with id {} not found",
+                id
+            )));
+        }
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE session_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(id as i64)
+        .execute(&mut *tx)
+        .await?;
+        // access tokens of the session expire by themselves after JWT_DURATION
+        let exp = Utc::now().timestamp() as u64 + JWT_DURATION;
+        self.revoke(&mut tx, Revoked::Session(id as i64), exp)
+            .await?;
+        tx.commit().await?;
+        self.revoked.revoke(Revoked::Session(id as i64), exp);
+        Ok(())
+    }
+
+    // sign out all the devices of the user but the current one
+    pub async fn revoke_other_sessions(
+        &self,
+        user_id: u64,
+        current: Option<i64>,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+                UPDATE sessions SET revoked_at = NOW()
+                WHERE user_id = $1 AND revoked_at IS NULL AND id IS DISTINCT FROM $2
+                RETURNING id"#,
+        )
+        .bind(user_id as i64)
+        .bind(current)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+                UPDATE refresh_tokens SET revoked_at = NOW()
+                WHERE user_id = $1 AND revoked_at IS NULL AND session_id IS DISTINCT FROM $2"#,
+        )
+        .bind(user_id as i64)
+        .bind(current)
+        .execute(&mut *tx)
+        .await?;
+        let exp = Utc::now().timestamp() as u64 + JWT_DURATION;
+        for id in &ids {
+            self.revoke(&mut tx, Revoked::Session(*id), exp).await?;
+        }
+        tx.commit().await?;
+        for id in ids {
+            self.revoked.revoke(Revoked::Session(id), exp);
+        }
+        Ok(())
+    }
+}
+
+impl DeviceInfo {
+    pub fn name(&self) -> String {
+        let name = match self.device_name.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => describe_user_agent(self.user_agent.as_deref().unwrap_or_default()),
+        };
+        name.chars().take(MAX_DEVICE_NAME_LEN).collect()
+    }
+}
+
+// "Chrome on macOS" like description of the user agent
+fn describe_user_agent(ua: &str) -> String {
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ];
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ];
+    let find = |list: &[(&str, &'static str)]| {
+        list.iter()
+            .find(|(pattern, _)| ua.contains(pattern))
+            .map(|(_, name)| *name)
+    };
+    match (find(BROWSERS), find(SYSTEMS)) {
+        (Some(browser), Some(system)) => format!("{} on {}", browser, system),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Unknown device".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_core::TokenVerifier;
+
+    #[test]
+    fn device_name_should_work() {
+        let device = DeviceInfo {
+            user_agent: Some("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(device.name(), "Chrome on macOS");
+        let device = DeviceInfo {
+            device_name: Some(" my laptop ".to_string()),
+            ..device
+        };
+        assert_eq!(device.name(), "my laptop");
+        assert_eq!(DeviceInfo::default().name(), "Unknown device");
+    }
+
+    #[tokio::test]
+    async fn revoke_session_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let sid = state
+            .create_session(user.id as u64, &DeviceInfo::default())
+            .await?;
+        let other = state
+            .create_session(user.id as u64, &DeviceInfo::default())
+            .await?;
+        let refresh_token = state.create_refresh_token(&user, Some(sid)).await?;
+        let token = state.ek.sign_session(user.clone(), sid)?;
+        state.verify(&token).await?;
+
+        let sessions = state.list_sessions(user.id as u64, Some(sid)).await?;
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().any(|s| s.id == sid && s.current));
+        assert!(sessions.iter().any(|s| s.id == other && !s.current));
+
+        // other users could not revoke the session
+        let ret = state.revoke_session(2, sid as u64).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        state.revoke_session(user.id as u64, sid as u64).await?;
+        assert!(state.verify(&token).await.is_err());
+        let ret = state.rotate_refresh_token(&refresh_token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let sessions = state.list_sessions(user.id as u64, None).await?;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, other);
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use chat_core::User;
+use serde::{Deserialize, Serialize};
+
+use super::token::hash_token;
+use crate::{
+    error::AppError,
+    oidc::{OidcClient, OidcIdentity},
+    state::AppState,
+};
+
+const SSO_LOGIN_TTL_MINUTES: i32 = 10;
+
+// query of the redirect from the provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SsoCallback {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_description: Option<String>,
+}
+
+impl AppState {
+    pub fn oidc_client(&self, ws_id: u64) -> Result<&OidcClient, AppError> {
+        self.sso
+            .get(&(ws_id as i64))
+            .ok_or_else(|| AppError::NotFound(format!("Workspace {} has no single sign-on", ws_id)))
+    }
+
+    // url of the provider to redirect the user agent to
+    pub async fn start_sso_login(&self, client: &OidcClient) -> Result<String, AppError> {
+        let req = client.authorization_request().await?;
+        sqlx::query(
+            r#"
+                INSERT INTO sso_logins (ws_id, state_hash, nonce, code_verifier, expires_at)
+                VALUES ($1, $2, $3, $4, NOW() + make_interval(mins => $5))"#,
+        )
+        .bind(client.ws_id())
+        .bind(hash_token(&req.state))
+        .bind(&req.nonce)
+        .bind(&req.code_verifier)
+        .bind(SSO_LOGIN_TTL_MINUTES)
+        .execute(&self.pool)
+        .await?;
+        Ok(req.url)
+    }
+
+    // the user signed in by the provider, it joins the workspace of the provider
+    pub async fn finish_sso_login(
+        &self,
+        client: &OidcClient,
+        input: &SsoCallback,
+    ) -> Result<User, AppError> {
+        if let Some(error) = &input.error {
+            return Err(AppError::Unauthorized(format!(
+                "Provider returned {}: {}",
+                error,
+                input.error_description.as_deref().unwrap_or_default()
+            )));
+        }
+        let (Some(code), Some(state)) = (&input.code, &input.state) else {
+            return Err(AppError::Unauthorized(
+                "Missing code or state of the provider".to_string(),
+            ));
+        };
+        // the state is single use
+        let login: Option<(String, String)> = sqlx::query_as(
+            r#"
+                DELETE FROM sso_logins
+                WHERE state_hash = $1 AND ws_id = $2 AND expires_at > NOW()
+                RETURNING nonce, code_verifier"#,
+        )
+        .bind(hash_token(state))
+        .bind(client.ws_id())
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((nonce, code_verifier)) = login else {
+            return Err(AppError::Unauthorized(
+                "Invalid or expired sso state".to_string(),
+            ));
+        };
+        let identity = client.exchange_code(code, &code_verifier, &nonce).await?;
+        let user_id = self.provision_sso_user(client.ws_id(), &identity).await?;
+        self.add_workspace_member(client.ws_id() as u64, user_id as u64)
+            .await?;
+        let mut user: User = sqlx::query_as(
+            "SELECT id, fullname, email, ws_id, created_at FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        user.ws_id = client.ws_id();
+        Ok(user)
+    }
+
+    // the linked account, otherwise the account with the verified email is linked or created
+    async fn provision_sso_user(
+        &self,
+        ws_id: i64,
+        identity: &OidcIdentity,
+    ) -> Result<i64, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let linked: Option<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM user_identities WHERE issuer = $1 AND subject = $2",
+        )
+        .bind(&identity.issuer)
+        .bind(&identity.subject)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(user_id) = linked {
+            return Ok(user_id);
+        }
+
+        // an unverified email could belong to someone else
+        let Some(email) = identity
+            .email
+            .as_deref()
+            .filter(|_| identity.email_verified)
+        else {
+            return Err(AppError::Unauthorized(
+                "Provider did not return a verified email".to_string(),
+            ));
+        };
+        let existing: Option<(i64, bool)> = sqlx::query_as(
+            "SELECT id, is_bot FROM users WHERE lower(email) = lower($1) FOR UPDATE",
+        )
+        .bind(email)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let user_id = match existing {
+            Some((_, true)) => {
+                return Err(AppError::Unauthorized("Bots could not sign in".to_string()))
+            }
+            Some((id, false)) => {
+                sqlx::query(
+                    "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1",
+                )
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+                id
+            }
+            None => {
+                let fullname = identity
+                    .name
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+                // the account has no password, it signs in with the provider
+                sqlx::query_scalar(
+                    r#"
+                        INSERT INTO users (fullname, email, ws_id, email_verified_at)
+                        VALUES ($1, $2, $3, NOW())
+                        RETURNING id"#,
+                )
+                .bind(fullname)
+                .bind(email)
+                .bind(ws_id)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+        sqlx::query(
+            r#"
+                INSERT INTO user_identities (user_id, issuer, subject)
+                VALUES ($1, $2, $3)"#,
+        )
+        .bind(user_id)
+        .bind(&identity.issuer)
+        .bind(&identity.subject)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(user_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::test_provider::{MockProvider, MockUser};
+
+    async fn sign_in(
+        state: &AppState,
+        provider: &MockProvider,
+        client: &OidcClient,
+        user: MockUser,
+    ) -> Result<User, AppError> {
+        let url = state.start_sso_login(client).await?;
+        let (code, csrf) = provider.authorize(&url, user);
+        let input = SsoCallback {
+            code: Some(code),
+            state: Some(csrf),
+            ..Default::default()
+        };
+        state.finish_sso_login(client, &input).await
+    }
+
+    fn mock_user(subject: &str, email: &str, email_verified: bool) -> MockUser {
+        MockUser {
+            subject: subject.to_string(),
+            email: email.to_string(),
+            email_verified,
+            name: "Alice Smith".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sso_should_provision_users() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let provider = MockProvider::start().await;
+        let client = OidcClient::new(provider.config(2))?;
+
+        let user = sign_in(
+            &state,
+            &provider,
+            &client,
+            mock_user("alice", "alice@acme.org", true),
+        )
+        .await?;
+        assert_eq!(user.fullname, "Alice Smith");
+        assert_eq!(user.ws_id, 2);
+        assert!(state.is_workspace_member(2, user.id as u64).await?);
+
+        // the subject is linked, the email could change at the provider
+        let again = sign_in(
+            &state,
+            &provider,
+            &client,
+            mock_user("alice", "alice@new.org", false),
+        )
+        .await?;
+        assert_eq!(again.id, user.id);
+
+        let ret = sign_in(
+            &state,
+            &provider,
+            &client,
+            mock_user("bob", "bob@acme.org", false),
+        )
+        .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sso_should_link_existing_accounts() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let provider = MockProvider::start().await;
+        let client = OidcClient::new(provider.config(2))?;
+        let existing = state.find_user_by_email("test1@none.org").await?.unwrap();
+
+        // an unverified email does not take over the account
+        let ret = sign_in(
+            &state,
+            &provider,
+            &client,
+            mock_user("t1", "test1@none.org", false),
+        )
+        .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let user = sign_in(
+            &state,
+            &provider,
+            &client,
+            mock_user("t1", "TEST1@none.org", true),
+        )
+        .await?;
+        assert_eq!(user.id, existing.id);
+        assert_eq!(user.ws_id, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sso_state_should_be_single_use() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let provider = MockProvider::start().await;
+        let client = OidcClient::new(provider.config(2))?;
+        let url = state.start_sso_login(&client).await?;
+        let (code, csrf) = provider.authorize(&url, mock_user("alice", "alice@acme.org", true));
+        let input = SsoCallback {
+            code: Some(code),
+            state: Some(csrf),
+            ..Default::default()
+        };
+        // the state of another workspace is rejected
+        let other = OidcClient::new(provider.config(1))?;
+        let ret = state.finish_sso_login(&other, &input).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        state.finish_sso_login(&client, &input).await?;
+        let ret = state.finish_sso_login(&client, &input).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+}
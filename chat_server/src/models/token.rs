@@ -0,0 +1,371 @@
+use std::time::Duration as StdDuration;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::{Revoked, User, JWT_DURATION};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgConnection};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::{error::AppError, state::AppState};
+
+const REFRESH_TOKEN_DURATION: i64 = 60 * 60 * 24 * 30; // 30 days
+const CLEANUP_INTERVAL: StdDuration = StdDuration::from_secs(600);
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RefreshToken {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Logout {
+    // the refresh token family is revoked as well if given
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct RefreshTokenRow {
+    id: i64,
+    user_id: i64,
+    ws_id: i64,
+    family_id: String,
+    session_id: Option<i64>,
+    expires_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+// pg_notify('token_revoked', json_build_object('item', item, 'exp', exp)::text);
+#[derive(Debug, Serialize)]
+struct TokenRevoked<'a> {
+    item: &'a Revoked,
+    exp: u64,
+}
+
+impl AppState {
+    // issue a refresh token starting a new family, scoped to the workspace of the user
+    pub async fn create_refresh_token(
+        &self,
+        user: &User,
+        session_id: Option<i64>,
+    ) -> Result<String, AppError> {
+        let token = generate_token();
+        sqlx::query(
+            r#"
+                INSERT INTO refresh_tokens (user_id, ws_id, token_hash, family_id, session_id, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6)"#,
+        )
+        .bind(user.id)
+        .bind(user.ws_id)
+        .bind(hash_token(&token))
+        .bind(generate_family_id())
+        .bind(session_id)
+        .bind(Utc::now() + Duration::seconds(REFRESH_TOKEN_DURATION))
+        .execute(&self.pool)
+        .await?;
+        Ok(token)
+    }
+
+    // exchange a refresh token for a new one, reusing a rotated token revokes the whole family,
+    // the session of the token is returned as well
+    pub async fn rotate_refresh_token(
+        &self,
+        token: &str,
+    ) -> Result<(User, String, Option<i64>), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let row: Option<RefreshTokenRow> = sqlx::query_as(
+            r#"
+                SELECT id, user_id, ws_id, family_id, session_id, expires_at, revoked_at
+                FROM refresh_tokens
+                WHERE token_hash = $1
+                FOR UPDATE"#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(row) = row else {
+            return Err(invalid_refresh_token());
+        };
+        if row.revoked_at.is_some() {
+            warn!(
+                "Refresh token reused, revoking token family {}",
+                row.family_id
+            );
+            revoke_family(&mut tx, &row.family_id).await?;
+            tx.commit().await?;
+            return Err(invalid_refresh_token());
+        }
+        if row.expires_at <= Utc::now() {
+            return Err(invalid_refresh_token());
+        }
+
+        let user: Option<User> = sqlx::query_as(
+            "SELECT id, fullname, email, ws_id, created_at FROM users WHERE id = $1",
+        )
+        .bind(row.user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let is_member = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM workspace_members WHERE ws_id = $1 AND user_id = $2)",
+        )
+        .bind(row.ws_id)
+        .bind(row.user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        let (Some(mut user), true) = (user, is_member) else {
+            revoke_family(&mut tx, &row.family_id).await?;
+            tx.commit().await?;
+            return Err(invalid_refresh_token());
+        };
+        user.ws_id = row.ws_id;
+
+        let token = generate_token();
+        let id: i64 = sqlx::query_scalar(
+            r#"
+                INSERT INTO refresh_tokens (user_id, ws_id, token_hash, family_id, session_id, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING id"#,
+        )
+        .bind(row.user_id)
+        .bind(row.ws_id)
+        .bind(hash_token(&token))
+        .bind(&row.family_id)
+        .bind(row.session_id)
+        .bind(Utc::now() + Duration::seconds(REFRESH_TOKEN_DURATION))
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = NOW(), replaced_by = $1 WHERE id = $2")
+            .bind(id)
+            .bind(row.id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(session_id) = row.session_id {
+            sqlx::query("UPDATE sessions SET last_seen_at = NOW() WHERE id = $1")
+                .bind(session_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok((user, token, row.session_id))
+    }
+
+    // revoke the family of the refresh token if it belongs to the user
+    pub async fn revoke_refresh_token(&self, user_id: u64, token: &str) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+                UPDATE refresh_tokens
+                SET revoked_at = NOW()
+                WHERE revoked_at IS NULL AND family_id = (
+                    SELECT family_id FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2
+                )"#,
+        )
+        .bind(hash_token(token))
+        .bind(user_id as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // revoke an access token until it expires, other servers are notified via pg_notify
+    pub async fn revoke_access_token(&self, jti: &str, expires_at: u64) -> Result<(), AppError> {
+        let item = Revoked::Token(jti.to_string());
+        let mut tx = self.pool.begin().await?;
+        self.revoke(&mut tx, item.clone(), expires_at).await?;
+        tx.commit().await?;
+        self.revoked.revoke(item, expires_at);
+        Ok(())
+    }
+
+    // persist the revocation and notify the other servers, revoked sessions are kept in sessions
+    pub(crate) async fn revoke(
+        &self,
+        conn: &mut PgConnection,
+        item: Revoked,
+        expires_at: u64,
+    ) -> Result<(), AppError> {
+        if let Revoked::Token(jti) = &item {
+            let exp = DateTime::from_timestamp(expires_at as i64, 0)
+                .ok_or_else(|| AppError::Unauthorized("Invalid token expiration".to_string()))?;
+            sqlx::query(
+                "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            )
+            .bind(jti)
+            .bind(exp)
+            .execute(&mut *conn)
+            .await?;
+        }
+        let payload = serde_json::to_string(&TokenRevoked {
+            item: &item,
+            exp: expires_at,
+        })
+        .map_err(anyhow::Error::from)?;
+        sqlx::query("SELECT pg_notify('token_revoked', $1)")
+            .bind(payload)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn load_revoked_tokens(&self) -> Result<(), AppError> {
+        let tokens: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+                SELECT jti, extract(epoch FROM expires_at)::bigint
+                FROM revoked_tokens
+                WHERE expires_at > NOW()"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for (jti, exp) in tokens {
+            self.revoked.revoke(Revoked::Token(jti), exp as u64);
+        }
+        // access tokens of revoked sessions are valid for at most JWT_DURATION
+        let sessions: Vec<(i64, i64)> = sqlx::query_as(
+            r#"
+                SELECT id, extract(epoch FROM revoked_at)::bigint + $1
+                FROM sessions
+                WHERE revoked_at > NOW() - make_interval(secs => $1)"#,
+        )
+        .bind(JWT_DURATION as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        for (id, exp) in sessions {
+            self.revoked.revoke(Revoked::Session(id), exp as u64);
+        }
+        Ok(())
+    }
+
+    pub async fn purge_expired_tokens(&self) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM revoked_tokens WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM password_resets WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM two_factor_challenges WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM sso_logins WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+        self.signin_throttle.purge().await?;
+        self.revoked.purge_expired(Utc::now().timestamp() as u64);
+        Ok(())
+    }
+}
+
+pub fn spawn_token_cleanup(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+            if let Err(e) = state.purge_expired_tokens().await {
+                warn!("Failed to purge expired tokens: {}", e);
+            }
+        }
+    });
+}
+
+async fn revoke_family(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    family_id: &str,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(family_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn invalid_refresh_token() -> AppError {
+    AppError::Unauthorized("Invalid or expired refresh token".to_string())
+}
+
+pub(super) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn generate_family_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub(super) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.trim().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_core::TokenVerifier;
+
+    #[tokio::test]
+    async fn refresh_token_should_rotate() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let token = state.create_refresh_token(&user, None).await?;
+
+        let (user2, token2, _) = state.rotate_refresh_token(&token).await?;
+        assert_eq!(user2.id, user.id);
+        assert_eq!(user2.ws_id, user.ws_id);
+        assert_ne!(token, token2);
+
+        // reusing the rotated token revokes the whole family
+        let ret = state.rotate_refresh_token(&token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let ret = state.rotate_refresh_token(&token2).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let ret = state.rotate_refresh_token("invalid").await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_refresh_token_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let token = state.create_refresh_token(&user, None).await?;
+
+        // other users could not revoke the token
+        state.revoke_refresh_token(2, &token).await?;
+        let (_, token, _) = state.rotate_refresh_token(&token).await?;
+
+        state.revoke_refresh_token(user.id as u64, &token).await?;
+        let ret = state.rotate_refresh_token(&token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoked_access_token_should_fail() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let token = state.ek.sign(user)?;
+        state.verify(&token).await?;
+
+        let claims = state.dk.decode(&token)?;
+        let jti = claims.jwt_id.unwrap();
+        let exp = claims.expires_at.unwrap().as_secs();
+        state.revoke_access_token(&jti, exp).await?;
+        assert!(matches!(
+            state.verify(&token).await,
+            Err(AppError::Unauthorized(_))
+        ));
+
+        // the revocation list is persisted
+        state.revoked.purge_expired(u64::MAX);
+        assert!(!state.revoked.is_revoked(&Revoked::Token(jti.clone())));
+        state.load_revoked_tokens().await?;
+        assert!(state.revoked.is_revoked(&Revoked::Token(jti)));
+        Ok(())
+    }
+}
@@ -0,0 +1,578 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::User;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+use totp_rs::{Algorithm, TOTP};
+use utoipa::ToSchema;
+
+use super::token::{generate_token, hash_token};
+use crate::{error::AppError, state::AppState};
+
+const TOTP_ISSUER: &str = "Chat";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+const TOTP_SECRET_LEN: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+const CHALLENGE_TTL_MINUTES: i32 = 5;
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TotpEnrollment {
+    // base32 secret for entering it by hand
+    pub secret: String,
+    // otpauth:// uri, usually shown as a qr code
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorCode {
+    // code of the authenticator app, or a recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RecoveryCodes {
+    // only returned once
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorChallenge {
+    // exchanged with a code for the tokens via /api/signin/2fa
+    pub challenge_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VerifyTwoFactor {
+    pub challenge_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, FromRow)]
+struct TotpRow {
+    secret: String,
+    confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_used_step: Option<i64>,
+}
+
+#[derive(Debug, FromRow)]
+struct ChallengeRow {
+    id: i64,
+    user_id: i64,
+    ws_id: i64,
+    device_name: Option<String>,
+    attempts: i32,
+}
+
+impl AppState {
+    pub async fn two_factor_status(&self, user_id: u64) -> Result<TwoFactorStatus, AppError> {
+        let (enabled, recovery_codes_left): (bool, i64) = sqlx::query_as(
+            r#"
+                SELECT
+                    EXISTS(SELECT 1 FROM user_totp WHERE user_id = $1 AND confirmed_at IS NOT NULL),
+                    (SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL)"#,
+        )
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(TwoFactorStatus {
+            enabled,
+            recovery_codes_left,
+        })
+    }
+
+    // a new secret replaces the pending one, it is not enabled until confirmed
+    pub async fn enroll_totp(&self, user: &User) -> Result<TotpEnrollment, AppError> {
+        let mut secret = [0u8; TOTP_SECRET_LEN];
+        OsRng.fill_bytes(&mut secret);
+        let totp = build_totp(secret.to_vec(), &user.email)?;
+        let ret = sqlx::query(
+            r#"
+                INSERT INTO user_totp (user_id, secret)
+                VALUES ($1, $2)
+                ON CONFLICT (user_id) DO UPDATE
+                SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+                WHERE user_totp.confirmed_at IS NULL"#,
+        )
+        .bind(user.id)
+        .bind(hex::encode(secret))
+        .execute(&self.pool)
+        .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::TwoFactor(
+                "Two-factor authentication is already enabled".to_string(),
+            ));
+        }
+        Ok(TotpEnrollment {
+            secret: totp.get_secret_base32(),
+            provisioning_uri: totp.get_url(),
+        })
+    }
+
+    // enable two-factor authentication with the first code of the app
+    pub async fn confirm_totp(
+        &self,
+        user: &User,
+        input: &TwoFactorCode,
+    ) -> Result<RecoveryCodes, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let row = find_totp(&mut tx, user.id).await?;
+        let Some(row) = row.filter(|r| r.confirmed_at.is_none()) else {
+            return Err(AppError::TwoFactor(
+                "No pending two-factor enrollment".to_string(),
+            ));
+        };
+        let Some(step) = match_totp(&row, &user.email, &input.code)? else {
+            return Err(invalid_code());
+        };
+        sqlx::query(
+            "UPDATE user_totp SET confirmed_at = NOW(), last_used_step = $2 WHERE user_id = $1",
+        )
+        .bind(user.id)
+        .bind(step)
+        .execute(&mut *tx)
+        .await?;
+        let codes = replace_recovery_codes(&mut tx, user.id).await?;
+        tx.commit().await?;
+        self.users.forget_user(user.id);
+        Ok(RecoveryCodes {
+            recovery_codes: codes,
+        })
+    }
+
+    pub async fn disable_two_factor(
+        &self,
+        user: &User,
+        input: &TwoFactorCode,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        if !verify_second_factor(&mut tx, user, &input.code).await? {
+            return Err(invalid_code());
+        }
+        sqlx::query("DELETE FROM user_totp WHERE user_id = $1")
+            .bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.users.forget_user(user.id);
+        Ok(())
+    }
+
+    pub async fn regenerate_recovery_codes(
+        &self,
+        user: &User,
+        input: &TwoFactorCode,
+    ) -> Result<RecoveryCodes, AppError> {
+        let mut tx = self.pool.begin().await?;
+        if !verify_second_factor(&mut tx, user, &input.code).await? {
+            return Err(invalid_code());
+        }
+        let codes = replace_recovery_codes(&mut tx, user.id).await?;
+        tx.commit().await?;
+        Ok(RecoveryCodes {
+            recovery_codes: codes,
+        })
+    }
+
+    // a challenge if the user has two-factor authentication enabled
+    pub async fn create_two_factor_challenge(
+        &self,
+        user: &User,
+        device_name: Option<&str>,
+    ) -> Result<Option<TwoFactorChallenge>, AppError> {
+        if !self.two_factor_status(user.id as u64).await?.enabled {
+            return Ok(None);
+        }
+        let token = generate_token();
+        sqlx::query(
+            r#"
+                INSERT INTO two_factor_challenges (user_id, ws_id, token_hash, device_name, expires_at)
+                VALUES ($1, $2, $3, $4, NOW() + make_interval(mins => $5))"#,
+        )
+        .bind(user.id)
+        .bind(user.ws_id)
+        .bind(hash_token(&token))
+        .bind(device_name)
+        .bind(CHALLENGE_TTL_MINUTES)
+        .execute(&self.pool)
+        .await?;
+        Ok(Some(TwoFactorChallenge {
+            challenge_token: token,
+            expires_in: CHALLENGE_TTL_MINUTES as i64 * 60,
+        }))
+    }
+
+    // the signed in user and the device name of the challenge
+    pub async fn verify_two_factor_challenge(
+        &self,
+        input: &VerifyTwoFactor,
+    ) -> Result<(User, Option<String>), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let challenge: Option<ChallengeRow> = sqlx::query_as(
+            r#"
+                SELECT id, user_id, ws_id, device_name, attempts
+                FROM two_factor_challenges
+                WHERE token_hash = $1 AND expires_at > NOW()
+                FOR UPDATE"#,
+        )
+        .bind(hash_token(&input.challenge_token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(challenge) = challenge.filter(|c| c.attempts < MAX_CHALLENGE_ATTEMPTS) else {
+            return Err(AppError::Unauthorized(
+                "Invalid or expired challenge token".to_string(),
+            ));
+        };
+        let user: Option<User> = sqlx::query_as(
+            "SELECT id, fullname, email, ws_id, created_at FROM users WHERE id = $1",
+        )
+        .bind(challenge.user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(mut user) = user else {
+            return Err(AppError::Unauthorized(
+                "Invalid or expired challenge token".to_string(),
+            ));
+        };
+        self.check_second_factor(&user).await?;
+        if !verify_second_factor(&mut tx, &user, &input.code).await? {
+            sqlx::query("UPDATE two_factor_challenges SET attempts = attempts + 1 WHERE id = $1")
+                .bind(challenge.id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            // a new signin starts a new challenge, the account lockout bounds the guesses
+            self.second_factor_failed(&user).await?;
+            return Err(invalid_code());
+        }
+        sqlx::query("DELETE FROM two_factor_challenges WHERE id = $1")
+            .bind(challenge.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.second_factor_verified(&user).await?;
+        user.ws_id = challenge.ws_id;
+        Ok((user, challenge.device_name))
+    }
+}
+
+async fn find_totp(conn: &mut PgConnection, user_id: i64) -> Result<Option<TotpRow>, AppError> {
+    let row = sqlx::query_as(
+        "SELECT secret, confirmed_at, last_used_step FROM user_totp WHERE user_id = $1 FOR UPDATE",
+    )
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row)
+}
+
+// totp code of the enabled app, or an unused recovery code which is used up
+async fn verify_second_factor(
+    conn: &mut PgConnection,
+    user: &User,
+    code: &str,
+) -> Result<bool, AppError> {
+    let row = find_totp(conn, user.id).await?;
+    let Some(row) = row.filter(|r| r.confirmed_at.is_some()) else {
+        return Err(AppError::TwoFactor(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    };
+    if let Some(step) = match_totp(&row, &user.email, code)? {
+        sqlx::query("UPDATE user_totp SET last_used_step = $2 WHERE user_id = $1")
+            .bind(user.id)
+            .bind(step)
+            .execute(&mut *conn)
+            .await?;
+        return Ok(true);
+    }
+    let ret = sqlx::query(
+        r#"
+            UPDATE recovery_codes SET used_at = NOW()
+            WHERE id = (
+                SELECT id FROM recovery_codes
+                WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+                LIMIT 1
+            )"#,
+    )
+    .bind(user.id)
+    .bind(hash_token(&normalize_recovery_code(code)))
+    .execute(&mut *conn)
+    .await?;
+    Ok(ret.rows_affected() > 0)
+}
+
+async fn replace_recovery_codes(
+    conn: &mut PgConnection,
+    user_id: i64,
+) -> Result<Vec<String>, AppError> {
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+    let codes = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect::<Vec<_>>();
+    let hashes = codes
+        .iter()
+        .map(|c| hash_token(&normalize_recovery_code(c)))
+        .collect::<Vec<_>>();
+    sqlx::query(
+        "INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, unnest($2::char(64)[])",
+    )
+    .bind(user_id)
+    .bind(&hashes)
+    .execute(&mut *conn)
+    .await?;
+    Ok(codes)
+}
+
+fn build_totp(secret: Vec<u8>, email: &str) -> Result<TOTP, AppError> {
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        1,
+        TOTP_STEP,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        email.replace(':', ""),
+    )
+    .map_err(|e| AppError::TwoFactor(e.to_string()))
+}
+
+// time step of the matched code, codes of the previous and next step are accepted for clock drift
+fn match_totp(row: &TotpRow, email: &str, code: &str) -> Result<Option<i64>, AppError> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let secret = hex::decode(&row.secret).map_err(|e| AppError::TwoFactor(e.to_string()))?;
+    let totp = build_totp(secret, email)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let current = (now / TOTP_STEP) as i64;
+    let step = (current - 1..=current + 1)
+        .filter(|step| row.last_used_step.is_none_or(|last| *step > last))
+        .find(|step| totp.generate(*step as u64 * TOTP_STEP) == code);
+    Ok(step)
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    OsRng.fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn invalid_code() -> AppError {
+    AppError::Unauthorized("Invalid two-factor code".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{SigninUser, SignupUser};
+    use chat_core::{AuthContext, WorkspaceSettings};
+
+    fn current_code(enrollment: &TotpEnrollment) -> String {
+        TOTP::from_url(&enrollment.provisioning_uri)
+            .unwrap()
+            .generate_current()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn totp_enrollment_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let enrollment = state.enroll_totp(&user).await?;
+        assert!(enrollment
+            .provisioning_uri
+            .starts_with("otpauth://totp/Chat:"));
+        assert!(!state.two_factor_status(user.id as u64).await?.enabled);
+
+        let ret = state
+            .confirm_totp(
+                &user,
+                &TwoFactorCode {
+                    code: "000000".to_string(),
+                },
+            )
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let code = TwoFactorCode {
+            code: current_code(&enrollment),
+        };
+        let codes = state.confirm_totp(&user, &code).await?;
+        assert_eq!(codes.recovery_codes.len(), RECOVERY_CODE_COUNT);
+        let status = state.two_factor_status(user.id as u64).await?;
+        assert!(status.enabled);
+        assert_eq!(status.recovery_codes_left, RECOVERY_CODE_COUNT as i64);
+        let ret = state.enroll_totp(&user).await;
+        assert!(matches!(ret, Err(AppError::TwoFactor(_))));
+
+        // the same code could not be used again
+        let ret = state.disable_two_factor(&user, &code).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let recovery = TwoFactorCode {
+            code: codes.recovery_codes[0].to_uppercase(),
+        };
+        state.disable_two_factor(&user, &recovery).await?;
+        assert!(!state.two_factor_status(user.id as u64).await?.enabled);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn two_factor_challenge_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        assert!(state
+            .create_two_factor_challenge(&user, None)
+            .await?
+            .is_none());
+
+        let enrollment = state.enroll_totp(&user).await?;
+        let code = TwoFactorCode {
+            code: current_code(&enrollment),
+        };
+        let codes = state.confirm_totp(&user, &code).await?;
+        let challenge = state
+            .create_two_factor_challenge(&user, Some("laptop"))
+            .await?
+            .unwrap();
+
+        let input = VerifyTwoFactor {
+            challenge_token: challenge.challenge_token.clone(),
+            code: "12345-abcde".to_string(),
+        };
+        let ret = state.verify_two_factor_challenge(&input).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let input = VerifyTwoFactor {
+            challenge_token: challenge.challenge_token.clone(),
+            code: codes.recovery_codes[1].clone(),
+        };
+        let (signed_in, device_name) = state.verify_two_factor_challenge(&input).await?;
+        assert_eq!(signed_in.id, user.id);
+        assert_eq!(device_name.as_deref(), Some("laptop"));
+        assert_eq!(
+            state
+                .two_factor_status(user.id as u64)
+                .await?
+                .recovery_codes_left,
+            RECOVERY_CODE_COUNT as i64 - 1
+        );
+        // the challenge could only be used once
+        let ret = state.verify_two_factor_challenge(&input).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        // too many wrong codes invalidate the challenge
+        let challenge = state
+            .create_two_factor_challenge(&user, None)
+            .await?
+            .unwrap();
+        for _ in 0..MAX_CHALLENGE_ATTEMPTS {
+            let input = VerifyTwoFactor {
+                challenge_token: challenge.challenge_token.clone(),
+                code: "000000".to_string(),
+            };
+            assert!(state.verify_two_factor_challenge(&input).await.is_err());
+        }
+        let input = VerifyTwoFactor {
+            challenge_token: challenge.challenge_token.clone(),
+            code: codes.recovery_codes[2].clone(),
+        };
+        let ret = state.verify_two_factor_challenge(&input).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repeated_signins_with_wrong_codes_should_lock_the_account() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("tom", "tom@123.com", "1qa2ws3ed");
+        let user = state.create_user(&input).await?;
+        let enrollment = state.enroll_totp(&user).await?;
+        let code = TwoFactorCode {
+            code: current_code(&enrollment),
+        };
+        let codes = state.confirm_totp(&user, &code).await?;
+        let pending = state
+            .create_two_factor_challenge(&user, None)
+            .await?
+            .unwrap();
+
+        let signin = SigninUser::new("tom@123.com", "1qa2ws3ed", 0);
+        for _ in 0..state.config.lockout.max_account_failures {
+            let user = state.verify_signin(&signin, None).await?.unwrap();
+            let challenge = state
+                .create_two_factor_challenge(&user, None)
+                .await?
+                .unwrap();
+            let input = VerifyTwoFactor {
+                challenge_token: challenge.challenge_token,
+                code: "000000".to_string(),
+            };
+            let ret = state.verify_two_factor_challenge(&input).await;
+            assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        }
+        // the password does not reset the failures of the second factor
+        let ret = state.verify_signin(&signin, None).await;
+        assert!(matches!(ret, Err(AppError::SigninLocked(_))));
+        // and even the right code of a pending challenge is rejected while locked
+        let input = VerifyTwoFactor {
+            challenge_token: pending.challenge_token,
+            code: codes.recovery_codes[0].clone(),
+        };
+        let ret = state.verify_two_factor_challenge(&input).await;
+        assert!(matches!(ret, Err(AppError::SigninLocked(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn workspace_two_factor_requirement_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let ctx = AuthContext::new(user.id, user.ws_id);
+        assert!(!state.load_member(&ctx).await?.needs_two_factor);
+
+        let settings = WorkspaceSettings {
+            require_two_factor: true,
+            ..Default::default()
+        };
+        state
+            .update_workspace_settings(user.ws_id as u64, 0, settings)
+            .await?;
+        assert!(state.load_member(&ctx).await?.needs_two_factor);
+
+        let enrollment = state.enroll_totp(&user).await?;
+        let code = TwoFactorCode {
+            code: current_code(&enrollment),
+        };
+        state.confirm_totp(&user, &code).await?;
+        assert!(!state.load_member(&ctx).await?.needs_two_factor);
+        Ok(())
+    }
+}
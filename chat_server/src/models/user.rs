@@ -2,25 +2,38 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
-use chat_core::{ChatUser, User};
+use chat_core::{ChatUser, User, CHAT_USER_COLUMNS};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::{error::AppError, state::AppState};
+use super::{
+    invite::{insert_pending_member, Admission},
+    workspace::{insert_workspace, insert_workspace_member},
+};
+use crate::{authenticator::Identity, error::AppError, state::AppState};
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SigninUser {
     pub email: String,
     pub password: String,
-    pub ws_id: i64,
+    // sign in to the default workspace of the user if not set
+    #[serde(default)]
+    pub ws_id: Option<i64>,
+    // name of the device shown in the session list, derived from the user agent if not set
+    #[serde(default)]
+    pub device_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SignupUser {
     pub fullname: String,
     pub email: String,
+    // name of the workspace to join, a new one is created if not exists
+    #[serde(default)]
     pub workspace: String,
     pub password: String,
+    #[serde(default)]
+    pub invite_code: Option<String>,
 }
 
 impl AppState {
@@ -40,58 +53,149 @@ impl AppState {
             return Err(AppError::EmailAlreadyExists(input.email.clone()));
         }
 
+        // joining an existing workspace must be allowed by an invite or the join policy
+        let ws = self.find_workspace_by_name(&input.workspace).await?;
+        // the invite is only used up if the user is created
+        let mut tx = self.pool.begin().await?;
+        let ws_id = match (&ws, &input.invite_code) {
+            (None, None) if input.workspace.trim().is_empty() => {
+                return Err(AppError::Invite("Workspace is required".to_string()));
+            }
+            (None, None) => None,
+            (ws, code) => Some(
+                self.authorize_join(&mut tx, &input.email, false, ws.as_ref(), code.as_deref())
+                    .await?,
+            ),
+        };
+
         let password_hash = hash_password(&input.password)?;
         let user: User = sqlx::query_as("INSERT INTO users (fullname, email, password_hash, ws_id) VALUES ($1, $2, $3, $4) RETURNING *")
             .bind(&input.fullname)
             .bind(&input.email)
             .bind(password_hash)
             .bind(0)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *tx)
             .await?;
-        let ws = match self.find_workspace_by_name(&input.workspace).await? {
-            Some(ws) => ws,
+        let ws_id = match ws_id {
+            Some(Admission::Member(ws_id)) => {
+                insert_workspace_member(&mut tx, ws_id as u64, user.id as u64).await?;
+                ws_id
+            }
+            Some(Admission::Pending(ws_id)) => {
+                insert_pending_member(&mut tx, ws_id as u64, user.id as u64).await?;
+                ws_id
+            }
             None => {
-                self.create_workspace(&input.workspace, user.id as u64)
+                insert_workspace(&mut tx, &input.workspace, user.id as u64)
                     .await?
+                    .id
             }
         };
-        self.add_user_to_workspace(user.id as u64, ws.id as u64)
-            .await
+        let user = sqlx::query_as(
+            "UPDATE users SET ws_id = $1 WHERE id = $2 RETURNING id, ws_id, fullname, email, created_at",
+        )
+        .bind(ws_id)
+        .bind(user.id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(user)
     }
 
     pub async fn verify_user(&self, input: &SigninUser) -> Result<Option<User>, AppError> {
-        let mut user = sqlx::query_as(
-            "SELECT id, fullname, email, password_hash, ws_id, created_at FROM users WHERE email = $1",
+        let identity = self
+            .authenticator
+            .authenticate(&input.email, &input.password)
+            .await?;
+        let Some(identity) = identity else {
+            return Ok(None);
+        };
+        let user = if identity.managed {
+            Some(self.sync_managed_user(&identity).await?)
+        } else {
+            self.find_user_by_email(&identity.email).await?
+        };
+        let Some(mut user) = user else {
+            return Ok(None);
+        };
+        if let Some(ws_id) = input.ws_id {
+            if !self
+                .is_workspace_member(ws_id as u64, user.id as u64)
+                .await?
+            {
+                return Err(AppError::LoginFailed(format!(
+                    "Not a member of workspace {}",
+                    ws_id
+                )));
+            }
+            user.ws_id = ws_id;
+        }
+        Ok(Some(user))
+    }
+
+    // create or update the account of a directory user and join its mapped workspaces,
+    // memberships are never removed here, that is left to the workspace admins
+    pub async fn sync_managed_user(&self, identity: &Identity) -> Result<User, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let existing: Option<(i64, bool)> = sqlx::query_as(
+            "SELECT id, is_bot FROM users WHERE lower(email) = lower($1) FOR UPDATE",
         )
-        .bind(&input.email)
-        .fetch_optional(&self.pool)
+        .bind(&identity.email)
+        .fetch_optional(&mut *tx)
         .await?;
-        match user {
-            Some(User {
-                password_hash: Some(ref password_hash),
-                id,
-                ..
-            }) => {
-                if verify_password(&input.password, password_hash)? {
-                    if let Some(u) = user.as_mut() {
-                        u.password_hash = None;
-                        if u.ws_id != input.ws_id {
-                            u.ws_id = input.ws_id;
-                            self.add_user_to_workspace(id as u64, input.ws_id as u64)
-                                .await?;
-                        }
-                    }
-
-                    Ok(user)
-                } else {
-                    Ok(None)
-                }
+        let user_id: i64 = match existing {
+            Some((_, true)) => {
+                return Err(AppError::LoginFailed("Bots could not sign in".to_string()));
+            }
+            Some((id, false)) => {
+                sqlx::query(
+                    r#"
+                        UPDATE users
+                        SET fullname = $2, email_verified_at = COALESCE(email_verified_at, NOW())
+                        WHERE id = $1"#,
+                )
+                .bind(id)
+                .bind(&identity.fullname)
+                .execute(&mut *tx)
+                .await?;
+                id
+            }
+            None => {
+                let Some(ws_id) = identity.ws_ids.first() else {
+                    return Err(AppError::LoginFailed(
+                        "No workspace is mapped to the groups of the user".to_string(),
+                    ));
+                };
+                sqlx::query_scalar(
+                    r#"
+                        INSERT INTO users (fullname, email, ws_id, email_verified_at)
+                        VALUES ($1, $2, $3, NOW())
+                        RETURNING id"#,
+                )
+                .bind(&identity.fullname)
+                .bind(&identity.email)
+                .bind(ws_id)
+                .fetch_one(&mut *tx)
+                .await?
             }
-            _ => Ok(None),
+        };
+        tx.commit().await?;
+        for ws_id in &identity.ws_ids {
+            self.add_workspace_member(*ws_id as u64, user_id as u64)
+                .await?;
         }
+        let user = sqlx::query_as(
+            "SELECT id, fullname, email, ws_id, created_at FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(user)
     }
 
+    // join the workspace and make it the default workspace of the user
     pub async fn add_user_to_workspace(&self, user_id: u64, ws_id: u64) -> Result<User, AppError> {
+        self.add_workspace_member(ws_id, user_id).await?;
         let user = sqlx::query_as(
             "UPDATE users SET ws_id = $1 WHERE id = $2  RETURNING id, ws_id, fullname, email, created_at",
         )
@@ -103,24 +207,33 @@ impl AppState {
     }
 
     pub async fn fetch_chat_users_by_ids(&self, ids: &[i64]) -> Result<Vec<ChatUser>, AppError> {
-        let users = sqlx::query_as("SELECT id, fullname, email FROM users WHERE id = ANY($1)")
-            .bind(ids)
-            .fetch_all(&self.pool)
-            .await?;
+        let sql = format!(
+            "SELECT {} FROM users u WHERE u.id = ANY($1)",
+            CHAT_USER_COLUMNS
+        );
+        let users = sqlx::query_as(&sql).bind(ids).fetch_all(&self.pool).await?;
         Ok(users)
     }
 
     pub async fn fetch_chat_users(&self, ws_id: i64) -> Result<Vec<ChatUser>, AppError> {
-        let users =
-            sqlx::query_as("SELECT id, fullname, email FROM users WHERE ws_id = $1 order by id")
-                .bind(ws_id)
-                .fetch_all(&self.pool)
-                .await?;
+        let sql = format!(
+            r#"
+                    SELECT {}
+                    FROM users u
+                    JOIN workspace_members wm ON wm.user_id = u.id
+                    WHERE wm.ws_id = $1
+                    ORDER BY u.id"#,
+            CHAT_USER_COLUMNS
+        );
+        let users = sqlx::query_as(&sql)
+            .bind(ws_id)
+            .fetch_all(&self.pool)
+            .await?;
         Ok(users)
     }
 }
 
-fn hash_password(password: &str) -> Result<String, AppError> {
+pub(super) fn hash_password(password: &str) -> Result<String, AppError> {
     let salt = SaltString::generate(&mut OsRng);
 
     // Argon2 with default params (Argon2id v19)
@@ -133,7 +246,7 @@ fn hash_password(password: &str) -> Result<String, AppError> {
     Ok(password_hash)
 }
 
-fn verify_password(password: &str, password_hash: &str) -> Result<bool, AppError> {
+pub(crate) fn verify_password(password: &str, password_hash: &str) -> Result<bool, AppError> {
     let argon2 = Argon2::default();
     let parsed_hash = PasswordHash::new(password_hash)?;
     let is_ok = argon2
@@ -148,7 +261,8 @@ impl SigninUser {
         Self {
             email: email.to_string(),
             password: password.to_string(),
-            ws_id,
+            ws_id: Some(ws_id),
+            device_name: None,
         }
     }
 }
@@ -161,6 +275,7 @@ impl SignupUser {
             email: email.to_string(),
             workspace: "default".to_string(),
             password: password.to_string(),
+            invite_code: None,
         }
     }
 }
@@ -219,6 +334,34 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn sync_managed_user_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let mut identity = Identity {
+            email: "alice@example.org".to_string(),
+            fullname: "Alice".to_string(),
+            managed: true,
+            ws_ids: vec![],
+        };
+        // new users need a workspace from their groups
+        let ret = state.sync_managed_user(&identity).await;
+        assert!(matches!(ret, Err(AppError::LoginFailed(_))));
+
+        identity.ws_ids = vec![2];
+        let user = state.sync_managed_user(&identity).await?;
+        assert_eq!(user.ws_id, 2);
+        assert!(state.is_workspace_member(2, user.id as u64).await?);
+
+        // the directory keeps the account up to date
+        identity.fullname = "Alice Smith".to_string();
+        identity.ws_ids = vec![1, 2];
+        let updated = state.sync_managed_user(&identity).await?;
+        assert_eq!(updated.id, user.id);
+        assert_eq!(updated.fullname, "Alice Smith");
+        assert!(state.is_workspace_member(1, user.id as u64).await?);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn add_to_workspace_should_work() -> Result<()> {
         let (_tdb, state) = AppState::new_for_test().await?;
@@ -233,6 +376,38 @@ mod tests {
             .add_user_to_workspace(user.id as u64, ws.id as u64)
             .await?;
         assert_eq!(user.ws_id, ws.id);
+        // the user is still a member of the old workspace
+        assert!(state.is_workspace_member(0, user.id as u64).await?);
+        assert!(
+            state
+                .is_workspace_member(ws.id as u64, user.id as u64)
+                .await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verify_user_should_check_workspace_membership() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = SignupUser::new("tom", "tom@123.com", "1qa2ws3ed");
+        state.create_user(&input).await?;
+
+        let ws = state.find_workspace_by_name("workspace2").await?.unwrap();
+        let signin = SigninUser::new(&input.email, &input.password, ws.id);
+        let ret = state.verify_user(&signin).await;
+        assert!(matches!(ret, Err(AppError::LoginFailed(_))));
+        // the user should not be moved out of the default workspace
+        let user = state.find_user_by_email(&input.email).await?.unwrap();
+        assert_eq!(user.ws_id, 0);
+
+        let signin = SigninUser {
+            email: input.email.clone(),
+            password: input.password.clone(),
+            ws_id: None,
+            device_name: None,
+        };
+        let user = state.verify_user(&signin).await?.unwrap();
+        assert_eq!(user.ws_id, 0);
         Ok(())
     }
 }
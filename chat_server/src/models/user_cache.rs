@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use chat_core::{AuthContext, User};
+use sqlx::FromRow;
+
+use crate::{error::AppError, state::AppState};
+
+// changes made by other instances are picked up after the ttl
+const USER_CACHE_TTL: Duration = Duration::from_secs(30);
+const USER_CACHE_CAPACITY: usize = 10_000;
+
+// users of the recent requests, keyed by user id and the workspace the token acts in
+type CachedUsers = HashMap<(i64, i64), (LoadedUser, Instant)>;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct LoadedUser {
+    #[sqlx(flatten)]
+    pub user: User,
+    // the workspace requires two-factor authentication the user has not enabled yet
+    pub needs_two_factor: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UserCache(Arc<RwLock<CachedUsers>>);
+
+impl UserCache {
+    fn get(&self, user_id: i64, ws_id: i64) -> Option<LoadedUser> {
+        let users = self.0.read().ok()?;
+        users
+            .get(&(user_id, ws_id))
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(user, _)| user.clone())
+    }
+
+    fn insert(&self, user: LoadedUser) {
+        let Ok(mut users) = self.0.write() else {
+            return;
+        };
+        let now = Instant::now();
+        if users.len() >= USER_CACHE_CAPACITY {
+            users.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        let key = (user.user.id, user.user.ws_id);
+        users.insert(key, (user, now + USER_CACHE_TTL));
+    }
+
+    pub fn forget_user(&self, user_id: i64) {
+        if let Ok(mut users) = self.0.write() {
+            users.retain(|(id, _), _| *id != user_id);
+        }
+    }
+
+    pub fn forget_workspace(&self, ws_id: i64) {
+        if let Ok(mut users) = self.0.write() {
+            users.retain(|(_, id), _| *id != ws_id);
+        }
+    }
+}
+
+impl AppState {
+    // the user of the token, it must still be a member of the workspace the token acts in
+    pub async fn load_user(&self, ctx: &AuthContext) -> Result<User, AppError> {
+        Ok(self.load_member(ctx).await?.user)
+    }
+
+    pub async fn load_member(&self, ctx: &AuthContext) -> Result<LoadedUser, AppError> {
+        if let Some(user) = self.users.get(ctx.user_id, ctx.ws_id) {
+            return Ok(user);
+        }
+        let user: Option<LoadedUser> = sqlx::query_as(
+            r#"
+                SELECT u.id, u.fullname, u.email, m.ws_id, u.created_at, u.is_bot,
+                    COALESCE((w.settings->>'require_two_factor')::BOOLEAN, FALSE)
+                        AND NOT u.is_bot
+                        AND NOT EXISTS(
+                            SELECT 1 FROM user_totp t
+                            WHERE t.user_id = u.id AND t.confirmed_at IS NOT NULL
+                        ) AS needs_two_factor
+                FROM users u
+                JOIN workspace_members m ON m.user_id = u.id AND m.ws_id = $2
+                JOIN workspaces w ON w.id = m.ws_id AND w.deleted_at IS NULL
+                WHERE u.id = $1 AND u.deactivated_at IS NULL"#,
+        )
+        .bind(ctx.user_id)
+        .bind(ctx.ws_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(user) = user else {
+            return Err(AppError::Unauthorized(
+                "User is not a member of the workspace".to_string(),
+            ));
+        };
+        self.users.insert(user.clone());
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn load_user_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        let ctx = AuthContext::new(user.id, 0);
+        assert_eq!(state.load_user(&ctx).await?, user);
+
+        // the workspace comes from the token, not the default one of the user
+        state.add_workspace_member(1, user.id as u64).await?;
+        let loaded = state.load_user(&AuthContext::new(user.id, 1)).await?;
+        assert_eq!(loaded.ws_id, 1);
+        assert_eq!(loaded.email, user.email);
+
+        // removed members are rejected at once
+        state
+            .remove_workspace_member(1, user.id as u64, user.id as u64)
+            .await?;
+        let ret = state.load_user(&AuthContext::new(user.id, 1)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        assert_eq!(state.load_user(&ctx).await?, user);
+        Ok(())
+    }
+}
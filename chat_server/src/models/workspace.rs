@@ -1,19 +1,293 @@
-use chat_core::Workspace;
+use std::{io::ErrorKind, time::Duration};
+
+use chat_core::{JoinPolicy, User, Workspace, WorkspaceMember, WorkspaceRole, WorkspaceSettings};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tracing::{info, warn};
+use utoipa::ToSchema;
 
 use crate::{error::AppError, state::AppState};
 
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateJoinPolicy {
+    pub join_policy: JoinPolicy,
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateWorkspace {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TransferWorkspace {
+    pub owner_id: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateMemberRole {
+    pub role: WorkspaceRole,
+}
+
 impl AppState {
     pub async fn create_workspace(&self, name: &str, user_id: u64) -> Result<Workspace, AppError> {
-        let workspace: Workspace =
-            sqlx::query_as("INSERT INTO workspaces (name, owner_id) VALUES ($1, $2) RETURNING *")
-                .bind(name)
-                .bind(user_id as i64)
+        let mut tx = self.pool.begin().await?;
+        let workspace = insert_workspace(&mut tx, name, user_id).await?;
+        tx.commit().await?;
+        Ok(workspace)
+    }
+
+    pub async fn fetch_user_workspaces(&self, user_id: u64) -> Result<Vec<Workspace>, AppError> {
+        let workspaces = sqlx::query_as(
+            r#"
+                    SELECT w.*
+                    FROM workspaces w
+                    JOIN workspace_members wm ON wm.ws_id = w.id
+                    WHERE wm.user_id = $1 AND w.deleted_at IS NULL
+                    ORDER BY w.id"#,
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(workspaces)
+    }
+
+    // join the workspace as a member, and the default channels of the workspace
+    pub async fn add_workspace_member(&self, ws_id: u64, user_id: u64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        insert_workspace_member(&mut tx, ws_id, user_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn is_workspace_member(&self, ws_id: u64, user_id: u64) -> Result<bool, AppError> {
+        let member = sqlx::query(
+            r#"
+                    SELECT 1
+                    FROM workspace_members wm
+                    JOIN workspaces w ON w.id = wm.ws_id
+                    WHERE wm.ws_id = $1 AND wm.user_id = $2 AND w.deleted_at IS NULL"#,
+        )
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(member.is_some())
+    }
+
+    // the owner of the workspace is always the owner, whether or not there is a membership
+    pub async fn get_workspace_role(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+    ) -> Result<Option<WorkspaceRole>, AppError> {
+        let row: Option<(i64, Option<WorkspaceRole>)> = sqlx::query_as(
+            r#"
+                    SELECT w.owner_id, wm.role
+                    FROM workspaces w
+                    LEFT JOIN workspace_members wm ON wm.ws_id = w.id AND wm.user_id = $2
+                    WHERE w.id = $1 AND w.deleted_at IS NULL"#,
+        )
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        match row {
+            Some((owner_id, _)) if owner_id == user_id as i64 => Ok(Some(WorkspaceRole::Owner)),
+            Some((_, role)) => Ok(role),
+            None => Err(AppError::NotFound(format!(
+                "Workspace with id {} not found",
+                ws_id
+            ))),
+        }
+    }
+
+    pub async fn verify_workspace_owner(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+    ) -> Result<WorkspaceRole, AppError> {
+        match self.get_workspace_role(ws_id, user_id).await? {
+            Some(WorkspaceRole::Owner) => Ok(WorkspaceRole::Owner),
+            _ => Err(AppError::Unauthorized(
+                "Only the workspace owner could do this".to_string(),
+            )),
+        }
+    }
+
+    pub async fn verify_workspace_admin(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+    ) -> Result<WorkspaceRole, AppError> {
+        match self.get_workspace_role(ws_id, user_id).await? {
+            Some(role @ (WorkspaceRole::Owner | WorkspaceRole::Admin)) => Ok(role),
+            _ => Err(AppError::Unauthorized(
+                "Only the workspace admins could manage the workspace".to_string(),
+            )),
+        }
+    }
+
+    pub async fn update_join_policy(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: UpdateJoinPolicy,
+    ) -> Result<Workspace, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let domains = input
+            .allowed_domains
+            .iter()
+            .map(|d| d.trim().trim_start_matches('@').to_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect::<Vec<_>>();
+        if input.join_policy == JoinPolicy::Domain && domains.is_empty() {
+            return Err(AppError::Invite(
+                "At least one allowed domain is required".to_string(),
+            ));
+        }
+        let workspace = sqlx::query_as(
+            "UPDATE workspaces SET join_policy = $1, allowed_domains = $2 WHERE id = $3 RETURNING *",
+        )
+        .bind(input.join_policy)
+        .bind(domains)
+        .bind(ws_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(workspace)
+    }
+
+    pub async fn get_workspace(&self, ws_id: u64, user_id: u64) -> Result<Workspace, AppError> {
+        if self.get_workspace_role(ws_id, user_id).await?.is_none() {
+            return Err(AppError::Unauthorized(format!(
+                "Not a member of workspace {}",
+                ws_id
+            )));
+        }
+        let ws = self.find_workspace_by_id(ws_id).await?;
+        ws.ok_or_else(|| AppError::NotFound(format!("Workspace with id {} not found", ws_id)))
+    }
+
+    pub async fn rename_workspace(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        input: UpdateWorkspace,
+    ) -> Result<Workspace, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        let name = input.name.trim();
+        if name.is_empty() || name.chars().count() > 32 {
+            return Err(AppError::Workspace(format!(
+                "Invalid workspace name: {}",
+                input.name
+            )));
+        }
+        if matches!(self.find_workspace_by_name(name).await?, Some(ws) if ws.id != ws_id as i64) {
+            return Err(AppError::Workspace(format!(
+                "Workspace {} already exists",
+                name
+            )));
+        }
+        let workspace = sqlx::query_as("UPDATE workspaces SET name = $1 WHERE id = $2 RETURNING *")
+            .bind(name)
+            .bind(ws_id as i64)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(workspace)
+    }
+
+    pub async fn update_workspace_settings(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        mut settings: WorkspaceSettings,
+    ) -> Result<Workspace, AppError> {
+        self.verify_workspace_admin(ws_id, user_id).await?;
+        if matches!(settings.message_retention_days, Some(days) if days < 1) {
+            return Err(AppError::Workspace(
+                "message_retention_days must be greater than 0".to_string(),
+            ));
+        }
+        if settings.max_upload_size == Some(0) {
+            return Err(AppError::Workspace(
+                "max_upload_size must be greater than 0".to_string(),
+            ));
+        }
+        // default channels must be public channels of the workspace
+        settings.default_channels.sort_unstable();
+        settings.default_channels.dedup();
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM chats WHERE id = ANY($1) AND ws_id = $2 AND type = 'public_channel'",
+        )
+        .bind(&settings.default_channels)
+        .bind(ws_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        if count as usize != settings.default_channels.len() {
+            return Err(AppError::Workspace(
+                "Default channels must be public channels of the workspace".to_string(),
+            ));
+        }
+
+        let workspace =
+            sqlx::query_as("UPDATE workspaces SET settings = $1 WHERE id = $2 RETURNING *")
+                .bind(sqlx::types::Json(&settings))
+                .bind(ws_id as i64)
                 .fetch_one(&self.pool)
                 .await?;
+        // the two-factor requirement of the members is cached
+        self.users.forget_workspace(ws_id as i64);
+        Ok(workspace)
+    }
+
+    // transfer the ownership to another member, the old owner becomes an admin
+    pub async fn transfer_workspace(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        owner_id: u64,
+    ) -> Result<Workspace, AppError> {
+        self.verify_workspace_owner(ws_id, user_id).await?;
+        if !self.is_workspace_member(ws_id, owner_id).await? {
+            return Err(AppError::Workspace(format!(
+                "User {} is not a member of the workspace",
+                owner_id
+            )));
+        }
+        // the owner and the roles are changed together, a concurrent transfer finds
+        // the owner changed and fails
+        let mut tx = self.pool.begin().await?;
+        let workspace: Option<Workspace> = sqlx::query_as(
+            "UPDATE workspaces SET owner_id = $1 WHERE id = $2 AND owner_id = $3 RETURNING *",
+        )
+        .bind(owner_id as i64)
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(workspace) = workspace else {
+            return Err(AppError::Unauthorized(
+                "Only the workspace owner could do this".to_string(),
+            ));
+        };
+        sqlx::query(
+            r#"
+                    INSERT INTO workspace_members (ws_id, user_id, role)
+                    VALUES ($1, $2, 'admin'), ($1, $3, 'owner')
+                    ON CONFLICT (ws_id, user_id) DO UPDATE SET role = EXCLUDED.role"#,
+        )
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .bind(owner_id as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
         Ok(workspace)
     }
 
-    #[allow(dead_code)]
     pub async fn update_workspace_owner(
         &self,
         ws_id: u64,
@@ -28,20 +302,379 @@ impl AppState {
         Ok(workspace)
     }
 
+    pub async fn fetch_workspace_members(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<WorkspaceMember>, AppError> {
+        if self.get_workspace_role(ws_id, user_id).await?.is_none() {
+            return Err(AppError::Unauthorized(format!(
+                "Not a member of workspace {}",
+                ws_id
+            )));
+        }
+        let members = sqlx::query_as(
+            r#"
+                    SELECT u.id, u.fullname, u.email, wm.role, wm.joined_at
+                    FROM workspace_members wm
+                    JOIN users u ON u.id = wm.user_id
+                    WHERE wm.ws_id = $1
+                    ORDER BY u.id"#,
+        )
+        .bind(ws_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(members)
+    }
+
+    pub async fn update_member_role(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+        role: WorkspaceRole,
+    ) -> Result<WorkspaceRole, AppError> {
+        self.verify_workspace_owner(ws_id, user_id).await?;
+        if role == WorkspaceRole::Owner {
+            return Err(AppError::Workspace(
+                "Transfer the workspace to change its owner".to_string(),
+            ));
+        }
+        match self.get_workspace_role(ws_id, member_id).await? {
+            Some(WorkspaceRole::Owner) => Err(AppError::Workspace(
+                "The role of the owner could not be changed".to_string(),
+            )),
+            Some(_) => {
+                sqlx::query(
+                    "UPDATE workspace_members SET role = $1 WHERE ws_id = $2 AND user_id = $3",
+                )
+                .bind(role)
+                .bind(ws_id as i64)
+                .bind(member_id as i64)
+                .execute(&self.pool)
+                .await?;
+                Ok(role)
+            }
+            None => Err(AppError::NotFound(format!(
+                "User {} is not a member of the workspace",
+                member_id
+            ))),
+        }
+    }
+
+    // remove the member from the workspace and its chats, members could leave by themselves
+    pub async fn remove_workspace_member(
+        &self,
+        ws_id: u64,
+        user_id: u64,
+        member_id: u64,
+    ) -> Result<(), AppError> {
+        let role = self.get_workspace_role(ws_id, user_id).await?;
+        let target = self.get_workspace_role(ws_id, member_id).await?;
+        let allowed = match (role, target) {
+            (_, None) => {
+                return Err(AppError::NotFound(format!(
+                    "User {} is not a member of the workspace",
+                    member_id
+                )))
+            }
+            (_, Some(WorkspaceRole::Owner)) => {
+                return Err(AppError::Workspace(
+                    "The owner could not leave the workspace".to_string(),
+                ))
+            }
+            _ if user_id == member_id => true,
+            (Some(WorkspaceRole::Owner), _) => true,
+            (Some(WorkspaceRole::Admin), Some(WorkspaceRole::Member)) => true,
+            _ => false,
+        };
+        if !allowed {
+            return Err(AppError::Unauthorized(
+                "Not allowed to remove the member".to_string(),
+            ));
+        }
+        self.delete_workspace_member(ws_id, member_id).await
+    }
+
+    // remove the member from the workspace and its chats, callers check the roles
+    pub(super) async fn delete_workspace_member(
+        &self,
+        ws_id: u64,
+        member_id: u64,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM workspace_members WHERE ws_id = $1 AND user_id = $2")
+            .bind(ws_id as i64)
+            .bind(member_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE chats SET members = array_remove(members, $2) WHERE ws_id = $1 AND $2 = ANY(members)",
+        )
+        .bind(ws_id as i64)
+        .bind(member_id as i64)
+        .execute(&mut *tx)
+        .await?;
+        reset_default_workspaces(&mut tx, ws_id as i64, Some(member_id as i64)).await?;
+        tx.commit().await?;
+        self.users.forget_user(member_id as i64);
+        Ok(())
+    }
+
+    // mark the workspace as deleted, the data is purged by the cleanup job
+    pub async fn delete_workspace(&self, ws_id: u64, user_id: u64) -> Result<(), AppError> {
+        if ws_id == 0 {
+            return Err(AppError::Workspace(
+                "The default workspace could not be deleted".to_string(),
+            ));
+        }
+        self.verify_workspace_owner(ws_id, user_id).await?;
+        sqlx::query("UPDATE workspaces SET deleted_at = NOW() WHERE id = $1")
+            .bind(ws_id as i64)
+            .execute(&self.pool)
+            .await?;
+        self.users.forget_workspace(ws_id as i64);
+        Ok(())
+    }
+
+    pub async fn purge_deleted_workspaces(&self) -> Result<usize, AppError> {
+        let ids: Vec<(i64,)> =
+            sqlx::query_as("SELECT id FROM workspaces WHERE deleted_at IS NOT NULL")
+                .fetch_all(&self.pool)
+                .await?;
+        // a workspace failing to purge does not hold back the others
+        let mut purged = 0;
+        for (id,) in &ids {
+            match self.purge_workspace(*id).await {
+                Ok(()) => {
+                    info!("workspace {} purged", id);
+                    purged += 1;
+                }
+                Err(e) => warn!("Failed to purge workspace {}: {}", id, e),
+            }
+        }
+        Ok(purged)
+    }
+
+    async fn purge_workspace(&self, ws_id: i64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM workspace_members WHERE ws_id = $1")
+            .bind(ws_id)
+            .execute(&mut *tx)
+            .await?;
+        reset_default_workspaces(&mut tx, ws_id, None).await?;
+        sqlx::query(
+            "DELETE FROM messages WHERE chat_id IN (SELECT id FROM chats WHERE ws_id = $1)",
+        )
+        .bind(ws_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM chats WHERE ws_id = $1")
+            .bind(ws_id)
+            .execute(&mut *tx)
+            .await?;
+        // commands, invites and tokens are deleted in cascade
+        sqlx::query("DELETE FROM workspaces WHERE id = $1")
+            .bind(ws_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let dir = self.config.server.base_dir.join(ws_id.to_string());
+        match tokio::fs::remove_dir_all(&dir).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // delete the messages older than the retention of their workspaces
+    pub async fn apply_message_retention(&self) -> Result<u64, AppError> {
+        let ret = sqlx::query(
+            r#"
+                    DELETE FROM messages m
+                    USING chats c, workspaces w
+                    WHERE m.chat_id = c.id
+                        AND c.ws_id = w.id
+                        AND (w.settings->>'message_retention_days') IS NOT NULL
+                        AND m.created_at < NOW() - make_interval(
+                            days => (w.settings->>'message_retention_days')::INT
+                        )"#,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(ret.rows_affected())
+    }
+
+    // make the workspace the default one of the user, the user must be a member of it
+    pub async fn switch_workspace(&self, user_id: u64, ws_id: u64) -> Result<User, AppError> {
+        if !self.is_workspace_member(ws_id, user_id).await? {
+            return Err(AppError::Unauthorized(format!(
+                "Not a member of workspace {}",
+                ws_id
+            )));
+        }
+        let user = sqlx::query_as(
+            "UPDATE users SET ws_id = $1 WHERE id = $2 RETURNING id, ws_id, fullname, email, created_at",
+        )
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(user)
+    }
+
+    pub async fn find_workspace_by_id(&self, id: u64) -> Result<Option<Workspace>, AppError> {
+        let workspace =
+            sqlx::query_as("SELECT * FROM workspaces WHERE id = $1 AND deleted_at IS NULL")
+                .bind(id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(workspace)
+    }
+
     pub async fn find_workspace_by_name(&self, name: &str) -> Result<Option<Workspace>, AppError> {
-        let workspace = sqlx::query_as("SELECT * FROM workspaces WHERE name = $1")
+        let workspace =
+            sqlx::query_as("SELECT * FROM workspaces WHERE name = $1 AND deleted_at IS NULL")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(workspace)
+    }
+}
+
+pub fn spawn_workspace_cleanup(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+            if let Err(e) = state.purge_deleted_workspaces().await {
+                warn!("Failed to purge deleted workspaces: {}", e);
+            }
+            if let Err(e) = state.apply_message_retention().await {
+                warn!("Failed to apply message retention: {}", e);
+            }
+            if let Err(e) = state.process_user_deletions().await {
+                warn!("Failed to process user deletions: {}", e);
+            }
+            if let Err(e) = state.process_data_exports().await {
+                warn!("Failed to process data exports: {}", e);
+            }
+            if let Err(e) = state.purge_expired_exports().await {
+                warn!("Failed to purge expired exports: {}", e);
+            }
+        }
+    });
+}
+
+// the owner is always a member of the workspace
+pub(super) async fn insert_workspace(
+    conn: &mut PgConnection,
+    name: &str,
+    user_id: u64,
+) -> Result<Workspace, AppError> {
+    let workspace: Workspace =
+        sqlx::query_as("INSERT INTO workspaces (name, owner_id) VALUES ($1, $2) RETURNING *")
             .bind(name)
-            .fetch_optional(&self.pool)
+            .bind(user_id as i64)
+            .fetch_one(&mut *conn)
             .await?;
-        Ok(workspace)
+    sqlx::query("INSERT INTO workspace_members (ws_id, user_id, role) VALUES ($1, $2, 'owner')")
+        .bind(workspace.id)
+        .bind(user_id as i64)
+        .execute(&mut *conn)
+        .await?;
+    Ok(workspace)
+}
+
+pub(super) async fn insert_workspace_member(
+    conn: &mut PgConnection,
+    ws_id: u64,
+    user_id: u64,
+) -> Result<(), AppError> {
+    let ret = sqlx::query(
+        "INSERT INTO workspace_members (ws_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind(ws_id as i64)
+    .bind(user_id as i64)
+    .execute(&mut *conn)
+    .await?;
+    if ret.rows_affected() > 0 {
+        sqlx::query(
+            r#"
+                UPDATE chats
+                SET members = array_append(members, $2)
+                WHERE ws_id = $1
+                    AND NOT ($2 = ANY(members))
+                    AND id IN (
+                        SELECT jsonb_array_elements_text(settings->'default_channels')::BIGINT
+                        FROM workspaces
+                        WHERE id = $1
+                    )
+                    -- unverified users join them once verified
+                    AND NOT EXISTS (
+                        SELECT 1 FROM workspaces w, users u
+                        WHERE w.id = $1 AND u.id = $2 AND u.email_verified_at IS NULL
+                            AND (w.settings->>'require_verified_email')::BOOLEAN
+                    )"#,
+        )
+        .bind(ws_id as i64)
+        .bind(user_id as i64)
+        .execute(&mut *conn)
+        .await?;
     }
+    Ok(())
+}
+
+// users whose default workspace is gone fall back to another workspace they belong to,
+// or the default workspace if there is none
+async fn reset_default_workspaces(
+    conn: &mut PgConnection,
+    ws_id: i64,
+    user_id: Option<i64>,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+            INSERT INTO workspace_members (ws_id, user_id)
+            SELECT 0, u.id
+            FROM users u
+            WHERE u.ws_id = $1
+                AND ($2::BIGINT IS NULL OR u.id = $2)
+                AND NOT EXISTS (
+                    SELECT 1 FROM workspace_members wm WHERE wm.user_id = u.id AND wm.ws_id <> $1
+                )
+            ON CONFLICT DO NOTHING"#,
+    )
+    .bind(ws_id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        r#"
+            UPDATE users u
+            SET ws_id = (
+                SELECT wm.ws_id
+                FROM workspace_members wm
+                WHERE wm.user_id = u.id AND wm.ws_id <> $1
+                ORDER BY wm.joined_at, wm.ws_id
+                LIMIT 1
+            )
+            WHERE u.ws_id = $1 AND ($2::BIGINT IS NULL OR u.id = $2)"#,
+    )
+    .bind(ws_id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::{error::AppError, models::SignupUser};
+    use crate::{
+        error::AppError,
+        models::{CreateAccessToken, SignupUser},
+    };
+    use chat_core::Scope;
 
     #[tokio::test]
     async fn workspace_should_work() -> Result<(), AppError> {
@@ -87,4 +720,203 @@ mod tests {
         assert_eq!(users.len(), 0);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn switch_workspace_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let signup_user = SignupUser::new("test", "test@1234.com", "password");
+        let user = state.create_user(&signup_user).await?;
+        let workspace = state.create_workspace("test", user.id as u64).await?;
+
+        let workspaces = state.fetch_user_workspaces(user.id as u64).await?;
+        let names = workspaces
+            .iter()
+            .map(|w| w.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["default", "test"]);
+
+        let user = state
+            .switch_workspace(user.id as u64, workspace.id as u64)
+            .await?;
+        assert_eq!(user.ws_id, workspace.id);
+
+        let ret = state.switch_workspace(user.id as u64, 1).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn workspace_roles_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // workspace1 is owned by user 0, add user 1 and 2 as members
+        state.add_workspace_member(1, 1).await?;
+        state.add_workspace_member(1, 2).await?;
+        assert_eq!(
+            state.get_workspace_role(1, 0).await?,
+            Some(WorkspaceRole::Owner)
+        );
+        assert_eq!(
+            state.get_workspace_role(1, 1).await?,
+            Some(WorkspaceRole::Member)
+        );
+        assert_eq!(state.get_workspace_role(1, 3).await?, None);
+
+        let ret = state
+            .rename_workspace(1, 1, UpdateWorkspace { name: "ws".into() })
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        state
+            .update_member_role(1, 0, 1, WorkspaceRole::Admin)
+            .await?;
+        let ws = state
+            .rename_workspace(1, 1, UpdateWorkspace { name: "ws".into() })
+            .await?;
+        assert_eq!(ws.name, "ws");
+        let ret = state
+            .rename_workspace(
+                1,
+                1,
+                UpdateWorkspace {
+                    name: "workspace2".into(),
+                },
+            )
+            .await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        // only the owner could manage roles
+        let ret = state
+            .update_member_role(1, 1, 2, WorkspaceRole::Admin)
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let members = state.fetch_workspace_members(1, 2).await?;
+        let roles = members.iter().map(|m| (m.id, m.role)).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            vec![(1, WorkspaceRole::Admin), (2, WorkspaceRole::Member)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transfer_workspace_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ret = state.transfer_workspace(1, 0, 1).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        state.add_workspace_member(1, 1).await?;
+        let ws = state.transfer_workspace(1, 0, 1).await?;
+        assert_eq!(ws.owner_id, 1);
+        assert_eq!(
+            state.get_workspace_role(1, 1).await?,
+            Some(WorkspaceRole::Owner)
+        );
+        assert_eq!(
+            state.get_workspace_role(1, 0).await?,
+            Some(WorkspaceRole::Admin)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_workspace_member_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state.add_user_to_workspace(1, 1).await?;
+        state.add_workspace_member(1, 2).await?;
+        state
+            .update_member_role(1, 0, 2, WorkspaceRole::Admin)
+            .await?;
+
+        // members could not remove others, but could leave
+        let ret = state.remove_workspace_member(1, 1, 2).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let ret = state.remove_workspace_member(1, 2, 0).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        state.remove_workspace_member(1, 2, 1).await?;
+        assert!(!state.is_workspace_member(1, 1).await?);
+        // user 1 is removed from the chats of the workspace
+        let chat = state.get_chat_by_id(1).await?.unwrap();
+        assert_eq!(chat.members, vec![2, 3, 4, 5]);
+        // and falls back to the old default workspace
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        assert_eq!(user.ws_id, 0);
+
+        state.remove_workspace_member(1, 2, 2).await?;
+        assert!(!state.is_workspace_member(1, 2).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn workspace_settings_should_work() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        // chat 2 is a private channel
+        let settings = WorkspaceSettings {
+            default_channels: vec![1, 2],
+            ..Default::default()
+        };
+        let ret = state.update_workspace_settings(1, 0, settings).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        let signup_user = SignupUser::new("tom", "tom@123.com", "password");
+        let user = state.create_user(&signup_user).await?;
+        sqlx::query("UPDATE chats SET members = array_remove(members, 5) WHERE id = 1")
+            .execute(&state.pool)
+            .await?;
+        let settings = WorkspaceSettings {
+            default_channels: vec![1],
+            message_retention_days: Some(30),
+            max_upload_size: Some(1024),
+            require_verified_email: false,
+            require_two_factor: false,
+        };
+        let ws = state
+            .update_workspace_settings(1, 0, settings.clone())
+            .await?;
+        assert_eq!(ws.settings, settings);
+
+        // new members join the default channels
+        state.add_workspace_member(1, user.id as u64).await?;
+        state.add_workspace_member(1, 5).await?;
+        let chat = state.get_chat_by_id(1).await?.unwrap();
+        assert_eq!(chat.members, vec![1, 2, 3, 4, user.id, 5]);
+
+        sqlx::query("UPDATE messages SET created_at = NOW() - INTERVAL '31 days' WHERE id <= 3")
+            .execute(&state.pool)
+            .await?;
+        assert_eq!(state.apply_message_retention().await?, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_workspace_should_purge_data() -> Result<(), AppError> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.add_user_to_workspace(1, 1).await?;
+        // tokens scoped to the workspace do not hold back the purge
+        state.create_refresh_token(&user, None).await?;
+        let input = CreateAccessToken {
+            name: "deploy".to_string(),
+            scopes: vec![Scope::ChatsRead],
+            expires_in: None,
+        };
+        state.create_personal_token(&user, input).await?;
+        let ret = state.delete_workspace(1, 1).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        let ret = state.delete_workspace(0, 0).await;
+        assert!(matches!(ret, Err(AppError::Workspace(_))));
+
+        state.delete_workspace(1, 0).await?;
+        assert!(state.find_workspace_by_id(1).await?.is_none());
+        assert!(!state.is_workspace_member(1, 1).await?);
+
+        assert_eq!(state.purge_deleted_workspaces().await?, 1);
+        assert!(state.get_chat_by_id(1).await?.is_none());
+        let user = state.find_user_by_email("test1@none.org").await?.unwrap();
+        assert_eq!(user.ws_id, 0);
+        assert!(state.list_personal_tokens(1).await?.is_empty());
+        assert!(state.find_workspace_by_name("workspace1").await?.is_none());
+        assert_eq!(state.purge_deleted_workspaces().await?, 0);
+        Ok(())
+    }
 }
@@ -0,0 +1,488 @@
+use std::{collections::HashMap, time::Duration};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use jwt_simple::prelude::*;
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{config::OidcConfig, error::AppError};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// openid connect client of the provider of a workspace
+pub struct OidcClient {
+    config: OidcConfig,
+    http: Client,
+}
+
+// endpoints from the discovery document
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderKeys {
+    keys: Vec<ProviderKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderKey {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IdTokenClaims {
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<bool>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+// the login started by the user agent, kept until the provider redirects back
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+// the user authenticated by the provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Result<Self, AppError> {
+        let http = Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(sso_error)?;
+        Ok(Self { config, http })
+    }
+
+    pub fn ws_id(&self) -> i64 {
+        self.config.ws_id
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.config.issuer
+    }
+
+    // url of the provider to redirect the user agent to, with a S256 pkce challenge
+    pub async fn authorization_request(&self) -> Result<AuthorizationRequest, AppError> {
+        let metadata = self.metadata().await?;
+        let state = random_string();
+        let nonce = random_string();
+        let code_verifier = random_string();
+        let challenge = Base64UrlSafeNoPadding::encode_to_string(Sha256::digest(&code_verifier))
+            .map_err(sso_error)?;
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", self.config.scopes.join(" ").as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(sso_error)?;
+        Ok(AuthorizationRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    // exchange the code of the callback and verify the id token it returns
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<OidcIdentity, AppError> {
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let res = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(sso_error)?;
+        if !res.status().is_success() {
+            return Err(AppError::Unauthorized(format!(
+                "Provider rejected the code with status {}",
+                res.status()
+            )));
+        }
+        let token: TokenResponse = res.json().await.map_err(sso_error)?;
+        let keys: ProviderKeys = self.get_json(&metadata.jwks_uri).await?;
+        self.verify_id_token(&token.id_token, &keys, nonce)
+    }
+
+    fn verify_id_token(
+        &self,
+        token: &str,
+        keys: &ProviderKeys,
+        nonce: &str,
+    ) -> Result<OidcIdentity, AppError> {
+        let metadata = Token::decode_metadata(token).map_err(invalid_id_token)?;
+        let key = keys
+            .keys
+            .iter()
+            .find(|k| metadata.key_id().is_none() || k.kid.as_deref() == metadata.key_id())
+            .ok_or_else(|| AppError::Unauthorized("Unknown key of the id token".to_string()))?;
+        let options = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[&self.config.issuer])),
+            allowed_audiences: Some(HashSet::from_strings(&[&self.config.client_id])),
+            required_nonce: Some(nonce.to_string()),
+            ..Default::default()
+        };
+        let claims = match (metadata.algorithm(), key.kty.as_str()) {
+            ("RS256", "RSA") => {
+                let n = decode_param(key.n.as_deref())?;
+                let e = decode_param(key.e.as_deref())?;
+                RS256PublicKey::from_components(&n, &e)
+                    .map_err(invalid_id_token)?
+                    .verify_token::<IdTokenClaims>(token, Some(options))
+            }
+            ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+                // uncompressed sec1 point
+                let mut point = vec![4u8];
+                point.extend(decode_param(key.x.as_deref())?);
+                point.extend(decode_param(key.y.as_deref())?);
+                ES256PublicKey::from_bytes(&point)
+                    .map_err(invalid_id_token)?
+                    .verify_token::<IdTokenClaims>(token, Some(options))
+            }
+            ("EdDSA", "OKP") if key.crv.as_deref() == Some("Ed25519") => {
+                Ed25519PublicKey::from_bytes(&decode_param(key.x.as_deref())?)
+                    .map_err(invalid_id_token)?
+                    .verify_token::<IdTokenClaims>(token, Some(options))
+            }
+            (alg, _) => {
+                return Err(AppError::Unauthorized(format!(
+                    "Unsupported id token algorithm {}",
+                    alg
+                )))
+            }
+        }
+        .map_err(invalid_id_token)?;
+        let subject = claims
+            .subject
+            .ok_or_else(|| AppError::Unauthorized("Id token without subject".to_string()))?;
+        Ok(OidcIdentity {
+            issuer: self.config.issuer.clone(),
+            subject,
+            email: claims.custom.email.map(|e| e.trim().to_string()),
+            email_verified: claims.custom.email_verified.unwrap_or(false),
+            name: claims.custom.name,
+        })
+    }
+
+    async fn metadata(&self) -> Result<ProviderMetadata, AppError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self.get_json(&url).await?;
+        if metadata.issuer != self.config.issuer {
+            return Err(AppError::Sso(format!(
+                "Issuer {} of the discovery document does not match",
+                metadata.issuer
+            )));
+        }
+        Ok(metadata)
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, AppError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(sso_error)?
+            .json()
+            .await
+            .map_err(sso_error)
+    }
+}
+
+// clients of the configured providers by workspace id
+pub fn build_oidc_clients(configs: &[OidcConfig]) -> Result<HashMap<i64, OidcClient>, AppError> {
+    configs
+        .iter()
+        .map(|config| Ok((config.ws_id, OidcClient::new(config.clone())?)))
+        .collect()
+}
+
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn decode_param(value: Option<&str>) -> Result<Vec<u8>, AppError> {
+    let value = value.ok_or_else(|| AppError::Unauthorized("Incomplete provider key".into()))?;
+    Base64UrlSafeNoPadding::decode_to_vec(value, None).map_err(invalid_id_token)
+}
+
+fn sso_error(e: impl ToString) -> AppError {
+    AppError::Sso(e.to_string())
+}
+
+fn invalid_id_token(e: impl ToString) -> AppError {
+    AppError::Unauthorized(format!("Invalid id token: {}", e.to_string()))
+}
+
+#[cfg(test)]
+pub mod test_provider {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::State,
+        routing::{get, post},
+        Form, Json, Router,
+    };
+    use jwt_simple::algorithms::ECDSAP256PublicKeyLike;
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    pub const CLIENT_ID: &str = "chat";
+
+    // user the provider signs in on its authorization page
+    #[derive(Debug, Clone)]
+    pub struct MockUser {
+        pub subject: String,
+        pub email: String,
+        pub email_verified: bool,
+        pub name: String,
+    }
+
+    // code => (pkce challenge, nonce, user)
+    type IssuedCodes = HashMap<String, (String, String, MockUser)>;
+
+    #[derive(Clone)]
+    struct MockState {
+        issuer: String,
+        key: Arc<ES256KeyPair>,
+        codes: Arc<Mutex<IssuedCodes>>,
+    }
+
+    // a local openid connect provider signing es256 id tokens
+    #[derive(Clone)]
+    pub struct MockProvider {
+        state: MockState,
+    }
+
+    impl MockProvider {
+        pub async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+            let state = MockState {
+                issuer,
+                key: Arc::new(ES256KeyPair::generate().with_key_id("mock")),
+                codes: Default::default(),
+            };
+            let app = Router::new()
+                .route("/.well-known/openid-configuration", get(discovery))
+                .route("/jwks", get(jwks))
+                .route("/token", post(token))
+                .with_state(state.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            Self { state }
+        }
+
+        pub fn config(&self, ws_id: i64) -> OidcConfig {
+            OidcConfig {
+                ws_id,
+                issuer: self.state.issuer.clone(),
+                client_id: CLIENT_ID.to_string(),
+                client_secret: Some("secret".to_string()),
+                redirect_uri: format!("http://localhost:8080/api/sso/{}/callback", ws_id),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+            }
+        }
+
+        // what the authorization page does: sign in the user and redirect back with a code
+        pub fn authorize(&self, url: &str, user: MockUser) -> (String, String) {
+            let url = Url::parse(url).unwrap();
+            let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+            let code = random_string();
+            self.state.codes.lock().unwrap().insert(
+                code.clone(),
+                (
+                    params["code_challenge"].clone(),
+                    params["nonce"].clone(),
+                    user,
+                ),
+            );
+            (code, params["state"].clone())
+        }
+    }
+
+    async fn discovery(State(state): State<MockState>) -> Json<Value> {
+        Json(json!({
+            "issuer": state.issuer,
+            "authorization_endpoint": format!("{}/authorize", state.issuer),
+            "token_endpoint": format!("{}/token", state.issuer),
+            "jwks_uri": format!("{}/jwks", state.issuer),
+        }))
+    }
+
+    async fn jwks(State(state): State<MockState>) -> Json<Value> {
+        let point = state.key.public_key().public_key().to_bytes_uncompressed();
+        let encode = |b: &[u8]| Base64UrlSafeNoPadding::encode_to_string(b).unwrap();
+        Json(json!({
+            "keys": [{
+                "kty": "EC",
+                "crv": "P-256",
+                "kid": "mock",
+                "x": encode(&point[1..33]),
+                "y": encode(&point[33..]),
+            }]
+        }))
+    }
+
+    async fn token(
+        State(state): State<MockState>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, (axum::http::StatusCode, &'static str)> {
+        let bad_request = (axum::http::StatusCode::BAD_REQUEST, "invalid_grant");
+        let code = form.get("code").ok_or(bad_request)?;
+        let (challenge, nonce, user) = state
+            .codes
+            .lock()
+            .unwrap()
+            .remove(code)
+            .ok_or(bad_request)?;
+        let verifier = form.get("code_verifier").ok_or(bad_request)?;
+        let expected = Base64UrlSafeNoPadding::encode_to_string(Sha256::digest(verifier)).unwrap();
+        if expected != challenge || form.get("client_id").map(String::as_str) != Some(CLIENT_ID) {
+            return Err(bad_request);
+        }
+        let claims = Claims::with_custom_claims(
+            IdTokenClaims {
+                email: Some(user.email),
+                email_verified: Some(user.email_verified),
+                name: Some(user.name),
+            },
+            jwt_simple::prelude::Duration::from_mins(5),
+        )
+        .with_issuer(&state.issuer)
+        .with_audience(CLIENT_ID)
+        .with_subject(user.subject)
+        .with_nonce(nonce);
+        let id_token = state.key.sign(claims).unwrap();
+        Ok(Json(json!({
+            "access_token": "unused",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_provider::*;
+    use super::*;
+
+    fn alice() -> MockUser {
+        MockUser {
+            subject: "alice".to_string(),
+            email: "Alice@Acme.org".to_string(),
+            email_verified: true,
+            name: "Alice".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn oidc_code_flow_should_work() -> anyhow::Result<()> {
+        let provider = MockProvider::start().await;
+        let client = OidcClient::new(provider.config(1))?;
+        let req = client.authorization_request().await?;
+        assert!(req.url.contains("code_challenge_method=S256"));
+        assert!(req.url.contains(&format!("state={}", req.state)));
+
+        let (code, state) = provider.authorize(&req.url, alice());
+        assert_eq!(state, req.state);
+        let identity = client
+            .exchange_code(&code, &req.code_verifier, &req.nonce)
+            .await?;
+        assert_eq!(identity.subject, "alice");
+        assert_eq!(identity.email.as_deref(), Some("Alice@Acme.org"));
+        assert!(identity.email_verified);
+
+        // the code is single use
+        let ret = client
+            .exchange_code(&code, &req.code_verifier, &req.nonce)
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oidc_should_reject_wrong_verifier_or_nonce() -> anyhow::Result<()> {
+        let provider = MockProvider::start().await;
+        let client = OidcClient::new(provider.config(1))?;
+        let req = client.authorization_request().await?;
+
+        let (code, _) = provider.authorize(&req.url, alice());
+        let ret = client.exchange_code(&code, "wrong", &req.nonce).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        let (code, _) = provider.authorize(&req.url, alice());
+        let ret = client
+            .exchange_code(&code, &req.code_verifier, "wrong")
+            .await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+        Ok(())
+    }
+}
@@ -1,9 +1,24 @@
 use crate::error::ErrorOutput;
-use crate::models::{CreateChat, CreateMessage, ListMessage, SignupUser, UpdateChat};
+use crate::models::{
+    ChangePassword, CreateAccessToken, CreateBot, CreateChat, CreateCommand, CreateInvite,
+    CreateMessage, CreatePoll, CreatedAccessToken, CreatedBot, DeleteAccount, ForgotPassword,
+    JoinWorkspace, ListAuditLogs, ListMessage, ListSaved, ListUsers, Logout, PrivacySettings,
+    RecoveryCodes, RefreshToken, ResetPassword, ScimEmail, ScimGroup, ScimGroupList, ScimListQuery,
+    ScimMember, ScimMeta, ScimName, ScimPatch, ScimPatchOp, ScimUser, ScimUserList, SignupUser,
+    TotpEnrollment, TransferWorkspace, TwoFactorChallenge, TwoFactorCode, TwoFactorStatus,
+    UpdateChat, UpdateJoinPolicy, UpdateMemberRole, UpdateProfile, UpdateStatus, UpdateWorkspace,
+    UserStatusFilter, VerifyEmail, VerifyTwoFactor, VotePoll,
+};
 use crate::AppState;
 use crate::{handlers::*, models::SigninUser};
 use axum::Router;
-use chat_core::{Chat, ChatType, ChatUser, Message, User, Workspace};
+use chat_core::{
+    AccessToken, AuditAction, AuditLog, Chat, ChatType, ChatUser, ContentFormat, DataExport,
+    DeletionStatus, DmPolicy, EphemeralMessage, ExportStatus, JoinPolicy, Jwk, Jwks, Message,
+    MessageKind, Poll, PollOption, SavedMessage, Scope, Session, User, UserDeletion, UserProfile,
+    Workspace, WorkspaceCommand, WorkspaceInvite, WorkspaceMember, WorkspaceRole,
+    WorkspaceSettings,
+};
 use utoipa::{
     openapi::security::{Http, HttpAuthScheme, SecurityScheme},
     Modify, OpenApi,
@@ -21,16 +36,111 @@ pub trait OpenApiRouter {
         paths(
             signup_handler,
             signin_handler,
+            signin_two_factor_handler,
+            sso_login_handler,
+            sso_callback_handler,
+            refresh_handler,
+            jwks_handler,
+            change_password_handler,
+            forgot_password_handler,
+            reset_password_handler,
+            verify_email_handler,
+            resend_verification_handler,
+            two_factor_status_handler,
+            enroll_totp_handler,
+            confirm_totp_handler,
+            disable_two_factor_handler,
+            regenerate_recovery_codes_handler,
+            logout_handler,
+            list_sessions_handler,
+            revoke_session_handler,
+            list_tokens_handler,
+            create_token_handler,
+            revoke_token_handler,
+            list_bots_handler,
+            create_bot_handler,
+            create_bot_token_handler,
+            list_workspace_tokens_handler,
+            revoke_workspace_token_handler,
             get_chat_handler,
             create_chat_handler,
             list_chat_handler,
             update_chat_handler,
             delete_chat_handler,
+            list_saved_handler,
+            save_message_handler,
+            unsave_message_handler,
+            get_poll_handler,
+            vote_poll_handler,
+            close_poll_handler,
+            list_commands_handler,
+            create_command_handler,
+            delete_command_handler,
+            list_workspaces_handler,
+            switch_workspace_handler,
+            join_workspace_handler,
+            update_join_policy_handler,
+            list_invites_handler,
+            create_invite_handler,
+            revoke_invite_handler,
+            get_workspace_handler,
+            update_workspace_handler,
+            delete_workspace_handler,
+            update_workspace_settings_handler,
+            transfer_workspace_handler,
+            list_workspace_members_handler,
+            remove_workspace_member_handler,
+            update_member_role_handler,
+            unlock_member_handler,
+            list_audit_logs_handler,
+            deactivate_member_handler,
+            reactivate_member_handler,
+            delete_member_account_handler,
+            get_user_deletion_handler,
+            delete_my_account_handler,
+            request_data_export_handler,
+            get_data_export_handler,
+            download_data_export_handler,
+            list_blocked_users_handler,
+            block_user_handler,
+            unblock_user_handler,
+            get_privacy_settings_handler,
+            update_privacy_settings_handler,
+            list_chat_users_handler,
+            get_my_profile_handler,
+            update_my_profile_handler,
+            get_user_profile_handler,
+            scim_service_provider_config_handler,
+            list_scim_users_handler,
+            get_scim_user_handler,
+            create_scim_user_handler,
+            replace_scim_user_handler,
+            patch_scim_user_handler,
+            delete_scim_user_handler,
+            list_scim_groups_handler,
+            get_scim_group_handler,
+            create_scim_group_handler,
+            replace_scim_group_handler,
+            patch_scim_group_handler,
+            delete_scim_group_handler,
         ),
         modifiers(&SecurityAddon),
         components(
-            schemas(User, Chat, ChatType, ChatUser, Message, Workspace, SignupUser, SigninUser,
-                AuthOutput, ErrorOutput, CreateChat, CreateMessage, ListMessage,  UpdateChat),
+            schemas(User, Chat, ChatType, ChatUser, ContentFormat, Message, Workspace, SignupUser, SigninUser,
+                AuthOutput, ErrorOutput, CreateChat, CreateMessage, ListMessage,  UpdateChat,
+                SavedMessage, ListSaved, MessageKind, Poll, PollOption, CreatePoll, VotePoll,
+                CreateCommand, WorkspaceCommand, EphemeralMessage, JoinPolicy,
+                WorkspaceInvite, CreateInvite, JoinWorkspace, UpdateJoinPolicy, WorkspaceSettings,
+                WorkspaceRole, WorkspaceMember, UpdateWorkspace, TransferWorkspace, UpdateMemberRole,
+                RefreshToken, Logout, Session, Scope, AccessToken, CreateAccessToken,
+                CreatedAccessToken, CreateBot, CreatedBot, Jwks, Jwk, ChangePassword, ForgotPassword,
+                ResetPassword, VerifyEmail, SigninOutput, TwoFactorChallenge, VerifyTwoFactor,
+                TwoFactorStatus, TotpEnrollment, TwoFactorCode, RecoveryCodes, ScimUser, ScimName,
+                ScimEmail, ScimMeta, ScimGroup, ScimMember, ScimUserList, ScimGroupList,
+                ScimListQuery, ScimPatch, ScimPatchOp, AuditLog, AuditAction, ListAuditLogs,
+                UserProfile, UpdateProfile, UpdateStatus, ListUsers, UserStatusFilter,
+                DeleteAccount, UserDeletion, DeletionStatus, DataExport, ExportStatus,
+                PrivacySettings, DmPolicy),
         ),
         tags(
             (name = "todo", description = "Todo items management API")
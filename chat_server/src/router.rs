@@ -1,6 +1,6 @@
 use axum::{
-    middleware::from_fn_with_state,
-    routing::{get, post},
+    middleware::{from_fn, from_fn_with_state},
+    routing::{delete, get, post, put},
     Router,
 };
 use chat_core::verify_token;
@@ -8,7 +8,7 @@ use chat_core::verify_token;
 use crate::{
     error::AppError,
     handlers::*,
-    middlewares::{set_layer, verify_is_chat_member},
+    middlewares::{load_user, set_layer, verify_is_chat_member, verify_scim_token, verify_scope},
     openapi::OpenApiRouter,
     state::AppState,
 };
@@ -27,17 +27,182 @@ pub async fn get_router(state: AppState) -> Result<Router, AppError> {
         .route("/", post(create_chat_handler).get(list_chat_handler));
     let api = Router::new()
         .route("/users", get(list_chat_users_handler))
+        .route(
+            "/users/me",
+            get(get_my_profile_handler)
+                .patch(update_my_profile_handler)
+                .delete(delete_my_account_handler),
+        )
+        .route("/users/me/blocks", get(list_blocked_users_handler))
+        .route(
+            "/users/me/blocks/:id",
+            put(block_user_handler).delete(unblock_user_handler),
+        )
+        .route(
+            "/users/me/privacy",
+            get(get_privacy_settings_handler).put(update_privacy_settings_handler),
+        )
+        .route("/users/me/exports", post(request_data_export_handler))
+        .route("/users/me/exports/:id", get(get_data_export_handler))
+        .route(
+            "/users/me/exports/:id/download",
+            get(download_data_export_handler),
+        )
+        .route("/users/:id", get(get_user_profile_handler))
+        .route("/workspaces", get(list_workspaces_handler))
+        .route("/workspaces/join", post(join_workspace_handler))
+        .route(
+            "/workspaces/:id",
+            get(get_workspace_handler)
+                .patch(update_workspace_handler)
+                .delete(delete_workspace_handler),
+        )
+        .route("/workspaces/:id/switch", post(switch_workspace_handler))
+        .route("/workspaces/:id/transfer", post(transfer_workspace_handler))
+        .route(
+            "/workspaces/:id/settings",
+            put(update_workspace_settings_handler),
+        )
+        .route(
+            "/workspaces/:id/members",
+            get(list_workspace_members_handler),
+        )
+        .route(
+            "/workspaces/:id/members/:user_id",
+            delete(remove_workspace_member_handler),
+        )
+        .route(
+            "/workspaces/:id/members/:user_id/role",
+            put(update_member_role_handler),
+        )
+        .route(
+            "/workspaces/:id/members/:user_id/unlock",
+            post(unlock_member_handler),
+        )
+        .route(
+            "/workspaces/:id/members/:user_id/deactivate",
+            post(deactivate_member_handler),
+        )
+        .route(
+            "/workspaces/:id/members/:user_id/reactivate",
+            post(reactivate_member_handler),
+        )
+        .route(
+            "/workspaces/:id/members/:user_id/account",
+            delete(delete_member_account_handler),
+        )
+        .route(
+            "/workspaces/:id/deletions/:deletion_id",
+            get(get_user_deletion_handler),
+        )
+        .route("/workspaces/:id/audit", get(list_audit_logs_handler))
+        .route("/workspaces/:id/policy", put(update_join_policy_handler))
+        .route(
+            "/workspaces/:id/invites",
+            get(list_invites_handler).post(create_invite_handler),
+        )
+        .route(
+            "/workspaces/:id/invites/:invite_id",
+            delete(revoke_invite_handler),
+        )
         .route("/upload", post(upload_handler))
         .route("/files/:ws_id/*path", get(file_handler))
+        .route("/polls/:msg_id", get(get_poll_handler))
+        .route("/polls/:msg_id/vote", post(vote_poll_handler))
+        .route("/polls/:msg_id/close", post(close_poll_handler))
+        .route("/saved", get(list_saved_handler))
+        .route(
+            "/saved/:msg_id",
+            post(save_message_handler).delete(unsave_message_handler),
+        )
+        .route(
+            "/commands",
+            get(list_commands_handler).post(create_command_handler),
+        )
+        .route("/commands/:id", delete(delete_command_handler))
+        .route("/logout", post(logout_handler))
+        .route("/sessions", get(list_sessions_handler))
+        .route("/sessions/:id", delete(revoke_session_handler))
+        .route(
+            "/tokens",
+            get(list_tokens_handler).post(create_token_handler),
+        )
+        .route("/tokens/:id", delete(revoke_token_handler))
+        .route(
+            "/workspaces/:id/bots",
+            get(list_bots_handler).post(create_bot_handler),
+        )
+        .route(
+            "/workspaces/:id/bots/:bot_id/tokens",
+            post(create_bot_token_handler),
+        )
+        .route("/workspaces/:id/tokens", get(list_workspace_tokens_handler))
+        .route(
+            "/workspaces/:id/tokens/:token_id",
+            delete(revoke_workspace_token_handler),
+        )
+        .route("/password", put(change_password_handler))
+        .route("/email/resend", post(resend_verification_handler))
+        .route("/2fa", get(two_factor_status_handler))
+        .route(
+            "/2fa/totp",
+            post(enroll_totp_handler).delete(disable_two_factor_handler),
+        )
+        .route("/2fa/totp/confirm", post(confirm_totp_handler))
+        .route(
+            "/2fa/recovery-codes",
+            post(regenerate_recovery_codes_handler),
+        )
         .nest("/chats", chats)
+        .layer(from_fn_with_state(state.clone(), load_user))
+        .layer(from_fn(verify_scope))
         .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
         .route("/signin", post(signin_handler))
-        .route("/signup", post(signup_handler));
+        .route("/signin/2fa", post(signin_two_factor_handler))
+        .route("/sso/:ws_id/login", get(sso_login_handler))
+        .route("/sso/:ws_id/callback", get(sso_callback_handler))
+        .route("/signup", post(signup_handler))
+        .route("/refresh", post(refresh_handler))
+        .route("/password/forgot", post(forgot_password_handler))
+        .route("/password/reset", post(reset_password_handler))
+        .route("/email/verify", post(verify_email_handler));
+
+    let scim = Router::new()
+        .route(
+            "/ServiceProviderConfig",
+            get(scim_service_provider_config_handler),
+        )
+        .route(
+            "/Users",
+            get(list_scim_users_handler).post(create_scim_user_handler),
+        )
+        .route(
+            "/Users/:id",
+            get(get_scim_user_handler)
+                .put(replace_scim_user_handler)
+                .patch(patch_scim_user_handler)
+                .delete(delete_scim_user_handler),
+        )
+        .route(
+            "/Groups",
+            get(list_scim_groups_handler).post(create_scim_group_handler),
+        )
+        .route(
+            "/Groups/:id",
+            get(get_scim_group_handler)
+                .put(replace_scim_group_handler)
+                .patch(patch_scim_group_handler)
+                .delete(delete_scim_group_handler),
+        )
+        .layer(from_fn_with_state(state.clone(), verify_scim_token))
+        .layer(from_fn_with_state(state.clone(), verify_token::<AppState>));
 
     let app = Router::new()
         .openapi()
         .route("/", get(index_handler))
+        .route("/.well-known/jwks.json", get(jwks_handler))
         .nest("/api", api)
+        .nest("/scim/v2", scim)
         .with_state(state.clone());
     Ok(set_layer(app))
 }
@@ -1,3 +1,5 @@
+use std::net::SocketAddr;
+
 use anyhow::Result;
 
 use tokio::net::TcpListener;
@@ -17,6 +19,10 @@ pub async fn run() -> Result<()> {
     let listener = TcpListener::bind(&addr).await?;
     info!("listening on {}", addr);
 
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
@@ -1,11 +1,22 @@
-use std::{fmt::Debug, fs, ops::Deref, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, fs, ops::Deref, sync::Arc};
 
 use anyhow::{Context, Result};
 
-use chat_core::{DecodingKey, EncodingKey, TokenVerifier};
+use axum::async_trait;
+use chat_core::{AuthContext, DecodingKey, EncodingKey, RevocationList, TokenVerifier};
 use sqlx::PgPool;
 
-use crate::{config::AppConfig, error::AppError};
+use crate::{
+    authenticator::{build_authenticator, Authenticator},
+    commands::{spawn_reminder_worker, CommandRegistry},
+    config::AppConfig,
+    error::AppError,
+    lockout::{build_signin_throttle, SigninThrottle},
+    mailer::{build_mailer, Mailer},
+    models::{is_personal_token, spawn_token_cleanup, spawn_workspace_cleanup, UserCache},
+    oidc::{build_oidc_clients, OidcClient},
+    unfurl::{HttpFetcher, UnfurlWorker, Unfurler},
+};
 
 #[derive(Clone, Debug)]
 pub struct AppState {
@@ -17,31 +28,78 @@ pub struct AppStateInner {
     pub dk: DecodingKey,
     pub ek: EncodingKey,
     pub pool: PgPool,
+    pub unfurler: Option<Unfurler>,
+    pub commands: CommandRegistry,
+    pub revoked: RevocationList,
+    pub users: UserCache,
+    pub mailer: Arc<dyn Mailer>,
+    // openid connect clients by workspace id
+    pub sso: HashMap<i64, OidcClient>,
+    pub authenticator: Arc<dyn Authenticator>,
+    pub signin_throttle: SigninThrottle,
 }
 
 impl AppState {
     pub async fn try_new(config: AppConfig) -> Result<Self, AppError> {
         fs::create_dir_all(&config.server.base_dir).context("create base dir failed")?;
-        let dk = DecodingKey::load(&config.auth.pk).context("load pk failed")?;
+        let dk = config.auth.decoding_key().context("load pk failed")?;
         let ek = EncodingKey::load(&config.auth.sk).context("loan sk failed")?;
         let pool = PgPool::connect(&config.server.db_url)
             .await
             .context("connect to db failed")?;
-        Ok(Self {
+        let unfurler = spawn_unfurler(&config, &pool);
+        let mailer = build_mailer(&config.mail)?;
+        let sso = build_oidc_clients(&config.sso)?;
+        let authenticator = build_authenticator(&config, pool.clone());
+        let signin_throttle = build_signin_throttle(&config.lockout, pool.clone());
+        spawn_reminder_worker(pool.clone());
+        let state = Self {
             inner: Arc::new(AppStateInner {
                 config,
                 dk,
                 ek,
                 pool,
+                unfurler,
+                commands: CommandRegistry::default(),
+                revoked: RevocationList::new(),
+                users: UserCache::default(),
+                mailer,
+                sso,
+                authenticator,
+                signin_throttle,
             }),
-        })
+        };
+        state
+            .load_revoked_tokens()
+            .await
+            .context("load revoked tokens failed")?;
+        spawn_workspace_cleanup(state.clone());
+        spawn_token_cleanup(state.clone());
+        Ok(state)
+    }
+}
+
+fn spawn_unfurler(config: &AppConfig, pool: &PgPool) -> Option<Unfurler> {
+    if !config.unfurl.enabled {
+        return None;
     }
+    let fetcher = HttpFetcher::new(config.unfurl.clone());
+    let worker = UnfurlWorker::new(pool.clone(), Arc::new(fetcher));
+    Some(Unfurler::spawn(worker))
 }
 
+#[async_trait]
 impl TokenVerifier for AppState {
     type Error = AppError;
-    fn verify(&self, token: &str) -> Result<chat_core::User, Self::Error> {
-        Ok(self.inner.dk.verify(token)?)
+    async fn verify(&self, token: &str) -> Result<AuthContext, Self::Error> {
+        if is_personal_token(token) {
+            return self.verify_personal_token(token).await;
+        }
+        let claims = self.inner.dk.decode(token)?;
+        if self.inner.revoked.is_claims_revoked(&claims) {
+            return Err(AppError::Unauthorized("Token revoked".to_string()));
+        }
+        Ok(claims.custom.into())
     }
 }
 
@@ -53,12 +111,19 @@ mod test_util {
     use std::path::Path;
     impl AppState {
         pub async fn new_for_test() -> Result<(sqlx_db_tester::TestPg, Self), AppError> {
-            let config = AppConfig::load()?;
-            let dk = DecodingKey::load(&config.auth.pk).context("load pk failed")?;
+            let mut config = AppConfig::load()?;
+            let dk = config.auth.decoding_key().context("load pk failed")?;
             let ek = EncodingKey::load(&config.auth.sk).context("loan sk failed")?;
             let server_url = config.server.db_url.rsplit_once('/').unwrap_or_default();
             let tdb = TestPg::new(server_url.0.to_string(), Path::new("../migrations"));
             let pool = tdb.get_pool().await;
+            // each test has its own outbox
+            config.mail.smtp_url = None;
+            config.mail.outbox_dir = config.mail.outbox_dir.join(&tdb.dbname);
+            // links of the test messages are not fetched, tests opt in with their own worker
+            config.unfurl.enabled = false;
+            let mailer = build_mailer(&config.mail)?;
+            let sso = build_oidc_clients(&config.sso)?;
             let sql = include_str!("../fixtures/test.sql").split(';');
             let mut tx = pool.begin().await.expect("begin failed");
             for q in sql {
@@ -68,12 +133,23 @@ mod test_util {
                 tx.execute(q).await.expect("execute failed");
             }
             tx.commit().await.expect("commit failed");
+            let unfurler = spawn_unfurler(&config, &pool);
+            let authenticator = build_authenticator(&config, pool.clone());
+            let signin_throttle = build_signin_throttle(&config.lockout, pool.clone());
             let state = Self {
                 inner: Arc::new(AppStateInner {
                     config,
                     dk,
                     ek,
                     pool,
+                    unfurler,
+                    commands: CommandRegistry::default(),
+                    revoked: RevocationList::new(),
+                    users: UserCache::default(),
+                    mailer,
+                    sso,
+                    authenticator,
+                    signin_throttle,
                 }),
             };
             Ok((tdb, state))
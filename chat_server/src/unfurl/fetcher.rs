@@ -0,0 +1,247 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use axum::async_trait;
+use reqwest::{header, redirect::Policy, Client, ClientBuilder};
+use tokio::net::lookup_host;
+use url::{Host, Url};
+
+use crate::{config::UnfurlConfig, error::AppError};
+
+pub const MAX_REDIRECTS: usize = 3;
+const USER_AGENT: &str = "chat_server-unfurl/0.1";
+
+#[async_trait]
+pub trait LinkFetcher: Send + Sync + 'static {
+    // fetch the html page of the url
+    async fn fetch(&self, url: &Url) -> Result<String, AppError>;
+}
+
+pub struct HttpFetcher {
+    config: UnfurlConfig,
+}
+
+impl HttpFetcher {
+    pub fn new(config: UnfurlConfig) -> Self {
+        Self { config }
+    }
+
+    async fn fetch_html(&self, url: &Url) -> Result<String, AppError> {
+        let mut url = url.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let addrs = resolve_public_addrs(&url, self.config.allow_private_ips)
+                .await
+                .map_err(AppError::Unfurl)?;
+            let client = pinned_client(&url, &addrs)
+                .timeout(Duration::from_millis(self.config.timeout_ms))
+                .user_agent(USER_AGENT)
+                .build()
+                .map_err(unfurl_error)?;
+            let mut res = client.get(url.clone()).send().await.map_err(unfurl_error)?;
+
+            if res.status().is_redirection() {
+                let location = res
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| AppError::Unfurl("redirect without location".to_string()))?;
+                url = url.join(location).map_err(unfurl_error)?;
+                continue;
+            }
+            if !res.status().is_success() {
+                return Err(AppError::Unfurl(format!(
+                    "unexpected status {} for {}",
+                    res.status(),
+                    url
+                )));
+            }
+            let is_html = res
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.starts_with("text/html"));
+            if !is_html {
+                return Err(AppError::Unfurl(format!("{} is not a html page", url)));
+            }
+
+            // the metadata lives in the head, so a truncated body is good enough
+            let mut body = Vec::new();
+            while let Some(chunk) = res.chunk().await.map_err(unfurl_error)? {
+                body.extend_from_slice(&chunk);
+                if body.len() >= self.config.max_size {
+                    body.truncate(self.config.max_size);
+                    break;
+                }
+            }
+            return Ok(String::from_utf8_lossy(&body).into_owned());
+        }
+        Err(AppError::Unfurl(format!("too many redirects for {}", url)))
+    }
+}
+
+// addresses of the http(s) url, all of them must be public unless allowed
+pub async fn resolve_public_addrs(
+    url: &Url,
+    allow_private_ips: bool,
+) -> Result<Vec<SocketAddr>, String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported url: {}", url));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = match url.host() {
+        Some(Host::Domain(domain)) => lookup_host((domain, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect::<Vec<_>>(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        None => vec![],
+    };
+    if addrs.is_empty() {
+        return Err(format!("failed to resolve {}", url));
+    }
+    if !allow_private_ips && addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(format!("{} resolves to a private address", url));
+    }
+    Ok(addrs)
+}
+
+// pin the checked addresses, so the url can't be resolved again to a private one,
+// redirects are not followed as their target has to be checked too, and proxies from the
+// environment are ignored as they would resolve the host by themselves
+pub fn pinned_client(url: &Url, addrs: &[SocketAddr]) -> ClientBuilder {
+    Client::builder()
+        .no_proxy()
+        .redirect(Policy::none())
+        .resolve_to_addrs(url.host_str().unwrap_or_default(), addrs)
+}
+
+#[async_trait]
+impl LinkFetcher for HttpFetcher {
+    async fn fetch(&self, url: &Url) -> Result<String, AppError> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        tokio::time::timeout(timeout, self.fetch_html(url))
+            .await
+            .map_err(|_| AppError::Unfurl(format!("fetch {} timeout", url)))?
+    }
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // 0.0.0.0/8
+        || a == 0
+        // shared address space 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // ietf protocol assignments 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // reserved 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = embedded_ipv4(ip) {
+        return is_public_ipv4(ip);
+    }
+    let segments = ip.segments();
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link local fe80::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        // documentation 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8))
+}
+
+// ipv6 addresses routed to the ipv4 address they carry
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let ipv4 = |hi: u16, lo: u16| Some(Ipv4Addr::from((hi as u32) << 16 | lo as u32));
+    match ip.segments() {
+        // nat64 64:ff9b::/96
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => ipv4(hi, lo),
+        // 6to4 2002::/16
+        [0x2002, hi, lo, ..] => ipv4(hi, lo),
+        // ipv4-compatible ::a.b.c.d, except the loopback and unspecified addresses
+        [0, 0, 0, 0, 0, 0, hi, lo] if hi != 0 || lo > 1 => ipv4(hi, lo),
+        _ => None,
+    }
+}
+
+fn unfurl_error(e: impl ToString) -> AppError {
+    AppError::Unfurl(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_public_ip_should_work() {
+        let private = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:a00:1::1",
+            "2002:7f00:1::",
+            "::127.0.0.1",
+            "::10.0.0.1",
+        ];
+        for ip in private {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} is private", ip);
+        }
+        let public = [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "64:ff9b::101:101",
+            "2002:101:101::1",
+        ];
+        for ip in public {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn http_fetcher_should_reject_private_address() {
+        let fetcher = HttpFetcher::new(UnfurlConfig::default());
+        let url = Url::parse("http://127.0.0.1:8080/").unwrap();
+        let ret = fetcher.fetch(&url).await;
+        assert!(matches!(ret, Err(AppError::Unfurl(_))));
+
+        let url = Url::parse("ftp://example.com/").unwrap();
+        let ret = fetcher.fetch(&url).await;
+        assert!(matches!(ret, Err(AppError::Unfurl(_))));
+    }
+}
@@ -0,0 +1,320 @@
+mod fetcher;
+
+use std::sync::Arc;
+
+use chat_core::{LinkPreview, Message};
+use scraper::{Html, Selector};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tracing::warn;
+use url::Url;
+
+use crate::error::AppError;
+
+pub use fetcher::{pinned_client, resolve_public_addrs, HttpFetcher, LinkFetcher, MAX_REDIRECTS};
+
+const MAX_URLS_PER_MESSAGE: usize = 3;
+const MAX_PENDING_JOBS: usize = 1024;
+const MAX_TEXT_LEN: usize = 300;
+// cached previews older than this are fetched again
+const CACHE_TTL_HOURS: i32 = 24;
+
+#[derive(Debug)]
+pub struct UnfurlJob {
+    pub message_id: i64,
+    pub urls: Vec<Url>,
+}
+
+// handle of the unfurl worker, jobs are processed in the background
+#[derive(Clone)]
+pub struct Unfurler {
+    sender: mpsc::Sender<UnfurlJob>,
+}
+
+#[derive(Clone)]
+pub struct UnfurlWorker {
+    pool: PgPool,
+    fetcher: Arc<dyn LinkFetcher>,
+}
+
+impl Unfurler {
+    pub fn spawn(worker: UnfurlWorker) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<UnfurlJob>(MAX_PENDING_JOBS);
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                let worker = worker.clone();
+                tokio::spawn(async move {
+                    let message_id = job.message_id;
+                    if let Err(e) = worker.unfurl(job).await {
+                        warn!("Failed to unfurl message {}: {}", message_id, e);
+                    }
+                });
+            }
+        });
+        Self { sender }
+    }
+
+    pub fn submit(&self, message: &Message) {
+        let urls = extract_urls(&message.content);
+        if urls.is_empty() {
+            return;
+        }
+        let job = UnfurlJob {
+            message_id: message.id,
+            urls,
+        };
+        if let Err(e) = self.sender.try_send(job) {
+            warn!("Failed to submit unfurl job: {}", e);
+        }
+    }
+}
+
+impl UnfurlWorker {
+    pub fn new(pool: PgPool, fetcher: Arc<dyn LinkFetcher>) -> Self {
+        Self { pool, fetcher }
+    }
+
+    // fetch previews of the urls and attach them to the message
+    pub async fn unfurl(&self, job: UnfurlJob) -> Result<Vec<LinkPreview>, AppError> {
+        let mut previews = vec![];
+        for url in job.urls {
+            match self.get_preview(&url).await {
+                Ok(Some(preview)) => previews.push(preview),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to get preview of {}: {}", url, e),
+            }
+        }
+        if previews.is_empty() {
+            return Ok(previews);
+        }
+
+        sqlx::query("UPDATE messages SET previews = $1 WHERE id = $2")
+            .bind(sqlx::types::Json(&previews))
+            .bind(job.message_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(previews)
+    }
+
+    async fn get_preview(&self, url: &Url) -> Result<Option<LinkPreview>, AppError> {
+        let cached = sqlx::query_as(
+            r#"
+                    SELECT url, title, description, image, site_name
+                    FROM link_previews
+                    WHERE url = $1 AND fetched_at > NOW() - make_interval(hours => $2)"#,
+        )
+        .bind(url.as_str())
+        .bind(CACHE_TTL_HOURS)
+        .fetch_optional(&self.pool)
+        .await?;
+        if cached.is_some() {
+            return Ok(cached);
+        }
+
+        let html = self.fetcher.fetch(url).await?;
+        let Some(preview) = parse_preview(url, &html) else {
+            return Ok(None);
+        };
+        sqlx::query(
+            r#"
+                    INSERT INTO link_previews (url, title, description, image, site_name)
+                    VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (url) DO UPDATE
+                    SET title = $2, description = $3, image = $4, site_name = $5, fetched_at = NOW()"#,
+        )
+        .bind(&preview.url)
+        .bind(&preview.title)
+        .bind(&preview.description)
+        .bind(&preview.image)
+        .bind(&preview.site_name)
+        .execute(&self.pool)
+        .await?;
+        Ok(Some(preview))
+    }
+}
+
+pub fn extract_urls(content: &str) -> Vec<Url> {
+    let mut urls: Vec<Url> = vec![];
+    let mut rest = content;
+    while let Some(start) = [rest.find("http://"), rest.find("https://")]
+        .into_iter()
+        .flatten()
+        .min()
+    {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || "<>\"'()[]{}`".contains(c))
+            .unwrap_or(candidate.len());
+        let candidate = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        if let Ok(url) = Url::parse(candidate) {
+            if url.host().is_some() && !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        if urls.len() >= MAX_URLS_PER_MESSAGE {
+            break;
+        }
+        rest = &rest[start + end..];
+    }
+    urls
+}
+
+pub fn parse_preview(url: &Url, html: &str) -> Option<LinkPreview> {
+    let document = Html::parse_document(html);
+    let meta = |attr: &str, name: &str| -> Option<String> {
+        let selector = Selector::parse(&format!("meta[{}=\"{}\"]", attr, name)).ok()?;
+        document
+            .select(&selector)
+            .filter_map(|e| e.value().attr("content"))
+            .map(clean_text)
+            .find(|s| !s.is_empty())
+    };
+    let title = meta("property", "og:title")
+        .or_else(|| meta("name", "twitter:title"))
+        .or_else(|| {
+            let selector = Selector::parse("title").ok()?;
+            document
+                .select(&selector)
+                .next()
+                .map(|e| clean_text(&e.text().collect::<String>()))
+                .filter(|s| !s.is_empty())
+        });
+    let description = meta("property", "og:description")
+        .or_else(|| meta("name", "twitter:description"))
+        .or_else(|| meta("name", "description"));
+    // only keep http(s) images, relative ones are resolved against the page
+    let image = meta("property", "og:image")
+        .or_else(|| meta("name", "twitter:image"))
+        .and_then(|image| url.join(&image).ok())
+        .filter(|image| matches!(image.scheme(), "http" | "https"))
+        .map(String::from);
+    let site_name = meta("property", "og:site_name");
+
+    if title.is_none() && description.is_none() && image.is_none() {
+        return None;
+    }
+    Some(LinkPreview {
+        url: url.to_string(),
+        title,
+        description,
+        image,
+        site_name,
+    })
+}
+
+fn clean_text(s: &str) -> String {
+    let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    s.chars().take(MAX_TEXT_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{config::UnfurlConfig, models::CreateMessage, AppState};
+    use axum::{http::header::CONTENT_TYPE, routing::get, Router};
+    use tokio::net::TcpListener;
+
+    const HTML: &str = r#"<html><head>
+        <title>ignored</title>
+        <meta property="og:title" content="Hello  World">
+        <meta name="description" content="a page">
+        <meta property="og:image" content="/logo.png">
+        </head><body></body></html>"#;
+
+    #[test]
+    fn extract_urls_should_work() {
+        let urls = extract_urls(
+            "see https://a.com/x?y=1, [doc](http://b.com/) and https://a.com/x?y=1 and ftp://c.com",
+        );
+        let urls = urls.iter().map(|u| u.as_str()).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["https://a.com/x?y=1", "http://b.com/"]);
+
+        let urls = extract_urls("https://1.com https://2.com https://3.com https://4.com");
+        assert_eq!(urls.len(), MAX_URLS_PER_MESSAGE);
+
+        assert!(extract_urls("no links here").is_empty());
+    }
+
+    #[test]
+    fn parse_preview_should_work() {
+        let url = Url::parse("https://example.com/post/1").unwrap();
+        let preview = parse_preview(&url, HTML).unwrap();
+        assert_eq!(preview.title, Some("Hello World".to_string()));
+        assert_eq!(preview.description, Some("a page".to_string()));
+        assert_eq!(
+            preview.image,
+            Some("https://example.com/logo.png".to_string())
+        );
+        assert_eq!(preview.site_name, None);
+
+        assert!(parse_preview(&url, "<html><body>hi</body></html>").is_none());
+    }
+
+    #[tokio::test]
+    async fn unfurl_worker_should_work() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+
+        // local http stub serving a html page
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { ([(CONTENT_TYPE, "text/html; charset=utf-8")], HTML) }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = UnfurlConfig {
+            allow_private_ips: true,
+            ..Default::default()
+        };
+        let worker = UnfurlWorker::new(state.pool.clone(), Arc::new(HttpFetcher::new(config)));
+
+        let content = format!("look at http://{}/", addr);
+        let create_message = CreateMessage {
+            content,
+            ..Default::default()
+        };
+        let message = state.create_message(create_message, 1, 1).await?;
+        let job = UnfurlJob {
+            message_id: message.id,
+            urls: extract_urls(&message.content),
+        };
+        let previews = worker.unfurl(job).await?;
+        assert_eq!(previews.len(), 1);
+        assert_eq!(previews[0].title, Some("Hello World".to_string()));
+
+        let message: Message = sqlx::query_as("SELECT * FROM messages WHERE id = $1")
+            .bind(message.id)
+            .fetch_one(&state.pool)
+            .await?;
+        assert_eq!(message.previews, previews);
+
+        // the second time should hit the cache
+        let job = UnfurlJob {
+            message_id: message.id,
+            urls: extract_urls(&message.content),
+        };
+        worker.unfurl(job).await?;
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // private addresses are rejected by default
+        let worker = UnfurlWorker::new(
+            state.pool.clone(),
+            Arc::new(HttpFetcher::new(UnfurlConfig::default())),
+        );
+        let job = UnfurlJob {
+            message_id: message.id,
+            urls: vec![Url::parse(&format!("http://{}/other", addr))?],
+        };
+        let previews = worker.unfurl(job).await?;
+        assert!(previews.is_empty());
+        Ok(())
+    }
+}
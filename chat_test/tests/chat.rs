@@ -81,7 +81,7 @@ impl ChatServer {
     pub async fn signin(&mut self, email: &str, password: &str, ws_id: i64) -> Result<String> {
         let res = self
             .client
-            .post(&format!("http://localhost:{}/api/signin", self.addr.port()))
+            .post(format!("http://localhost:{}/api/signin", self.addr.port()))
             .json(&serde_json::json!({ "email": email, "password": password, "ws_id": ws_id }))
             .send()
             .await?;
@@ -98,7 +98,7 @@ impl ChatServer {
     ) -> Result<()> {
         let res = self
             .client
-            .post(&format!("http://localhost:{}/api/signup", self.addr.port()))
+            .post(format!("http://localhost:{}/api/signup", self.addr.port()))
             .json(&serde_json::json!({ "fullname": fullname, "email": email, "password": password, "workspace": workspace }))
             .send()
             .await?;
@@ -115,7 +115,7 @@ impl ChatServer {
     ) -> Result<Chat> {
         let res = self
             .client
-            .post(&format!("http://localhost:{}/api/chats", self.addr.port()))
+            .post(format!("http://localhost:{}/api/chats", self.addr.port()))
             .header("Authorization", format!("Bearer {}", self.token))
             .json(&serde_json::json!({ "name": name, "members": members, "ws_id": ws_id, "public": public }))
             .send()
@@ -138,7 +138,7 @@ impl ChatServer {
 
         let resp = self
             .client
-            .post(&format!("http://localhost:{}/api/upload", self.addr.port()))
+            .post(format!("http://localhost:{}/api/upload", self.addr.port()))
             .header("Authorization", format!("Bearer {}", self.token))
             .multipart(reqwest::multipart::Form::new().part("file", part))
             .send()
@@ -148,7 +148,7 @@ impl ChatServer {
 
         let res = self
             .client
-            .post(&format!(
+            .post(format!(
                 "http://localhost:{}/api/chats/{}",
                 self.addr.port(),
                 chat_id
@@ -0,0 +1,32 @@
+-- Add migration script here
+
+-- create saved message table, a private bookmark list per user
+CREATE TABLE IF NOT EXISTS saved_messages (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- remove the bookmark automatically when the message is deleted
+    message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, message_id)
+);
+
+-- create index for saved messages for user_id order by id desc
+CREATE INDEX IF NOT EXISTS saved_messages_user_id_idx ON saved_messages(user_id, id DESC);
+
+-- if chat members changed, remove bookmarks of users who are no longer members
+CREATE OR REPLACE FUNCTION remove_saved_messages_of_non_members()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM saved_messages s
+    USING messages m
+    WHERE s.message_id = m.id
+        AND m.chat_id = NEW.id
+        AND NOT (s.user_id = ANY(NEW.members));
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER remove_saved_messages_trigger
+AFTER UPDATE OF members ON chats
+FOR EACH ROW
+EXECUTE FUNCTION remove_saved_messages_of_non_members();
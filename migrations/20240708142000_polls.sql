@@ -0,0 +1,27 @@
+-- Add migration script here
+
+-- create message kind: text, poll
+CREATE TYPE message_kind AS ENUM ('text', 'poll');
+
+ALTER TABLE messages
+ADD COLUMN kind message_kind NOT NULL DEFAULT 'text';
+
+-- create poll table, a poll is always attached to a message with kind poll
+CREATE TABLE IF NOT EXISTS polls (
+    message_id BIGINT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    question TEXT NOT NULL,
+    options TEXT[] NOT NULL,
+    multi_select BOOLEAN NOT NULL DEFAULT FALSE,
+    anonymous BOOLEAN NOT NULL DEFAULT FALSE,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+-- create poll vote table, option is the index of polls.options
+CREATE TABLE IF NOT EXISTS poll_votes (
+    message_id BIGINT NOT NULL REFERENCES polls(message_id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    option_idx INT NOT NULL,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id, option_idx)
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+
+-- create content format: plain, markdown
+CREATE TYPE content_format AS ENUM ('plain', 'markdown');
+
+-- existing messages are treated as plain text, the server renders them on insert
+ALTER TABLE messages
+ADD COLUMN format content_format NOT NULL DEFAULT 'plain',
+ADD COLUMN content_html TEXT NOT NULL DEFAULT '',
+ADD COLUMN content_text TEXT NOT NULL DEFAULT '';
+
+UPDATE messages
+SET content_text = content,
+    content_html = '<p>' || replace(replace(replace(content, '&', '&amp;'), '<', '&lt;'), '>', '&gt;') || '</p>';
@@ -0,0 +1,38 @@
+-- Add migration script here
+
+-- previews of the links in the message, filled by the unfurl worker
+ALTER TABLE messages
+ADD COLUMN previews JSONB NOT NULL DEFAULT '[]';
+
+-- cache of fetched link previews per url
+CREATE TABLE IF NOT EXISTS link_previews (
+    url TEXT PRIMARY KEY,
+    title TEXT,
+    description TEXT,
+    image TEXT,
+    site_name TEXT,
+    fetched_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+-- if message changed, notify with message data
+CREATE OR REPLACE FUNCTION add_to_message()
+RETURNS TRIGGER AS $$
+BEGIN
+
+    IF TG_OP = 'INSERT' THEN
+        RAISE NOTICE 'add_to_message: %', NEW;
+        PERFORM pg_notify('chat_message_created', json_build_object(
+        'message', NEW,
+        'chat', (select row_to_json(chats) from chats where id = NEW.chat_id)
+
+    )::text);
+    ELSIF TG_OP = 'UPDATE' THEN
+        RAISE NOTICE 'update_message: %', NEW;
+        PERFORM pg_notify('chat_message_updated', json_build_object(
+        'message', NEW,
+        'chat', (select row_to_json(chats) from chats where id = NEW.chat_id)
+    )::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
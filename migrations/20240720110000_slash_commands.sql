@@ -0,0 +1,31 @@
+-- Add migration script here
+
+-- chat topic, set by the /topic command
+ALTER TABLE chats
+ADD COLUMN topic VARCHAR(256);
+
+-- workspace registered slash commands, executed by calling out to the url
+CREATE TABLE IF NOT EXISTS workspace_commands (
+    id BIGSERIAL PRIMARY KEY,
+    ws_id BIGINT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    name VARCHAR(32) NOT NULL,
+    url TEXT NOT NULL,
+    description VARCHAR(256) NOT NULL DEFAULT '',
+    created_by BIGINT NOT NULL REFERENCES users(id),
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (ws_id, name)
+);
+
+-- reminders created by the /remind command
+CREATE TABLE IF NOT EXISTS reminders (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    remind_at timestamptz NOT NULL,
+    delivered BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+-- create index for undelivered reminders order by remind_at
+CREATE INDEX IF NOT EXISTS reminders_remind_at_idx ON reminders(remind_at) WHERE NOT delivered;
@@ -0,0 +1,19 @@
+-- Add migration script here
+
+-- a user could belong to multiple workspaces, users.ws_id is the default one
+CREATE TABLE IF NOT EXISTS workspace_members (
+    ws_id BIGINT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    joined_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ws_id, user_id)
+);
+
+-- create index for workspace members for user_id
+CREATE INDEX IF NOT EXISTS workspace_members_user_id_idx ON workspace_members(user_id);
+
+-- existing users are members of their current workspace, owners are members of their workspaces
+INSERT INTO workspace_members (ws_id, user_id)
+SELECT ws_id, id FROM users
+UNION
+SELECT id, owner_id FROM workspaces
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,30 @@
+-- Add migration script here
+
+-- how users could join a workspace without an invite
+CREATE TYPE join_policy AS ENUM ('invite_only', 'domain', 'open');
+
+ALTER TABLE workspaces
+ADD COLUMN join_policy join_policy NOT NULL DEFAULT 'invite_only',
+-- email domains allowed to join when the policy is 'domain'
+ADD COLUMN allowed_domains TEXT[] NOT NULL DEFAULT '{}';
+
+-- the default workspace stays open for everyone
+UPDATE workspaces SET join_policy = 'open' WHERE id = 0;
+
+-- create workspace invite table
+CREATE TABLE IF NOT EXISTS workspace_invites (
+    id BIGSERIAL PRIMARY KEY,
+    ws_id BIGINT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    code VARCHAR(32) NOT NULL UNIQUE,
+    -- only the user with this email could use the invite if set
+    email VARCHAR(64),
+    -- unlimited if null
+    max_uses INT,
+    uses INT NOT NULL DEFAULT 0,
+    expires_at timestamptz NOT NULL,
+    created_by BIGINT NOT NULL REFERENCES users(id),
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+-- create index for workspace invites for ws_id
+CREATE INDEX IF NOT EXISTS workspace_invites_ws_id_idx ON workspace_invites(ws_id);
@@ -0,0 +1,18 @@
+-- Add migration script here
+
+-- role of a user in a workspace
+CREATE TYPE workspace_role AS ENUM ('owner', 'admin', 'member');
+
+ALTER TABLE workspace_members
+ADD COLUMN role workspace_role NOT NULL DEFAULT 'member';
+
+UPDATE workspace_members wm
+SET role = 'owner'
+FROM workspaces w
+WHERE w.id = wm.ws_id AND w.owner_id = wm.user_id;
+
+ALTER TABLE workspaces
+-- default channels, message retention and upload limits
+ADD COLUMN settings JSONB NOT NULL DEFAULT '{}',
+-- deleted workspaces are purged by the cleanup job
+ADD COLUMN deleted_at timestamptz;
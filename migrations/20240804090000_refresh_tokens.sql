@@ -0,0 +1,24 @@
+-- Add migration script here
+
+-- rotating refresh tokens, only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id),
+  -- workspace the issued access tokens are scoped to
+  ws_id bigint NOT NULL REFERENCES workspaces(id),
+  token_hash char(64) NOT NULL UNIQUE,
+  -- all the tokens rotated from the same signin share a family
+  family_id char(32) NOT NULL,
+  expires_at timestamptz NOT NULL,
+  revoked_at timestamptz,
+  replaced_by bigint REFERENCES refresh_tokens(id) ON DELETE SET NULL,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_family_id_idx ON refresh_tokens(family_id);
+
+-- revoked access tokens (jti), kept until the token expires
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+  jti text PRIMARY KEY,
+  expires_at timestamptz NOT NULL
+);
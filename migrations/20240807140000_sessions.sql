@@ -0,0 +1,18 @@
+-- Add migration script here
+
+-- a signed in device, refresh tokens and access tokens are issued for a session
+CREATE TABLE IF NOT EXISTS sessions (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id),
+  device_name varchar(128) NOT NULL,
+  user_agent text,
+  ip varchar(64),
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  last_seen_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  revoked_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions(user_id);
+
+ALTER TABLE refresh_tokens
+ADD COLUMN session_id bigint REFERENCES sessions(id);
@@ -0,0 +1,35 @@
+-- Add migration script here
+
+-- bots have no password and could only use personal access tokens
+ALTER TABLE users
+ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT FALSE,
+ALTER COLUMN password_hash DROP NOT NULL;
+
+CREATE TYPE token_scope AS ENUM (
+  'chats:read',
+  'chats:write',
+  'messages:read',
+  'messages:write',
+  'workspaces:read',
+  'workspaces:write'
+);
+
+-- personal access tokens, only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS access_tokens (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id),
+  -- workspace the token acts in
+  ws_id bigint NOT NULL REFERENCES workspaces(id),
+  name varchar(64) NOT NULL,
+  token_hash char(64) NOT NULL UNIQUE,
+  prefix varchar(16) NOT NULL,
+  scopes token_scope[] NOT NULL,
+  created_by bigint NOT NULL REFERENCES users(id),
+  last_used_at timestamptz,
+  expires_at timestamptz,
+  revoked_at timestamptz,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS access_tokens_user_id_idx ON access_tokens(user_id);
+CREATE INDEX IF NOT EXISTS access_tokens_ws_id_idx ON access_tokens(ws_id);
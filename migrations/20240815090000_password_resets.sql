@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- single use tokens mailed to reset the password, only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS password_resets (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  token_hash char(64) NOT NULL UNIQUE,
+  expires_at timestamptz NOT NULL,
+  used_at timestamptz,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS password_resets_user_id_idx ON password_resets(user_id);
@@ -0,0 +1,18 @@
+-- Add migration script here
+
+ALTER TABLE users ADD COLUMN email_verified_at timestamptz;
+
+-- existing accounts are trusted
+UPDATE users SET email_verified_at = NOW();
+
+-- single use tokens mailed to verify the email, only the sha256 of the token is stored
+CREATE TABLE IF NOT EXISTS email_verifications (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  token_hash char(64) NOT NULL UNIQUE,
+  expires_at timestamptz NOT NULL,
+  used_at timestamptz,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS email_verifications_user_id_idx ON email_verifications(user_id);
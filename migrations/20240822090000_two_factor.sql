@@ -0,0 +1,35 @@
+-- Add migration script here
+
+-- totp secret of the user, two-factor authentication is enabled once confirmed with a code
+CREATE TABLE IF NOT EXISTS user_totp (
+  user_id bigint PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+  -- hex encoded, it is shared with the authenticator app so it can't be hashed
+  secret varchar(64) NOT NULL,
+  confirmed_at timestamptz,
+  -- time step of the last accepted code, so a code could not be used twice
+  last_used_step bigint,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+-- single use codes to sign in without the authenticator, only the sha256 is stored
+CREATE TABLE IF NOT EXISTS recovery_codes (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  code_hash char(64) NOT NULL,
+  used_at timestamptz,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS recovery_codes_user_id_idx ON recovery_codes(user_id);
+
+-- pending sign ins waiting for the second factor
+CREATE TABLE IF NOT EXISTS two_factor_challenges (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  ws_id bigint NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+  token_hash char(64) NOT NULL UNIQUE,
+  device_name varchar(128),
+  attempts int NOT NULL DEFAULT 0,
+  expires_at timestamptz NOT NULL,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
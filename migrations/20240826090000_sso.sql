@@ -0,0 +1,25 @@
+-- Add migration script here
+
+-- accounts of the users at the openid connect providers
+CREATE TABLE IF NOT EXISTS user_identities (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  issuer varchar(256) NOT NULL,
+  subject varchar(256) NOT NULL,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (issuer, subject)
+);
+
+CREATE INDEX IF NOT EXISTS user_identities_user_id_idx ON user_identities(user_id);
+
+-- sso logins waiting for the provider to redirect back, only the sha256 of the state is stored
+CREATE TABLE IF NOT EXISTS sso_logins (
+  id bigserial PRIMARY KEY,
+  ws_id bigint NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+  state_hash char(64) NOT NULL UNIQUE,
+  nonce varchar(64) NOT NULL,
+  -- pkce verifier sent with the code
+  code_verifier varchar(128) NOT NULL,
+  expires_at timestamptz NOT NULL,
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,32 @@
+-- Add migration script here
+
+ALTER TYPE token_scope ADD VALUE IF NOT EXISTS 'scim';
+
+-- users provisioned by the identity provider of the workspace,
+-- kept when deactivated so the provider could activate them again
+CREATE TABLE IF NOT EXISTS scim_users (
+  ws_id bigint NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  external_id varchar(256),
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+  updated_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (ws_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS user_groups (
+  id bigserial PRIMARY KEY,
+  ws_id bigint NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+  display_name varchar(256) NOT NULL,
+  external_id varchar(256),
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+  updated_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (ws_id, display_name)
+);
+
+CREATE TABLE IF NOT EXISTS user_group_members (
+  group_id bigint NOT NULL REFERENCES user_groups(id) ON DELETE CASCADE,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS user_group_members_user_id_idx ON user_group_members(user_id);
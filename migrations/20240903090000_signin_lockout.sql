@@ -0,0 +1,29 @@
+-- Add migration script here
+
+-- failed signins by account or ip, shared by the nodes of a cluster
+CREATE TABLE IF NOT EXISTS signin_attempts (
+  key varchar(320) PRIMARY KEY,
+  failures integer NOT NULL DEFAULT 0,
+  last_failed_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  locked_until timestamptz
+);
+
+CREATE TYPE audit_action AS ENUM (
+  'signin_locked',
+  'signin_unlocked'
+);
+
+-- security relevant events, user_id is the account the event is about
+CREATE TABLE IF NOT EXISTS audit_logs (
+  id bigserial PRIMARY KEY,
+  ws_id bigint REFERENCES workspaces(id) ON DELETE CASCADE,
+  user_id bigint REFERENCES users(id) ON DELETE SET NULL,
+  actor_id bigint REFERENCES users(id) ON DELETE SET NULL,
+  action audit_action NOT NULL,
+  ip varchar(64),
+  details jsonb NOT NULL DEFAULT '{}',
+  created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS audit_logs_user_id_idx ON audit_logs(user_id);
+CREATE INDEX IF NOT EXISTS audit_logs_ws_id_idx ON audit_logs(ws_id);
@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- profile shown next to the user name, the status is hidden after it expires
+ALTER TABLE users
+  ADD COLUMN display_name varchar(64),
+  ADD COLUMN avatar_url varchar(256),
+  ADD COLUMN title varchar(128),
+  ADD COLUMN timezone varchar(64),
+  ADD COLUMN status_text varchar(100),
+  ADD COLUMN status_emoji varchar(32),
+  ADD COLUMN status_expires_at timestamptz;
@@ -0,0 +1,38 @@
+-- Add migration script here
+
+-- deactivated users could not sign in, deleted ones are anonymized
+ALTER TABLE users
+  ADD COLUMN deactivated_at timestamptz,
+  ADD COLUMN deleted_at timestamptz;
+
+ALTER TYPE audit_action ADD VALUE 'user_deactivated';
+ALTER TYPE audit_action ADD VALUE 'user_reactivated';
+ALTER TYPE audit_action ADD VALUE 'user_deletion_requested';
+ALTER TYPE audit_action ADD VALUE 'user_deleted';
+ALTER TYPE audit_action ADD VALUE 'user_deletion_failed';
+
+CREATE TYPE deletion_status AS ENUM (
+  'pending',
+  'running',
+  'done',
+  'failed'
+);
+
+-- deletions run in the background, failed ones are retried a few times
+CREATE TABLE IF NOT EXISTS user_deletions (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id),
+  -- workspace of the admin who requested it, none when users delete themselves
+  ws_id bigint REFERENCES workspaces(id) ON DELETE SET NULL,
+  requested_by bigint NOT NULL REFERENCES users(id),
+  scrub_messages boolean NOT NULL DEFAULT FALSE,
+  scrub_files boolean NOT NULL DEFAULT FALSE,
+  status deletion_status NOT NULL DEFAULT 'pending',
+  attempts integer NOT NULL DEFAULT 0,
+  error text,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  started_at timestamptz,
+  finished_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS user_deletions_status_idx ON user_deletions(status, id);
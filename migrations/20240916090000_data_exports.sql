@@ -0,0 +1,28 @@
+-- Add migration script here
+
+CREATE TYPE export_status AS ENUM (
+  'pending',
+  'running',
+  'ready',
+  'failed',
+  'expired'
+);
+
+-- archives of the personal data of users, downloadable until they expire
+CREATE TABLE IF NOT EXISTS data_exports (
+  id bigserial PRIMARY KEY,
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  -- the archive is stored in the files of this workspace
+  ws_id bigint NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+  status export_status NOT NULL DEFAULT 'pending',
+  file_name varchar(128),
+  size_bytes bigint,
+  error text,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  started_at timestamptz,
+  finished_at timestamptz,
+  expires_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS data_exports_user_id_idx ON data_exports(user_id, id DESC);
+CREATE INDEX IF NOT EXISTS data_exports_status_idx ON data_exports(status, id);
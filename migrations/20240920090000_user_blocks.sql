@@ -0,0 +1,19 @@
+-- Add migration script here
+
+CREATE TYPE dm_policy AS ENUM (
+  'anyone',
+  -- only users sharing a channel could start a direct message
+  'shared_channels'
+);
+
+ALTER TABLE users ADD COLUMN dm_policy dm_policy NOT NULL DEFAULT 'anyone';
+
+-- users blocked by a user, their messages are hidden from the blocker
+CREATE TABLE IF NOT EXISTS user_blocks (
+  user_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  blocked_id bigint NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, blocked_id)
+);
+
+CREATE INDEX IF NOT EXISTS user_blocks_blocked_id_idx ON user_blocks(blocked_id);
@@ -0,0 +1,29 @@
+-- Add migration script here
+
+-- new messages default to markdown like the api, existing ones were backfilled as plain
+ALTER TABLE messages
+ALTER COLUMN format SET DEFAULT 'markdown';
+
+-- pg_notify payloads are limited to 8000 bytes, the renderings are left out
+-- and rebuilt from the content by the notify server
+CREATE OR REPLACE FUNCTION add_to_message()
+RETURNS TRIGGER AS $$
+BEGIN
+
+    IF TG_OP = 'INSERT' THEN
+        RAISE NOTICE 'add_to_message: %', NEW.id;
+        PERFORM pg_notify('chat_message_created', json_build_object(
+        'message', to_jsonb(NEW) - 'content_html' - 'content_text',
+        'chat', (select row_to_json(chats) from chats where id = NEW.chat_id)
+
+    )::text);
+    ELSIF TG_OP = 'UPDATE' THEN
+        RAISE NOTICE 'update_message: %', NEW.id;
+        PERFORM pg_notify('chat_message_updated', json_build_object(
+        'message', to_jsonb(NEW) - 'content_html' - 'content_text',
+        'chat', (select row_to_json(chats) from chats where id = NEW.chat_id)
+    )::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- removing workspaces and members needs its own scope, workspaces:write is not enough
+ALTER TYPE token_scope ADD VALUE IF NOT EXISTS 'workspaces:admin';
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+-- tokens scoped to a workspace go with it when the workspace is purged
+ALTER TABLE refresh_tokens
+DROP CONSTRAINT refresh_tokens_ws_id_fkey,
+ADD CONSTRAINT refresh_tokens_ws_id_fkey FOREIGN KEY (ws_id) REFERENCES workspaces(id) ON DELETE CASCADE;
+
+ALTER TABLE access_tokens
+DROP CONSTRAINT access_tokens_ws_id_fkey,
+ADD CONSTRAINT access_tokens_ws_id_fkey FOREIGN KEY (ws_id) REFERENCES workspaces(id) ON DELETE CASCADE;
@@ -0,0 +1,9 @@
+-- Add migration script here
+
+-- users admitted by the email domain of a workspace join it once their email is verified
+CREATE TABLE IF NOT EXISTS pending_workspace_members (
+    ws_id BIGINT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ws_id, user_id)
+);
@@ -0,0 +1,19 @@
+-- Add migration script here
+
+-- plain messages backfilled by 20240712103000 lost their line breaks, render them again like
+-- the server does. Only the rows still holding the backfilled html are updated, and clients are
+-- not notified of these messages
+ALTER TABLE messages DISABLE TRIGGER add_to_message_trigger;
+
+UPDATE messages
+SET content_text = btrim(content, E' \t\r\n'),
+    content_html = '<p>' || replace(
+        replace(replace(replace(
+            regexp_replace(replace(content, E'\r\n', E'\n'), E'\n$', ''),
+            '&', '&amp;'), '<', '&lt;'), '>', '&gt;'),
+        E'\n', E'<br>\n'
+    ) || E'</p>\n'
+WHERE format = 'plain'
+    AND content_html = '<p>' || replace(replace(replace(content, '&', '&amp;'), '<', '&lt;'), '>', '&gt;') || '</p>';
+
+ALTER TABLE messages ENABLE TRIGGER add_to_message_trigger;
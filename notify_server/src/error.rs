@@ -22,6 +22,9 @@ pub enum AppError {
 
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("sql error: {0}")]
+    Sqlx(#[from] sqlx::Error),
 }
 
 impl IntoResponse for AppError {
@@ -30,7 +33,7 @@ impl IntoResponse for AppError {
         use axum::response::Json;
 
         let status = match &self {
-            AppError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Io(_) | AppError::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Jwt(_) => StatusCode::UNAUTHORIZED,
         };
 
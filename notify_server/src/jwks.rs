@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use chat_core::{DecodingKey, Jwks};
+use tracing::{info, warn};
+
+use crate::AppState;
+
+const JWKS_TIMEOUT: Duration = Duration::from_secs(5);
+
+// load the key set published by chat_server and reload it periodically,
+// the keys in the config are used until the first load succeeds
+pub(crate) async fn setup_jwks_loader(state: AppState) {
+    let Some(url) = state.config.auth.jwks_url.clone() else {
+        return;
+    };
+    let client = reqwest::Client::builder()
+        .timeout(JWKS_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    reload_jwks(&state, &client, &url).await;
+    let interval = Duration::from_secs(state.config.auth.jwks_refresh_secs.max(1));
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            reload_jwks(&state, &client, &url).await;
+        }
+    });
+}
+
+async fn reload_jwks(state: &AppState, client: &reqwest::Client, url: &str) {
+    match fetch_jwks(client, url).await {
+        Ok(pk) => {
+            if let Ok(mut current) = state.pk.write() {
+                if current.kids() != pk.kids() {
+                    info!("jwks loaded, key ids: {:?}", pk.kids());
+                }
+                *current = pk;
+            }
+        }
+        Err(e) => warn!("failed to load jwks from {}: {}", url, e),
+    }
+}
+
+async fn fetch_jwks(client: &reqwest::Client, url: &str) -> anyhow::Result<DecodingKey> {
+    let jwks: Jwks = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    DecodingKey::from_jwks(&jwks)
+}
@@ -1,12 +1,22 @@
 mod config;
 mod error;
+mod jwks;
 mod notify;
 pub use config::AppConfig;
 pub use notify::AppEvent;
-use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::{
+    postgres::PgPoolOptions,
+    types::chrono::{DateTime, Utc},
+    PgPool,
+};
 use tracing::info;
 
-use std::{ops::Deref, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    ops::Deref,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use anyhow::Context;
 use axum::{
@@ -16,9 +26,12 @@ use axum::{
     routing::get,
     Extension, Router,
 };
-use chat_core::{verify_token, DecodingKey, TokenVerifier, User};
+use chat_core::{
+    verify_token, AuthContext, DecodingKey, RevocationList, TokenVerifier, UserClaims,
+};
 use dashmap::DashMap;
 use error::AppError;
+use jwt_simple::prelude::JWTClaims;
 
 use sse::sse_handler;
 use tokio::sync::broadcast;
@@ -26,6 +39,8 @@ use tokio::sync::broadcast;
 mod sse;
 
 pub type UserMap = DashMap<u64, broadcast::Sender<Arc<AppEvent>>>;
+// users blocked by each user
+pub type BlockMap = DashMap<u64, HashSet<u64>>;
 
 const INDEX_HTML: &str = include_str!("../static/index.html");
 
@@ -33,15 +48,23 @@ const INDEX_HTML: &str = include_str!("../static/index.html");
 pub struct AppState(Arc<AppStateInner>);
 
 pub struct AppStateInner {
-    pub pk: DecodingKey,
+    // replaced when the key set is reloaded from chat_server
+    pub pk: RwLock<DecodingKey>,
     pub users: Arc<UserMap>,
     pub alive_users: Arc<DashMap<u64, DateTime<Utc>>>,
+    pub blocks: Arc<BlockMap>,
+    pub revoked: RevocationList,
+    // connected on first use, events sent without their recipients are resolved with it
+    pub pool: PgPool,
     pub config: AppConfig,
 }
 
 pub async fn get_router(config: AppConfig) -> Result<Router, AppError> {
     let state = AppState::try_new(config)?;
     notify::setup_pg_listener(state.clone()).await?;
+    notify::load_revoked_tokens(&state).await?;
+    notify::load_user_blocks(&state).await?;
+    jwks::setup_jwks_loader(state.clone()).await;
     set_alive_user_checker(state.clone());
     let router = Router::new()
         .route("/events", get(sse_handler))
@@ -58,8 +81,8 @@ async fn index() -> impl IntoResponse {
 }
 
 // alive_users handler
-async fn alive_handler(Extension(user): Extension<User>, State(state): State<AppState>) {
-    let user_id = user.id;
+async fn alive_handler(Extension(ctx): Extension<AuthContext>, State(state): State<AppState>) {
+    let user_id = ctx.user_id;
     state
         .alive_users
         .insert(user_id as u64, Utc::now() + Duration::from_secs(2));
@@ -78,27 +101,52 @@ fn set_alive_user_checker(state: AppState) {
             }
             // remove dead users from alive_users
             state.alive_users.retain(|_, v| *v > now);
+            state.revoked.purge_expired(now.timestamp() as u64);
         }
     });
 }
+// personal access tokens are not accepted for event streams
+#[axum::async_trait]
 impl TokenVerifier for AppState {
     type Error = anyhow::Error;
-    fn verify(&self, token: &str) -> Result<User, Self::Error> {
-        self.0.pk.verify(token)
+    async fn verify(&self, token: &str) -> Result<AuthContext, Self::Error> {
+        let claims = self.decode(token)?;
+        if self.0.revoked.is_claims_revoked(&claims) {
+            anyhow::bail!("token revoked");
+        }
+        Ok(claims.custom.into())
     }
 }
 
 impl AppState {
+    pub fn is_blocked(&self, user_id: u64, sender_id: u64) -> bool {
+        self.blocks
+            .get(&user_id)
+            .is_some_and(|blocked| blocked.contains(&sender_id))
+    }
+
     pub fn try_new(config: AppConfig) -> Result<Self, AppError> {
-        let pk = DecodingKey::load(&config.auth.pk).context("load pk failed")?;
+        let pk = config.auth.decoding_key().context("load pk failed")?;
+        let pool = PgPoolOptions::new().connect_lazy(&config.server.db_url)?;
 
         Ok(Self(Arc::new(AppStateInner {
-            pk,
+            pk: RwLock::new(pk),
+            pool,
             config,
             users: Arc::new(DashMap::default()),
             alive_users: Arc::new(DashMap::default()),
+            blocks: Arc::new(DashMap::default()),
+            revoked: RevocationList::new(),
         })))
     }
+
+    pub fn decode(&self, token: &str) -> Result<JWTClaims<UserClaims>, jwt_simple::Error> {
+        let pk = self
+            .pk
+            .read()
+            .map_err(|_| anyhow::anyhow!("key set lock poisoned"))?;
+        pk.decode(token)
+    }
 }
 
 impl Deref for AppState {
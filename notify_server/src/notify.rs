@@ -1,8 +1,13 @@
 use std::sync::Arc;
 
-use chat_core::{Chat, Message};
+use chat_core::{
+    Chat, ChatUser, EphemeralMessage, Message, Poll, Revoked, CHAT_USER_COLUMNS, JWT_DURATION,
+};
 use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgListener;
+use sqlx::{
+    postgres::{PgConnection, PgListener},
+    Connection,
+};
 use tokio_stream::StreamExt;
 use tracing::{info, warn};
 
@@ -15,6 +20,10 @@ pub enum AppEvent {
     AddToChat(Chat),
     RemoveFromChat(Chat),
     NewMessage(Message),
+    MessageUpdated(Message),
+    PollUpdated(Poll),
+    EphemeralMessage(EphemeralMessage),
+    UserUpdated(ChatUser),
     Alive,
 }
 // PERFORM pg_notify('chat_updated', json_build_object('op', TG_OP,'old', OLD, 'new', NEW )::text);
@@ -26,7 +35,9 @@ pub struct ChatUpdated {
     pub new: Option<Chat>,
 }
 
-// PERFORM pg_notify('chat_message_created', row_to_json(NEW)::text);
+// PERFORM pg_notify('chat_message_created', json_build_object('message', message, 'chat', chat)::text);
+// PERFORM pg_notify('chat_message_updated', json_build_object('message', message, 'chat', chat)::text);
+// the message comes without content_html and content_text to keep the payload small
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct ChatMessageCreated {
@@ -34,9 +45,49 @@ pub struct ChatMessageCreated {
     pub message: Message,
 }
 
+// PERFORM pg_notify('poll_updated', json_build_object('poll', poll, 'chat', chat)::text);
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct ChatPollUpdated {
+    pub chat: Chat,
+    pub poll: Poll,
+}
+
+// PERFORM pg_notify('ephemeral_message', message::text);
+// the message is only delivered to its user
+
+// pg_notify('user_updated', json_build_object('user_id', user_id)::text);
+// the profile and everyone sharing a workspace with the user are loaded from the db
+#[derive(Debug, Deserialize)]
+pub struct UserUpdated {
+    pub user_id: u64,
+}
+
+// pg_notify('token_revoked', json_build_object('item', item, 'exp', exp)::text);
+// open streams using the token or the session are closed
+#[derive(Debug, Deserialize)]
+pub struct TokenRevoked {
+    pub item: Revoked,
+    pub exp: u64,
+}
+
+// pg_notify('user_blocks', json_build_object('user_id', user_id, 'blocked_id', blocked_id, 'blocked', blocked)::text);
+// events of blocked users are not delivered to the user who blocked them
+#[derive(Debug, Deserialize)]
+pub struct UserBlocks {
+    pub user_id: u64,
+    pub blocked_id: u64,
+    pub blocked: bool,
+}
+
+// pg_notify('user_deactivated', user_id::text);
+// the user is dropped, its streams are closed by the revocation of its sessions
+
 #[derive(Debug)]
 pub struct Notification {
     pub user_ids: Vec<u64>,
+    // user the event comes from, if the event could be blocked
+    pub sender_id: Option<u64>,
     pub event: Arc<AppEvent>,
 }
 
@@ -45,24 +96,90 @@ pub async fn setup_pg_listener(state: AppState) -> anyhow::Result<()> {
 
     lisitener.listen("chat_updated").await?;
     lisitener.listen("chat_message_created").await?;
+    lisitener.listen("chat_message_updated").await?;
+    lisitener.listen("poll_updated").await?;
+    lisitener.listen("ephemeral_message").await?;
+    lisitener.listen("user_updated").await?;
+    lisitener.listen("token_revoked").await?;
+    lisitener.listen("user_deactivated").await?;
+    lisitener.listen("user_blocks").await?;
 
     let mut pg_stream = lisitener.into_stream();
 
     tokio::spawn(async move {
         while let Some(notification) = pg_stream.next().await {
             match notification {
+                Ok(notification) if notification.channel() == "token_revoked" => {
+                    let token: TokenRevoked = match serde_json::from_str(notification.payload()) {
+                        Ok(token) => token,
+                        Err(err) => {
+                            warn!("Failed to parse revoked token: {:?}", err);
+                            continue;
+                        }
+                    };
+                    info!("{:?} revoked", token.item);
+                    state.revoked.revoke(token.item, token.exp);
+                }
+                Ok(notification) if notification.channel() == "user_deactivated" => {
+                    let user_id: u64 = match notification.payload().parse() {
+                        Ok(user_id) => user_id,
+                        Err(err) => {
+                            warn!("Failed to parse deactivated user: {:?}", err);
+                            continue;
+                        }
+                    };
+                    info!("user {} deactivated", user_id);
+                    state.users.remove(&user_id);
+                }
+                Ok(notification) if notification.channel() == "user_blocks" => {
+                    let blocks: UserBlocks = match serde_json::from_str(notification.payload()) {
+                        Ok(blocks) => blocks,
+                        Err(err) => {
+                            warn!("Failed to parse user blocks: {:?}", err);
+                            continue;
+                        }
+                    };
+                    let mut blocked = state.blocks.entry(blocks.user_id).or_default();
+                    if blocks.blocked {
+                        blocked.insert(blocks.blocked_id);
+                    } else {
+                        blocked.remove(&blocks.blocked_id);
+                    }
+                }
+                Ok(notification) if notification.channel() == "user_updated" => {
+                    let updated: UserUpdated = match serde_json::from_str(notification.payload()) {
+                        Ok(updated) => updated,
+                        Err(err) => {
+                            warn!("Failed to parse updated user: {:?}", err);
+                            continue;
+                        }
+                    };
+                    // the lookup runs aside so other events are not held by the db
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        match load_user_updated(&state, updated.user_id).await {
+                            Ok(Some(notification)) => send_notification(&state, notification),
+                            Ok(None) => {}
+                            Err(err) => {
+                                warn!("Failed to load updated user {}: {:?}", updated.user_id, err)
+                            }
+                        }
+                    });
+                }
                 Ok(notification) => {
                     let notification =
-                        Notification::load(notification.channel(), notification.payload())?;
-                    let user_map = state.users.clone();
-                    for user_id in notification.user_ids {
-                        if let Some(sender) = user_map.get(&user_id) {
-                            info!("sending notification to user {}", user_id);
-                            if let Err(err) = sender.send(notification.event.clone()) {
-                                warn!("Failed to send notification to user {}: {:?}", user_id, err);
+                        match Notification::load(notification.channel(), notification.payload()) {
+                            Ok(notification) => notification,
+                            Err(err) => {
+                                warn!(
+                                    "Failed to load {} notification: {:?}",
+                                    notification.channel(),
+                                    err
+                                );
+                                continue;
                             }
-                        }
-                    }
+                        };
+                    send_notification(&state, notification);
                 }
                 Err(err) => {
                     eprintln!("error: {:?}", err);
@@ -74,6 +191,96 @@ pub async fn setup_pg_listener(state: AppState) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn send_notification(state: &AppState, notification: Notification) {
+    for user_id in notification.user_ids {
+        if notification
+            .sender_id
+            .is_some_and(|sender_id| state.is_blocked(user_id, sender_id))
+        {
+            continue;
+        }
+        if let Some(sender) = state.users.get(&user_id) {
+            info!("sending notification to user {}", user_id);
+            if let Err(err) = sender.send(notification.event.clone()) {
+                warn!("Failed to send notification to user {}: {:?}", user_id, err);
+            }
+        }
+    }
+}
+
+// the user gets its own profile too, a deleted user is not notified
+async fn load_user_updated(state: &AppState, user_id: u64) -> anyhow::Result<Option<Notification>> {
+    let sql = format!("SELECT {} FROM users u WHERE u.id = $1", CHAT_USER_COLUMNS);
+    let Some(user): Option<ChatUser> = sqlx::query_as(&sql)
+        .bind(user_id as i64)
+        .fetch_optional(&state.pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let members: Vec<i64> = sqlx::query_scalar(
+        r#"
+            SELECT $1::bigint
+            UNION
+            SELECT m2.user_id
+            FROM workspace_members m1
+            JOIN workspace_members m2 ON m2.ws_id = m1.ws_id
+            WHERE m1.user_id = $1"#,
+    )
+    .bind(user_id as i64)
+    .fetch_all(&state.pool)
+    .await?;
+    Ok(Some(Notification {
+        user_ids: members.into_iter().map(|id| id as u64).collect(),
+        sender_id: Some(user_id),
+        event: Arc::new(AppEvent::UserUpdated(user)),
+    }))
+}
+
+// tokens and sessions revoked before the server started
+pub async fn load_revoked_tokens(state: &AppState) -> anyhow::Result<()> {
+    let mut conn = PgConnection::connect(&state.config.server.db_url).await?;
+    let tokens: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+            SELECT jti, extract(epoch FROM expires_at)::bigint
+            FROM revoked_tokens
+            WHERE expires_at > NOW()"#,
+    )
+    .fetch_all(&mut conn)
+    .await?;
+    for (jti, exp) in tokens {
+        state.revoked.revoke(Revoked::Token(jti), exp as u64);
+    }
+    let sessions: Vec<(i64, i64)> = sqlx::query_as(
+        r#"
+            SELECT id, extract(epoch FROM revoked_at)::bigint + $1
+            FROM sessions
+            WHERE revoked_at > NOW() - make_interval(secs => $1)"#,
+    )
+    .bind(JWT_DURATION as i64)
+    .fetch_all(&mut conn)
+    .await?;
+    for (id, exp) in sessions {
+        state.revoked.revoke(Revoked::Session(id), exp as u64);
+    }
+    Ok(())
+}
+
+pub async fn load_user_blocks(state: &AppState) -> anyhow::Result<()> {
+    let mut conn = PgConnection::connect(&state.config.server.db_url).await?;
+    let blocks: Vec<(i64, i64)> = sqlx::query_as("SELECT user_id, blocked_id FROM user_blocks")
+        .fetch_all(&mut conn)
+        .await?;
+    for (user_id, blocked_id) in blocks {
+        state
+            .blocks
+            .entry(user_id as u64)
+            .or_default()
+            .insert(blocked_id as u64);
+    }
+    Ok(())
+}
+
 impl Notification {
     fn load(channel: &str, payload: &str) -> anyhow::Result<Self> {
         match channel {
@@ -92,7 +299,11 @@ impl Notification {
                     _ => anyhow::bail!("unknown operation: {}", chat_updated.op),
                 };
                 let event = Arc::new(event);
-                Ok(Self { user_ids, event })
+                Ok(Self {
+                    user_ids,
+                    sender_id: None,
+                    event,
+                })
             }
             "chat_message_created" => {
                 let chat_message_created: ChatMessageCreated = serde_json::from_str(payload)?;
@@ -102,14 +313,72 @@ impl Notification {
                     .iter()
                     .map(|id| *id as u64)
                     .collect();
-                let event = Arc::new(AppEvent::NewMessage(chat_message_created.message));
-                Ok(Self { user_ids, event })
+                let sender_id = Some(chat_message_created.message.sender_id as u64);
+                let message = render_message(chat_message_created.message);
+                let event = Arc::new(AppEvent::NewMessage(message));
+                Ok(Self {
+                    user_ids,
+                    sender_id,
+                    event,
+                })
+            }
+            "chat_message_updated" => {
+                let chat_message_updated: ChatMessageCreated = serde_json::from_str(payload)?;
+                let user_ids = chat_message_updated
+                    .chat
+                    .members
+                    .iter()
+                    .map(|id| *id as u64)
+                    .collect();
+                let sender_id = Some(chat_message_updated.message.sender_id as u64);
+                let message = render_message(chat_message_updated.message);
+                let event = Arc::new(AppEvent::MessageUpdated(message));
+                Ok(Self {
+                    user_ids,
+                    sender_id,
+                    event,
+                })
+            }
+            "poll_updated" => {
+                let chat_poll_updated: ChatPollUpdated = serde_json::from_str(payload)?;
+                let user_ids = chat_poll_updated
+                    .chat
+                    .members
+                    .iter()
+                    .map(|id| *id as u64)
+                    .collect();
+                let event = Arc::new(AppEvent::PollUpdated(chat_poll_updated.poll));
+                Ok(Self {
+                    user_ids,
+                    sender_id: None,
+                    event,
+                })
+            }
+            "ephemeral_message" => {
+                let message: EphemeralMessage = serde_json::from_str(payload)?;
+                let user_ids = vec![message.user_id as u64];
+                let event = Arc::new(AppEvent::EphemeralMessage(message));
+                Ok(Self {
+                    user_ids,
+                    sender_id: None,
+                    event,
+                })
             }
             _ => anyhow::bail!("unknown channel: {}", channel),
         }
     }
 }
 
+// content of deleted accounts is cleared along with its renderings
+fn render_message(mut message: Message) -> Message {
+    if !message.content.is_empty() {
+        let rendered = message.format.render(&message.content);
+        message.content_html = rendered.html;
+        message.content_text = rendered.text;
+    }
+    message
+}
+
 fn get_affected_chat_user_ids(old: Option<&Chat>, new: Option<&Chat>) -> Vec<u64> {
     match (old, new) {
         (Some(old), Some(new)) => {
@@ -126,3 +395,44 @@ fn get_affected_chat_user_ids(old: Option<&Chat>, new: Option<&Chat>) -> Vec<u64
         (None, None) => Vec::new(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn message_notification_should_render_content() -> anyhow::Result<()> {
+        let payload = json!({
+            "message": {
+                "id": 1,
+                "chat_id": 1,
+                "sender_id": 2,
+                "kind": "text",
+                "format": "markdown",
+                "content": "**hi**",
+                "files": [],
+                "previews": [],
+                "created_at": "2024-09-23T09:00:00Z"
+            },
+            "chat": {
+                "id": 1,
+                "ws_id": 0,
+                "name": "general",
+                "type": "public_channel",
+                "members": [1, 2],
+                "topic": null,
+                "created_at": "2024-09-23T09:00:00Z"
+            }
+        });
+        let notification = Notification::load("chat_message_created", &payload.to_string())?;
+        assert_eq!(notification.user_ids, vec![1, 2]);
+        assert_eq!(notification.sender_id, Some(2));
+        let AppEvent::NewMessage(message) = notification.event.as_ref() else {
+            panic!("unexpected event: {:?}", notification.event);
+        };
+        assert_eq!(message.content_html, "<p><strong>hi</strong></p>\n");
+        assert_eq!(message.content_text, "hi");
+        Ok(())
+    }
+}
@@ -3,20 +3,29 @@ use axum::{
     response::sse::{Event, Sse},
     Extension,
 };
-use chat_core::User;
-use futures::stream::{self, Stream};
-use std::{convert::Infallible, sync::Arc, time::Duration};
-use tokio::sync::broadcast;
+use chat_core::{revoked_items, AuthContext, AuthHeader};
+use futures::{
+    future,
+    stream::{self, Stream},
+};
+use std::{
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio_stream::{wrappers::BroadcastStream, StreamExt as _};
 use tracing::info;
 
 use crate::{AppEvent, AppState};
 const MAX_CHANNEL_SIZE: usize = 100;
 pub async fn sse_handler(
-    Extension(user): Extension<User>,
+    Extension(ctx): Extension<AuthContext>,
     State(state): State<AppState>,
+    AuthHeader(token): AuthHeader,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let user_id = user.id as u64;
+    let user_id = ctx.user_id as u64;
+    let closed = token_closed(state.clone(), user_id, token);
     let receive = if let Some(sender) = state.users.get(&user_id) {
         info!("user {} subscribed", user_id);
         sender.subscribe()
@@ -36,11 +45,17 @@ pub async fn sse_handler(
                 AppEvent::AddToChat(_) => "AddToChat",
                 AppEvent::RemoveFromChat(_) => "RemoveFromChat",
                 AppEvent::NewMessage(_) => "NewMessage",
+                AppEvent::MessageUpdated(_) => "MessageUpdated",
+                AppEvent::PollUpdated(_) => "PollUpdated",
+                AppEvent::EphemeralMessage(_) => "EphemeralMessage",
+                AppEvent::UserUpdated(_) => "UserUpdated",
                 AppEvent::Alive => "Alive",
             };
             let data = serde_json::to_string(&e).expect("Failed to serialize event");
             Ok(Event::default().event(name).data(data))
         });
+    // the stream ends when the token expires or is revoked
+    let stream = futures::StreamExt::take_until(stream, closed);
 
     Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
@@ -49,3 +64,37 @@ pub async fn sse_handler(
             .text("keep-alive-text"),
     )
 }
+
+// resolves when the token expires, or the token or its session is revoked
+async fn token_closed(state: AppState, user_id: u64, token: String) {
+    let Ok(claims) = state.decode(&token) else {
+        return;
+    };
+    let (Some(items), Some(exp)) = (revoked_items(&claims), claims.expires_at) else {
+        return;
+    };
+    // subscribe before checking to not miss a revocation in between
+    let mut revoked = state.revoked.subscribe();
+    if state.revoked.is_claims_revoked(&claims) {
+        return;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let expires_in = Duration::from_secs(exp.as_secs()).saturating_sub(now);
+    let revoked = async move {
+        loop {
+            match revoked.recv().await {
+                Ok(item) if items.contains(&item) => break,
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) if state.revoked.is_claims_revoked(&claims) => break,
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => future::pending::<()>().await,
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::time::sleep(expires_in) => info!("token of user {} expired, closing stream", user_id),
+        _ = revoked => info!("token of user {} revoked, closing stream", user_id),
+    }
+}